@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: CC0-1.0
+
+#![cfg_attr(fuzzing, no_main)]
+
+#[cfg(fuzzing)]
+fn do_test(data: &[u8]) {
+    use simplicity::jet::Elements;
+    use simplicity::{BitIter, RedeemNode};
+
+    let prog_iter = BitIter::new(data.iter().cloned());
+    let wit_iter = BitIter::new(core::iter::repeat(0));
+    let program = match RedeemNode::<Elements>::decode(prog_iter, wit_iter) {
+        Ok(program) => program,
+        Err(_) => return,
+    };
+
+    // If C rejects the program then there is nothing to compare; whether
+    // C and Rust agree on decoding is checked by the `c_rust_merkle` target.
+    if let Ok(mismatches) = simplicity::compare_c_bounds(&program) {
+        if !mismatches.is_empty() {
+            for mismatch in &mismatches {
+                println!("{}", mismatch);
+            }
+            panic!("Rust and C static analyses disagree");
+        }
+    }
+}
+
+#[cfg(fuzzing)]
+libfuzzer_sys::fuzz_target!(|data| do_test(data));
+
+#[cfg(not(fuzzing))]
+fn main() {}
@@ -4,7 +4,7 @@
 
 #[cfg(any(fuzzing, test))]
 fn do_test(data: &[u8]) {
-    use simplicity::human_encoding::Forest;
+    use simplicity::human_encoding::{format_source, Forest};
     use simplicity::jet::Elements;
 
     let s = match std::str::from_utf8(data) {
@@ -16,6 +16,11 @@ fn do_test(data: &[u8]) {
         let reserialize = program.string_serialize();
         let round_trip = Forest::<Elements>::parse(&reserialize).unwrap();
         assert_eq!(program, round_trip);
+
+        let formatted = format_source::<Elements>(s).unwrap();
+        let round_trip = Forest::<Elements>::parse(&formatted).unwrap();
+        assert_eq!(program, round_trip);
+        assert_eq!(format_source::<Elements>(&formatted).unwrap(), formatted);
     }
 }
 
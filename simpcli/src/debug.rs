@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Bit Machine Debugger
+//!
+//! Steps through the execution of a program, reading commands from standard
+//! input and printing the frames of the Bit Machine at each stop.
+
+use simplicity::bit_machine::{Debugger, ExecutionError, Stop};
+use simplicity::dag::{DagLike, MaxSharing};
+use simplicity::human_encoding::{Forest, SourceMap};
+use simplicity::jet::Jet;
+use simplicity::node::RedeemNode;
+use simplicity::{Cmr, Value};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const HELP: &str = "\
+Commands:
+  step, s          execute the current node, stopping at the next one
+  next, n          execute the current node and every node below it
+  out, o           finish executing the parent of the current node
+  continue, c      run until a breakpoint or the end of the program
+  break, b TARGET  stop before every node named TARGET or with CMR TARGET
+  delete, d TARGET remove a breakpoint
+  where, w         print the current node and frames again
+  help, h          print this message
+  quit, q          exit";
+
+/// Debugs `program` on the given input, stopping first before its root node.
+///
+/// Breakpoints are given by name, looked up in `main` of `forest`, or by CMR.
+pub fn run<J: Jet>(
+    forest: &Forest<J>,
+    program: &RedeemNode<J>,
+    env: &J::Environment,
+    input: &Value,
+    breakpoints: &[String],
+) -> Result<(), String> {
+    let names = node_names(forest);
+    let source_map = forest.redeem_source_map(program);
+    let mut debugger = Debugger::new(program, env, input)
+        .map_err(|e| format!("failed to start execution: {}", e))?;
+    for target in breakpoints {
+        debugger.add_breakpoint(resolve(&names, target)?);
+    }
+
+    print_state(&debugger, &source_map);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(debug) ");
+        io::stdout()
+            .flush()
+            .map_err(|e| format!("failed to write prompt: {}", e))?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| format!("failed to read input: {}", e))?,
+            None => {
+                println!();
+                return Ok(());
+            }
+        };
+        let (command, arg) = match line.trim().split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (line.trim(), ""),
+        };
+
+        let result = match command {
+            "" => continue,
+            "step" | "s" => debugger.step_into(),
+            "next" | "n" => debugger.step_over(),
+            "out" | "o" => debugger.step_out(),
+            "continue" | "c" => debugger.resume(),
+            "break" | "b" => {
+                match resolve(&names, arg) {
+                    Ok(cmr) => {
+                        debugger.add_breakpoint(cmr);
+                        println!("Breakpoint at {}", cmr);
+                    }
+                    Err(e) => eprintln!("{}", e),
+                }
+                continue;
+            }
+            "delete" | "d" => {
+                match resolve(&names, arg) {
+                    Ok(cmr) if debugger.remove_breakpoint(cmr) => {
+                        println!("Deleted breakpoint at {}", cmr)
+                    }
+                    Ok(cmr) => eprintln!("no breakpoint at {}", cmr),
+                    Err(e) => eprintln!("{}", e),
+                }
+                continue;
+            }
+            "where" | "w" => {
+                print_state(&debugger, &source_map);
+                continue;
+            }
+            "help" | "h" => {
+                println!("{}", HELP);
+                continue;
+            }
+            "quit" | "q" => return Ok(()),
+            _ => {
+                eprintln!("unknown command {}; enter help for help", command);
+                continue;
+            }
+        };
+        print_stop(result, &debugger, &source_map);
+    }
+}
+
+/// Maps the name of every node of `main` to its CMR.
+fn node_names<J: Jet>(forest: &Forest<J>) -> HashMap<Arc<str>, Cmr> {
+    let mut names = HashMap::new();
+    if let Some(main) = forest.roots().get("main") {
+        for data in main.as_ref().post_order_iter::<MaxSharing<_>>() {
+            names.insert(Arc::clone(data.node.name()), data.node.cmr());
+        }
+    }
+    names
+}
+
+/// Looks up the CMR of a breakpoint, given as a name or a CMR.
+fn resolve(names: &HashMap<Arc<str>, Cmr>, target: &str) -> Result<Cmr, String> {
+    match names.get(target) {
+        Some(cmr) => Ok(*cmr),
+        None => Cmr::from_str(target)
+            .map_err(|_| format!("{} is neither the name of a node nor a CMR", target)),
+    }
+}
+
+fn print_stop<J: Jet>(
+    result: Result<Stop, ExecutionError>,
+    debugger: &Debugger<J>,
+    source_map: &SourceMap,
+) {
+    match result {
+        Ok(Stop::Step) => print_state(debugger, source_map),
+        Ok(Stop::Breakpoint) => {
+            println!("Breakpoint reached");
+            print_state(debugger, source_map);
+        }
+        Ok(Stop::Finished(output)) => println!("Finished with output {}", format_value(&output)),
+        Err(e) => {
+            println!("Execution failed: {}", e);
+            print_state(debugger, source_map);
+        }
+    }
+}
+
+/// Prints the current node, where it is in the source, and the frames of the
+/// Bit Machine.
+fn print_state<J: Jet>(debugger: &Debugger<J>, source_map: &SourceMap) {
+    let node = match debugger.current() {
+        Some(node) => node,
+        None => {
+            println!("The program has finished.");
+            return;
+        }
+    };
+    println!(
+        "At {} : {} -> {} (depth {}, CMR {})",
+        node.inner(),
+        node.arrow().source,
+        node.arrow().target,
+        debugger.path().count() - 1,
+        node.cmr(),
+    );
+    for location in source_map.lookup(node) {
+        println!("  in {}", location);
+    }
+    match debugger.input_value() {
+        Some(value) => println!("Input: {}", format_value(&value)),
+        None => println!("Input: (cannot be decoded)"),
+    }
+    println!("Read frames, active last:");
+    for frame in debugger.read_frames() {
+        println!("  {} (cursor at {})", frame, frame.cursor());
+    }
+    println!("Write frames, active last:");
+    for frame in debugger.write_frames() {
+        println!("  {} (cursor at {})", frame, frame.cursor());
+    }
+}
+
+/// Formats words in hex, and other values structurally.
+fn format_value(value: &Value) -> String {
+    match value.to_word() {
+        Some(word) => word.to_string(),
+        None => value.to_string(),
+    }
+}
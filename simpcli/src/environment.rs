@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Transaction environments
+//!
+//! Programs are run against a transaction, which is either a dummy one or, for
+//! Elements jets, is read from a JSON file. The file is an object with the
+//! following fields, all of which are optional:
+//!
+//! * `tx`, the consensus encoding of the transaction in hex;
+//! * `utxos`, an array with one object for each input of the transaction,
+//!   giving the output that it spends as the hex `script_pubkey`, and the hex
+//!   consensus encodings of its `asset` and `value`;
+//! * `input_index`, the index of the input being spent;
+//! * `control_block`, the taproot control block of the input in hex;
+//! * `annex`, the annex of the input in hex;
+//! * `genesis_hash`, the hash of the genesis block of the chain.
+
+use simplicity::elements::confidential;
+use simplicity::elements::encode::deserialize;
+use simplicity::elements::secp256k1_zkp::Parity;
+use simplicity::elements::taproot::{ControlBlock, TaprootMerkleBranch};
+use simplicity::elements::{self, AssetIssuance, BlockHash, Transaction};
+use simplicity::hashes::Hash;
+use simplicity::hex::FromHex;
+use simplicity::jet::bitcoin::BitcoinEnv;
+use simplicity::jet::elements::{ElementsEnv, ElementsUtxo};
+use simplicity::jet::{Bitcoin, Core, Elements, Jet};
+use simplicity::{leaf_version, Cmr};
+
+use crate::liquid;
+
+use std::convert::TryFrom;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A family of jets that simpcli can run programs with
+pub trait Family: Jet + 'static {
+    /// The environment of a program with the given CMR, read from the given
+    /// file or else a dummy one.
+    fn environment(file: Option<&str>, cmr: Cmr) -> Result<Self::Environment, String>;
+}
+
+impl Family for Core {
+    fn environment(file: Option<&str>, _: Cmr) -> Result<(), String> {
+        match file {
+            Some(_) => Err("core jets take no environment".into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Family for Elements {
+    fn environment(file: Option<&str>, cmr: Cmr) -> Result<ElementsEnv<Arc<Transaction>>, String> {
+        match file {
+            Some(file) => read(file, cmr),
+            None => Ok(dummy(cmr)),
+        }
+    }
+}
+
+impl Family for Bitcoin {
+    fn environment(file: Option<&str>, _: Cmr) -> Result<BitcoinEnv, String> {
+        match file {
+            Some(_) => Err("environment files are not supported for bitcoin jets".into()),
+            None => Ok(BitcoinEnv::default()),
+        }
+    }
+}
+
+/// The fields of an environment file.
+const FIELDS: [&str; 6] = [
+    "tx",
+    "utxos",
+    "input_index",
+    "control_block",
+    "annex",
+    "genesis_hash",
+];
+
+/// The environment of a program with the given CMR, spending the only input of
+/// a dummy transaction.
+fn dummy(cmr: Cmr) -> ElementsEnv<Arc<Transaction>> {
+    let tx = dummy_transaction();
+    let utxos = vec![dummy_utxo(); tx.input.len()];
+    ElementsEnv::new(
+        Arc::new(tx),
+        utxos,
+        0,
+        cmr,
+        default_control_block(),
+        None,
+        BlockHash::all_zeros(),
+    )
+}
+
+/// Reads the environment of a program with the given CMR from a JSON file.
+fn read(name: &str, cmr: Cmr) -> Result<ElementsEnv<Arc<Transaction>>, String> {
+    let contents =
+        fs::read_to_string(name).map_err(|e| format!("failed to read env {}: {}", name, e))?;
+    from_json(&contents, cmr).map_err(|e| format!("bad env in {}: {}", name, e))
+}
+
+/// Reads the outputs spent by a transaction from a JSON file, which holds an
+/// array of objects in the format of `utxos` in an environment file.
+pub fn read_utxos(name: &str) -> Result<Vec<ElementsUtxo>, String> {
+    let contents =
+        fs::read_to_string(name).map_err(|e| format!("failed to read utxos {}: {}", name, e))?;
+    let json: serde_json::Value =
+        serde_json::from_str(&contents).map_err(|e| format!("bad utxos in {}: {}", name, e))?;
+    json.as_array()
+        .ok_or_else(|| format!("bad utxos in {}: expected a JSON array", name))?
+        .iter()
+        .map(|json| utxo(json).map_err(|e| format!("bad utxos in {}: {}", name, e)))
+        .collect()
+}
+
+fn from_json(s: &str, cmr: Cmr) -> Result<ElementsEnv<Arc<Transaction>>, String> {
+    let json: serde_json::Value = serde_json::from_str(s).map_err(|e| e.to_string())?;
+    let object = json.as_object().ok_or("expected a JSON object")?;
+    if let Some(field) = object.keys().find(|key| !FIELDS.contains(&key.as_str())) {
+        return Err(format!("unknown field `{}`", field));
+    }
+
+    let tx = match object.get("tx") {
+        Some(tx) => decode("tx", tx)?,
+        None => dummy_transaction(),
+    };
+    let utxos = match object.get("utxos") {
+        Some(utxos) => utxos
+            .as_array()
+            .ok_or("`utxos` is not an array")?
+            .iter()
+            .map(utxo)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![dummy_utxo(); tx.input.len()],
+    };
+    if utxos.len() != tx.input.len() {
+        return Err(format!(
+            "transaction has {} inputs but {} utxos were given",
+            tx.input.len(),
+            utxos.len(),
+        ));
+    }
+    let ix = match object.get("input_index") {
+        Some(ix) => ix
+            .as_u64()
+            .and_then(|ix| u32::try_from(ix).ok())
+            .ok_or("`input_index` is not an index")?,
+        None => 0,
+    };
+    if ix as usize >= tx.input.len() {
+        return Err(format!(
+            "input index {} is out of range for a transaction with {} inputs",
+            ix,
+            tx.input.len(),
+        ));
+    }
+    let control_block = match object.get("control_block") {
+        Some(control_block) => ControlBlock::from_slice(&bytes("control_block", control_block)?)
+            .map_err(|e| format!("bad `control_block`: {}", e))?,
+        None => default_control_block(),
+    };
+    let annex = match object.get("annex") {
+        Some(annex) => Some(bytes("annex", annex)?),
+        None => None,
+    };
+    let genesis_hash = match object.get("genesis_hash") {
+        Some(hash) => hash
+            .as_str()
+            .and_then(|hash| BlockHash::from_str(hash).ok())
+            .ok_or("`genesis_hash` is not a block hash")?,
+        None => BlockHash::all_zeros(),
+    };
+
+    Ok(ElementsEnv::new(
+        Arc::new(tx),
+        utxos,
+        ix,
+        cmr,
+        control_block,
+        annex,
+        genesis_hash,
+    ))
+}
+
+fn utxo(json: &serde_json::Value) -> Result<ElementsUtxo, String> {
+    let field = |name: &str| {
+        json.get(name)
+            .ok_or_else(|| format!("utxo has no `{}`", name))
+    };
+    Ok(ElementsUtxo {
+        script_pubkey: elements::Script::from(bytes("script_pubkey", field("script_pubkey")?)?),
+        asset: decode::<confidential::Asset>("asset", field("asset")?)?,
+        value: decode::<confidential::Value>("value", field("value")?)?,
+    })
+}
+
+/// Decodes a hex string field.
+fn bytes(name: &str, json: &serde_json::Value) -> Result<Vec<u8>, String> {
+    json.as_str()
+        .and_then(|s| Vec::<u8>::from_hex(s).ok())
+        .ok_or_else(|| format!("`{}` is not a hex string", name))
+}
+
+/// Decodes a field which holds the consensus encoding of a value in hex.
+fn decode<T: elements::encode::Decodable>(
+    name: &str,
+    json: &serde_json::Value,
+) -> Result<T, String> {
+    deserialize(&bytes(name, json)?).map_err(|e| format!("bad `{}`: {}", name, e))
+}
+
+fn dummy_transaction() -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: elements::LockTime::ZERO,
+        input: vec![elements::TxIn {
+            previous_output: elements::OutPoint::default(),
+            is_pegin: false,
+            script_sig: elements::Script::new(),
+            sequence: elements::Sequence::MAX,
+            asset_issuance: AssetIssuance::default(),
+            witness: elements::TxInWitness::default(),
+        }],
+        output: vec![],
+    }
+}
+
+fn dummy_utxo() -> ElementsUtxo {
+    ElementsUtxo {
+        script_pubkey: elements::Script::new(),
+        asset: confidential::Asset::Null,
+        value: confidential::Value::Null,
+    }
+}
+
+/// The control block used if none is given: the Simplicity leaf version, with
+/// the unspendable internal key of [`liquid::internal_key`].
+fn default_control_block() -> ControlBlock {
+    ControlBlock {
+        leaf_version: leaf_version(),
+        output_key_parity: Parity::Even,
+        internal_key: liquid::internal_key(None).expect("unspendable key is valid"),
+        merkle_branch: TaprootMerkleBranch::default(),
+    }
+}
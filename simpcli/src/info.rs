@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Program Information
+//!
+//! Summarizes a finalized program: its roots and type, its size, and the
+//! bounds on the resources needed to execute it.
+
+use simplicity::dag::{DagLike, MaxSharing};
+use simplicity::jet::Jet;
+use simplicity::{Amr, Cmr, Imr, NodeBounds, RedeemNode};
+
+use std::fmt;
+
+/// The length of the control block of a program which is the only leaf of its
+/// taproot tree.
+const CONTROL_BLOCK_LEN: usize = 33;
+
+/// A summary of a finalized program
+pub struct Info {
+    cmr: Cmr,
+    imr: Imr,
+    amr: Amr,
+    source_ty: String,
+    target_ty: String,
+    /// Number of distinct nodes
+    nodes: usize,
+    /// Number of nodes if no node were shared, saturating at `u64::MAX`
+    unshared_nodes: u64,
+    program_len: usize,
+    witness_len: usize,
+    bounds: NodeBounds,
+    /// The witness length assumed when computing the annex padding
+    padding_witness_len: usize,
+    /// The length of the annex needed to pay for the cost of the program, if any
+    annex_len: Option<usize>,
+}
+
+impl Info {
+    /// Summarizes a program, computing the annex padding for a spend whose
+    /// encoded witness data has the given length, or the length of the
+    /// program's own witness data if none is given.
+    pub fn new<J: Jet>(program: &RedeemNode<J>, witness_len: Option<usize>) -> Self {
+        // The number of unshared nodes below each node, by post-order index
+        let mut tree_sizes = Vec::<u64>::new();
+        for data in program.post_order_iter::<MaxSharing<_>>() {
+            let child = |idx: Option<usize>| idx.map_or(0, |idx| tree_sizes[idx]);
+            let size = child(data.left_index)
+                .saturating_add(child(data.right_index))
+                .saturating_add(1);
+            tree_sizes.push(size);
+        }
+
+        let (program_bytes, witness_bytes) = program.encode_to_vec();
+        let padding_witness_len = witness_len.unwrap_or(witness_bytes.len());
+        // The script witness of a Simplicity spend is the witness data, the
+        // program, the script (which is the CMR) and the control block.
+        let script_witness = vec![
+            vec![0; padding_witness_len],
+            program_bytes.clone(),
+            program.cmr().as_ref().to_vec(),
+            vec![0; CONTROL_BLOCK_LEN],
+        ];
+        let bounds = program.bounds();
+
+        Info {
+            cmr: program.cmr(),
+            imr: program.imr(),
+            amr: program.amr(),
+            source_ty: program.arrow().source.to_string(),
+            target_ty: program.arrow().target.to_string(),
+            nodes: tree_sizes.len(),
+            unshared_nodes: tree_sizes.last().copied().unwrap_or(0),
+            program_len: program_bytes.len(),
+            witness_len: witness_bytes.len(),
+            bounds,
+            padding_witness_len,
+            annex_len: bounds
+                .cost
+                .get_padding(&script_witness)
+                .map(|annex| annex.len()),
+        }
+    }
+
+    /// Unshared nodes per distinct node
+    fn sharing_ratio(&self) -> f64 {
+        self.unshared_nodes as f64 / self.nodes as f64
+    }
+
+    /// The summary as a JSON object
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "cmr": self.cmr.to_string(),
+            "imr": self.imr.to_string(),
+            "amr": self.amr.to_string(),
+            "source_type": self.source_ty,
+            "target_type": self.target_ty,
+            "nodes": self.nodes,
+            "unshared_nodes": self.unshared_nodes,
+            "sharing_ratio": self.sharing_ratio(),
+            "program_size": self.program_len,
+            "witness_size": self.witness_len,
+            "extra_cells": self.bounds.extra_cells,
+            "extra_frames": self.bounds.extra_frames,
+            "cost": self.bounds.cost.to_milliweight(),
+            "consensus_valid": self.bounds.cost.is_consensus_valid(),
+            "padding_witness_size": self.padding_witness_len,
+            "annex_size": self.annex_len,
+        })
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "CMR:     {}", self.cmr)?;
+        writeln!(f, "IMR:     {}", self.imr)?;
+        writeln!(f, "AMR:     {}", self.amr)?;
+        writeln!(f, "Type:    {} -> {}", self.source_ty, self.target_ty)?;
+        writeln!(
+            f,
+            "Nodes:   {} ({} unshared, sharing ratio {:.2})",
+            self.nodes,
+            self.unshared_nodes,
+            self.sharing_ratio(),
+        )?;
+        writeln!(
+            f,
+            "Size:    {} bytes of program, {} bytes of witness",
+            self.program_len, self.witness_len,
+        )?;
+        writeln!(
+            f,
+            "Bounds:  {} extra cells, {} extra frames, cost {} milliweight units",
+            self.bounds.extra_cells, self.bounds.extra_frames, self.bounds.cost,
+        )?;
+        if self.bounds.cost.is_consensus_valid() {
+            writeln!(f, "The cost is within the consensus limit.")?;
+        } else {
+            writeln!(f, "The cost exceeds the consensus limit.")?;
+        }
+        match self.annex_len {
+            None => write!(
+                f,
+                "No annex is needed with {} bytes of witness.",
+                self.padding_witness_len
+            ),
+            Some(len) => write!(
+                f,
+                "An annex of {} bytes is needed with {} bytes of witness.",
+                len, self.padding_witness_len
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simplicity::human_encoding::Forest;
+    use simplicity::jet::Core;
+    use std::collections::HashMap;
+
+    #[test]
+    fn fields() {
+        let program = Forest::<Core>::parse(
+            "
+            x := const 0x2a
+            main := comp (comp (pair x x) jet_eq_8) jet_verify
+            ",
+        )
+        .unwrap()
+        .to_witness_node(&HashMap::new())
+        .unwrap()
+        .finalize_unpruned()
+        .unwrap();
+        let info = Info::new(&program, None);
+        let json = info.to_json();
+        assert_eq!(json["cmr"], program.cmr().to_string());
+        assert_eq!(json["imr"], program.imr().to_string());
+        assert_eq!(json["amr"], program.amr().to_string());
+        assert_eq!(json["source_type"], "1");
+        assert_eq!(json["target_type"], "1");
+        // `x` is shared by the two sides of `pair`.
+        assert_eq!(json["nodes"], 6);
+        assert_eq!(json["unshared_nodes"], 7);
+        assert_eq!(json["program_size"], program.encode_to_vec().0.len());
+        assert_eq!(json["witness_size"], 0);
+        assert_eq!(json["extra_cells"], program.bounds().extra_cells);
+        assert_eq!(json["extra_frames"], program.bounds().extra_frames);
+        assert_eq!(json["cost"], program.bounds().cost.to_milliweight());
+        assert_eq!(json["consensus_valid"], true);
+        assert_eq!(json["padding_witness_size"], 0);
+        assert_eq!(json["annex_size"], serde_json::Value::Null);
+
+        let display = info.to_string();
+        assert!(display.contains(&format!("CMR:     {}", program.cmr())));
+        assert!(display.contains("Nodes:   6 (7 unshared, sharing ratio 1.17)"));
+        assert!(display.contains("No annex is needed with 0 bytes of witness."));
+    }
+
+    #[test]
+    fn padding() {
+        // Eight signature checks cost more than the budget of a small witness.
+        let program = Forest::<Core>::parse(
+            "
+            check := comp (const 0x0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000) jet_bip_0340_verify
+            check2 := comp check check
+            check4 := comp check2 check2
+            main := comp check4 check4
+            ",
+        )
+        .unwrap()
+        .to_witness_node(&HashMap::new())
+        .unwrap()
+        .finalize_unpruned()
+        .unwrap();
+
+        let info = Info::new(&program, None);
+        let annex_len = info.annex_len.expect("small witness needs an annex");
+        assert!(info.to_string().contains(&format!(
+            "An annex of {} bytes is needed with 0 bytes of witness.",
+            annex_len
+        )));
+
+        let info = Info::new(&program, Some(annex_len + 1000));
+        assert_eq!(info.annex_len, None);
+        assert_eq!(info.to_json()["padding_witness_size"], annex_len + 1000);
+    }
+}
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Liquid Outputs and Spends
+//!
+//! A program is committed to by a taproot output whose only leaf is the CMR
+//! of the program, with the Simplicity leaf version. By default the internal
+//! key of the output has no known discrete logarithm, so that the output can
+//! only be spent by the program.
+
+use simplicity::elements::secp256k1_zkp::{Secp256k1, XOnlyPublicKey};
+use simplicity::elements::taproot::{ControlBlock, TaprootBuilder, TaprootSpendInfo};
+use simplicity::elements::{self, Address, AddressParams, BlockHash, Script, Transaction};
+use simplicity::jet::elements::{ElementsEnv, ElementsUtxo};
+use simplicity::jet::Elements;
+use simplicity::{leaf_version, Cmr, RedeemNode};
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The internal key used if none is given: the point `H` of BIP 341, which has
+/// no known discrete logarithm.
+const UNSPENDABLE_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Number of times that a program is pruned to find the annex of a spend
+const MAX_PRUNINGS: usize = 4;
+
+/// A Liquid network, selected with `--network`
+#[derive(Copy, Clone)]
+pub enum Network {
+    Liquid,
+    LiquidTestnet,
+    Regtest,
+}
+
+impl FromStr for Network {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "liquid" => Ok(Network::Liquid),
+            "liquidtestnet" => Ok(Network::LiquidTestnet),
+            "regtest" => Ok(Network::Regtest),
+            x => Err(format!("unknown network {}", x)),
+        }
+    }
+}
+
+impl Network {
+    pub fn address_params(self) -> &'static AddressParams {
+        match self {
+            Network::Liquid => &AddressParams::LIQUID,
+            Network::LiquidTestnet => &AddressParams::LIQUID_TESTNET,
+            Network::Regtest => &AddressParams::ELEMENTS,
+        }
+    }
+
+    /// The hash of the genesis block, which every regtest chain chooses for
+    /// itself.
+    pub fn genesis_hash(self) -> Option<BlockHash> {
+        let hash = match self {
+            Network::Liquid => "1466275836220db2944ca059a3a10ef6fd2ea684b0688d2c379296888a206003",
+            Network::LiquidTestnet => {
+                "a771da8e52ee6ad581ed1e9a99825e5b3b7992225534eaa2ae23244fe26ab1c1"
+            }
+            Network::Regtest => return None,
+        };
+        Some(BlockHash::from_str(hash).expect("constant genesis hash"))
+    }
+}
+
+/// Parses an internal key, or returns the unspendable key if none is given.
+pub fn internal_key(s: Option<&str>) -> Result<XOnlyPublicKey, String> {
+    let s = s.unwrap_or(UNSPENDABLE_KEY);
+    XOnlyPublicKey::from_str(s).map_err(|e| format!("bad internal key {}: {}", s, e))
+}
+
+/// A taproot output with a single Simplicity leaf
+pub struct Leaf {
+    cmr: Cmr,
+    spend_info: TaprootSpendInfo,
+}
+
+impl Leaf {
+    pub fn new(cmr: Cmr, internal_key: XOnlyPublicKey) -> Self {
+        let spend_info = TaprootBuilder::new()
+            .add_leaf_with_ver(0, script(cmr), leaf_version())
+            .expect("tree with one leaf")
+            .finalize(&Secp256k1::verification_only(), internal_key)
+            .expect("tree with one leaf is complete");
+        Leaf { cmr, spend_info }
+    }
+
+    pub fn script_pubkey(&self) -> Script {
+        Script::new_v1_p2tr_tweaked(self.spend_info.output_key())
+    }
+
+    pub fn address(&self, network: Network) -> Address {
+        Address::p2tr_tweaked(self.spend_info.output_key(), None, network.address_params())
+    }
+
+    pub fn control_block(&self) -> ControlBlock {
+        self.spend_info
+            .control_block(&(script(self.cmr), leaf_version()))
+            .expect("leaf is in tree")
+    }
+}
+
+/// The leaf script of a program, which is its CMR.
+fn script(cmr: Cmr) -> Script {
+    Script::from(cmr.as_ref().to_vec())
+}
+
+/// Completes the witness stack of input `ix` of `tx`, which spends `leaf` with
+/// `program`, and checks that the input is then valid.
+///
+/// The program is pruned for the transaction, and an annex is added if it is
+/// needed to pay for the cost of the program. Programs can read the annex, so
+/// pruning is done with the annex that is added.
+pub fn spend(
+    program: &RedeemNode<Elements>,
+    leaf: &Leaf,
+    mut tx: Transaction,
+    utxos: Vec<ElementsUtxo>,
+    ix: usize,
+    genesis_hash: BlockHash,
+) -> Result<Transaction, String> {
+    if ix >= tx.input.len() {
+        return Err(format!(
+            "input index {} is out of range for a transaction with {} inputs",
+            ix,
+            tx.input.len(),
+        ));
+    }
+    if utxos.len() != tx.input.len() {
+        return Err(format!(
+            "transaction has {} inputs but {} utxos were given",
+            tx.input.len(),
+            utxos.len(),
+        ));
+    }
+    if program.cmr() != leaf.cmr {
+        return Err(format!(
+            "program has CMR {} but the output commits to {}",
+            program.cmr(),
+            leaf.cmr,
+        ));
+    }
+
+    // The annex depends on the pruned program, which may depend on the annex.
+    // Pruning is repeated with the annex of the previous pruning until the
+    // annex is the one that was pruned with.
+    let mut annex = None;
+    let mut script_witness = None;
+    for _ in 0..MAX_PRUNINGS {
+        let env = ElementsEnv::new(
+            Arc::new(tx.clone()),
+            utxos.clone(),
+            ix as u32, // cast safety: Elements transactions have fewer than 2^32 inputs
+            leaf.cmr,
+            leaf.control_block(),
+            annex.clone(),
+            genesis_hash,
+        );
+        let pruned = program
+            .prune(&env)
+            .map_err(|e| format!("program fails on the transaction: {}", e))?;
+
+        let (program_bytes, witness_bytes) = pruned.encode_to_vec();
+        let mut stack = vec![
+            witness_bytes,
+            program_bytes,
+            script(leaf.cmr).into_bytes(),
+            leaf.control_block().serialize(),
+        ];
+        let padding = pruned.bounds().cost.get_padding(&stack);
+        if padding == annex {
+            stack.extend(padding);
+            script_witness = Some(stack);
+            break;
+        }
+        annex = padding;
+    }
+    tx.input[ix].witness.script_witness = script_witness
+        .ok_or_else(|| "pruning the program does not settle on an annex".to_string())?;
+
+    simplicity::consensus::verify_input(Arc::new(tx.clone()), &utxos, ix, genesis_hash)
+        .map_err(|e| format!("spend is not valid: {}", e))?;
+    Ok(tx)
+}
+
+/// Decodes a transaction from hex.
+pub fn decode_transaction(s: &str) -> Result<Transaction, String> {
+    let bytes = <Vec<u8> as simplicity::hex::FromHex>::from_hex(s)
+        .map_err(|e| format!("transaction is not hex: {}", e))?;
+    elements::encode::deserialize(&bytes).map_err(|e| format!("bad transaction: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simplicity::elements::{confidential, AssetIssuance, OutPoint, Sequence, TxIn};
+    use simplicity::hashes::Hash;
+    use simplicity::human_encoding::Forest;
+    use std::collections::HashMap;
+
+    /// A program which costs more than the budget of its witness, so that it
+    /// needs an annex, and which takes the right branch of `case` only if the
+    /// input has an annex.
+    const PROGRAM: &str = "
+        scalar := const 0x0000000000000000000000000000000000000000000000000000000000000001
+        gen := comp (comp scalar jet_generate) unit : 1 -> 1
+        annex := comp (pair jet_current_annex_hash unit) (case unit unit) : 1 -> 1
+        main := comp gen (comp gen (comp gen (comp gen (comp gen annex))))
+    ";
+
+    #[test]
+    fn spend_with_annex() {
+        let program = Forest::<Elements>::parse(PROGRAM)
+            .unwrap()
+            .to_witness_node(&HashMap::new())
+            .unwrap()
+            .finalize_unpruned()
+            .unwrap();
+        let leaf = Leaf::new(program.cmr(), internal_key(None).unwrap());
+        let tx = Transaction {
+            version: 2,
+            lock_time: elements::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                is_pegin: false,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                asset_issuance: AssetIssuance::default(),
+                witness: Default::default(),
+            }],
+            output: vec![],
+        };
+        let utxos = vec![ElementsUtxo {
+            script_pubkey: leaf.script_pubkey(),
+            asset: confidential::Asset::Null,
+            value: confidential::Value::Null,
+        }];
+        let genesis_hash = BlockHash::all_zeros();
+
+        let tx = spend(&program, &leaf, tx, utxos.clone(), 0, genesis_hash).unwrap();
+        let script_witness = &tx.input[0].witness.script_witness;
+        assert_eq!(script_witness.len(), 5, "spend has an annex");
+
+        let tx = Arc::new(tx);
+        simplicity::consensus::verify_input(Arc::clone(&tx), &utxos, 0, genesis_hash).unwrap();
+        simplicity::consensus::verify_input_c(tx, &utxos, 0, genesis_hash).unwrap();
+    }
+}
@@ -12,26 +12,171 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use simplicity::human_encoding::Forest;
-use simplicity::node::CommitNode;
-use simplicity::{self, BitIter};
+mod debug;
+mod environment;
+mod info;
+mod liquid;
+mod repl;
+
+use environment::Family;
+use liquid::Network;
+
+use simplicity::bit_machine::{ExecTracker, ExecutionError};
+use simplicity::dag::{DagLike, MaxSharing};
+use simplicity::elements::{self, BlockHash};
+use simplicity::hex::DisplayHex;
+use simplicity::human_encoding::{DebugInfo, ErrorSet, Forest};
+use simplicity::jet::{Bitcoin, Core, Elements, Jet};
+use simplicity::node::{CommitNode, Inner, RedeemNode};
+use simplicity::types::Final;
+use simplicity::{self, BitIter, BitMachine, Cost, Imr, ProgramBounds, Value, Word};
 
 use base64::display::Base64Display;
 use base64::engine::general_purpose::STANDARD;
+use std::collections::HashMap;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::{env, fs};
 
-/// What set of jets to use in the program.
-// FIXME this should probably be configurable.
-type DefaultJet = simplicity::jet::Elements;
+/// The family of jets that a program may use, selected with `--jets`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum JetFamily {
+    Core,
+    Elements,
+    Bitcoin,
+}
+
+impl FromStr for JetFamily {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "core" => Ok(JetFamily::Core),
+            "elements" => Ok(JetFamily::Elements),
+            "bitcoin" => Ok(JetFamily::Bitcoin),
+            x => Err(format!("unknown jet family {}", x)),
+        }
+    }
+}
+
+/// Detects the jet family of a base64 encoded program.
+///
+/// Core jets are encoded differently from the Core jets within Elements, so a
+/// program with jets usually decodes with only one of the two families. It is
+/// an error if a program with jets decodes with both, or with neither. Bitcoin
+/// jets are never detected, since their CMRs are not yet implemented, and
+/// Bitcoin programs need `--jets bitcoin`.
+fn detect_jets(program: &str) -> Result<JetFamily, String> {
+    let bytes = base64::Engine::decode(&STANDARD, program.as_bytes())
+        .map_err(|e| format!("failed to parse base64: {}", e))?;
+    let core = CommitNode::<Core>::decode(BitIter::from(bytes.iter().copied()));
+    let elements = CommitNode::<Elements>::decode(BitIter::from(bytes.iter().copied()));
+    match (core, elements) {
+        (Ok(core), Ok(_)) => {
+            let has_jets = core
+                .post_order_iter::<MaxSharing<_>>()
+                .any(|data| matches!(data.node.inner(), Inner::Jet(_)));
+            if has_jets {
+                Err(
+                    "program decodes with both core and elements jets; choose one with --jets"
+                        .into(),
+                )
+            } else {
+                Ok(JetFamily::Elements)
+            }
+        }
+        (Ok(_), Err(_)) => Ok(JetFamily::Core),
+        (Err(_), Ok(_)) => Ok(JetFamily::Elements),
+        (Err(_), Err(_)) => Err(
+            "program decodes with neither core nor elements jets; choose a family with --jets"
+                .into(),
+        ),
+    }
+}
 
 fn usage(process_name: &str) {
     eprintln!("Usage:");
-    eprintln!("  {} assemble <filename>", process_name);
-    eprintln!("  {} disassemble <base64>", process_name);
-    eprintln!("  {} relabel <base64>", process_name);
+    eprintln!(
+        "  {} assemble [--jets FAMILY] [--error-format FORMAT] [--debug-info FILE] [--arg NAME=VALUE]... <filename>",
+        process_name
+    );
+    eprintln!(
+        "  {} disassemble [--jets FAMILY] [--debug-info FILE] <base64>",
+        process_name
+    );
+    eprintln!(
+        "  {} relabel [--jets FAMILY] [--error-format FORMAT] [--debug-info FILE] [--arg NAME=VALUE]... <filename>",
+        process_name
+    );
+    eprintln!(
+        "  {} fmt [--jets FAMILY] [--error-format FORMAT] [--check] <filename>",
+        process_name
+    );
+    eprintln!(
+        "  {} debug [--jets FAMILY] [--error-format FORMAT] [--arg NAME=VALUE]... <filename> [--witness FILE] [--env FILE] [--input VALUE] [--break NAME|CMR]...",
+        process_name
+    );
+    eprintln!(
+        "  {} address [--jets FAMILY] [--error-format FORMAT] [--arg NAME=VALUE]... <filename> [--network NETWORK] [--internal-key KEY]",
+        process_name
+    );
+    eprintln!(
+        "  {} spend [--jets FAMILY] [--error-format FORMAT] [--arg NAME=VALUE]... <filename> --tx HEX --utxos FILE [--witness FILE] [--input-index N] [--network NETWORK] [--internal-key KEY] [--genesis-hash HASH]",
+        process_name
+    );
+    eprintln!("  {} repl [--jets FAMILY]", process_name);
+    eprintln!(
+        "  {} info [--jets FAMILY] [--error-format FORMAT] [--arg NAME=VALUE]... <filename|base64> [--witness FILE|BASE64] [--witness-size BYTES] [--json]",
+        process_name
+    );
+    eprintln!(
+        "  {} run [--jets FAMILY] [--error-format FORMAT] [--arg NAME=VALUE]... <filename> [--witness FILE] [--env FILE] [--input VALUE]",
+        process_name
+    );
     eprintln!();
     eprintln!("For commands which take an optional expression, the default value is \"main\".");
+    eprintln!("Each --arg gives a hex or binary value for a parameter of the `main` template.");
+    eprintln!();
+    eprintln!("FAMILY is `core`, `elements` or `bitcoin`, the jets that the program may use.");
+    eprintln!("It defaults to `elements`, except that `disassemble` and `info` detect the");
+    eprintln!("family of a base64 program where its encoding allows. Bitcoin programs are");
+    eprintln!("never detected and need `--jets bitcoin`.");
+    eprintln!();
+    eprintln!("Options may be given before or after the filename or base64 program.");
+    eprintln!();
+    eprintln!("FORMAT is `human` (the default) or `json`. With `json`, errors in the file are");
+    eprintln!("printed to stdout as one JSON object per line.");
+    eprintln!();
+    eprintln!("`assemble --debug-info FILE` writes the names, source positions and comments of");
+    eprintln!("the program to FILE; `disassemble` and `relabel` read them back from FILE.");
+    eprintln!();
+    eprintln!("`run` executes the program on the Bit Machine and prints its output and cost.");
+    eprintln!("--witness gives a file of witness values `NAME <- VALUE`, --env a JSON file");
+    eprintln!("describing the transaction (otherwise a dummy one is used), and --input a hex");
+    eprintln!("or binary value for the input of the program. Only Elements programs take --env.");
+    eprintln!();
+    eprintln!("`debug` executes the program one node at a time, reading commands from standard");
+    eprintln!("input. It takes the options of `run`, and stops at the nodes named or with the");
+    eprintln!("CMRs given by --break; enter `help` at its prompt for its commands.");
+    eprintln!();
+    eprintln!("`address` prints the address, script pubkey and control block of the taproot");
+    eprintln!("output whose only leaf is the program. NETWORK is `liquid` (the default),");
+    eprintln!("`liquidtestnet` or `regtest`, and KEY is the x-only internal key of the output,");
+    eprintln!("which by default is a key that no one can sign for.");
+    eprintln!();
+    eprintln!("`spend` fills in the witness stack of input N (default 0) of the hex transaction");
+    eprintln!("--tx, which spends the output printed by `address`, and prints the transaction.");
+    eprintln!("--utxos is a JSON array of the outputs spent by the transaction, as in --env.");
+    eprintln!("The program is pruned, padded with an annex if needed, and checked to be valid.");
+    eprintln!();
+    eprintln!("`repl` reads definitions and expressions from standard input, printing the type");
+    eprintln!("of each definition and the value of each expression; enter :help for more.");
+    eprintln!();
+    eprintln!("`info` prints the roots, type, size, bounds and cost of the program, which is");
+    eprintln!("either a file or a base64 encoded program. --witness gives a file of witness");
+    eprintln!("values for a file, or base64 encoded witness data for a base64 program.");
+    eprintln!("--witness-size gives the size of the witness data assumed when computing the");
+    eprintln!("annex padding needed to pay for the program, and --json prints JSON.");
     eprintln!();
     eprintln!("Run `{} help` to display this message.", process_name);
 }
@@ -45,6 +190,13 @@ enum Command {
     Assemble,
     Disassemble,
     Relabel,
+    Fmt,
+    Info,
+    Run,
+    Debug,
+    Address,
+    Spend,
+    Repl,
     Help,
 }
 
@@ -55,37 +207,304 @@ impl FromStr for Command {
             "assemble" => Ok(Command::Assemble),
             "disassemble" => Ok(Command::Disassemble),
             "relabel" => Ok(Command::Relabel),
+            "fmt" => Ok(Command::Fmt),
+            "info" => Ok(Command::Info),
+            "run" => Ok(Command::Run),
+            "debug" => Ok(Command::Debug),
+            "address" => Ok(Command::Address),
+            "spend" => Ok(Command::Spend),
+            "repl" => Ok(Command::Repl),
             "help" => Ok(Command::Help),
             x => Err(format!("unknown command {}", x)),
         }
     }
 }
 
+/// How to print errors found in a source file.
+#[derive(Copy, Clone)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            x => Err(format!("unknown error format {}", x)),
+        }
+    }
+}
+
+impl ErrorFormat {
+    fn print(self, errs: &ErrorSet) {
+        match self {
+            ErrorFormat::Human => {
+                eprintln!("Errors:");
+                eprintln!("{}", errs);
+                eprintln!();
+            }
+            ErrorFormat::Json => {
+                for diagnostic in errs.diagnostics() {
+                    println!("{}", serde_json::to_string(&diagnostic).unwrap());
+                }
+            }
+        }
+    }
+}
+
 impl Command {
     fn takes_optional_exprname(&self) -> bool {
         match *self {
             Command::Assemble => false,
             Command::Disassemble => false,
             Command::Relabel => false,
+            Command::Fmt => false,
+            Command::Info => false,
+            Command::Run => false,
+            Command::Debug => false,
+            Command::Address => false,
+            Command::Spend => false,
+            Command::Repl => false,
             Command::Help => false,
         }
     }
+
+    fn reads_source_file(&self) -> bool {
+        match *self {
+            Command::Assemble => true,
+            Command::Disassemble => false,
+            Command::Relabel => true,
+            Command::Fmt => true,
+            Command::Info => true,
+            Command::Run => true,
+            Command::Debug => true,
+            Command::Address => true,
+            Command::Spend => true,
+            Command::Repl => false,
+            Command::Help => false,
+        }
+    }
+
+    fn takes_debug_info(&self) -> bool {
+        match *self {
+            Command::Assemble => true,
+            Command::Disassemble => true,
+            Command::Relabel => true,
+            Command::Fmt => false,
+            Command::Info => false,
+            Command::Run => false,
+            Command::Debug => false,
+            Command::Address => false,
+            Command::Spend => false,
+            Command::Repl => false,
+            Command::Help => false,
+        }
+    }
+
+    fn takes_template_arguments(&self) -> bool {
+        match *self {
+            Command::Assemble => true,
+            Command::Disassemble => false,
+            Command::Relabel => true,
+            Command::Fmt => false,
+            Command::Info => true,
+            Command::Run => true,
+            Command::Debug => true,
+            Command::Address => true,
+            Command::Spend => true,
+            Command::Repl => false,
+            Command::Help => false,
+        }
+    }
+}
+
+/// Parses a template argument of the form `NAME=VALUE`.
+fn parse_argument(s: &str) -> Result<(Arc<str>, Word), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("argument {} is not of the form NAME=VALUE", s))?;
+    let value = Word::from_str(value).map_err(|e| format!("bad value for {}: {}", name, e))?;
+    Ok((Arc::from(name), value))
 }
 
-fn parse_file(name: &str) -> Result<Forest<DefaultJet>, String> {
-    let s = fs::read_to_string(name).map_err(|e| format!("failed to read file {}: {}", name, e))?;
-    match Forest::parse(&s) {
+fn parse_file<J: Family>(
+    name: &str,
+    arguments: &HashMap<Arc<str>, Word>,
+    error_format: ErrorFormat,
+) -> Result<Forest<J>, String> {
+    match Forest::parse_file_with_arguments(name, arguments) {
         Ok(prog) => Ok(prog),
-        Err(mut errs) => {
-            errs.add_context(std::sync::Arc::from(s));
-            eprintln!("Errors:");
-            eprintln!("{}", errs);
-            eprintln!();
+        Err(errs) => {
+            error_format.print(&errs);
             Err(format!("failed to parse file {}", name))
         }
     }
 }
 
+/// Parses a hex or binary literal, such as `0x2a` or `0b101`, as a value of the
+/// given type.
+fn parse_value(s: &str, ty: &Final) -> Result<Value, String> {
+    let (digits, radix, digit_bits) = if let Some(digits) = s.strip_prefix("0x") {
+        (digits, 16, 4)
+    } else if let Some(digits) = s.strip_prefix("0b") {
+        (digits, 2, 1)
+    } else {
+        return Err(format!("value {} does not start with 0x or 0b", s));
+    };
+
+    let mut bytes = vec![];
+    let mut bit_length = 0;
+    for ch in digits.chars() {
+        let digit = ch
+            .to_digit(radix)
+            .ok_or_else(|| format!("value {} has a bad digit {}", s, ch))?;
+        for i in (0..digit_bits).rev() {
+            if bit_length % 8 == 0 {
+                bytes.push(0);
+            }
+            if (digit >> i) & 1 == 1 {
+                *bytes.last_mut().unwrap() |= 0x80 >> (bit_length % 8);
+            }
+            bit_length += 1;
+        }
+    }
+
+    let mut iter = BitIter::from(bytes);
+    match Value::from_compact_bits(&mut iter, ty) {
+        Ok(value) if iter.n_total_read() == bit_length => Ok(value),
+        _ => Err(format!(
+            "{}-bit value {} is not a value of type {}",
+            bit_length, s, ty
+        )),
+    }
+}
+
+/// Options of the commands which take a program, which follow the program.
+#[derive(Default)]
+struct ProgramOptions {
+    witness: Option<String>,
+    env_file: Option<String>,
+    input: Option<String>,
+    witness_size: Option<usize>,
+    json: bool,
+    breakpoints: Vec<String>,
+    network: Option<Network>,
+    internal_key: Option<String>,
+    tx: Option<String>,
+    utxos: Option<String>,
+    input_index: Option<usize>,
+    genesis_hash: Option<String>,
+}
+
+/// Adds up the cost of the nodes executed by the Bit Machine, and remembers the
+/// last one, which is the one that failed if execution fails.
+struct RunTracker {
+    cost: Cost,
+    last: Option<Imr>,
+}
+
+impl<J: Jet> ExecTracker<J> for RunTracker {
+    fn visit_node(&mut self, node: &RedeemNode<J>) {
+        self.cost = self.cost + Cost::of_node(node);
+        self.last = Some(node.imr());
+    }
+}
+
+/// Executes a program on the Bit Machine with the given input, returning the
+/// tracker of the executed nodes along with the result of execution.
+fn run<J: Jet>(
+    program: &RedeemNode<J>,
+    env: &J::Environment,
+    input: &Value,
+) -> Result<(RunTracker, Result<Value, ExecutionError>), String> {
+    let mut mac =
+        BitMachine::for_program(program).map_err(|e| format!("program exceeds limits: {}", e))?;
+    let mut tracker = RunTracker {
+        cost: Cost::from_milliweight(0),
+        last: None,
+    };
+    let result = mac
+        .input(input)
+        .and_then(|()| mac.exec_with_tracker(program, env, &mut tracker));
+    Ok((tracker, result))
+}
+
+/// Finalizes the `main` expression of a forest, with the witness values
+/// assigned in the forest and those in the given file of witness values.
+fn finalize<J: Family>(
+    prog: &Forest<J>,
+    witness_file: Option<&str>,
+    error_format: ErrorFormat,
+) -> Result<Arc<RedeemNode<J>>, String> {
+    let mut witness = prog.witness().clone();
+    if let Some(file) = witness_file {
+        let source = fs::read_to_string(file)
+            .map_err(|e| format!("failed to read witness file {}: {}", file, e))?;
+        match prog.parse_witness_values(&source) {
+            Ok(values) => witness.extend(values),
+            Err(mut errs) => {
+                errs.add_context(Arc::from(source.as_str()));
+                error_format.print(&errs);
+                return Err(format!("failed to parse witness file {}", file));
+            }
+        }
+    }
+
+    prog.to_witness_node(&witness)
+        .ok_or("expression `main` not found")?
+        .finalize_unpruned()
+        .map_err(|e| format!("failed to finalize program: {}", e))
+}
+
+/// Completes the witness of a transaction input which spends the program in a
+/// file, which must use Elements jets, and prints the transaction.
+fn spend(invocation: Invocation) -> Result<(), String> {
+    let Invocation {
+        first_arg,
+        error_format,
+        arguments,
+        options,
+        ..
+    } = invocation;
+
+    let prog = parse_file::<Elements>(&first_arg, &arguments, error_format)?;
+    let program = finalize(&prog, options.witness.as_deref(), error_format)?;
+    let network = options.network.unwrap_or(Network::Liquid);
+    let genesis_hash = match options.genesis_hash {
+        Some(hash) => {
+            BlockHash::from_str(&hash).map_err(|e| format!("bad genesis hash {}: {}", hash, e))?
+        }
+        None => network
+            .genesis_hash()
+            .ok_or("regtest needs --genesis-hash")?,
+    };
+    let tx = liquid::decode_transaction(options.tx.as_deref().ok_or("spend needs --tx")?)?;
+    let utxos = environment::read_utxos(options.utxos.as_deref().ok_or("spend needs --utxos")?)?;
+    let internal_key = liquid::internal_key(options.internal_key.as_deref())?;
+    let leaf = liquid::Leaf::new(program.cmr(), internal_key);
+
+    let tx = liquid::spend(
+        &program,
+        &leaf,
+        tx,
+        utxos,
+        options.input_index.unwrap_or(0),
+        genesis_hash,
+    )?;
+    println!("{}", elements::encode::serialize_hex(&tx));
+    Ok(())
+}
+
+/// Reads debug information written by `assemble --debug-info`.
+fn read_debug_info(name: &str) -> Result<DebugInfo, String> {
+    let contents = fs::read_to_string(name)
+        .map_err(|e| format!("failed to read debug info {}: {}", name, e))?;
+    DebugInfo::from_str(&contents).map_err(|e| format!("bad debug info in {}: {}", name, e))
+}
+
 fn main() -> Result<(), String> {
     let mut args = env::args();
     let process_name = args.next().unwrap();
@@ -94,7 +513,7 @@ fn main() -> Result<(), String> {
         None => &process_name[..],
     };
 
-    // Parse command-line args into (command, first_arg, expression)
+    // Parse command-line args into (command, jets, invocation)
     let command = match args.next() {
         Some(cmd) => match Command::from_str(&cmd) {
             Ok(cmd) => cmd,
@@ -112,23 +531,169 @@ fn main() -> Result<(), String> {
         return Ok(());
     }
 
-    let first_arg = match args.next() {
-        Some(s) => s,
-        None => return invalid_usage(process_name),
+    let (jets, invocation) = match parse_arguments(command, args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("Error: {}.", e);
+            eprintln!();
+            return invalid_usage(process_name);
+        }
     };
-    let _expression = if command.takes_optional_exprname() {
-        args.next().unwrap_or("main".to_owned())
-    } else {
-        String::new()
+
+    if let Command::Repl = invocation.command {
+        return match jets.unwrap_or(JetFamily::Elements) {
+            JetFamily::Core => repl::run::<Core>(),
+            JetFamily::Elements => repl::run::<Elements>(),
+            JetFamily::Bitcoin => repl::run::<Bitcoin>(),
+        };
+    }
+
+    let jets = match jets {
+        Some(jets) => jets,
+        None => match invocation.command {
+            Command::Disassemble => detect_jets(&invocation.first_arg)?,
+            Command::Info if !Path::new(&invocation.first_arg).is_file() => {
+                detect_jets(&invocation.first_arg)?
+            }
+            _ => JetFamily::Elements,
+        },
     };
-    if args.next().is_some() {
-        invalid_usage(process_name)?;
+    if let Command::Spend = invocation.command {
+        return match jets {
+            JetFamily::Elements => spend(invocation),
+            _ => Err("`spend` needs elements jets".into()),
+        };
+    }
+    match jets {
+        JetFamily::Core => execute::<Core>(invocation),
+        JetFamily::Elements => execute::<Elements>(invocation),
+        JetFamily::Bitcoin => execute::<Bitcoin>(invocation),
+    }
+}
+
+/// Parses the arguments which follow the command into the jet family, if one
+/// is given, and the invocation of the command. Options may be given in any
+/// order, before or after the first argument.
+fn parse_arguments<I: Iterator<Item = String>>(
+    command: Command,
+    mut args: I,
+) -> Result<(Option<JetFamily>, Invocation), String> {
+    let mut jets = None;
+    let mut first_arg = None;
+    let mut error_format = ErrorFormat::Human;
+    let mut debug_info_file = None;
+    let mut arguments = HashMap::new();
+    let mut check = false;
+    let mut options = ProgramOptions::default();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            match (&first_arg, &command) {
+                (_, Command::Repl) => return Err(format!("unexpected argument {}", arg)),
+                (None, _) => first_arg = Some(arg),
+                (Some(_), _) if command.takes_optional_exprname() => {}
+                (Some(_), _) => return Err(format!("unexpected argument {}", arg)),
+            }
+            continue;
+        }
+        match (&command, arg.as_str()) {
+            (Command::Fmt, "--check") => {
+                check = true;
+                continue;
+            }
+            (Command::Info, "--json") => {
+                options.json = true;
+                continue;
+            }
+            _ => {}
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("option {} needs a value", arg))?;
+        match (&command, arg.as_str()) {
+            (_, "--jets") => jets = Some(JetFamily::from_str(&value)?),
+            (_, "--error-format") if command.reads_source_file() => {
+                error_format = ErrorFormat::from_str(&value)?
+            }
+            (_, "--debug-info") if command.takes_debug_info() => debug_info_file = Some(value),
+            (_, "--arg") if command.takes_template_arguments() => {
+                let (name, value) = parse_argument(&value)?;
+                arguments.insert(name, value);
+            }
+            (Command::Info | Command::Run | Command::Debug | Command::Spend, "--witness") => {
+                options.witness = Some(value)
+            }
+            (Command::Run | Command::Debug, "--env") => options.env_file = Some(value),
+            (Command::Run | Command::Debug, "--input") => options.input = Some(value),
+            (Command::Debug, "--break") => options.breakpoints.push(value),
+            (Command::Address | Command::Spend, "--network") => {
+                options.network = Some(Network::from_str(&value)?)
+            }
+            (Command::Address | Command::Spend, "--internal-key") => {
+                options.internal_key = Some(value)
+            }
+            (Command::Spend, "--tx") => options.tx = Some(value),
+            (Command::Spend, "--utxos") => options.utxos = Some(value),
+            (Command::Spend, "--input-index") => {
+                options.input_index = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("bad input index {}: {}", value, e))?,
+                )
+            }
+            (Command::Spend, "--genesis-hash") => options.genesis_hash = Some(value),
+            (Command::Info, "--witness-size") => {
+                options.witness_size = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("bad witness size {}: {}", value, e))?,
+                )
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
     }
 
-    // Execute command
+    let first_arg = match (first_arg, &command) {
+        (None, Command::Repl) => String::new(),
+        (Some(first_arg), _) => first_arg,
+        (None, _) => return Err("missing argument".into()),
+    };
+    let invocation = Invocation {
+        command,
+        first_arg,
+        error_format,
+        debug_info_file,
+        arguments,
+        check,
+        options,
+    };
+    Ok((jets, invocation))
+}
+
+/// A command along with its arguments
+struct Invocation {
+    command: Command,
+    first_arg: String,
+    error_format: ErrorFormat,
+    debug_info_file: Option<String>,
+    arguments: HashMap<Arc<str>, Word>,
+    check: bool,
+    options: ProgramOptions,
+}
+
+fn execute<J: Family>(invocation: Invocation) -> Result<(), String> {
+    let Invocation {
+        command,
+        first_arg,
+        error_format,
+        debug_info_file,
+        arguments,
+        check,
+        options,
+    } = invocation;
+
     match command {
         Command::Assemble => {
-            let prog = parse_file(&first_arg)?;
+            let prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
 
             let roots = prog.roots();
             let mut error = false;
@@ -139,10 +704,15 @@ fn main() -> Result<(), String> {
                 }
             }
 
-            if let Some(prog) = roots.get("main") {
+            if let Some(main) = roots.get("main") {
                 if !error {
-                    let encoded = prog.encode_to_vec();
+                    let encoded = main.encode_to_vec();
                     println!("{}", Base64Display::new(&encoded, &STANDARD));
+                    if let Some(file) = debug_info_file {
+                        fs::write(&file, prog.debug_info().to_string()).map_err(|e| {
+                            format!("failed to write debug info to {}: {}", file, e)
+                        })?;
+                    }
                 }
             } else {
                 eprintln!("Expression `main` not found.");
@@ -154,15 +724,285 @@ fn main() -> Result<(), String> {
             let iter = BitIter::from(v.into_iter());
             let commit =
                 CommitNode::decode(iter).map_err(|e| format!("failed to decode program: {}", e))?;
-            let prog = Forest::<DefaultJet>::from_program(commit);
+            let prog = match debug_info_file {
+                Some(file) => {
+                    let debug_info = read_debug_info(&file)?;
+                    Forest::<J>::from_program_with_debug_info(commit, &debug_info)
+                }
+                None => Forest::<J>::from_program(commit),
+            };
             println!("{}", prog.string_serialize());
         }
         Command::Relabel => {
-            let prog = parse_file(&first_arg)?;
+            let mut prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
+            if let Some(file) = debug_info_file {
+                let debug_info = read_debug_info(&file)?;
+                let main = prog
+                    .roots()
+                    .get("main")
+                    .ok_or("expression `main` not found")?
+                    .to_commit_node();
+                prog = Forest::from_program_with_debug_info(main, &debug_info);
+            }
             println!("{}", prog.string_serialize());
         }
-        Command::Help => unreachable!(),
+        Command::Fmt => {
+            let source = fs::read_to_string(&first_arg)
+                .map_err(|e| format!("failed to read file {}: {}", first_arg, e))?;
+            let formatted = match simplicity::human_encoding::format_source::<J>(&source) {
+                Ok(formatted) => formatted,
+                Err(mut errs) => {
+                    errs.add_context(Arc::from(source.as_str()));
+                    error_format.print(&errs);
+                    return Err(format!("failed to parse file {}", first_arg));
+                }
+            };
+            if check {
+                if formatted != source {
+                    return Err(format!("file {} is not formatted", first_arg));
+                }
+            } else {
+                print!("{}", formatted);
+            }
+        }
+        Command::Info => {
+            let program = if Path::new(&first_arg).is_file() {
+                let prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
+                finalize(&prog, options.witness.as_deref(), error_format)?
+            } else {
+                let decode = |s: &str| {
+                    base64::Engine::decode(&STANDARD, s.as_bytes())
+                        .map_err(|e| format!("failed to parse base64: {}", e))
+                };
+                let program = decode(&first_arg)?;
+                let witness = match options.witness {
+                    Some(ref witness) => decode(witness)?,
+                    None => vec![],
+                };
+                RedeemNode::<J>::decode(
+                    BitIter::from(program.into_iter()),
+                    BitIter::from(witness.into_iter()),
+                )
+                .map_err(|e| match options.witness {
+                    Some(_) => format!("failed to decode program: {}", e),
+                    None => format!("failed to decode program (without --witness): {}", e),
+                })?
+            };
+
+            let info = info::Info::new(&program, options.witness_size);
+            if options.json {
+                println!("{}", info.to_json());
+            } else {
+                println!("{}", info);
+            }
+        }
+        Command::Run => {
+            let prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
+            let program = finalize(&prog, options.witness.as_deref(), error_format)?;
+            let env = J::environment(options.env_file.as_deref(), program.cmr())?;
+            let input = match options.input {
+                Some(s) => parse_value(&s, &program.arrow().source)?,
+                None => Value::unit(),
+            };
+
+            let bounds = ProgramBounds::from_rust(&program);
+            let (tracker, result) = run(&program, &env, &input)?;
+
+            println!(
+                "Cost: {} milliweight units (at most {})",
+                tracker.cost, bounds.cost
+            );
+            println!(
+                "Bounds: at most {} cells and {} frames",
+                bounds.cells, bounds.frames
+            );
+            match result {
+                Ok(output) => println!("Output: {}", output),
+                Err(e) => {
+                    eprintln!("Execution failed: {}", e);
+                    let source_map = prog.redeem_source_map(&program);
+                    for location in tracker.last.iter().flat_map(|&imr| source_map.by_imr(imr)) {
+                        eprintln!("  at {}", location);
+                    }
+                    return Err("execution failed".into());
+                }
+            }
+        }
+        Command::Debug => {
+            let prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
+            let program = finalize(&prog, options.witness.as_deref(), error_format)?;
+            let env = J::environment(options.env_file.as_deref(), program.cmr())?;
+            let input = match options.input {
+                Some(s) => parse_value(&s, &program.arrow().source)?,
+                None => Value::unit(),
+            };
+            debug::run(&prog, &program, &env, &input, &options.breakpoints)?;
+        }
+        Command::Address => {
+            let prog = parse_file::<J>(&first_arg, &arguments, error_format)?;
+            let main = prog
+                .roots()
+                .get("main")
+                .ok_or("expression `main` not found")?;
+            let internal_key = liquid::internal_key(options.internal_key.as_deref())?;
+            let leaf = liquid::Leaf::new(main.cmr(), internal_key);
+            let network = options.network.unwrap_or(Network::Liquid);
+            println!("Address:       {}", leaf.address(network));
+            println!(
+                "Script pubkey: {}",
+                leaf.script_pubkey().as_bytes().as_hex()
+            );
+            println!(
+                "Control block: {}",
+                leaf.control_block().serialize().as_hex()
+            );
+            println!("CMR:           {}", main.cmr());
+        }
+        Command::Spend | Command::Repl | Command::Help => unreachable!(),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a source file to a fresh path in the temporary directory.
+    fn write_source(name: &str, source: &str) -> String {
+        let path = env::temp_dir().join(format!("simpcli-{}-{}.simpl", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn run_invocation(file: String) -> Invocation {
+        Invocation {
+            command: Command::Run,
+            first_arg: file,
+            error_format: ErrorFormat::Human,
+            debug_info_file: None,
+            arguments: HashMap::new(),
+            check: false,
+            options: ProgramOptions::default(),
+        }
+    }
+
+    /// Assembles a program into base64.
+    fn assemble<J: Jet>(source: &str) -> String {
+        let prog = Forest::<J>::parse(source).unwrap();
+        let encoded = prog.roots()["main"].encode_to_vec();
+        Base64Display::new(&encoded, &STANDARD).to_string()
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|&arg| arg.to_owned())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn detect() {
+        let core = assemble::<Core>(
+            "main := comp (comp (pair (const 0x2a) (const 0x2a)) jet_eq_8) jet_verify",
+        );
+        let elements =
+            assemble::<Elements>("main := comp (comp jet_current_index jet_is_zero_32) jet_verify");
+        let no_jets = assemble::<Core>("main := unit");
+        assert_eq!(detect_jets(&core), Ok(JetFamily::Core));
+        assert_eq!(detect_jets(&elements), Ok(JetFamily::Elements));
+        assert_eq!(detect_jets(&no_jets), Ok(JetFamily::Elements));
+        assert!(detect_jets("not base64!").is_err());
+        assert!(detect_jets("AAAA").is_err());
+    }
+
+    #[test]
+    fn parse_options() {
+        let (jets, invocation) = parse_arguments(
+            Command::Run,
+            args(&["prog.simpl", "--jets", "core", "--input", "0x2a"]),
+        )
+        .unwrap();
+        assert_eq!(jets, Some(JetFamily::Core));
+        assert_eq!(invocation.first_arg, "prog.simpl");
+        assert_eq!(invocation.options.input.as_deref(), Some("0x2a"));
+
+        let (jets, invocation) = parse_arguments(
+            Command::Run,
+            args(&[
+                "--input",
+                "0x2a",
+                "--arg",
+                "x=0x01",
+                "prog.simpl",
+                "--jets",
+                "bitcoin",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(jets, Some(JetFamily::Bitcoin));
+        assert_eq!(invocation.first_arg, "prog.simpl");
+        assert_eq!(invocation.options.input.as_deref(), Some("0x2a"));
+        assert_eq!(invocation.arguments.len(), 1);
+
+        let (jets, invocation) =
+            parse_arguments(Command::Fmt, args(&["prog.simpl", "--check"])).unwrap();
+        assert_eq!(jets, None);
+        assert!(invocation.check);
+
+        let (_, invocation) = parse_arguments(
+            Command::Info,
+            args(&["--json", "AAAA", "--witness-size", "10"]),
+        )
+        .unwrap();
+        assert!(invocation.options.json);
+        assert_eq!(invocation.options.witness_size, Some(10));
+
+        let (jets, _) = parse_arguments(Command::Repl, args(&["--jets", "elements"])).unwrap();
+        assert_eq!(jets, Some(JetFamily::Elements));
+
+        for bad in [
+            (Command::Run, &["prog.simpl", "--jets"][..]),
+            (Command::Run, &["prog.simpl", "--jets", "liquid"]),
+            (Command::Run, &["prog.simpl", "other.simpl"]),
+            (Command::Run, &["--input", "0x2a"]),
+            (Command::Run, &["prog.simpl", "--tx", "00"]),
+            (Command::Fmt, &["prog.simpl", "--json"]),
+            (Command::Disassemble, &["AAAA", "--arg", "x=0x01"]),
+            (Command::Repl, &["prog.simpl"]),
+        ] {
+            assert!(parse_arguments(bad.0, args(bad.1)).is_err());
+        }
+    }
+
+    #[test]
+    fn run_cost_is_bound() {
+        let prog = Forest::<Core>::parse(
+            "main := comp (pair (const 0x2a) (const 0x2a)) jet_eq_8 : 1 -> 2",
+        )
+        .unwrap();
+        let program = finalize(&prog, None, ErrorFormat::Human).unwrap();
+        let (tracker, result) = run(&program, &(), &Value::unit()).unwrap();
+        assert_eq!(result.unwrap(), Value::u1(1));
+        assert_eq!(tracker.cost, program.bounds().cost);
+    }
+
+    #[test]
+    fn run_fails() {
+        let ok = write_source(
+            "run-ok",
+            "main := comp (comp (pair (const 0x2a) (const 0x2a)) jet_eq_8) jet_verify : 1 -> 1",
+        );
+        let fail = write_source(
+            "run-fail",
+            "main := comp (comp (pair (const 0x2a) (const 0x2b)) jet_eq_8) jet_verify : 1 -> 1",
+        );
+        let ok_result = execute::<Core>(run_invocation(ok.clone()));
+        let fail_result = execute::<Core>(run_invocation(fail.clone()));
+        fs::remove_file(ok).unwrap();
+        fs::remove_file(fail).unwrap();
+
+        assert_eq!(ok_result, Ok(()));
+        assert_eq!(fail_result, Err("execution failed".to_owned()));
+    }
+}
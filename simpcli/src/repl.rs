@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Read-Eval-Print Loop
+//!
+//! Builds up a program one definition at a time. Each input is either a set of
+//! definitions, which are checked against the earlier ones and whose types are
+//! printed, a command, or an expression, which is evaluated on the Bit Machine.
+
+use simplicity::human_encoding::{symbols, Forest, NamedCommitNode, SymbolKind, SymbolRole};
+use simplicity::node::RedeemNode;
+use simplicity::types::Type;
+use simplicity::{BitMachine, Value};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::environment::Family;
+
+/// The name given to the expression being typed or evaluated
+const EXPRESSION: &str = "repl.it";
+
+const HELP: &str = "\
+Enter definitions, type bounds, type aliases and witness values as in a program, or
+an expression to evaluate it with no input. Commands:
+  :eval VALUE EXPR  evaluate an expression with a hex or binary value as input
+  :type EXPR        print the type of an expression
+  :cmr EXPR         print the CMR of an expression
+  :cost EXPR        print the bound on the cost of evaluating an expression
+  :load FILE        add the definitions in a file
+  :reset            forget every definition
+  :help             print this message
+  :quit             exit
+Types which are not constrained are taken to be 1, so to evaluate a polymorphic
+expression on an input, give its type, as in `:eval 0x2a (iden : 2^8 -> 2^8)`.";
+
+/// Runs the loop on standard input until it ends or `:quit` is entered.
+pub fn run<J: Family>() -> Result<(), String> {
+    let stdin = io::stdin();
+    session::<J, _, _, _>(stdin.lock().lines(), io::stdout(), io::stderr())
+}
+
+/// Runs the loop on the given lines of input until they end or `:quit` is
+/// entered, writing output and errors to the given writers.
+fn session<J, I, W, E>(mut lines: I, out: W, mut err: E) -> Result<(), String>
+where
+    J: Family,
+    I: Iterator<Item = io::Result<String>>,
+    W: Write,
+    E: Write,
+{
+    let mut repl = Repl::<J, W>::new(out);
+    writeln!(repl.out, "Simplicity REPL. Enter :help for help.").map_err(write_error)?;
+    while let Some(input) = read_input(&mut lines, &mut repl.out)? {
+        match repl.handle(input.trim()) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => writeln!(err, "{}", e).map_err(write_error)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_error(e: io::Error) -> String {
+    format!("failed to write output: {}", e)
+}
+
+/// Reads one input, which continues over several lines while it has unclosed
+/// braces. Returns `None` at the end of the input.
+fn read_input<I, W>(lines: &mut I, out: &mut W) -> Result<Option<String>, String>
+where
+    I: Iterator<Item = io::Result<String>>,
+    W: Write,
+{
+    let mut input = String::new();
+    let mut depth = 0;
+    loop {
+        write!(out, "{}", if input.is_empty() { "> " } else { ". " })
+            .and_then(|()| out.flush())
+            .map_err(|e| format!("failed to write prompt: {}", e))?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| format!("failed to read input: {}", e))?,
+            None if input.is_empty() => {
+                writeln!(out).map_err(write_error)?;
+                return Ok(None);
+            }
+            None => return Ok(Some(input)),
+        };
+        for ch in line.chars() {
+            match ch {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        input.push_str(&line);
+        input.push('\n');
+        if depth <= 0 {
+            return Ok(Some(input));
+        }
+    }
+}
+
+struct Repl<J, W> {
+    /// Every definition accepted so far, as source
+    session: String,
+    out: W,
+    phantom: PhantomData<J>,
+}
+
+impl<J: Family, W: Write> Repl<J, W> {
+    fn new(out: W) -> Self {
+        Repl {
+            session: String::new(),
+            out,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Handles one input, returning whether to carry on.
+    fn handle(&mut self, input: &str) -> Result<bool, String> {
+        let (command, arg) = match input.strip_prefix(':') {
+            Some(command) => match command.split_once(char::is_whitespace) {
+                Some((command, arg)) => (command, arg.trim()),
+                None => (command, ""),
+            },
+            None if input.is_empty() => return Ok(true),
+            // Anything which parses as a program is a set of definitions.
+            None if symbols::<J>(input).is_ok() => {
+                self.define(input)?;
+                return Ok(true);
+            }
+            None => {
+                self.eval(None, input)?;
+                return Ok(true);
+            }
+        };
+
+        match command {
+            "eval" => {
+                let (value, expr) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or("expected a value and an expression")?;
+                self.eval(Some(value), expr)?;
+            }
+            "type" => {
+                let (forest, node) = self.expression(arg)?;
+                writeln!(
+                    self.out,
+                    "{} -> {}",
+                    forest.type_string(&node.arrow().source),
+                    forest.type_string(&node.arrow().target),
+                )
+                .map_err(write_error)?;
+            }
+            "cmr" => {
+                let cmr = self.expression(arg)?.1.cmr();
+                writeln!(self.out, "{}", cmr).map_err(write_error)?;
+            }
+            "cost" => {
+                let program = self.finalize(arg)?;
+                writeln!(self.out, "{} milliweight units", program.bounds().cost)
+                    .map_err(write_error)?;
+            }
+            "load" => {
+                let source = fs::read_to_string(arg)
+                    .map_err(|e| format!("failed to read file {}: {}", arg, e))?;
+                self.define(&source)?;
+            }
+            "reset" => self.session.clear(),
+            "help" => writeln!(self.out, "{}", HELP).map_err(write_error)?,
+            "quit" => return Ok(false),
+            _ => {
+                return Err(format!(
+                    "unknown command :{}; enter :help for help",
+                    command
+                ))
+            }
+        }
+        Ok(true)
+    }
+
+    /// Parses the session followed by the given source.
+    fn parse(&self, source: &str) -> Result<(String, Forest<J>), String> {
+        let source = format!("{}{}\n", self.session, source);
+        match Forest::parse(&source) {
+            Ok(forest) => Ok((source, forest)),
+            Err(mut errs) => {
+                errs.add_context(Arc::from(source.as_str()));
+                Err(errs.to_string().trim_end().to_owned())
+            }
+        }
+    }
+
+    /// Adds definitions to the session, and prints the type of each one.
+    fn define(&mut self, source: &str) -> Result<(), String> {
+        let (session, _) = self.parse(source)?;
+        self.session = session;
+
+        // Parameters, locals and templates cannot be referred to on their own,
+        // so have no type to print.
+        let mut seen = HashSet::new();
+        for symbol in symbols::<J>(source).map_err(|errs| errs.to_string())? {
+            if symbol.kind != SymbolKind::Name
+                || symbol.role != SymbolRole::Definition
+                || !seen.insert(Arc::clone(&symbol.name))
+            {
+                continue;
+            }
+            if let Ok((forest, node)) = self.expression(&symbol.name) {
+                writeln!(
+                    self.out,
+                    "{} : {} -> {}",
+                    symbol.name,
+                    forest.type_string(&node.arrow().source),
+                    forest.type_string(&node.arrow().target),
+                )
+                .map_err(write_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an expression in the context of the session.
+    fn expression(&self, expr: &str) -> Result<(Forest<J>, Arc<NamedCommitNode<J>>), String> {
+        let (_, forest) = self.parse(&format!("{} := {}", EXPRESSION, expr))?;
+        let node = forest
+            .roots()
+            .get(EXPRESSION)
+            .cloned()
+            .ok_or("expression is used by another definition")?;
+        Ok((forest, node))
+    }
+
+    /// Finalizes an expression, with the witness values assigned in the session.
+    fn finalize(&self, expr: &str) -> Result<Arc<RedeemNode<J>>, String> {
+        let (forest, node) = self.expression(expr)?;
+        let construct = node.to_construct_node(forest.witness(), forest.roots());
+        // Converting the expression infers its type afresh, so restore the
+        // type it has in the session.
+        let arrow = construct.arrow();
+        let ctx = &arrow.inference_context;
+        ctx.unify(
+            &arrow.source,
+            &Type::complete(ctx, Arc::clone(&node.arrow().source)),
+            "binding source type of expression",
+        )
+        .and_then(|()| {
+            ctx.unify(
+                &arrow.target,
+                &Type::complete(ctx, Arc::clone(&node.arrow().target)),
+                "binding target type of expression",
+            )
+        })
+        .map_err(|e| format!("failed to type expression: {}", e))?;
+        construct
+            .finalize_unpruned()
+            .map_err(|e| format!("failed to finalize expression: {}", e))
+    }
+
+    /// Evaluates an expression on the given input, or on no input.
+    fn eval(&mut self, input: Option<&str>, expr: &str) -> Result<(), String> {
+        let program = self.finalize(expr)?;
+        let input = match input {
+            Some(s) => crate::parse_value(s, &program.arrow().source)?,
+            None => Value::unit(),
+        };
+        let env = J::environment(None, program.cmr())?;
+        let mut mac = BitMachine::for_program(&program)
+            .map_err(|e| format!("expression exceeds limits: {}", e))?;
+        let output = mac
+            .input(&input)
+            .and_then(|()| mac.exec(&program, &env))
+            .map_err(|e| format!("execution failed: {}", e))?;
+        match output.to_word() {
+            Some(word) => writeln!(self.out, "{}", word),
+            None => writeln!(self.out, "{}", output),
+        }
+        .map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simplicity::jet::Core;
+
+    /// Runs a session with Core jets, returning its output and errors.
+    fn run_session(input: &str) -> (String, String) {
+        let lines = input.lines().map(|line| Ok(line.to_owned()));
+        let (mut out, mut err) = (vec![], vec![]);
+        session::<Core, _, _, _>(lines, &mut out, &mut err).unwrap();
+        (
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn scripted_session() {
+        let (out, err) = run_session(
+            "\
+x := const 0x2a
+eq := comp (pair iden iden) jet_eq_8
+:type eq
+comp x eq
+:eval 0x07 eq
+:cost comp x eq
+:cmr unit
+:reset
+x
+:quit
+comp x eq
+",
+        );
+        assert_eq!(
+            out,
+            "\
+Simplicity REPL. Enter :help for help.
+> x : 1 -> 2^8
+> eq : 2^8 -> 2
+> 2^8 -> 2
+> 0b1
+> 0b1
+> 843 milliweight units
+> c40a10263f7436b4160acbef1c36fba4be4d95df181a968afeab5eac247adff7
+> > > ",
+        );
+        // After `:reset`, `x` is no longer defined.
+        assert!(err.contains("name `x` is referred to but does not exist"));
+    }
+
+    #[test]
+    fn multiline_input() {
+        let (out, err) = run_session(
+            "\
+y := comp (pair x x) jet_eq_8 where {
+  x := const 0x2a
+}
+y
+",
+        );
+        assert_eq!(
+            out,
+            "Simplicity REPL. Enter :help for help.\n> . . y : 1 -> 2\n> 0b1\n> \n",
+        );
+        assert_eq!(err, "");
+    }
+}
@@ -0,0 +1,387 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Documents
+//!
+//! Analysis of a single open source file: everything the server reports about
+//! a file is computed once, when its contents change, and kept here.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use simplicity::dag::{DagLike, MaxSharing};
+use simplicity::human_encoding::{
+    self, ErrorSet, Forest, Position, Symbol, SymbolKind, SymbolRole,
+};
+use simplicity::types::Final;
+use simplicity::{Cmr, Imr};
+
+/// What set of jets to use in the program.
+pub type DefaultJet = simplicity::jet::Elements;
+
+/// A position as the protocol counts it: lines from 0, and characters in
+/// UTF-16 code units from 0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextPosition {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// A range of text, from `start` up to but not including `end`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextRange {
+    pub start: TextPosition,
+    pub end: TextPosition,
+}
+
+/// An error to report in the editor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: TextRange,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// The type and commitments of a named expression
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NameInfo {
+    source: Arc<Final>,
+    target: Arc<Final>,
+    cmr: Cmr,
+    imr: Option<Imr>,
+}
+
+/// An open source file, along with the results of analysing it
+#[derive(Clone, Debug)]
+pub struct Document {
+    lines: Vec<String>,
+    /// Every name and hole in the file; empty if the file has syntax errors.
+    symbols: Vec<Symbol>,
+    /// Type and commitments of every named expression; empty if the file
+    /// could not be assembled.
+    names: HashMap<Arc<str>, NameInfo>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    /// Analyses a source file.
+    ///
+    /// If `path` is given, it is the path of the file itself; imports are
+    /// resolved relative to it, and imported files are read with `read`.
+    /// Otherwise the file may not use imports.
+    pub fn new<F>(text: &str, path: Option<&Path>, mut read: F) -> Self
+    where
+        F: FnMut(&Path) -> io::Result<String>,
+    {
+        let mut doc = Document {
+            lines: text.split('\n').map(str::to_owned).collect(),
+            symbols: human_encoding::symbols::<DefaultJet>(text).unwrap_or_default(),
+            names: HashMap::new(),
+            diagnostics: vec![],
+        };
+
+        let forest = match path {
+            Some(path) => Forest::<DefaultJet>::parse_file_with(path, |file| {
+                if file == path {
+                    Ok(text.to_owned())
+                } else {
+                    read(file)
+                }
+            }),
+            None => Forest::<DefaultJet>::parse(text),
+        };
+        match forest {
+            Ok(forest) => {
+                for root in forest.roots().values() {
+                    for data in root.as_ref().post_order_iter::<MaxSharing<_>>() {
+                        let node = data.node;
+                        doc.names.insert(
+                            Arc::clone(node.name()),
+                            NameInfo {
+                                source: Arc::clone(&node.arrow().source),
+                                target: Arc::clone(&node.arrow().target),
+                                cmr: node.cmr(),
+                                imr: node.imr(),
+                            },
+                        );
+                    }
+                }
+            }
+            Err(errs) => doc.diagnostics = doc.diagnostics_from(&errs),
+        }
+        doc
+    }
+
+    /// The errors found in the file.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn diagnostics_from(&self, errs: &ErrorSet) -> Vec<Diagnostic> {
+        errs.iter_with_positions()
+            .map(|(position, error)| match position {
+                Some(position) if position.file() == 0 => Diagnostic {
+                    range: self.token_range(position),
+                    code: error.code(),
+                    message: error.to_string(),
+                },
+                // Errors in imported files are reported at the top of the file,
+                // since there is nowhere better to put them.
+                Some(position) => Diagnostic {
+                    range: TextRange::default(),
+                    code: error.code(),
+                    message: match errs.file_name(position.file()) {
+                        Some(name) => format!(
+                            "{}:{}:{}: {}",
+                            name,
+                            position.line(),
+                            position.column(),
+                            error
+                        ),
+                        None => error.to_string(),
+                    },
+                },
+                None => Diagnostic {
+                    range: TextRange::default(),
+                    code: error.code(),
+                    message: error.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    /// Converts a position in the source to a protocol position.
+    fn text_position(&self, line: usize, column: usize) -> TextPosition {
+        let text = self.lines.get(line - 1).map_or("", String::as_str);
+        TextPosition {
+            line: line - 1,
+            character: text.chars().take(column - 1).map(char::len_utf16).sum(),
+        }
+    }
+
+    /// The range of the token at the given position in the source.
+    ///
+    /// Only names and literals are given their full length; any other token is
+    /// taken to be a single character long.
+    fn token_range(&self, position: Position) -> TextRange {
+        let text = self
+            .lines
+            .get(position.line() - 1)
+            .map_or("", String::as_str);
+        let len = text
+            .chars()
+            .skip(position.column() - 1)
+            .take_while(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.' | '\''))
+            .count();
+        TextRange {
+            start: self.text_position(position.line(), position.column()),
+            end: self.text_position(position.line(), position.column() + len.max(1)),
+        }
+    }
+
+    /// The range of a symbol in the source.
+    fn symbol_range(&self, symbol: &Symbol) -> TextRange {
+        let (line, column) = (symbol.position.line(), symbol.position.column());
+        TextRange {
+            start: self.text_position(line, column),
+            end: self.text_position(line, column + symbol.name.len()),
+        }
+    }
+
+    /// The symbol at the given position, including the position just after it.
+    fn symbol_at(&self, position: TextPosition) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| {
+            let range = self.symbol_range(symbol);
+            range.start.line == position.line && range.start <= position && position <= range.end
+        })
+    }
+
+    /// Every occurrence of the same name as `symbol`, in the same namespace.
+    fn occurrences<'s>(&'s self, symbol: &'s Symbol) -> impl Iterator<Item = &'s Symbol> + 's {
+        self.symbols
+            .iter()
+            .filter(move |other| other.kind == symbol.kind && other.name == symbol.name)
+    }
+
+    /// A description of the name at the given position, in Markdown.
+    pub fn hover(&self, position: TextPosition) -> Option<(TextRange, String)> {
+        let symbol = self.symbol_at(position)?;
+        if symbol.kind != SymbolKind::Name {
+            return None;
+        }
+        let info = self.names.get(&symbol.name)?;
+        let arrow = format!("{} : {} -> {}", symbol.name, info.source, info.target);
+        let imr = match info.imr {
+            Some(imr) => format!("`{}`", imr),
+            None => "undetermined".to_owned(),
+        };
+        let text = format!(
+            "```simplicity\n{}\n```\nCMR: `{}`\n\nIMR: {}",
+            // for human-readable encoding stick with ASCII
+            arrow.replace('×', "*"),
+            info.cmr,
+            imr,
+        );
+        Some((self.symbol_range(symbol), text))
+    }
+
+    /// The definition of the name or hole at the given position.
+    ///
+    /// Names without a definition in this file (for example, names from
+    /// imported files) fall back to their type bounds, if any.
+    pub fn definition(&self, position: TextPosition) -> Vec<TextRange> {
+        let symbol = match self.symbol_at(position) {
+            Some(symbol) => symbol,
+            None => return vec![],
+        };
+        let with_role = |role| {
+            self.occurrences(symbol)
+                .filter(|other| other.role == role)
+                .map(|other| self.symbol_range(other))
+                .collect::<Vec<_>>()
+        };
+        let definitions = with_role(SymbolRole::Definition);
+        if definitions.is_empty() {
+            with_role(SymbolRole::TypeBound)
+        } else {
+            definitions
+        }
+    }
+
+    /// Every occurrence of the name or hole at the given position.
+    pub fn references(&self, position: TextPosition, include_declaration: bool) -> Vec<TextRange> {
+        match self.symbol_at(position) {
+            Some(symbol) => self
+                .occurrences(symbol)
+                .filter(|other| include_declaration || other.role == SymbolRole::Reference)
+                .map(|other| self.symbol_range(other))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// The edits needed to rename the name or hole at the given position.
+    pub fn rename(&self, position: TextPosition, new_name: &str) -> Result<Vec<TextRange>, String> {
+        if self.symbol_at(position).is_none() {
+            return Err("there is no name at this position".to_owned());
+        }
+        // A valid name is one which parses as a name.
+        let valid = new_name != "_"
+            && human_encoding::symbols::<DefaultJet>(&format!("{} := unit", new_name))
+                .is_ok_and(|symbols| symbols.len() == 1 && symbols[0].name.as_ref() == new_name);
+        if !valid {
+            return Err(format!("`{}` is not a valid name", new_name));
+        }
+        Ok(self.references(position, true))
+    }
+}
+
+/// Every jet, as `(name, source type, target type)`, for completion.
+pub fn jets() -> impl Iterator<Item = (String, Arc<Final>, Arc<Final>)> {
+    DefaultJet::ALL.iter().map(|jet| {
+        use simplicity::jet::Jet as _;
+        (
+            format!("jet_{}", jet),
+            jet.source_ty().to_final(),
+            jet.target_ty().to_final(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+-- ünïcödé comment
+main := comp (disconnect iden ?dis) helper : 1 -> 1
+dis := unit
+helper := unit
+";
+
+    fn pos(line: usize, character: usize) -> TextPosition {
+        TextPosition { line, character }
+    }
+
+    fn range(line: usize, start: usize, end: usize) -> TextRange {
+        TextRange {
+            start: pos(line, start),
+            end: pos(line, end),
+        }
+    }
+
+    fn no_files(_: &Path) -> io::Result<String> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "file not found"))
+    }
+
+    #[test]
+    fn navigation() {
+        let doc = Document::new(SOURCE, None, no_files);
+        assert!(doc.diagnostics().is_empty());
+
+        // `?dis` refers to the definition of `dis`
+        assert_eq!(doc.definition(pos(2, 32)), [range(3, 0, 3)]);
+        assert_eq!(
+            doc.references(pos(3, 1), true),
+            [range(2, 31, 34), range(3, 0, 3)]
+        );
+        assert_eq!(doc.references(pos(3, 1), false), [range(2, 31, 34)]);
+        assert_eq!(doc.definition(pos(2, 40)), [range(4, 0, 6)]);
+        assert!(doc.definition(pos(2, 5)).is_empty());
+
+        let (hover_range, text) = doc.hover(pos(4, 0)).unwrap();
+        assert_eq!(hover_range, range(4, 0, 6));
+        assert!(text.contains("helper : 2^256 * 1 -> 1"), "{}", text);
+        assert!(text.contains("CMR: `"), "{}", text);
+
+        assert_eq!(doc.rename(pos(4, 3), "helper2").unwrap().len(), 2);
+        assert!(doc.rename(pos(4, 3), "comp").is_err());
+        assert!(doc.rename(pos(4, 3), "two words").is_err());
+        assert!(doc.rename(pos(4, 3), "_").is_err());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let doc = Document::new("-- é\nmain := comp unit hlper", None, no_files);
+        assert_eq!(
+            doc.diagnostics(),
+            [Diagnostic {
+                range: range(1, 18, 23),
+                code: "E0016",
+                message: "name `hlper` is referred to but does not exist".to_owned(),
+            }]
+        );
+        // Navigation still works without a valid program
+        assert_eq!(doc.definition(pos(1, 1)), [range(1, 0, 4)]);
+
+        // Columns count characters, but the protocol counts UTF-16 code units
+        let doc = Document::new("import \"😀\" as 1", None, no_files);
+        assert_eq!(doc.diagnostics()[0].range, range(0, 15, 16));
+
+        let doc = Document::new(
+            "import \"lib.simpl\"\nmain := lib",
+            Some(Path::new("main.simpl")),
+            |path: &Path| {
+                assert_eq!(path, Path::new("lib.simpl"));
+                Ok("lib := comp unit x\n".to_owned())
+            },
+        );
+        assert_eq!(
+            doc.diagnostics(),
+            [Diagnostic {
+                range: TextRange::default(),
+                code: "E0016",
+                message: "lib.simpl:1:18: name `x` is referred to but does not exist".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn jet_names() {
+        let (name, source, target) = jets().find(|jet| jet.0 == "jet_add_32").unwrap();
+        assert_eq!(name, "jet_add_32");
+        assert_eq!(source.to_string(), "2^64");
+        assert_eq!(target.to_string(), "2 × 2^32");
+    }
+}
@@ -0,0 +1,28 @@
+// Simplicity "Human-Readable" Language Server
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! A language server for the Simplicity human-readable encoding, which talks
+//! to the editor over stdin and stdout.
+
+mod document;
+mod server;
+mod transport;
+
+use std::io;
+
+fn main() -> Result<(), String> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    server::Server::new()
+        .run(&mut stdin.lock(), &mut stdout.lock())
+        .map_err(|e| e.to_string())
+}
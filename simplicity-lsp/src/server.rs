@@ -0,0 +1,496 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Server
+//!
+//! Dispatches requests and notifications from the client, keeping track of the
+//! contents of every open document.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::document::{self, Document, TextPosition, TextRange};
+use crate::transport::{read_message, write_message};
+
+/// JSON-RPC error codes, from the protocol specification
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+const REQUEST_FAILED: i64 = -32803;
+
+/// `TextDocumentSyncKind.Full`: the client sends the whole text on every change
+const SYNC_FULL: u64 = 1;
+/// `CompletionItemKind.Function`
+const COMPLETION_FUNCTION: u64 = 3;
+
+/// An error to return in response to a request
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn invalid_params() -> Self {
+        ResponseError {
+            code: INVALID_PARAMS,
+            message: "invalid parameters".to_owned(),
+        }
+    }
+}
+
+/// The state of the server
+#[derive(Default)]
+pub struct Server {
+    /// Text of every open document, by URI
+    texts: BTreeMap<String, String>,
+    /// Analysis of every open document, by URI
+    documents: HashMap<String, Document>,
+    /// Whether the client has asked the server to shut down
+    shutdown: bool,
+}
+
+impl Server {
+    /// Constructs a new server with no open documents.
+    pub fn new() -> Self {
+        Server::default()
+    }
+
+    /// Handles messages from `r` until the client asks the server to exit,
+    /// writing responses and notifications to `w`.
+    ///
+    /// Returns an error if the input is not a valid sequence of messages, or
+    /// ends without the client asking the server to exit.
+    pub fn run<R: BufRead, W: Write>(&mut self, r: &mut R, w: &mut W) -> io::Result<()> {
+        while let Some(message) = read_message(r)? {
+            let method = message.get("method").and_then(Value::as_str);
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            match (message.get("id"), method) {
+                (Some(id), Some(method)) => {
+                    let response = match self.request(method, &params) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err(e) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": e.code, "message": e.message },
+                        }),
+                    };
+                    write_message(w, &response)?;
+                }
+                (None, Some("exit")) => {
+                    return if self.shutdown {
+                        Ok(())
+                    } else {
+                        Err(io::Error::other("exit without shutdown"))
+                    };
+                }
+                (None, Some(method)) => {
+                    for notification in self.notification(method, &params) {
+                        write_message(w, &notification)?;
+                    }
+                }
+                // The server sends no requests, so there are no responses to handle.
+                (_, None) => {}
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "input ended without exit",
+        ))
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, ResponseError> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "completionProvider": {},
+                    "renameProvider": true,
+                },
+                "serverInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => {
+                let (_, doc, position) = self.document_position(params)?;
+                Ok(match doc.hover(position) {
+                    Some((range, text)) => json!({
+                        "contents": { "kind": "markdown", "value": text },
+                        "range": range_json(range),
+                    }),
+                    None => Value::Null,
+                })
+            }
+            "textDocument/definition" => {
+                let (uri, doc, position) = self.document_position(params)?;
+                Ok(locations_json(uri, doc.definition(position)))
+            }
+            "textDocument/references" => {
+                let (uri, doc, position) = self.document_position(params)?;
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                Ok(locations_json(
+                    uri,
+                    doc.references(position, include_declaration),
+                ))
+            }
+            "textDocument/completion" => Ok(document::jets()
+                .map(|(name, source, target)| {
+                    json!({
+                        "label": name,
+                        "kind": COMPLETION_FUNCTION,
+                        "detail": format!("{} -> {}", source, target).replace('×', "*"),
+                    })
+                })
+                .collect()),
+            "textDocument/rename" => {
+                let (uri, doc, position) = self.document_position(params)?;
+                let new_name = params["newName"]
+                    .as_str()
+                    .ok_or_else(ResponseError::invalid_params)?;
+                let ranges = doc
+                    .rename(position, new_name)
+                    .map_err(|message| ResponseError {
+                        code: REQUEST_FAILED,
+                        message,
+                    })?;
+                let edits: Vec<_> = ranges
+                    .into_iter()
+                    .map(|range| json!({ "range": range_json(range), "newText": new_name }))
+                    .collect();
+                Ok(json!({ "changes": { uri: edits } }))
+            }
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {}", method),
+            }),
+        }
+    }
+
+    /// Handles a notification, returning the notifications to send in reply.
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().map(str::to_owned);
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                if let Some(text) = params["textDocument"]["text"].as_str() {
+                    self.texts.insert(uri, text.to_owned());
+                }
+                self.analyse()
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // With full synchronization, the last change has the whole text.
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                if let Some(text) = text {
+                    self.texts.insert(uri, text.to_owned());
+                }
+                self.analyse()
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.texts.remove(&uri);
+                let mut notifications = self.analyse();
+                notifications.push(diagnostics_json(&uri, &[]));
+                notifications
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Re-analyses every open document, since any of them may import the one
+    /// which changed, returning their diagnostics.
+    fn analyse(&mut self) -> Vec<Value> {
+        let open: HashMap<PathBuf, &str> = self
+            .texts
+            .iter()
+            .filter_map(|(uri, text)| Some((uri_to_path(uri)?, text.as_str())))
+            .collect();
+        self.documents.clear();
+        let mut notifications = vec![];
+        for (uri, text) in &self.texts {
+            let path = uri_to_path(uri);
+            let doc = Document::new(text, path.as_deref(), |path| match open.get(path) {
+                Some(text) => Ok(text.to_string()),
+                None => fs::read_to_string(path),
+            });
+            notifications.push(diagnostics_json(uri, doc.diagnostics()));
+            self.documents.insert(uri.clone(), doc);
+        }
+        notifications
+    }
+
+    /// Looks up the document and position given in the parameters of a request.
+    fn document_position(
+        &self,
+        params: &Value,
+    ) -> Result<(&str, &Document, TextPosition), ResponseError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(ResponseError::invalid_params)?;
+        let (uri, doc) = self
+            .documents
+            .get_key_value(uri)
+            .ok_or_else(|| ResponseError {
+                code: REQUEST_FAILED,
+                message: format!("document {} is not open", uri),
+            })?;
+        let line = params["position"]["line"].as_u64();
+        let character = params["position"]["character"].as_u64();
+        match (line, character) {
+            (Some(line), Some(character)) => Ok((
+                uri,
+                doc,
+                TextPosition {
+                    line: line as usize,
+                    character: character as usize,
+                },
+            )),
+            _ => Err(ResponseError::invalid_params()),
+        }
+    }
+}
+
+/// Converts a `file:` URI to a path, returning `None` for any other URI.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = if byte == b'%' && tail.len() >= 2 {
+            std::str::from_utf8(&tail[..2])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn position_json(position: TextPosition) -> Value {
+    json!({ "line": position.line, "character": position.character })
+}
+
+fn range_json(range: TextRange) -> Value {
+    json!({ "start": position_json(range.start), "end": position_json(range.end) })
+}
+
+fn locations_json(uri: &str, ranges: Vec<TextRange>) -> Value {
+    ranges
+        .into_iter()
+        .map(|range| json!({ "uri": uri, "range": range_json(range) }))
+        .collect()
+}
+
+fn diagnostics_json(uri: &str, diagnostics: &[document::Diagnostic]) -> Value {
+    let diagnostics: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "range": range_json(diagnostic.range),
+                "severity": 1,
+                "code": diagnostic.code,
+                "source": "simplicity",
+                "message": diagnostic.message,
+            })
+        })
+        .collect();
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a session with the given messages from the client, returning the
+    /// messages sent by the server.
+    fn session(messages: &[Value]) -> Vec<Value> {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = vec![];
+        Server::new().run(&mut &input[..], &mut output).unwrap();
+
+        let mut r = &output[..];
+        let mut ret = vec![];
+        while let Some(message) = read_message(&mut r).unwrap() {
+            ret.push(message);
+        }
+        ret
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    const URI: &str = "file:///nonexistent/dir%20name/main.simpl";
+
+    #[test]
+    fn uri_paths() {
+        assert_eq!(
+            uri_to_path(URI),
+            Some(PathBuf::from("/nonexistent/dir name/main.simpl"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn full_session() {
+        let responses = session(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": {
+                    "uri": URI,
+                    "languageId": "simplicity",
+                    "version": 1,
+                    "text": "main := comp unit hlper",
+                }}),
+            ),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": [{ "text": "main := comp helper jet_verify\nhelper := injl unit\n" }],
+                }),
+            ),
+            request(2, "textDocument/hover", at(1, 2)),
+            request(3, "textDocument/definition", at(0, 15)),
+            request(
+                4,
+                "textDocument/references",
+                json!({
+                    "textDocument": { "uri": URI },
+                    "position": { "line": 1, "character": 0 },
+                    "context": { "includeDeclaration": false },
+                }),
+            ),
+            request(5, "textDocument/completion", at(0, 22)),
+            request(
+                6,
+                "textDocument/rename",
+                json!({
+                    "textDocument": { "uri": URI },
+                    "position": { "line": 1, "character": 0 },
+                    "newName": "one",
+                }),
+            ),
+            request(7, "textDocument/rename", {
+                let mut params = at(1, 0);
+                params["newName"] = json!("comp");
+                params
+            }),
+            request(8, "textDocument/formatting", json!({})),
+            request(9, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+
+        let mut responses = responses.iter();
+        let mut next = || responses.next().unwrap();
+
+        let init = next();
+        assert_eq!(init["id"], 1);
+        assert_eq!(init["result"]["capabilities"]["hoverProvider"], true);
+
+        let diagnostics = next();
+        assert_eq!(diagnostics["method"], "textDocument/publishDiagnostics");
+        assert_eq!(
+            diagnostics["params"]["diagnostics"],
+            json!([{
+                "range": {
+                    "start": { "line": 0, "character": 18 },
+                    "end": { "line": 0, "character": 23 },
+                },
+                "severity": 1,
+                "code": "E0016",
+                "source": "simplicity",
+                "message": "name `hlper` is referred to but does not exist",
+            }])
+        );
+        let diagnostics = next();
+        assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+
+        let hover = next();
+        let text = hover["result"]["contents"]["value"].as_str().unwrap();
+        assert!(text.contains("helper : 1 -> 2"), "{}", text);
+
+        let definition_range = json!({
+            "start": { "line": 1, "character": 0 },
+            "end": { "line": 1, "character": 6 },
+        });
+        let reference_range = json!({
+            "start": { "line": 0, "character": 13 },
+            "end": { "line": 0, "character": 19 },
+        });
+        assert_eq!(
+            next()["result"],
+            json!([{ "uri": URI, "range": definition_range }])
+        );
+        assert_eq!(
+            next()["result"],
+            json!([{ "uri": URI, "range": reference_range }])
+        );
+
+        let completion = next();
+        let items = completion["result"].as_array().unwrap();
+        assert!(items
+            .iter()
+            .any(|item| item["label"] == "jet_verify" && item["detail"] == "2 -> 1"));
+
+        assert_eq!(
+            next()["result"]["changes"][URI],
+            json!([
+                { "range": reference_range, "newText": "one" },
+                { "range": definition_range, "newText": "one" },
+            ])
+        );
+        assert_eq!(next()["error"]["code"], REQUEST_FAILED);
+        assert_eq!(next()["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(next()["result"], Value::Null);
+        assert!(responses.next().is_none());
+    }
+
+    #[test]
+    fn exit_without_shutdown() {
+        let mut input = vec![];
+        write_message(&mut input, &notification("exit", Value::Null)).unwrap();
+        assert!(Server::new().run(&mut &input[..], &mut vec![]).is_err());
+        assert!(Server::new().run(&mut &b""[..], &mut vec![]).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Transport
+//!
+//! Reads and writes JSON-RPC messages framed with a `Content-Length` header,
+//! as the Language Server Protocol specifies for stdio.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Reads a single message, returning `None` at the end of the input.
+pub fn read_message<R: BufRead>(r: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // Other headers (only `Content-Type` is defined) can be ignored.
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                let len = value.trim().parse::<usize>().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad Content-Length: {}", e),
+                    )
+                })?;
+                content_length = Some(len);
+            }
+        }
+    }
+
+    let len = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes a single message.
+pub fn write_message<W: Write>(w: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }),
+            json!({ "jsonrpc": "2.0", "method": "exit", "params": "ünïcödé" }),
+        ];
+        let mut buf = vec![];
+        for message in &messages {
+            write_message(&mut buf, message).unwrap();
+        }
+
+        let mut r = &buf[..];
+        for message in &messages {
+            assert_eq!(read_message(&mut r).unwrap().as_ref(), Some(message));
+        }
+        assert!(read_message(&mut r).unwrap().is_none());
+
+        let mut r = &b"Content-Type: x\r\n\r\n{}"[..];
+        assert!(read_message(&mut r).is_err());
+    }
+}
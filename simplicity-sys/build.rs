@@ -8,14 +8,22 @@ fn main() {
     println!("cargo:rerun-if-changed=depend");
     let simplicity_path = Path::new("depend/simplicity");
     let jet_files: Vec<_> = vec![
+        "bitstream.c",
+        "dag.c",
+        "deserialize.c",
+        "eval.c",
         "frame.c",
         "jets.c",
         "jets-secp256k1.c",
         "rsort.c",
         "sha256.c",
+        "type.c",
+        "typeInference.c",
         "primitive/elements/env.c",
+        "primitive/elements/exec.c",
         "primitive/elements/ops.c",
         "primitive/elements/elementsJets.c",
+        "primitive/elements/primitive.c",
     ]
     .into_iter()
     .map(|x| simplicity_path.join(x))
@@ -34,14 +42,6 @@ fn main() {
 
     if cfg!(feature = "test-utils") {
         let test_files: Vec<_> = vec![
-            "bitstream.c",
-            "dag.c",
-            "deserialize.c",
-            "eval.c",
-            "type.c",
-            "typeInference.c",
-            "primitive/elements/exec.c",
-            "primitive/elements/primitive.c",
             "ctx8Pruned.c",
             "ctx8Unpruned.c",
             "hashBlock.c",
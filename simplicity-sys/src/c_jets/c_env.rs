@@ -114,11 +114,11 @@ pub enum CTransaction {}
 #[derive(Debug)]
 #[repr(C)]
 pub struct CElementsTxEnv {
-    tx: *const CTransaction,
-    taproot: *const CTapEnv,
-    genesis_hash: CSha256Midstate,
+    pub(crate) tx: *const CTransaction,
+    pub(crate) taproot: *const CTapEnv,
+    pub(crate) genesis_hash: CSha256Midstate,
     sighash_all: CSha256Midstate,
-    ix: c_uint_fast32_t,
+    pub(crate) ix: c_uint_fast32_t,
 }
 
 #[derive(Debug)]
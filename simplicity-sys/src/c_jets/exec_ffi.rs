@@ -1 +1,82 @@
+// SPDX-License-Identifier: CC0-1.0
 
+//! FFI Bindings for the consensus evaluator
+//!
+//! This module contains bindings to `simplicity/elements/exec.h`, which
+//! decodes, typechecks and evaluates a Simplicity program in the same way
+//! that Elements does during consensus validation.
+
+use hashes::sha256::Midstate;
+
+use crate::c_jets::c_env::{CElementsTxEnv, CTapEnv, CTransaction};
+use crate::ffi::{c_int, c_size_t, c_uchar, c_uint_fast32_t, SimplicityErr};
+
+extern "C" {
+    /// Deserialize a Simplicity 'program' with its 'witness' data and execute it
+    /// in the environment of the 'ix'th input of 'tx' with `taproot`.
+    ///
+    /// Refer to the C documentation for preconditions.
+    pub fn simplicity_elements_execSimplicity(
+        error: *mut c_int,
+        imr: *mut c_uchar,
+        tx: *const CTransaction,
+        ix: c_uint_fast32_t,
+        taproot: *const CTapEnv,
+        genesis_block_hash: *const c_uchar,
+        budget: i64,
+        amr: *const c_uchar,
+        program: *const c_uchar,
+        program_len: c_size_t,
+        witness: *const c_uchar,
+        witness_len: c_size_t,
+    ) -> bool;
+}
+
+/// Decode, typecheck and evaluate a Simplicity program using the C implementation.
+///
+/// The program is run in the transaction environment `env` with the given `budget`,
+/// which is measured in weight units (the serialized size of the witness stack
+/// plus 50) and is capped at [`crate::ffi::BUDGET_MAX`]. The CMR of the program
+/// must match the script CMR of `env`. If `amr` is given, then the AMR of the
+/// program must match it as well.
+///
+/// This is the code path that Elements uses for consensus validation.
+/// On success, the IMR of the program is returned.
+///
+/// Transient errors (see [`SimplicityErr::is_permanent`]) mean that the
+/// validity of the program could not be determined.
+pub fn elements_exec_simplicity(
+    program: &[u8],
+    witness: &[u8],
+    env: &CElementsTxEnv,
+    budget: u32,
+    amr: Option<&[u8; 32]>,
+) -> Result<[u8; 32], SimplicityErr> {
+    let genesis_hash = Midstate::from(env.genesis_hash).to_byte_array();
+    // The error is read as an integer, since the C library may return codes
+    // that `SimplicityErr` does not know.
+    let mut error: c_int = 0;
+    let mut imr = [0u8; 32];
+
+    // SAFETY: All pointers are valid for the duration of the call and point to
+    // buffers of the required lengths. The environment was constructed by the C
+    // library, so its transaction and taproot pointers are non-null.
+    unsafe {
+        simplicity_elements_execSimplicity(
+            &mut error,
+            imr.as_mut_ptr(),
+            env.tx,
+            env.ix,
+            env.taproot,
+            genesis_hash.as_ptr(),
+            i64::from(budget),
+            amr.map(|amr| amr.as_ptr()).unwrap_or(std::ptr::null()),
+            program.as_ptr(),
+            program.len(),
+            witness.as_ptr(),
+            witness.len(),
+        );
+    }
+
+    SimplicityErr::from_i32(error).map(|_| imr)
+}
@@ -8,12 +8,14 @@
 
 pub mod c_env;
 pub mod c_frame;
+pub mod exec_ffi;
 pub mod frame_ffi;
 #[rustfmt::skip] pub mod jets_ffi;
 #[rustfmt::skip] pub mod jets_wrapper;
 
 pub use c_env::{CElementsTxEnv, CTapEnv, CTransaction};
 pub use c_frame::{byte_width, uword_width};
+pub use exec_ffi::elements_exec_simplicity;
 pub use frame_ffi::CFrameItem;
 
 // The bindings use elements_ffi instead of jets_ffi.
@@ -21,9 +23,6 @@ pub use jets_ffi as elements_ffi;
 
 use crate::c_jets::c_env::{CRawBuffer, CRawInput, CRawOutput, CRawTapEnv, CRawTransaction};
 
-#[cfg(feature = "test-utils")]
-pub mod exec_ffi;
-
 /// sanity checks for using the types.
 /// We are not using the internal representation of the types at all, but
 /// we do care about the size and alignments of the types.
@@ -14,6 +14,8 @@
 
 pub use core::ffi::c_void;
 
+use std::fmt;
+
 pub type c_uchar = u8;
 pub type c_int = i32;
 pub type c_uint = u32;
@@ -79,6 +81,112 @@ pub mod bounded {
     }
 }
 
+/// Simplicity error codes
+///
+/// If you update this list, please also update [`SimplicityErr::from_i32`].
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SimplicityErr {
+    NoError = 0,
+    Malloc = -1,
+    NotYetImplemented = -3,
+    DataOutOfRange = -2,
+    DataOutOfOrder = -4,
+    FailCode = -6,
+    StopCode = -8,
+    Hidden = -10,
+    BitstreamEof = -12,
+    BitstreamTrailingBytes = -14,
+    BitstreamIllegalPadding = -16,
+    TypeInferenceUnification = -18,
+    TypeInferenceOccursCheck = -20,
+    TypeInferenceNotProgram = -22,
+    WitnessEof = -24,
+    WitnessTrailingBytes = -26,
+    WitnessIllegalPadding = -28,
+    UnsharedSubexpression = -30,
+    Cmr = -32,
+    ExecBudget = -34,
+    ExecMemory = -36,
+    ExecJet = -38,
+    ExecAssert = -40,
+    AntiDoS = -42,
+    HiddenRoot = -44,
+    Amr = -46,
+    /// An error code that this library does not know, which is taken to be
+    /// transient since the validity of the program is unknown
+    Unknown = -255,
+}
+
+extern "C" {
+    pub static c_sizeof_simplicity_err: c_size_t;
+    pub static c_alignof_simplicity_err: c_size_t;
+}
+
+impl fmt::Display for SimplicityErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for SimplicityErr {}
+
+impl SimplicityErr {
+    /// Whether the error is permanent, i.e. the result of processing the program.
+    ///
+    /// By convention, odd error codes are transient failures (e.g. out of memory)
+    /// while even error codes are permanent failures (e.g. a parsing error).
+    /// `NoError` is considered permanent.
+    pub fn is_permanent(self) -> bool {
+        (self as i32) & 1 == 0
+    }
+
+    /// Converts the error code into a `Result` by splitting off the `NoError` case.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self == SimplicityErr::NoError {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Converts an `i32` result to either a positive value or an error code
+    ///
+    /// Negative values which are not error codes of this library, for example
+    /// those added by a newer version of Simplicity, become [`SimplicityErr::Unknown`].
+    pub fn from_i32(n: i32) -> Result<u32, Self> {
+        match n {
+            n if n >= 0 => Ok(n as u32),
+            -1 => Err(SimplicityErr::Malloc),
+            -2 => Err(SimplicityErr::DataOutOfRange),
+            -3 => Err(SimplicityErr::NotYetImplemented),
+            -4 => Err(SimplicityErr::DataOutOfOrder),
+            -6 => Err(SimplicityErr::FailCode),
+            -8 => Err(SimplicityErr::StopCode),
+            -10 => Err(SimplicityErr::Hidden),
+            -12 => Err(SimplicityErr::BitstreamEof),
+            -14 => Err(SimplicityErr::BitstreamTrailingBytes),
+            -16 => Err(SimplicityErr::BitstreamIllegalPadding),
+            -18 => Err(SimplicityErr::TypeInferenceUnification),
+            -20 => Err(SimplicityErr::TypeInferenceOccursCheck),
+            -22 => Err(SimplicityErr::TypeInferenceNotProgram),
+            -24 => Err(SimplicityErr::WitnessEof),
+            -26 => Err(SimplicityErr::WitnessTrailingBytes),
+            -28 => Err(SimplicityErr::WitnessIllegalPadding),
+            -30 => Err(SimplicityErr::UnsharedSubexpression),
+            -32 => Err(SimplicityErr::Cmr),
+            -34 => Err(SimplicityErr::ExecBudget),
+            -36 => Err(SimplicityErr::ExecMemory),
+            -38 => Err(SimplicityErr::ExecJet),
+            -40 => Err(SimplicityErr::ExecAssert),
+            -42 => Err(SimplicityErr::AntiDoS),
+            -44 => Err(SimplicityErr::HiddenRoot),
+            -46 => Err(SimplicityErr::Amr),
+            _ => Err(SimplicityErr::Unknown),
+        }
+    }
+}
+
 pub type UWORD = c_uint_fast16_t;
 
 extern "C" {
@@ -142,6 +250,7 @@ mod tests {
             assert_eq!(size_of::<c_uint_fast64_t>(), c_sizeof_uint_fast64_t);
             assert_eq!(size_of::<c_uint_least32_t>(), c_sizeof_uint_least32_t);
             assert_eq!(size_of::<ubounded>(), c_sizeof_ubounded);
+            assert_eq!(size_of::<SimplicityErr>(), c_sizeof_simplicity_err);
             assert_eq!(size_of::<UWORD>(), c_sizeof_UWORD);
             assert_eq!(size_of::<sha256::CSha256Midstate>(), sha256::c_sizeof_sha256_midstate);
         }
@@ -161,8 +270,27 @@ mod tests {
             assert_eq!(align_of::<c_uint_fast64_t>(), c_alignof_uint_fast64_t);
             assert_eq!(align_of::<c_uint_least32_t>(), c_alignof_uint_least32_t);
             assert_eq!(align_of::<ubounded>(), c_alignof_ubounded);
+            assert_eq!(align_of::<SimplicityErr>(), c_alignof_simplicity_err);
             assert_eq!(align_of::<UWORD>(), c_alignof_UWORD);
             assert_eq!(align_of::<sha256::CSha256Midstate>(), sha256::c_alignof_sha256_midstate);
         }
     }
+
+    #[test]
+    fn from_i32() {
+        assert_eq!(SimplicityErr::from_i32(5), Ok(5));
+        assert_eq!(SimplicityErr::from_i32(0), Ok(0));
+        assert_eq!(SimplicityErr::from_i32(-34), Err(SimplicityErr::ExecBudget));
+        assert_eq!(SimplicityErr::from_i32(-46), Err(SimplicityErr::Amr));
+        assert_eq!(SimplicityErr::from_i32(-48), Err(SimplicityErr::Unknown));
+        assert_eq!(
+            SimplicityErr::from_i32(i32::MIN),
+            Err(SimplicityErr::Unknown)
+        );
+        assert!(!SimplicityErr::Unknown.is_permanent());
+        // Error codes are read from C as integers.
+        unsafe {
+            assert_eq!(size_of::<c_int>(), c_sizeof_simplicity_err);
+        }
+    }
 }
@@ -12,102 +12,9 @@
 
 #![allow(non_camel_case_types)]
 
-use std::fmt;
-
+pub use crate::ffi::SimplicityErr;
 use crate::ffi::{c_int, c_size_t, c_uchar, c_void, ubounded};
 
-/// Simplicity error codes
-///
-/// If you update this list, please also update [`SimplicityErr::from_i32`].
-#[repr(C)]
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-pub enum SimplicityErr {
-    NoError = 0,
-    Malloc = -1,
-    NotYetImplemented = -3,
-    DataOutOfRange = -2,
-    DataOutOfOrder = -4,
-    FailCode = -6,
-    StopCode = -8,
-    Hidden = -10,
-    BitstreamEof = -12,
-    BitstreamTrailingBytes = -14,
-    BitstreamIllegalPadding = -16,
-    TypeInferenceUnification = -18,
-    TypeInferenceOccursCheck = -20,
-    TypeInferenceNotProgram = -22,
-    WitnessEof = -24,
-    WitnessTrailingBytes = -26,
-    WitnessIllegalPadding = -28,
-    UnsharedSubexpression = -30,
-    Cmr = -32,
-    ExecBudget = -34,
-    ExecMemory = -36,
-    ExecJet = -38,
-    ExecAssert = -40,
-    AntiDoS = -42,
-    HiddenRoot = -44,
-    Amr = -46,
-}
-
-extern "C" {
-    pub static c_sizeof_simplicity_err: c_size_t;
-    pub static c_alignof_simplicity_err: c_size_t;
-}
-
-impl fmt::Display for SimplicityErr {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
-    }
-}
-
-impl SimplicityErr {
-    /// Converts the error code into a `Result` by splitting off the `NoError` case.
-    pub fn into_result(self) -> Result<(), Self> {
-        if self == SimplicityErr::NoError {
-            Ok(())
-        } else {
-            Err(self)
-        }
-    }
-
-    /// Converts an `i32` result to either a positive value or an error code
-    ///
-    /// # Panics
-    /// Panics if the i32 result is not one of the error values returned from Simplicity.
-    pub fn from_i32(n: i32) -> Result<u32, Self> {
-        match n {
-            n if n >= 0 => Ok(n as u32),
-            -1 => Err(SimplicityErr::Malloc),
-            -2 => Err(SimplicityErr::DataOutOfRange),
-            -3 => Err(SimplicityErr::NotYetImplemented),
-            -4 => Err(SimplicityErr::DataOutOfOrder),
-            -6 => Err(SimplicityErr::FailCode),
-            -8 => Err(SimplicityErr::StopCode),
-            -10 => Err(SimplicityErr::Hidden),
-            -12 => Err(SimplicityErr::BitstreamEof),
-            -14 => Err(SimplicityErr::BitstreamTrailingBytes),
-            -16 => Err(SimplicityErr::BitstreamIllegalPadding),
-            -18 => Err(SimplicityErr::TypeInferenceUnification),
-            -20 => Err(SimplicityErr::TypeInferenceOccursCheck),
-            -22 => Err(SimplicityErr::TypeInferenceNotProgram),
-            -24 => Err(SimplicityErr::WitnessEof),
-            -26 => Err(SimplicityErr::WitnessTrailingBytes),
-            -28 => Err(SimplicityErr::WitnessIllegalPadding),
-            -30 => Err(SimplicityErr::UnsharedSubexpression),
-            -32 => Err(SimplicityErr::Cmr),
-            -34 => Err(SimplicityErr::ExecBudget),
-            -36 => Err(SimplicityErr::ExecMemory),
-            -38 => Err(SimplicityErr::ExecJet),
-            -40 => Err(SimplicityErr::ExecAssert),
-            -42 => Err(SimplicityErr::AntiDoS),
-            -44 => Err(SimplicityErr::HiddenRoot),
-            -46 => Err(SimplicityErr::Amr),
-            x => panic!("unexpected error code {}", x),
-        }
-    }
-}
-
 pub mod bitstream {
     use super::*;
     use crate::tests::ffi::bitstring::CBitstring;
@@ -531,7 +438,6 @@ mod tests {
     #[rustfmt::skip]
     fn test_sizes() {
         unsafe {
-            assert_eq!(size_of::<SimplicityErr>(), c_sizeof_simplicity_err);
             assert_eq!(size_of::<bitstream::CBitstream>(), bitstream::c_sizeof_bitstream);
             assert_eq!(size_of::<bitstring::CBitstring>(), bitstring::c_sizeof_bitstring);
             assert_eq!(size_of::<dag::CTag>(), dag::c_sizeof_tag);
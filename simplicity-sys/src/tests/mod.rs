@@ -27,6 +27,10 @@ pub struct TestOutput {
     pub cmr: CSha256Midstate,
     /// The IMR of the root node
     pub imr: CSha256Midstate,
+    /// The bound on the number of cells required to run the program
+    pub cell_bound: ubounded,
+    /// The bound on the number of frames required to run the program
+    pub frame_bound: ubounded,
     /// The cost bound of the program
     pub cost_bound: ubounded,
     /// Whether or not evaluation succeded
@@ -119,6 +123,8 @@ pub fn run_program(
         amr: CSha256Midstate::default(),
         cmr: CSha256Midstate::default(),
         imr: CSha256Midstate::default(),
+        cell_bound: 0,
+        frame_bound: 0,
         cost_bound: 0,
         eval_result: SimplicityErr::NoError,
     };
@@ -194,6 +200,8 @@ pub fn run_program(
             len,
         )
         .into_result()?;
+        result.cell_bound = cell_bound;
+        result.frame_bound = frame_bound;
         result.cost_bound = cost_bound;
         if test_up_to <= TestUpTo::ComputeCostUnbounded {
             return Ok(result);
@@ -1,6 +1,13 @@
 // SPDX-License-Identifier: CC0-1.0
 
+use crate::dag::{DagLike, InternalSharing};
+#[cfg(feature = "elements")]
+use crate::jet::Elements;
 use crate::jet::Jet;
+use crate::node::Inner;
+use crate::RedeemNode;
+#[cfg(feature = "elements")]
+use simplicity_sys::ffi::SimplicityErr;
 use std::{cmp, fmt};
 
 use crate::value::Word;
@@ -104,6 +111,49 @@ impl Cost {
         Cost(milliweight)
     }
 
+    /// Return the cost in milli weight units.
+    pub const fn to_milliweight(self) -> u32 {
+        self.0
+    }
+
+    /// Return the cost of executing the given node once, not counting the cost
+    /// of executing its children.
+    ///
+    /// Summing this over every node visited by
+    /// [`crate::bit_machine::ExecTracker::visit_node`] gives the cost of a
+    /// particular execution, which may be less than the bound
+    /// [`NodeBounds::cost`] if some branches were not taken.
+    pub fn of_node<J: Jet>(node: &RedeemNode<J>) -> Self {
+        // The bounds of a node with children that cost nothing
+        let free = NodeBounds::NEVER_EXECUTED;
+        let arrow = node.arrow();
+        let bounds = match node.inner() {
+            Inner::Iden => NodeBounds::iden(arrow.source.bit_width()),
+            Inner::Unit => NodeBounds::unit(),
+            Inner::InjL(_)
+            | Inner::InjR(_)
+            | Inner::Take(_)
+            | Inner::Drop(_)
+            | Inner::AssertL(..)
+            | Inner::AssertR(..) => NodeBounds::from_child(free),
+            Inner::Comp(left, _) => NodeBounds::comp(free, free, left.arrow().target.bit_width()),
+            Inner::Case(..) => NodeBounds::case(free, free),
+            Inner::Pair(..) => NodeBounds::pair(free, free),
+            Inner::Disconnect(left, right) => NodeBounds::disconnect(
+                free,
+                free,
+                left.arrow().target.bit_width() - right.arrow().source.bit_width(),
+                left.arrow().source.bit_width(),
+                left.arrow().target.bit_width(),
+            ),
+            Inner::Witness(_) => NodeBounds::witness(arrow.target.bit_width()),
+            Inner::Fail(_) => NodeBounds::fail(),
+            Inner::Jet(jet) => NodeBounds::jet(*jet),
+            Inner::Word(word) => NodeBounds::const_word(word),
+        };
+        bounds.cost
+    }
+
     /// Return whether the cost is allowed by consensus.
     ///
     /// This means the cost is within the maximum budget
@@ -382,6 +432,218 @@ impl NodeBounds {
 /// Number of frames required for the input and output of a Simplicity expression
 pub(crate) const IO_EXTRA_FRAMES: usize = 2;
 
+/// Bounds on the resources required to execute an entire program,
+/// including its input and output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ProgramBounds {
+    /// Upper bound on the required number of cells (bits).
+    pub cells: usize,
+    /// Upper bound on the required number of frames.
+    pub frames: usize,
+    /// CPU cost
+    pub cost: Cost,
+}
+
+impl ProgramBounds {
+    /// Compute the bounds of a program using the Rust static analysis.
+    ///
+    /// The cell and frame bounds are those of a Bit Machine with tail call
+    /// optimization, which is how the C library executes programs, so they
+    /// are smaller than the ones of [`RedeemNode::bounds`].
+    pub fn from_rust<J: Jet>(program: &RedeemNode<J>) -> Self {
+        let tco = TcoBounds::of(program);
+        ProgramBounds {
+            cells: program.arrow().source.bit_width()
+                + program.arrow().target.bit_width()
+                + cmp::max(tco.extra_cells[0], tco.extra_cells[1]),
+            frames: tco.extra_frames[0] + IO_EXTRA_FRAMES,
+            cost: program.bounds().cost,
+        }
+    }
+
+    /// Compute the bounds of a program using the C static analysis.
+    ///
+    /// The program is encoded and then decoded, typechecked and analysed
+    /// by the C library. Returns an error if the C library rejects it.
+    #[cfg(feature = "elements")]
+    pub fn from_c(program: &RedeemNode<Elements>) -> Result<Self, SimplicityErr> {
+        use simplicity_sys::tests::{run_program, TestUpTo};
+
+        let (prog_bytes, wit_bytes) = program.encode_to_vec();
+        let output = run_program(&prog_bytes, &wit_bytes, TestUpTo::ComputeCostUnbounded)?;
+        Ok(ProgramBounds {
+            cells: output.cell_bound as usize,
+            frames: output.frame_bound as usize,
+            cost: Cost::from_milliweight(output.cost_bound),
+        })
+    }
+
+    /// Compare the bounds computed by Rust (`self`) with those computed by C.
+    ///
+    /// Every field must agree exactly, since consensus depends on both
+    /// implementations accepting and rejecting the same programs.
+    pub fn mismatches(&self, c: &Self) -> Vec<BoundsMismatch> {
+        let mut ret = vec![];
+        if self.cells != c.cells {
+            ret.push(BoundsMismatch::Cells {
+                rust: self.cells,
+                c: c.cells,
+            });
+        }
+        if self.frames != c.frames {
+            ret.push(BoundsMismatch::Frames {
+                rust: self.frames,
+                c: c.frames,
+            });
+        }
+        if self.cost != c.cost {
+            ret.push(BoundsMismatch::Cost {
+                rust: self.cost,
+                c: c.cost,
+            });
+        }
+        ret
+    }
+}
+
+/// Cell and frame bounds of a node on a Bit Machine with tail call optimization
+///
+/// The first entry of each bound is for tail call optimization off, and the
+/// second entry is for tail call optimization on. This is a port of
+/// `simplicity_analyseBounds` in C `eval.c`.
+#[derive(Debug, Copy, Clone, Default)]
+struct TcoBounds {
+    extra_cells: [usize; 2],
+    extra_frames: [usize; 2],
+}
+
+impl TcoBounds {
+    fn of<J: Jet>(program: &RedeemNode<J>) -> Self {
+        let mut bounds: Vec<Self> = vec![];
+        for data in program.post_order_iter::<InternalSharing>() {
+            let left = data.left_index.map(|i| bounds[i]).unwrap_or_default();
+            let right = data.right_index.map(|i| bounds[i]).unwrap_or_default();
+            let node_bounds = match data.node.inner() {
+                Inner::Iden
+                | Inner::Unit
+                | Inner::Witness(_)
+                | Inner::Fail(_)
+                | Inner::Jet(_)
+                | Inner::Word(_) => TcoBounds::default(),
+                Inner::InjL(_)
+                | Inner::InjR(_)
+                | Inner::Take(_)
+                | Inner::Drop(_)
+                | Inner::AssertL(..)
+                | Inner::AssertR(..) => left,
+                Inner::Case(..) => TcoBounds {
+                    extra_cells: [
+                        cmp::max(left.extra_cells[0], right.extra_cells[0]),
+                        cmp::max(left.extra_cells[1], right.extra_cells[1]),
+                    ],
+                    extra_frames: [
+                        cmp::max(left.extra_frames[0], right.extra_frames[0]),
+                        cmp::max(left.extra_frames[1], right.extra_frames[1]),
+                    ],
+                },
+                Inner::Comp(left_node, _) => {
+                    let b = left_node.arrow().target.bit_width();
+                    TcoBounds {
+                        extra_cells: [
+                            cmp::max(
+                                b.saturating_add(cmp::max(
+                                    left.extra_cells[0],
+                                    right.extra_cells[1],
+                                )),
+                                right.extra_cells[0],
+                            ),
+                            b.saturating_add(left.extra_cells[1]),
+                        ],
+                        extra_frames: [
+                            cmp::max(left.extra_frames[0], right.extra_frames[1]) + 1,
+                            cmp::max(left.extra_frames[1] + 1, right.extra_frames[1]),
+                        ],
+                    }
+                }
+                Inner::Pair(..) => TcoBounds {
+                    extra_cells: [
+                        right.extra_cells[0],
+                        cmp::max(
+                            left.extra_cells[0],
+                            cmp::max(left.extra_cells[1], right.extra_cells[1]),
+                        ),
+                    ],
+                    extra_frames: [
+                        cmp::max(left.extra_frames[0], right.extra_frames[0]),
+                        cmp::max(left.extra_frames[0], right.extra_frames[1]),
+                    ],
+                },
+                Inner::Disconnect(left_node, _) => {
+                    let w256a = left_node.arrow().source.bit_width();
+                    let bc = left_node.arrow().target.bit_width();
+                    let frames = cmp::max(left.extra_frames[1] + 1, right.extra_frames[1]);
+                    TcoBounds {
+                        extra_cells: [
+                            cmp::max(
+                                bc.saturating_add(cmp::max(
+                                    w256a.saturating_add(left.extra_cells[1]),
+                                    cmp::max(left.extra_cells[0], right.extra_cells[1]),
+                                )),
+                                right.extra_cells[0],
+                            ),
+                            w256a,
+                        ],
+                        extra_frames: [frames + 1, frames],
+                    }
+                }
+            };
+            bounds.push(node_bounds);
+        }
+        bounds.pop().expect("program has a root")
+    }
+}
+
+/// A disagreement between the Rust and C static analyses of a program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BoundsMismatch {
+    /// The cell bounds differ.
+    Cells { rust: usize, c: usize },
+    /// The frame bounds differ.
+    Frames { rust: usize, c: usize },
+    /// The costs differ.
+    Cost { rust: Cost, c: Cost },
+}
+
+impl fmt::Display for BoundsMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundsMismatch::Cells { rust, c } => {
+                write!(f, "cell bound {} differs from C cell bound {}", rust, c)
+            }
+            BoundsMismatch::Frames { rust, c } => {
+                write!(f, "frame bound {} differs from C frame bound {}", rust, c)
+            }
+            BoundsMismatch::Cost { rust, c } => {
+                write!(f, "cost {} differs from C cost {}", rust, c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BoundsMismatch {}
+
+/// Compute the bounds of a program with both the Rust and the C static analyses
+/// and return every field on which they disagree.
+///
+/// Returns an error if the C library rejects the program.
+#[cfg(feature = "elements")]
+pub fn compare_c_bounds(
+    program: &RedeemNode<Elements>,
+) -> Result<Vec<BoundsMismatch>, SimplicityErr> {
+    let c = ProgramBounds::from_c(program)?;
+    Ok(ProgramBounds::from_rust(program).mismatches(&c))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -393,6 +655,42 @@ mod tests {
         assert_eq!(Cost::OVERHEAD.0, cost_overhead());
     }
 
+    #[test]
+    fn cost_of_nodes() {
+        use crate::human_encoding::Forest;
+        use crate::jet::Core;
+        use crate::BitMachine;
+
+        struct CostTracker(Cost);
+        impl<J: Jet> crate::bit_machine::ExecTracker<J> for CostTracker {
+            fn visit_node(&mut self, node: &RedeemNode<J>) {
+                self.0 = self.0 + Cost::of_node(node);
+            }
+        }
+
+        let s = "
+            not := comp (pair iden unit) (case (injr unit) (comp (injl unit) iden)) : 2 -> 2
+            main := comp (comp (injl unit) not) (comp (pair iden unit) (case unit unit))
+        ";
+        let forest = Forest::<Core>::parse(s).unwrap();
+        let unpruned = forest.roots()["main"]
+            .to_construct_node(&Default::default(), forest.roots())
+            .finalize_unpruned()
+            .unwrap();
+        let pruned = forest.to_redeem_node(&()).unwrap().unwrap();
+
+        for program in [&unpruned, &pruned] {
+            let mut tracker = CostTracker(Cost::NEVER_EXECUTED);
+            let mut mac = BitMachine::for_program(program).unwrap();
+            mac.exec_with_tracker(program, &(), &mut tracker).unwrap();
+            // Each `case` executes one branch only, so only the bound of the
+            // pruned program is the cost of executing it.
+            assert_eq!(tracker.0, pruned.bounds().cost);
+            assert!(tracker.0 <= program.bounds().cost);
+        }
+        assert!(pruned.bounds().cost < unpruned.bounds().cost);
+    }
+
     #[test]
     #[cfg(feature = "bitcoin")]
     fn cost_to_weight() {
@@ -457,4 +755,105 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    #[cfg(feature = "elements")]
+    fn c_bounds_test_vectors() {
+        use crate::BitIter;
+
+        for test in [
+            simplicity_sys::tests::schnorr0_test_data(),
+            simplicity_sys::tests::schnorr6_test_data(),
+            simplicity_sys::tests::ctx8_pruned_test_data(),
+            simplicity_sys::tests::ctx8_unpruned_test_data(),
+        ] {
+            let prog = BitIter::from(test.prog.as_slice());
+            let witness = BitIter::from(test.witness.as_slice());
+            let program = RedeemNode::<Elements>::decode(prog, witness).unwrap();
+            assert_eq!(compare_c_bounds(&program), Ok(vec![]));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "elements")]
+    fn c_bounds_human() {
+        use crate::human_encoding::Forest;
+        use crate::Value;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+
+        let witness = HashMap::from([
+            (Arc::from("wit1"), Value::u32(1337)),
+            (Arc::from("wit2"), Value::u32(1337)),
+        ]);
+        for s in [
+            "main := iden : 1 -> 1",
+            "
+                wit1 := witness : 1 -> 2^32
+                wit2 := witness : 1 -> 2^32
+                wits_are_equal := comp (pair wit1 wit2) jet_eq_32 : 1 -> 2
+                main := comp wits_are_equal jet_verify : 1 -> 1
+            ",
+            "
+                id1 := iden : 2^256 * 1 -> 2^256 * 1
+                main := comp (disconnect id1 ?hole) unit
+                hole := unit
+            ",
+            "
+                wit1 := witness : 1 -> 2^32
+                input := pair (pair wit1 unit) unit : 1 -> (2^32 * 1) * 1
+                dup := pair iden iden : 2^32 -> 2^32 * 2^32
+                check := comp dup comp jet_eq_32 jet_verify : 2^32 -> 1
+                main := comp input take take check : 1 -> 1
+            ",
+        ] {
+            let program = Forest::<Elements>::parse(s)
+                .expect("Failed to parse human encoding")
+                .to_witness_node(&witness)
+                .expect("Forest is missing expected root")
+                .finalize_unpruned()
+                .expect("Failed to finalize");
+            assert_eq!(compare_c_bounds(&program), Ok(vec![]), "{}", s);
+        }
+    }
+
+    #[test]
+    fn bounds_mismatches() {
+        let rust = ProgramBounds {
+            cells: 10,
+            frames: 4,
+            cost: Cost::from_milliweight(500),
+        };
+        assert_eq!(rust.mismatches(&rust), vec![]);
+
+        let c = ProgramBounds {
+            cells: 8,
+            frames: 3,
+            ..rust
+        };
+        assert_eq!(
+            rust.mismatches(&c),
+            vec![
+                BoundsMismatch::Cells { rust: 10, c: 8 },
+                BoundsMismatch::Frames { rust: 4, c: 3 },
+            ]
+        );
+
+        let c = ProgramBounds {
+            cells: 11,
+            frames: 5,
+            cost: Cost::from_milliweight(400),
+        };
+        assert_eq!(
+            rust.mismatches(&c),
+            vec![
+                BoundsMismatch::Cells { rust: 10, c: 11 },
+                BoundsMismatch::Frames { rust: 4, c: 5 },
+                BoundsMismatch::Cost {
+                    rust: Cost::from_milliweight(500),
+                    c: Cost::from_milliweight(400),
+                },
+            ]
+        );
+    }
 }
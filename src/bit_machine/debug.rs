@@ -0,0 +1,362 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Bit Machine Debugger
+//!
+//! Executes a program one node at a time, so that the frames of the Bit Machine
+//! can be inspected between nodes.
+//!
+//! The debugger is always stopped just before executing some node, which is
+//! its *current* node. Each node which the current node was reached through,
+//! from the root of the program down, is one of its *ancestors*: stepping over
+//! a node runs it to completion, including every node it executes, and
+//! stepping out of it does the same for its parent.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::frame::Frame;
+use super::{BitMachine, CallStack, ExecutionError, NoTracker};
+use crate::jet::Jet;
+use crate::node::RedeemNode;
+use crate::{BitIter, Cmr, Value};
+
+/// The reason that the debugger stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested step was completed.
+    Step,
+    /// A node with a breakpoint was reached before the step was completed.
+    Breakpoint,
+    /// The program finished executing, with the given output.
+    Finished(Value),
+}
+
+/// The contents of a frame of the Bit Machine, along with its cursor
+///
+/// Displayed as a bit string with a `|` at the position of the cursor.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FrameView {
+    bits: Vec<bool>,
+    cursor: usize,
+}
+
+impl FrameView {
+    fn new(frame: &Frame, data: &[u8]) -> Self {
+        FrameView {
+            bits: frame.as_bit_iter(data).take(frame.bit_width()).collect(),
+            cursor: frame.cursor(),
+        }
+    }
+
+    /// The bits of the frame.
+    pub fn bits(&self) -> &[bool] {
+        &self.bits
+    }
+
+    /// The position of the cursor, from the start of the frame.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+}
+
+impl fmt::Display for FrameView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, bit) in self.bits.iter().enumerate() {
+            if idx == self.cursor {
+                f.write_str("|")?;
+            }
+            f.write_str(if *bit { "1" } else { "0" })?;
+        }
+        if self.cursor >= self.bits.len() {
+            f.write_str("|")?;
+        }
+        Ok(())
+    }
+}
+
+/// A program being executed one node at a time
+pub struct Debugger<'a, J: Jet> {
+    mac: BitMachine,
+    program: &'a RedeemNode<J>,
+    env: &'a J::Environment,
+    call_stack: Vec<CallStack<'a, J>>,
+    /// The current node and its ancestors, from the root down, along with the
+    /// length of the call stack when each was reached
+    ///
+    /// Every instruction which a node pushes onto the call stack lies above
+    /// the length at which it was reached, so the nodes reached at or above
+    /// that length are exactly the nodes it executes.
+    path: Vec<(&'a RedeemNode<J>, usize)>,
+    breakpoints: HashSet<Cmr>,
+    /// The result of the program, once it has finished or failed
+    outcome: Option<Result<Value, ExecutionError>>,
+}
+
+impl<'a, J: Jet> Debugger<'a, J> {
+    /// Prepare to execute `program` on the given input, stopping before its
+    /// root node.
+    pub fn new(
+        program: &'a RedeemNode<J>,
+        env: &'a J::Environment,
+        input: &Value,
+    ) -> Result<Self, ExecutionError> {
+        let mut mac = BitMachine::for_program(program)?;
+        mac.input(input)?;
+        mac.start(program)?;
+        Ok(Debugger {
+            mac,
+            program,
+            env,
+            call_stack: vec![],
+            path: vec![(program, 0)],
+            breakpoints: HashSet::new(),
+            outcome: None,
+        })
+    }
+
+    /// The node which will be executed next.
+    ///
+    /// If execution failed, this is the node which failed. Once the program
+    /// has finished, there is no current node.
+    pub fn current(&self) -> Option<&'a RedeemNode<J>> {
+        self.path.last().map(|&(node, _)| node)
+    }
+
+    /// The ancestors of the current node, from the root of the program down,
+    /// followed by the current node itself.
+    pub fn path(&self) -> impl Iterator<Item = &'a RedeemNode<J>> + '_ {
+        self.path.iter().map(|&(node, _)| node)
+    }
+
+    /// The read frame stack, with the active frame last.
+    pub fn read_frames(&self) -> Vec<FrameView> {
+        self.mac
+            .read
+            .iter()
+            .map(|frame| FrameView::new(frame, &self.mac.data))
+            .collect()
+    }
+
+    /// The write frame stack, with the active frame last.
+    pub fn write_frames(&self) -> Vec<FrameView> {
+        self.mac
+            .write
+            .iter()
+            .map(|frame| FrameView::new(frame, &self.mac.data))
+            .collect()
+    }
+
+    /// The input of the current node, decoded from the active read frame
+    /// starting at its cursor.
+    ///
+    /// Returns `None` if there is no current node, or if the active read frame
+    /// does not hold enough bits after its cursor.
+    pub fn input_value(&self) -> Option<Value> {
+        let ty = &self.current()?.arrow().source;
+        if ty.bit_width() == 0 {
+            return Some(Value::unit());
+        }
+        let frame = self.mac.read.last()?;
+        let start = frame.start() + frame.cursor();
+        let end = start + ty.bit_width();
+        if end > frame.start() + frame.bit_width() {
+            return None;
+        }
+        let mut bits = BitIter::byte_slice_window(&self.mac.data, start, end);
+        Value::from_padded_bits(&mut bits, ty).ok()
+    }
+
+    /// The result of the program, once it has finished or failed.
+    pub fn outcome(&self) -> Option<&Result<Value, ExecutionError>> {
+        self.outcome.as_ref()
+    }
+
+    /// Stop before executing any node with the given CMR.
+    ///
+    /// Returns whether the breakpoint is new.
+    pub fn add_breakpoint(&mut self, cmr: Cmr) -> bool {
+        self.breakpoints.insert(cmr)
+    }
+
+    /// Remove the breakpoint at the given CMR.
+    ///
+    /// Returns whether there was such a breakpoint.
+    pub fn remove_breakpoint(&mut self, cmr: Cmr) -> bool {
+        self.breakpoints.remove(&cmr)
+    }
+
+    /// The CMRs of every node with a breakpoint, in no particular order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = Cmr> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Execute the current node, stopping before the next node executed,
+    /// which may be a child of the current node.
+    pub fn step_into(&mut self) -> Result<Stop, ExecutionError> {
+        self.run(|_| true)
+    }
+
+    /// Execute the current node along with every node that it executes,
+    /// stopping before the next node.
+    pub fn step_over(&mut self) -> Result<Stop, ExecutionError> {
+        let len = self.path.last().map_or(0, |&(_, len)| len);
+        self.run(|next_len| next_len < len)
+    }
+
+    /// Finish executing the parent of the current node, stopping before the
+    /// next node, or run the program to completion at the root.
+    pub fn step_out(&mut self) -> Result<Stop, ExecutionError> {
+        match self.path.len().checked_sub(2).map(|idx| self.path[idx].1) {
+            Some(len) => self.run(|next_len| next_len < len),
+            None => self.resume(),
+        }
+    }
+
+    /// Run the program until a breakpoint is reached or it finishes.
+    pub fn resume(&mut self) -> Result<Stop, ExecutionError> {
+        self.run(|_| false)
+    }
+
+    /// Execute nodes until the next node is reached with a call stack length
+    /// for which `done` holds, or has a breakpoint.
+    fn run<F: Fn(usize) -> bool>(&mut self, done: F) -> Result<Stop, ExecutionError> {
+        if let Some(outcome) = &self.outcome {
+            return outcome.clone().map(Stop::Finished);
+        }
+
+        loop {
+            let (node, _) = *self
+                .path
+                .last()
+                .expect("unfinished program has a current node");
+            if let Err(e) = self
+                .mac
+                .exec_node(node, self.env, &mut self.call_stack, &mut NoTracker)
+            {
+                self.outcome = Some(Err(e.clone()));
+                return Err(e);
+            }
+
+            let next = match self.mac.next_node(&mut self.call_stack) {
+                Some(next) => next,
+                None => {
+                    self.path.clear();
+                    let output = self.mac.output(self.program);
+                    self.outcome = Some(Ok(output.clone()));
+                    return Ok(Stop::Finished(output));
+                }
+            };
+            let len = self.call_stack.len();
+            while self
+                .path
+                .last()
+                .is_some_and(|&(_, ancestor)| ancestor > len)
+            {
+                self.path.pop();
+            }
+            self.path.push((next, len));
+
+            if done(len) {
+                return Ok(Stop::Step);
+            }
+            if self.breakpoints.contains(&next.cmr()) {
+                return Ok(Stop::Breakpoint);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::{DagLike, MaxSharing};
+    use crate::human_encoding::Forest;
+    use crate::jet::Core;
+    use std::collections::HashMap;
+
+    fn program(s: &str) -> (Forest<Core>, std::sync::Arc<RedeemNode<Core>>) {
+        let forest = Forest::<Core>::parse(s).expect("parsing program");
+        let program = forest
+            .to_witness_node(&HashMap::new())
+            .expect("program has main")
+            .finalize_unpruned()
+            .expect("finalizing program");
+        (forest, program)
+    }
+
+    const NOT: &str = "
+        not := comp (pair iden unit) (case (injr unit) (injl unit)) : 2 -> 2
+        bit := injr unit : 1 -> 2
+        main := comp (comp bit not) unit
+    ";
+
+    #[test]
+    fn step() {
+        let (_, program) = program(NOT);
+        let mut debugger = Debugger::new(&program, &(), &Value::unit()).unwrap();
+        assert_eq!(debugger.current().unwrap().cmr(), program.cmr());
+        assert_eq!(debugger.input_value(), Some(Value::unit()));
+
+        // main -> comp bit not -> bit
+        assert_eq!(debugger.step_into().unwrap(), Stop::Step);
+        assert_eq!(debugger.step_into().unwrap(), Stop::Step);
+        assert_eq!(debugger.path().count(), 3);
+        assert_eq!(debugger.current().unwrap().arrow().target.bit_width(), 1);
+        // bit -> unit inside it, then on to `not`, reading the bit it wrote
+        assert_eq!(debugger.step_into().unwrap(), Stop::Step);
+        assert_eq!(debugger.step_into().unwrap(), Stop::Step);
+        assert_eq!(debugger.path().count(), 3);
+        assert_eq!(debugger.input_value(), Some(Value::u1(1)));
+        let read = debugger.read_frames();
+        assert_eq!(read.last().unwrap().to_string(), "|1");
+
+        // Over `not`, which leaves its output in the frame for `unit`
+        assert_eq!(debugger.step_over().unwrap(), Stop::Step);
+        assert_eq!(debugger.path().count(), 2);
+        assert_eq!(debugger.input_value(), Some(Value::u1(0)));
+        assert_eq!(debugger.step_over().unwrap(), Stop::Finished(Value::unit()));
+        assert!(debugger.current().is_none());
+        assert_eq!(debugger.step_into().unwrap(), Stop::Finished(Value::unit()));
+    }
+
+    #[test]
+    fn step_out() {
+        let (_, program) = program(NOT);
+        let mut debugger = Debugger::new(&program, &(), &Value::unit()).unwrap();
+        debugger.step_into().unwrap();
+        debugger.step_into().unwrap();
+        // Out of `comp bit not`, to the `unit` which follows it
+        assert_eq!(debugger.step_out().unwrap(), Stop::Step);
+        assert_eq!(debugger.path().count(), 2);
+        assert_eq!(debugger.current().unwrap().arrow().source.bit_width(), 1);
+        assert_eq!(debugger.step_out().unwrap(), Stop::Finished(Value::unit()));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (forest, program) = program(NOT);
+        let not = forest.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .find(|data| data.node.name().as_ref() == "not")
+            .unwrap()
+            .node
+            .cmr();
+
+        let mut debugger = Debugger::new(&program, &(), &Value::unit()).unwrap();
+        assert!(debugger.add_breakpoint(not));
+        assert!(!debugger.add_breakpoint(not));
+        assert_eq!(debugger.resume().unwrap(), Stop::Breakpoint);
+        assert_eq!(debugger.current().unwrap().cmr(), not);
+        let write = debugger.write_frames();
+        assert_eq!(write.len(), 1);
+        assert_eq!(write[0].to_string(), "|0");
+
+        // Stepping over a node stops early at a breakpoint inside it
+        let mut debugger = Debugger::new(&program, &(), &Value::unit()).unwrap();
+        debugger.add_breakpoint(not);
+        assert_eq!(debugger.step_over().unwrap(), Stop::Breakpoint);
+        assert!(debugger.remove_breakpoint(not));
+        assert_eq!(debugger.resume().unwrap(), Stop::Finished(Value::unit()));
+    }
+}
@@ -43,6 +43,11 @@ impl Frame {
         self.len
     }
 
+    /// Return the position of the cursor relative to the start of the frame.
+    pub fn cursor(&self) -> usize {
+        self.cursor - self.start
+    }
+
     /// Reset the cursor to the start.
     pub(super) fn reset_cursor(&mut self) {
         self.cursor = self.start;
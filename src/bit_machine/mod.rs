@@ -6,6 +6,7 @@
 //! frame management optimizations which can be used to great benefit.
 //!
 
+mod debug;
 mod frame;
 mod limits;
 
@@ -21,6 +22,7 @@ use crate::{analysis, Imr};
 use crate::{Cmr, FailEntropy, Value};
 use frame::Frame;
 
+pub use self::debug::{Debugger, FrameView, Stop};
 pub use self::limits::LimitError;
 
 /// An execution context for a Simplicity program
@@ -222,12 +224,14 @@ impl BitMachine {
         self.exec_with_tracker(program, env, &mut NoTracker)
     }
 
-    /// Execute the given `program` on the Bit Machine and track executed case branches.
+    /// Execute the given `program` on the Bit Machine and track executed nodes
+    /// and case branches.
     ///
-    /// If the program runs successfully, then two sets of IMRs are returned:
+    /// If the program runs successfully, then three sets of IMRs are returned:
     ///
     /// 1) The IMRs of case nodes whose _left_ branch was executed.
     /// 2) The IMRs of case nodes whose _right_ branch was executed.
+    /// 3) The IMRs of all nodes which were executed.
     ///
     /// ## Precondition
     ///
@@ -242,169 +246,182 @@ impl BitMachine {
         Ok(tracker)
     }
 
-    fn exec_with_tracker<J: Jet, T: CaseTracker>(
+    /// Execute the given `program` on the Bit Machine, reporting each step of
+    /// the execution to the given tracker.
+    ///
+    /// If execution fails, the last node passed to [`ExecTracker::visit_node`]
+    /// is the node which failed.
+    ///
+    /// ## Precondition
+    ///
+    /// The Bit Machine is constructed via [`Self::for_program()`] to ensure enough space.
+    pub fn exec_with_tracker<J: Jet, T: ExecTracker<J>>(
         &mut self,
         program: &RedeemNode<J>,
         env: &J::Environment,
         tracker: &mut T,
     ) -> Result<Value, ExecutionError> {
-        enum CallStack<'a, J: Jet> {
-            Goto(&'a RedeemNode<J>),
-            MoveFrame,
-            DropFrame,
-            CopyFwd(usize),
-            Back(usize),
-        }
-
-        // Not used, but useful for debugging, so keep it around
-        impl<J: Jet> fmt::Debug for CallStack<'_, J> {
-            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                match self {
-                    CallStack::Goto(ins) => write!(f, "goto {}", ins.inner()),
-                    CallStack::MoveFrame => f.write_str("move frame"),
-                    CallStack::DropFrame => f.write_str("drop frame"),
-                    CallStack::CopyFwd(n) => write!(f, "copy/fwd {}", n),
-                    CallStack::Back(n) => write!(f, "back {}", n),
-                }
-            }
+        self.start(program)?;
+        let mut call_stack = vec![];
+        let mut ip = Some(program);
+        while let Some(node) = ip {
+            tracker.visit_node(node);
+            self.exec_node(node, env, &mut call_stack, tracker)?;
+            ip = self.next_node(&mut call_stack);
         }
+        Ok(self.output(program))
+    }
 
+    /// Check the input of `program` and push the frame for its output.
+    fn start<J: Jet>(&mut self, program: &RedeemNode<J>) -> Result<(), ExecutionError> {
         if self.read.is_empty() != self.source_ty.is_empty() {
             return Err(ExecutionError::InputWrongType(self.source_ty.clone()));
         }
 
-        let mut ip = program;
-        let mut call_stack = vec![];
-
-        let output_width = ip.arrow().target.bit_width();
+        let output_width = program.arrow().target.bit_width();
         if output_width > 0 {
             self.new_frame(output_width);
         }
+        Ok(())
+    }
 
-        'main_loop: loop {
-            match ip.inner() {
-                node::Inner::Unit => {}
-                node::Inner::Iden => {
-                    let size_a = ip.arrow().source.bit_width();
-                    self.copy(size_a);
-                }
-                node::Inner::InjL(left) => {
-                    let (b, c) = ip.arrow().target.as_sum().unwrap();
-                    self.write_bit(false);
-                    self.skip(b.pad_left(c));
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::InjR(left) => {
-                    let (b, c) = ip.arrow().target.as_sum().unwrap();
-                    self.write_bit(true);
-                    self.skip(b.pad_right(c));
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::Pair(left, right) => {
-                    call_stack.push(CallStack::Goto(right));
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::Comp(left, right) => {
-                    let size_b = left.arrow().target.bit_width();
-
-                    self.new_frame(size_b);
-                    call_stack.push(CallStack::DropFrame);
-                    call_stack.push(CallStack::Goto(right));
-                    call_stack.push(CallStack::MoveFrame);
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::Disconnect(left, right) => {
-                    let size_prod_256_a = left.arrow().source.bit_width();
-                    let size_a = size_prod_256_a - 256;
-                    let size_prod_b_c = left.arrow().target.bit_width();
-                    let size_b = size_prod_b_c - right.arrow().source.bit_width();
-
-                    self.new_frame(size_prod_256_a);
-                    self.write_bytes(right.cmr().as_ref());
-                    self.copy(size_a);
-                    self.move_frame();
-                    self.new_frame(size_prod_b_c);
-
-                    // Remember that call stack pushes are executed in reverse order
-                    call_stack.push(CallStack::DropFrame);
-                    call_stack.push(CallStack::DropFrame);
-                    call_stack.push(CallStack::Goto(right));
-                    call_stack.push(CallStack::CopyFwd(size_b));
-                    call_stack.push(CallStack::MoveFrame);
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::Take(left) => call_stack.push(CallStack::Goto(left)),
-                node::Inner::Drop(left) => {
-                    let size_a = ip.arrow().source.as_product().unwrap().0.bit_width();
-                    self.fwd(size_a);
-                    call_stack.push(CallStack::Back(size_a));
-                    call_stack.push(CallStack::Goto(left));
-                }
-                node::Inner::Case(..) | node::Inner::AssertL(..) | node::Inner::AssertR(..) => {
-                    let choice_bit = self.read[self.read.len() - 1].peek_bit(&self.data);
-
-                    let (sum_a_b, _c) = ip.arrow().source.as_product().unwrap();
-                    let (a, b) = sum_a_b.as_sum().unwrap();
-
-                    match (ip.inner(), choice_bit) {
-                        (node::Inner::Case(_, right), true)
-                        | (node::Inner::AssertR(_, right), true) => {
-                            self.fwd(1 + a.pad_right(b));
-                            call_stack.push(CallStack::Back(1 + a.pad_right(b)));
-                            call_stack.push(CallStack::Goto(right));
-                            tracker.track_right(ip.imr());
-                        }
-                        (node::Inner::Case(left, _), false)
-                        | (node::Inner::AssertL(left, _), false) => {
-                            self.fwd(1 + a.pad_left(b));
-                            call_stack.push(CallStack::Back(1 + a.pad_left(b)));
-                            call_stack.push(CallStack::Goto(left));
-                            tracker.track_left(ip.imr());
-                        }
-                        (node::Inner::AssertL(_, r_cmr), true) => {
-                            return Err(ExecutionError::ReachedPrunedBranch(*r_cmr))
-                        }
-                        (node::Inner::AssertR(l_cmr, _), false) => {
-                            return Err(ExecutionError::ReachedPrunedBranch(*l_cmr))
-                        }
-                        _ => unreachable!(),
+    /// Execute the given node, pushing the instructions which complete its
+    /// execution onto the call stack.
+    fn exec_node<'a, J: Jet, T: ExecTracker<J>>(
+        &mut self,
+        ip: &'a RedeemNode<J>,
+        env: &J::Environment,
+        call_stack: &mut Vec<CallStack<'a, J>>,
+        tracker: &mut T,
+    ) -> Result<(), ExecutionError> {
+        match ip.inner() {
+            node::Inner::Unit => {}
+            node::Inner::Iden => {
+                let size_a = ip.arrow().source.bit_width();
+                self.copy(size_a);
+            }
+            node::Inner::InjL(left) => {
+                let (b, c) = ip.arrow().target.as_sum().unwrap();
+                self.write_bit(false);
+                self.skip(b.pad_left(c));
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::InjR(left) => {
+                let (b, c) = ip.arrow().target.as_sum().unwrap();
+                self.write_bit(true);
+                self.skip(b.pad_right(c));
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::Pair(left, right) => {
+                call_stack.push(CallStack::Goto(right));
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::Comp(left, right) => {
+                let size_b = left.arrow().target.bit_width();
+
+                self.new_frame(size_b);
+                call_stack.push(CallStack::DropFrame);
+                call_stack.push(CallStack::Goto(right));
+                call_stack.push(CallStack::MoveFrame);
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::Disconnect(left, right) => {
+                let size_prod_256_a = left.arrow().source.bit_width();
+                let size_a = size_prod_256_a - 256;
+                let size_prod_b_c = left.arrow().target.bit_width();
+                let size_b = size_prod_b_c - right.arrow().source.bit_width();
+
+                self.new_frame(size_prod_256_a);
+                self.write_bytes(right.cmr().as_ref());
+                self.copy(size_a);
+                self.move_frame();
+                self.new_frame(size_prod_b_c);
+
+                // Remember that call stack pushes are executed in reverse order
+                call_stack.push(CallStack::DropFrame);
+                call_stack.push(CallStack::DropFrame);
+                call_stack.push(CallStack::Goto(right));
+                call_stack.push(CallStack::CopyFwd(size_b));
+                call_stack.push(CallStack::MoveFrame);
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::Take(left) => call_stack.push(CallStack::Goto(left)),
+            node::Inner::Drop(left) => {
+                let size_a = ip.arrow().source.as_product().unwrap().0.bit_width();
+                self.fwd(size_a);
+                call_stack.push(CallStack::Back(size_a));
+                call_stack.push(CallStack::Goto(left));
+            }
+            node::Inner::Case(..) | node::Inner::AssertL(..) | node::Inner::AssertR(..) => {
+                let choice_bit = self.read[self.read.len() - 1].peek_bit(&self.data);
+
+                let (sum_a_b, _c) = ip.arrow().source.as_product().unwrap();
+                let (a, b) = sum_a_b.as_sum().unwrap();
+
+                match (ip.inner(), choice_bit) {
+                    (node::Inner::Case(_, right), true)
+                    | (node::Inner::AssertR(_, right), true) => {
+                        self.fwd(1 + a.pad_right(b));
+                        call_stack.push(CallStack::Back(1 + a.pad_right(b)));
+                        call_stack.push(CallStack::Goto(right));
+                        tracker.track_right(ip.imr());
                     }
-                }
-                node::Inner::Witness(value) => self.write_value(value),
-                node::Inner::Jet(jet) => self.exec_jet(*jet, env)?,
-                node::Inner::Word(value) => self.write_value(value.as_value()),
-                node::Inner::Fail(entropy) => {
-                    return Err(ExecutionError::ReachedFailNode(*entropy))
+                    (node::Inner::Case(left, _), false)
+                    | (node::Inner::AssertL(left, _), false) => {
+                        self.fwd(1 + a.pad_left(b));
+                        call_stack.push(CallStack::Back(1 + a.pad_left(b)));
+                        call_stack.push(CallStack::Goto(left));
+                        tracker.track_left(ip.imr());
+                    }
+                    (node::Inner::AssertL(_, r_cmr), true) => {
+                        return Err(ExecutionError::ReachedPrunedBranch(*r_cmr))
+                    }
+                    (node::Inner::AssertR(l_cmr, _), false) => {
+                        return Err(ExecutionError::ReachedPrunedBranch(*l_cmr))
+                    }
+                    _ => unreachable!(),
                 }
             }
+            node::Inner::Witness(value) => self.write_value(value),
+            node::Inner::Jet(jet) => self.exec_jet(*jet, env)?,
+            node::Inner::Word(value) => self.write_value(value.as_value()),
+            node::Inner::Fail(entropy) => return Err(ExecutionError::ReachedFailNode(*entropy)),
+        }
+        Ok(())
+    }
 
-            ip = loop {
-                match call_stack.pop() {
-                    Some(CallStack::Goto(next)) => break next,
-                    Some(CallStack::MoveFrame) => self.move_frame(),
-                    Some(CallStack::DropFrame) => self.drop_frame(),
-                    Some(CallStack::CopyFwd(n)) => {
-                        self.copy(n);
-                        self.fwd(n);
-                    }
-                    Some(CallStack::Back(n)) => self.back(n),
-                    None => break 'main_loop,
-                };
-            };
+    /// Carry out the instructions on the call stack up to the next node to
+    /// execute, returning `None` once the call stack is empty.
+    fn next_node<'a, J: Jet>(
+        &mut self,
+        call_stack: &mut Vec<CallStack<'a, J>>,
+    ) -> Option<&'a RedeemNode<J>> {
+        loop {
+            match call_stack.pop()? {
+                CallStack::Goto(next) => return Some(next),
+                CallStack::MoveFrame => self.move_frame(),
+                CallStack::DropFrame => self.drop_frame(),
+                CallStack::CopyFwd(n) => {
+                    self.copy(n);
+                    self.fwd(n);
+                }
+                CallStack::Back(n) => self.back(n),
+            }
         }
+    }
 
-        if output_width > 0 {
+    /// Read the output of `program` from the output frame.
+    fn output<J: Jet>(&mut self, program: &RedeemNode<J>) -> Value {
+        if program.arrow().target.bit_width() > 0 {
             let out_frame = self.write.last_mut().unwrap();
             out_frame.reset_cursor();
-            let value = Value::from_padded_bits(
+            Value::from_padded_bits(
                 &mut out_frame.as_bit_iter(&self.data),
                 &program.arrow().target,
             )
-            .expect("Decode value of output frame");
-
-            Ok(value)
+            .expect("Decode value of output frame")
         } else {
-            Ok(Value::unit())
+            Value::unit()
         }
     }
 
@@ -510,27 +527,51 @@ impl BitMachine {
     }
 }
 
-/// A type that keeps track of which case branches were executed
-/// during the execution of the Bit Machine.
-///
-/// The trait is implemented for [`SetTracker`], which does the actual tracking,
-/// and it is implemented for [`NoTracker`], which is a dummy tracker that is
-/// optimized out by the compiler.
+/// An instruction on the call stack of the Bit Machine
+enum CallStack<'a, J: Jet> {
+    Goto(&'a RedeemNode<J>),
+    MoveFrame,
+    DropFrame,
+    CopyFwd(usize),
+    Back(usize),
+}
+
+// Not used, but useful for debugging, so keep it around
+impl<J: Jet> fmt::Debug for CallStack<'_, J> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallStack::Goto(ins) => write!(f, "goto {}", ins.inner()),
+            CallStack::MoveFrame => f.write_str("move frame"),
+            CallStack::DropFrame => f.write_str("drop frame"),
+            CallStack::CopyFwd(n) => write!(f, "copy/fwd {}", n),
+            CallStack::Back(n) => write!(f, "back {}", n),
+        }
+    }
+}
+
+/// A tracker of the execution of a program on the Bit Machine, such as which
+/// nodes were executed and which case branches were taken.
 ///
-/// The trait enables us to turn tracking on or off depending on a generic parameter.
-trait CaseTracker {
+/// Every method does nothing by default, so that implementations need only
+/// provide the ones they are interested in.
+pub trait ExecTracker<J: Jet> {
+    /// Track the execution of a node, just before it is executed.
+    fn visit_node(&mut self, _node: &RedeemNode<J>) {}
+
     /// Track the execution of the left branch of the case node with the given `imr`.
-    fn track_left(&mut self, imr: Imr);
+    fn track_left(&mut self, _imr: Imr) {}
 
     /// Track the execution of the right branch of the case node with the given `imr`.
-    fn track_right(&mut self, imr: Imr);
+    fn track_right(&mut self, _imr: Imr) {}
 }
 
-/// Tracker of executed left and right branches for each case node.
+/// Tracker of executed nodes and of executed left and right branches for each
+/// case node.
 #[derive(Clone, Debug, Default)]
 pub(crate) struct SetTracker {
     left: HashSet<Imr>,
     right: HashSet<Imr>,
+    executed: HashSet<Imr>,
 }
 
 impl SetTracker {
@@ -543,12 +584,21 @@ impl SetTracker {
     pub fn right(&self) -> &HashSet<Imr> {
         &self.right
     }
+
+    /// Access the set of IMRs of nodes which were executed.
+    pub fn executed(&self) -> &HashSet<Imr> {
+        &self.executed
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 struct NoTracker;
 
-impl CaseTracker for SetTracker {
+impl<J: Jet> ExecTracker<J> for SetTracker {
+    fn visit_node(&mut self, node: &RedeemNode<J>) {
+        self.executed.insert(node.imr());
+    }
+
     fn track_left(&mut self, imr: Imr) {
         self.left.insert(imr);
     }
@@ -558,14 +608,10 @@ impl CaseTracker for SetTracker {
     }
 }
 
-impl CaseTracker for NoTracker {
-    fn track_left(&mut self, _: Imr) {}
-
-    fn track_right(&mut self, _: Imr) {}
-}
+impl<J: Jet> ExecTracker<J> for NoTracker {}
 
 /// Errors related to simplicity Execution
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ExecutionError {
     /// Provided input is of wrong type
     InputWrongType(Arc<Final>),
@@ -0,0 +1,635 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Consensus Validation
+//!
+//! Validation of transaction inputs that spend a Simplicity leaf of a
+//! Taproot output, following the same steps as the Elements interpreter.
+//!
+//! The witness stack of such an input consists of the Simplicity witness
+//! data, the Simplicity program, the leaf script (which is the 32-byte CMR
+//! of the program) and the control block, optionally followed by an annex.
+//!
+
+use std::fmt;
+use std::sync::Arc;
+
+use elements::schnorr::TapTweak;
+use elements::secp256k1_zkp::{self, XOnlyPublicKey};
+use elements::taproot::{ControlBlock, LeafVersion, TaprootError};
+
+use crate::analysis::Cost;
+use crate::bit_machine::{ExecutionError, SetTracker};
+use crate::dag::{DagLike, InternalSharing};
+use crate::jet::elements::{ElementsEnv, ElementsUtxo};
+use crate::jet::Elements;
+use crate::node::{Inner, RedeemNode};
+use crate::{BitIter, BitMachine, Cmr, Imr};
+use simplicity_sys::ffi::SimplicityErr;
+
+/// First byte of the annex, as defined in BIP 341.
+const ANNEX_TAG: u8 = 0x50;
+
+/// Reason why a Simplicity input failed validation.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// The input index is not an input of the transaction
+    InputIndexOutOfRange {
+        /// The requested input index.
+        ix: usize,
+        /// The number of inputs of the transaction.
+        n_inputs: usize,
+    },
+    /// The number of spent UTXOs does not match the number of inputs
+    UtxoCountMismatch {
+        /// The number of given UTXOs.
+        n_utxos: usize,
+        /// The number of inputs of the transaction.
+        n_inputs: usize,
+    },
+    /// The witness stack (without annex) does not consist of exactly four elements
+    WrongWitnessStackLength(usize),
+    /// The control block could not be parsed
+    ControlBlock(TaprootError),
+    /// The control block has a leaf version other than Simplicity's
+    WrongLeafVersion(LeafVersion),
+    /// The spent output is not a version 1 witness program
+    NotTaproot,
+    /// The spent output has an invalid output key
+    InvalidOutputKey(secp256k1_zkp::UpstreamError),
+    /// The leaf script is not a 32-byte CMR
+    WrongScriptLength(usize),
+    /// The control block does not commit to the leaf script
+    TaprootCommitment,
+    /// The program or its witness data could not be decoded
+    Decode(crate::Error),
+    /// The CMR of the decoded program does not match the leaf script
+    CmrMismatch {
+        /// The CMR committed to in the leaf script.
+        expected: Cmr,
+        /// The CMR of the decoded program.
+        got: Cmr,
+    },
+    /// The cost of the program exceeds the budget of the witness stack
+    BudgetExceeded(Cost),
+    /// The program failed during execution
+    Execution(ExecutionError),
+    /// The program has a node or a case branch which was never executed
+    Unpruned(Imr),
+    /// The C implementation rejected the program
+    CExecution(SimplicityErr),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InputIndexOutOfRange { ix, n_inputs } => write!(
+                f,
+                "input index {} out of range (transaction has {} inputs)",
+                ix, n_inputs
+            ),
+            Error::UtxoCountMismatch { n_utxos, n_inputs } => write!(
+                f,
+                "got {} spent UTXOs for a transaction with {} inputs",
+                n_utxos, n_inputs
+            ),
+            Error::WrongWitnessStackLength(n) => write!(
+                f,
+                "witness stack has {} elements (excluding annex); expected 4",
+                n
+            ),
+            Error::ControlBlock(ref e) => write!(f, "invalid control block: {}", e),
+            Error::WrongLeafVersion(ver) => write!(
+                f,
+                "leaf version {:#04x} is not the Simplicity leaf version",
+                ver.as_u8()
+            ),
+            Error::NotTaproot => f.write_str("spent output is not a taproot output"),
+            Error::InvalidOutputKey(ref e) => write!(f, "invalid taproot output key: {}", e),
+            Error::WrongScriptLength(n) => {
+                write!(f, "leaf script has {} bytes; expected a 32-byte CMR", n)
+            }
+            Error::TaprootCommitment => {
+                f.write_str("control block does not commit to the leaf script")
+            }
+            Error::Decode(ref e) => fmt::Display::fmt(e, f),
+            Error::CmrMismatch { expected, got } => write!(
+                f,
+                "program has CMR {} but leaf script commits to {}",
+                got, expected
+            ),
+            Error::BudgetExceeded(cost) => write!(
+                f,
+                "program cost {} exceeds the budget of the witness stack",
+                cost
+            ),
+            Error::Execution(ref e) => fmt::Display::fmt(e, f),
+            Error::Unpruned(imr) => write!(
+                f,
+                "program is not pruned: node with IMR {} is not fully executed",
+                imr
+            ),
+            Error::CExecution(ref e) => write!(f, "C implementation rejected program: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::InputIndexOutOfRange { .. }
+            | Error::UtxoCountMismatch { .. }
+            | Error::WrongWitnessStackLength(..)
+            | Error::WrongLeafVersion(..)
+            | Error::NotTaproot
+            | Error::WrongScriptLength(..)
+            | Error::TaprootCommitment
+            | Error::CmrMismatch { .. }
+            | Error::BudgetExceeded(..)
+            | Error::Unpruned(..) => None,
+            Error::ControlBlock(ref e) => Some(e),
+            Error::InvalidOutputKey(ref e) => Some(e),
+            Error::Decode(ref e) => Some(e),
+            Error::Execution(ref e) => Some(e),
+            Error::CExecution(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<ExecutionError> for Error {
+    fn from(e: ExecutionError) -> Error {
+        Error::Execution(e)
+    }
+}
+
+/// The parts of the witness stack of an input that spends a Simplicity leaf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimplicityWitness<'a> {
+    /// Encoded witness data of the program
+    pub witness: &'a [u8],
+    /// Encoded program
+    pub program: &'a [u8],
+    /// Leaf script, which should be the CMR of the program
+    pub script: &'a [u8],
+    /// Encoded control block
+    pub control_block: &'a [u8],
+    /// Optional annex
+    pub annex: Option<&'a [u8]>,
+}
+
+impl<'a> SimplicityWitness<'a> {
+    /// Split the witness stack of a script-path spend into its parts.
+    ///
+    /// If the stack has at least two elements and its last element starts
+    /// with `0x50`, then that element is the annex (BIP 341).
+    pub fn from_stack(stack: &'a [Vec<u8>]) -> Result<Self, Error> {
+        let (stack, annex) = match stack.split_last() {
+            Some((last, rest)) if !rest.is_empty() && last.first() == Some(&ANNEX_TAG) => {
+                (rest, Some(&last[..]))
+            }
+            _ => (stack, None),
+        };
+
+        match stack {
+            [witness, program, script, control_block] => Ok(SimplicityWitness {
+                witness,
+                program,
+                script,
+                control_block,
+                annex,
+            }),
+            _ => Err(Error::WrongWitnessStackLength(stack.len())),
+        }
+    }
+}
+
+/// The parts of a Simplicity input that passed the Taproot checks.
+struct TaprootSpend<'a> {
+    parts: SimplicityWitness<'a>,
+    control_block: ControlBlock,
+    script_cmr: Cmr,
+}
+
+/// Check that the `ix`th input of `tx` spends a Simplicity leaf that is
+/// committed to by the spent output.
+fn check_taproot<'a>(
+    tx: &'a elements::Transaction,
+    utxos: &[ElementsUtxo],
+    ix: usize,
+) -> Result<TaprootSpend<'a>, Error> {
+    let n_inputs = tx.input.len();
+    if ix >= n_inputs {
+        return Err(Error::InputIndexOutOfRange { ix, n_inputs });
+    }
+    if utxos.len() != n_inputs {
+        return Err(Error::UtxoCountMismatch {
+            n_utxos: utxos.len(),
+            n_inputs,
+        });
+    }
+
+    let parts = SimplicityWitness::from_stack(&tx.input[ix].witness.script_witness)?;
+    let control_block =
+        ControlBlock::from_slice(parts.control_block).map_err(Error::ControlBlock)?;
+    if control_block.leaf_version != crate::leaf_version() {
+        return Err(Error::WrongLeafVersion(control_block.leaf_version));
+    }
+    let spk = &utxos[ix].script_pubkey;
+    if !spk.is_v1_p2tr() {
+        return Err(Error::NotTaproot);
+    }
+    let output_key = XOnlyPublicKey::from_slice(&spk.as_bytes()[2..])
+        .map(|key| key.dangerous_assume_tweaked())
+        .map_err(Error::InvalidOutputKey)?;
+    let script = elements::Script::from(parts.script.to_vec());
+    let secp = secp256k1_zkp::Secp256k1::verification_only();
+    if !control_block.verify_taproot_commitment(&secp, &output_key, &script) {
+        return Err(Error::TaprootCommitment);
+    }
+    let script_cmr = <[u8; 32]>::try_from(parts.script)
+        .map(Cmr::from_byte_array)
+        .map_err(|_| Error::WrongScriptLength(parts.script.len()))?;
+
+    Ok(TaprootSpend {
+        parts,
+        control_block,
+        script_cmr,
+    })
+}
+
+/// Verify that the `ix`th input of `tx` is a valid spend of a Simplicity leaf.
+///
+/// `utxos` are the outputs spent by the transaction, in the order of its inputs.
+///
+/// The check parses the witness stack of the input, verifies the Taproot
+/// commitment of the control block to the leaf script, decodes the program
+/// and its witness data, checks that the CMR matches the leaf script and
+/// that the cost of the program is within the budget of the witness stack,
+/// and finally executes the program on the Bit Machine. As in consensus, the
+/// program must be pruned: every node and both branches of every case node
+/// must have been executed.
+pub fn verify_input(
+    tx: Arc<elements::Transaction>,
+    utxos: &[ElementsUtxo],
+    ix: usize,
+    genesis_hash: elements::BlockHash,
+) -> Result<(), Error> {
+    let spend = check_taproot(&tx, utxos, ix)?;
+    let script_witness = &tx.input[ix].witness.script_witness;
+
+    // 1. Decode the program and check its CMR
+    let program = RedeemNode::<Elements>::decode(
+        BitIter::from(spend.parts.program),
+        BitIter::from(spend.parts.witness),
+    )
+    .map_err(Error::Decode)?;
+    if program.cmr() != spend.script_cmr {
+        return Err(Error::CmrMismatch {
+            expected: spend.script_cmr,
+            got: program.cmr(),
+        });
+    }
+
+    // 2. Check the budget
+    let cost = program.bounds().cost;
+    if !cost.is_budget_valid(script_witness) {
+        return Err(Error::BudgetExceeded(cost));
+    }
+
+    // 3. Execute the program
+    let env = ElementsEnv::new(
+        Arc::clone(&tx),
+        utxos.to_vec(),
+        ix as u32, // cast safety: Elements transactions have fewer than 2^32 inputs
+        spend.script_cmr,
+        spend.control_block,
+        spend.parts.annex.map(<[u8]>::to_vec),
+        genesis_hash,
+    );
+    let mut mac = BitMachine::for_program(&program).map_err(ExecutionError::from)?;
+    let tracker = mac.exec_prune(&program, &env)?;
+
+    // 4. Check that the program is pruned
+    check_pruned(&program, &tracker)
+}
+
+/// Check that every node of `program` and both branches of each of its case
+/// nodes were executed, like the anti-DoS checks of the C implementation.
+fn check_pruned(program: &RedeemNode<Elements>, tracker: &SetTracker) -> Result<(), Error> {
+    for data in program.post_order_iter::<InternalSharing>() {
+        let imr = data.node.imr();
+        let executed = tracker.executed().contains(&imr)
+            && match data.node.inner() {
+                Inner::Case(..) => tracker.left().contains(&imr) && tracker.right().contains(&imr),
+                _ => true,
+            };
+        if !executed {
+            return Err(Error::Unpruned(imr));
+        }
+    }
+    Ok(())
+}
+
+/// Verify that the `ix`th input of `tx` is a valid spend of a Simplicity leaf,
+/// using the C implementation of Simplicity for decoding and evaluation.
+///
+/// This performs the same Taproot checks as [`verify_input`], but then runs
+/// the program through [`simplicity_sys::c_jets::elements_exec_simplicity`],
+/// which is the code path that Elements uses for consensus. This is useful
+/// to compare the verdicts of the Rust and the C implementation.
+pub fn verify_input_c(
+    tx: Arc<elements::Transaction>,
+    utxos: &[ElementsUtxo],
+    ix: usize,
+    genesis_hash: elements::BlockHash,
+) -> Result<(), Error> {
+    let spend = check_taproot(&tx, utxos, ix)?;
+    let script_witness = &tx.input[ix].witness.script_witness;
+    let budget = u32::try_from(elements::encode::serialize(script_witness).len())
+        .unwrap_or(u32::MAX)
+        .saturating_add(50);
+
+    let env = ElementsEnv::new(
+        Arc::clone(&tx),
+        utxos.to_vec(),
+        ix as u32, // cast safety: Elements transactions have fewer than 2^32 inputs
+        spend.script_cmr,
+        spend.control_block,
+        spend.parts.annex.map(<[u8]>::to_vec),
+        genesis_hash,
+    );
+    simplicity_sys::c_jets::elements_exec_simplicity(
+        spend.parts.program,
+        spend.parts.witness,
+        env.c_tx_env(),
+        budget.min(simplicity_sys::ffi::BUDGET_MAX),
+        None,
+    )
+    .map_err(Error::CExecution)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{CoreConstructible, JetConstructible};
+    use crate::types;
+    use crate::ConstructNode;
+    use elements::taproot::TaprootBuilder;
+    use elements::{confidential, AssetIssuance, OutPoint, Sequence, TxIn, TxInWitness};
+    use hashes::Hash;
+
+    type Program = Arc<RedeemNode<Elements>>;
+
+    /// Build a transaction that spends a Taproot output with a single Simplicity leaf
+    /// using the given program.
+    fn spend(program: &Program) -> (elements::Transaction, Vec<ElementsUtxo>) {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let keypair = secp256k1_zkp::Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let internal_key = keypair.x_only_public_key().0;
+
+        let script = elements::Script::from(program.cmr().as_ref().to_vec());
+        let script_ver = (script, crate::leaf_version());
+        let spend_info = TaprootBuilder::new()
+            .add_leaf_with_ver(0, script_ver.0.clone(), script_ver.1)
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let control_block = spend_info.control_block(&script_ver).unwrap();
+
+        let (program_bytes, witness_bytes) = program.encode_to_vec();
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: elements::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                is_pegin: false,
+                script_sig: elements::Script::new(),
+                sequence: Sequence::MAX,
+                asset_issuance: AssetIssuance::default(),
+                witness: TxInWitness {
+                    script_witness: vec![
+                        witness_bytes,
+                        program_bytes,
+                        script_ver.0.to_bytes(),
+                        control_block.serialize(),
+                    ],
+                    ..TxInWitness::default()
+                },
+            }],
+            output: vec![],
+        };
+        let utxo = ElementsUtxo {
+            script_pubkey: elements::Script::new_v1_p2tr_tweaked(spend_info.output_key()),
+            asset: confidential::Asset::Null,
+            value: confidential::Value::Null,
+        };
+
+        (tx, vec![utxo])
+    }
+
+    fn unit_program() -> Program {
+        Arc::<ConstructNode<Elements>>::unit(&types::Context::new())
+            .finalize_unpruned()
+            .unwrap()
+    }
+
+    /// Verify the first input with both the Rust and the C implementation
+    /// and check that they agree.
+    fn verify(tx: elements::Transaction, utxos: &[ElementsUtxo]) -> Result<(), Error> {
+        let tx = Arc::new(tx);
+        let genesis_hash = elements::BlockHash::all_zeros();
+        let rust_res = verify_input(Arc::clone(&tx), utxos, 0, genesis_hash);
+        let c_res = verify_input_c(tx, utxos, 0, genesis_hash);
+        assert_eq!(
+            rust_res.is_ok(),
+            c_res.is_ok(),
+            "Rust result {:?} does not match C result {:?}",
+            rust_res,
+            c_res,
+        );
+        rust_res
+    }
+
+    #[test]
+    fn valid_spend() {
+        let (tx, utxos) = spend(&unit_program());
+        verify(tx, &utxos).expect("valid spend");
+    }
+
+    #[test]
+    fn valid_spend_with_annex() {
+        let (mut tx, utxos) = spend(&unit_program());
+        tx.input[0]
+            .witness
+            .script_witness
+            .push(vec![ANNEX_TAG, 0x00]);
+        verify(tx, &utxos).expect("valid spend");
+    }
+
+    #[test]
+    fn bad_index_and_utxos() {
+        let (tx, utxos) = spend(&unit_program());
+        match verify_input(
+            Arc::new(tx.clone()),
+            &utxos,
+            1,
+            elements::BlockHash::all_zeros(),
+        ) {
+            Err(Error::InputIndexOutOfRange { ix: 1, n_inputs: 1 }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match verify(tx, &[]) {
+            Err(Error::UtxoCountMismatch {
+                n_utxos: 0,
+                n_inputs: 1,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn bad_witness_stack() {
+        let (mut tx, utxos) = spend(&unit_program());
+        tx.input[0].witness.script_witness.remove(0);
+        match verify(tx, &utxos) {
+            Err(Error::WrongWitnessStackLength(3)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn bad_commitment() {
+        let (tx, mut utxos) = spend(&unit_program());
+        let other_program = Arc::<ConstructNode<Elements>>::iden(&types::Context::new())
+            .finalize_unpruned()
+            .unwrap();
+        utxos[0] = spend(&other_program).1.remove(0);
+        match verify(tx, &utxos) {
+            Err(Error::TaprootCommitment) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn cmr_mismatch() {
+        let (mut tx, utxos) = spend(&unit_program());
+        let iden = Arc::<ConstructNode<Elements>>::iden(&types::Context::new())
+            .finalize_unpruned()
+            .unwrap();
+        let (program_bytes, witness_bytes) = iden.encode_to_vec();
+        tx.input[0].witness.script_witness[0] = witness_bytes;
+        tx.input[0].witness.script_witness[1] = program_bytes;
+        match verify(tx, &utxos) {
+            Err(Error::CmrMismatch { got, .. }) if got == iden.cmr() => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn failed_execution() {
+        let ctx = types::Context::new();
+        let program = Arc::<ConstructNode<Elements>>::comp(
+            &Arc::<ConstructNode<Elements>>::const_word(&ctx, crate::Word::u1(0)),
+            &Arc::<ConstructNode<Elements>>::jet(&ctx, Elements::Verify),
+        )
+        .unwrap()
+        .finalize_unpruned()
+        .unwrap();
+        let (tx, utxos) = spend(&program);
+        match verify_input_c(
+            Arc::new(tx.clone()),
+            &utxos,
+            0,
+            elements::BlockHash::all_zeros(),
+        ) {
+            Err(Error::CExecution(SimplicityErr::ExecJet)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match verify(tx, &utxos) {
+            Err(Error::Execution(ExecutionError::JetFailed(..))) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unpruned_case() {
+        // The right branch of the case node is never executed.
+        let ctx = types::Context::new();
+        let unit = || Arc::<ConstructNode<Elements>>::unit(&ctx);
+        let program = Arc::<ConstructNode<Elements>>::comp(
+            &Arc::<ConstructNode<Elements>>::pair(
+                &Arc::<ConstructNode<Elements>>::injl(&unit()),
+                &unit(),
+            )
+            .unwrap(),
+            &Arc::<ConstructNode<Elements>>::case(&unit(), &unit()).unwrap(),
+        )
+        .unwrap()
+        .finalize_unpruned()
+        .unwrap();
+        let (tx, utxos) = spend(&program);
+        match verify_input_c(
+            Arc::new(tx.clone()),
+            &utxos,
+            0,
+            elements::BlockHash::all_zeros(),
+        ) {
+            Err(Error::CExecution(SimplicityErr::AntiDoS)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match verify(tx, &utxos) {
+            Err(Error::Unpruned(imr)) if imr == program.right_child().unwrap().imr() => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Once pruned, the program is valid.
+        let pruned = program.prune(&ElementsEnv::dummy()).unwrap();
+        let (tx, utxos) = spend(&pruned);
+        verify(tx, &utxos).expect("valid spend");
+    }
+
+    /// Run the first input of a spend of `program` through the C evaluator
+    /// directly, with the given budget and AMR.
+    fn exec_c(
+        program: &Program,
+        budget: u32,
+        amr: Option<&[u8; 32]>,
+    ) -> Result<[u8; 32], SimplicityErr> {
+        let (tx, utxos) = spend(program);
+        let (program_bytes, witness_bytes) = program.encode_to_vec();
+        let control_block =
+            ControlBlock::from_slice(&tx.input[0].witness.script_witness[3]).unwrap();
+        let env = ElementsEnv::new(
+            Arc::new(tx),
+            utxos,
+            0,
+            program.cmr(),
+            control_block,
+            None,
+            elements::BlockHash::all_zeros(),
+        );
+        simplicity_sys::c_jets::elements_exec_simplicity(
+            &program_bytes,
+            &witness_bytes,
+            env.c_tx_env(),
+            budget,
+            amr,
+        )
+    }
+
+    #[test]
+    fn exec_simplicity() {
+        let program = unit_program();
+        let imr = program.imr().to_byte_array();
+        let amr = program.amr().to_byte_array();
+
+        assert_eq!(exec_c(&program, 100, None), Ok(imr));
+        assert_eq!(exec_c(&program, 100, Some(&amr)), Ok(imr));
+        assert_eq!(
+            exec_c(&program, 100, Some(&[0; 32])),
+            Err(SimplicityErr::Amr)
+        );
+        assert_eq!(exec_c(&program, 0, None), Err(SimplicityErr::ExecBudget));
+    }
+}
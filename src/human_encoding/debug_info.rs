@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Debug Information
+//!
+//! The bit encoding of a program keeps only its structure, so a decoded
+//! program is given made-up names. Debug information records the names,
+//! source locations and comments of the nodes of a program, so that they can
+//! be restored when it is decoded. It is kept in a file alongside the encoded
+//! program, in a simple line-based text format.
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{error, fmt};
+
+use crate::human_encoding::{Position, SourceLocation};
+use crate::{Cmr, Imr};
+
+/// The first line of every debug information file.
+const HEADER: &str = "simplicity-debug-info 1";
+
+/// The debug information of a single node
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeInfo {
+    /// The CMR of the node.
+    pub cmr: Cmr,
+    /// The IMR of the node, if it was known when the node was named.
+    pub imr: Option<Imr>,
+    /// The name of the node.
+    pub name: Arc<str>,
+    /// The location of the node in the source, if it was parsed from source.
+    pub source: Option<SourceLocation>,
+}
+
+/// The names, source locations and comments of the nodes of a program
+///
+/// Nodes are identified by IMR where it is known, and otherwise by CMR. A
+/// [`crate::human_encoding::Forest`] produces debug information with
+/// [`crate::human_encoding::Forest::debug_info`], and takes it back with
+/// [`crate::human_encoding::Forest::from_program_with_debug_info`].
+///
+/// Debug information is displayed in a line-based text format, which is parsed
+/// by its [`FromStr`] implementation. After a header line, each line has a kind
+/// followed by fields, all separated by tabs:
+///
+/// * `node`, the CMR, the IMR or `-`, and the name of a node, optionally
+///   followed by the `file:line:column` position of the node, the name of the
+///   definition that contains it and, if the node was imported, the name of its
+///   file;
+/// * `comment`, a name and the text of a comment describing it.
+///
+/// Backslashes, tabs and newlines within fields are escaped as `\\`, `\t` and `\n`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    nodes: Vec<NodeInfo>,
+    comments: BTreeMap<Arc<str>, Vec<Arc<str>>>,
+    /// Index into `nodes` of the first node with each IMR
+    by_imr: HashMap<Imr, usize>,
+    /// Index into `nodes` of the first node with each CMR
+    by_cmr: HashMap<Cmr, usize>,
+}
+
+impl DebugInfo {
+    /// Constructs new, empty debug information.
+    pub fn new() -> Self {
+        DebugInfo::default()
+    }
+
+    /// The debug information of every node, in the order it was added.
+    pub fn nodes(&self) -> &[NodeInfo] {
+        &self.nodes
+    }
+
+    /// The comments describing the given name, in order.
+    pub fn comments(&self, name: &str) -> &[Arc<str>] {
+        self.comments.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The debug information of the node with the given CMR and IMR.
+    ///
+    /// Nodes are looked up by IMR if it is given, falling back to looking up by
+    /// CMR. If several nodes match, the first one added is returned.
+    pub fn lookup(&self, cmr: Cmr, imr: Option<Imr>) -> Option<&NodeInfo> {
+        imr.and_then(|imr| self.by_imr.get(&imr))
+            .or_else(|| self.by_cmr.get(&cmr))
+            .map(|&idx| &self.nodes[idx])
+    }
+
+    /// Adds the debug information of a node.
+    pub fn add_node(&mut self, node: NodeInfo) {
+        let idx = self.nodes.len();
+        if let Some(imr) = node.imr {
+            self.by_imr.entry(imr).or_insert(idx);
+        }
+        self.by_cmr.entry(node.cmr).or_insert(idx);
+        self.nodes.push(node);
+    }
+
+    /// Adds a comment describing the given name, after any others.
+    pub fn add_comment(&mut self, name: Arc<str>, text: Arc<str>) {
+        self.comments.entry(name).or_default().push(text);
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for node in &self.nodes {
+            write!(f, "node\t{}\t", node.cmr)?;
+            match node.imr {
+                Some(imr) => write!(f, "{}", imr)?,
+                None => f.write_str("-")?,
+            }
+            write!(f, "\t{}", escape(&node.name))?;
+            if let Some(ref source) = node.source {
+                let pos = source.position;
+                write!(
+                    f,
+                    "\t{}:{}:{}\t{}",
+                    pos.file,
+                    pos.line,
+                    pos.column,
+                    escape(&source.definition),
+                )?;
+                if let Some(ref file) = source.file {
+                    write!(f, "\t{}", escape(file))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        for (name, texts) in &self.comments {
+            for text in texts {
+                writeln!(f, "comment\t{}\t{}", escape(name), escape(text))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DebugInfo {
+    type Err = ParseDebugInfoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(ParseDebugInfoError::BadHeader);
+        }
+
+        let mut info = DebugInfo::new();
+        for (idx, line) in lines.enumerate() {
+            // Line numbers count from 1, and the header is line 1.
+            let bad_line = ParseDebugInfoError::BadLine(idx + 2);
+            let fields: Vec<_> = line.split('\t').collect();
+            match fields[..] {
+                [] | [""] => {}
+                ["node", cmr, imr, name, ref location @ ..] => {
+                    let source = match *location {
+                        [] => None,
+                        [position, definition, ref file @ ..] if file.len() <= 1 => {
+                            Some(SourceLocation {
+                                definition: unescape(definition).ok_or(bad_line)?,
+                                position: parse_position(position).ok_or(bad_line)?,
+                                file: match *file {
+                                    [file] => Some(unescape(file).ok_or(bad_line)?),
+                                    _ => None,
+                                },
+                            })
+                        }
+                        _ => return Err(bad_line),
+                    };
+                    info.add_node(NodeInfo {
+                        cmr: Cmr::from_str(cmr).map_err(|_| bad_line)?,
+                        imr: match imr {
+                            "-" => None,
+                            imr => Some(Imr::from_str(imr).map_err(|_| bad_line)?),
+                        },
+                        name: unescape(name).ok_or(bad_line)?,
+                        source,
+                    });
+                }
+                ["comment", name, text] => info.add_comment(
+                    unescape(name).ok_or(bad_line)?,
+                    unescape(text).ok_or(bad_line)?,
+                ),
+                _ => return Err(bad_line),
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// Parses a position of the form `file:line:column`.
+fn parse_position(s: &str) -> Option<Position> {
+    let mut parts = s.split(':').map(usize::from_str);
+    let position = Position {
+        file: parts.next()?.ok()?,
+        line: parts.next()?.ok()?,
+        column: parts.next()?.ok()?,
+    };
+    match parts.next() {
+        None => Some(position),
+        Some(_) => None,
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => ret.push_str("\\\\"),
+            '\t' => ret.push_str("\\t"),
+            '\n' => ret.push_str("\\n"),
+            ch => ret.push(ch),
+        }
+    }
+    ret
+}
+
+/// Reverses [`escape`], or returns `None` if the string contains an unknown escape.
+fn unescape(s: &str) -> Option<Arc<str>> {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next()? {
+                '\\' => ret.push('\\'),
+                't' => ret.push('\t'),
+                'n' => ret.push('\n'),
+                _ => return None,
+            },
+            ch => ret.push(ch),
+        }
+    }
+    Some(Arc::from(ret))
+}
+
+/// An error parsing [`DebugInfo`] from a string
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseDebugInfoError {
+    /// The first line was not the debug information header.
+    BadHeader,
+    /// The given line, counting from 1, could not be parsed.
+    BadLine(usize),
+}
+
+impl fmt::Display for ParseDebugInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseDebugInfoError::BadHeader => {
+                write!(f, "debug information does not start with `{}`", HEADER)
+            }
+            ParseDebugInfoError::BadLine(line) => {
+                write!(f, "line {} of debug information is invalid", line)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseDebugInfoError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(DebugInfo::from_str(""), Err(ParseDebugInfoError::BadHeader));
+        assert_eq!(
+            DebugInfo::from_str("simplicity-debug-info 2\n"),
+            Err(ParseDebugInfoError::BadHeader)
+        );
+        assert_eq!(
+            DebugInfo::from_str("simplicity-debug-info 1\n\ncomment\tx"),
+            Err(ParseDebugInfoError::BadLine(3))
+        );
+        assert_eq!(
+            DebugInfo::from_str("simplicity-debug-info 1\ncomment\tx\tbad \\escape"),
+            Err(ParseDebugInfoError::BadLine(2))
+        );
+        assert_eq!(
+            DebugInfo::from_str("simplicity-debug-info 1\nnode\tnot a cmr\t-\tx"),
+            Err(ParseDebugInfoError::BadLine(2))
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let mut info = DebugInfo::new();
+        info.add_node(NodeInfo {
+            cmr: Cmr::unit(),
+            imr: None,
+            name: Arc::from("a\tb"),
+            source: Some(SourceLocation {
+                definition: Arc::from("c\\d"),
+                position: Position {
+                    file: 0,
+                    line: 3,
+                    column: 7,
+                },
+                file: None,
+            }),
+        });
+        info.add_comment(Arc::from("a\tb"), Arc::from("-- one\n-- two"));
+
+        let s = info.to_string();
+        assert_eq!(
+            s,
+            format!(
+                "simplicity-debug-info 1\nnode\t{}\t-\ta\\tb\t0:3:7\tc\\\\d\ncomment\ta\\tb\t-- one\\n-- two\n",
+                Cmr::unit(),
+            ),
+        );
+        assert_eq!(DebugInfo::from_str(&s), Ok(info.clone()));
+        assert_eq!(
+            info.lookup(Cmr::unit(), None).map(|node| &*node.name),
+            Some("a\tb")
+        );
+        assert_eq!(info.lookup(Cmr::iden(), None), None);
+    }
+
+    #[test]
+    fn imported_file() {
+        let s = format!(
+            "simplicity-debug-info 1\nnode\t{}\t-\tx\t1:2:3\tlib.x\tdir/lib\\tsimpl\n",
+            Cmr::unit(),
+        );
+        let info = DebugInfo::from_str(&s).unwrap();
+        let source = info.lookup(Cmr::unit(), None).unwrap().source.as_ref();
+        assert_eq!(
+            source.and_then(|source| source.file.as_deref()),
+            Some("dir/lib\tsimpl")
+        );
+        assert_eq!(info.to_string(), s);
+
+        let extra = format!("{}\textra", s.trim_end());
+        assert_eq!(
+            DebugInfo::from_str(&extra),
+            Err(ParseDebugInfoError::BadLine(2))
+        );
+    }
+}
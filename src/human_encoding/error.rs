@@ -2,37 +2,23 @@
 
 //! Parsing Errors
 
-use santiago::lexer::Lexeme;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 use std::{error, fmt, iter};
 
 use crate::types;
 
-use super::Position;
+use super::{parse, Position};
 
 /// A set of errors found in a human-readable encoding of a Simplicity program.
 #[derive(Clone, Debug, Default)]
 pub struct ErrorSet {
     context: Option<Arc<str>>,
-    line_map: Arc<Mutex<Vec<usize>>>,
-    errors: BTreeMap<Option<Position>, Vec<Error>>,
-}
-
-impl<T> From<santiago::parser::ParseError<T>> for ErrorSet {
-    fn from(e: santiago::parser::ParseError<T>) -> Self {
-        let lex = e.at.map(|rc| (*rc).clone());
-        match lex.as_ref().map(|lex| &lex.position).map(Position::from) {
-            Some(pos) => ErrorSet::single(pos, Error::ParseFailed(lex)),
-            None => ErrorSet::single_no_position(Error::ParseFailed(lex)),
-        }
-    }
-}
-
-impl From<santiago::lexer::LexerError> for ErrorSet {
-    fn from(e: santiago::lexer::LexerError) -> Self {
-        ErrorSet::single(e.position, Error::LexFailed(e.message))
-    }
+    /// Name and source of each file read while following imports, by file index
+    files: BTreeMap<usize, (Arc<str>, Arc<str>)>,
+    line_maps: Arc<Mutex<HashMap<usize, Vec<usize>>>>,
+    /// Each error, along with any other positions relevant to it
+    errors: BTreeMap<Option<Position>, Vec<(Error, Vec<(Position, String)>)>>,
 }
 
 impl ErrorSet {
@@ -44,47 +30,73 @@ impl ErrorSet {
     /// Returns the first (and presumably most important) error in the set, if it
     /// is non-empty, along with its position.
     pub fn first_error(&self) -> Option<(Option<Position>, &Error)> {
-        self.errors.iter().next().map(|(a, b)| (*a, &b[0]))
+        self.errors.iter().next().map(|(a, b)| (*a, &b[0].0))
     }
 
     /// Return an iterator over the errors in the error set.
     pub fn iter(&self) -> impl Iterator<Item = &Error> {
-        self.errors.values().flatten()
+        self.errors.values().flatten().map(|(err, _)| err)
+    }
+
+    /// Return an iterator over the errors in the error set, along with their
+    /// positions, in order of position.
+    pub fn iter_with_positions(&self) -> impl Iterator<Item = (Option<Position>, &Error)> {
+        self.errors
+            .iter()
+            .flat_map(|(pos, errs)| errs.iter().map(move |(err, _)| (*pos, err)))
+    }
+
+    /// Returns the name of the file with the given index, if it was read while
+    /// following imports.
+    pub fn file_name(&self, file: usize) -> Option<&str> {
+        self.files.get(&file).map(|(name, _)| name.as_ref())
     }
 
     /// Constructs a new error set with a single error in it.
     pub fn single<P: Into<Position>, E: Into<Error>>(position: P, err: E) -> Self {
         let mut errors = BTreeMap::default();
-        errors.insert(Some(position.into()), vec![err.into()]);
+        errors.insert(Some(position.into()), vec![(err.into(), vec![])]);
         ErrorSet {
-            context: None,
-            line_map: Arc::new(Mutex::new(vec![])),
             errors,
+            ..ErrorSet::default()
         }
     }
 
     /// Constructs a new error set with a single error in it.
     pub fn single_no_position<E: Into<Error>>(err: E) -> Self {
         let mut errors = BTreeMap::default();
-        errors.insert(None, vec![err.into()]);
+        errors.insert(None, vec![(err.into(), vec![])]);
         ErrorSet {
-            context: None,
-            line_map: Arc::new(Mutex::new(vec![])),
             errors,
+            ..ErrorSet::default()
         }
     }
 
     /// Adds an error to the error set.
     pub fn add<P: Into<Position>, E: Into<Error>>(&mut self, position: P, err: E) {
+        self.add_with_related(position, err, vec![]);
+    }
+
+    /// Adds an error to the error set, along with other positions relevant to
+    /// it, each described by a message.
+    pub fn add_with_related<P: Into<Position>, E: Into<Error>>(
+        &mut self,
+        position: P,
+        err: E,
+        related: Vec<(Position, String)>,
+    ) {
         self.errors
             .entry(Some(position.into()))
             .or_default()
-            .push(err.into());
+            .push((err.into(), related));
     }
 
     /// Adds an error to the error set.
     pub fn add_no_position<E: Into<Error>>(&mut self, err: E) {
-        self.errors.entry(None).or_default().push(err.into());
+        self.errors
+            .entry(None)
+            .or_default()
+            .push((err.into(), vec![]));
     }
 
     /// Merges another set of errors into the current set.
@@ -102,6 +114,9 @@ impl ErrorSet {
             }
         };
 
+        for (file, source) in &other.files {
+            self.files.entry(*file).or_insert_with(|| source.clone());
+        }
         for (pos, errs) in &other.errors {
             self.errors
                 .entry(*pos)
@@ -124,6 +139,62 @@ impl ErrorSet {
         self.context = Some(s);
     }
 
+    /// Attaches the name and source code of an imported file, so that error
+    /// messages at positions in that file can include the file name and line.
+    pub(crate) fn add_file(&mut self, file: usize, name: Arc<str>, source: Arc<str>) {
+        self.files.insert(file, (name, source));
+    }
+
+    /// Returns the errors in the set as diagnostics, in order of position.
+    ///
+    /// The length of each span is that of the token at its position, if the
+    /// source of its file is known.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut token_lengths = HashMap::new();
+        let mut span = |pos: Position| {
+            let (name, source) = match self.files.get(&pos.file) {
+                Some((name, source)) => (Some(name), Some(source)),
+                None if pos.file == 0 => (None, self.context.as_ref()),
+                None => (None, None),
+            };
+            let length = match source {
+                Some(source) => {
+                    let lengths = token_lengths
+                        .entry(pos.file)
+                        .or_insert_with(|| parse::token_lengths(source, pos.file));
+                    lengths.get(&pos).copied().unwrap_or(1)
+                }
+                None => 0,
+            };
+            Span {
+                file: name.map(|name| name.to_string()),
+                line: pos.line,
+                column: pos.column,
+                length,
+            }
+        };
+
+        let mut diagnostics = vec![];
+        for (pos, errs) in &self.errors {
+            for (err, related) in errs {
+                diagnostics.push(Diagnostic {
+                    code: err.code(),
+                    severity: Severity::Error,
+                    message: err.to_string(),
+                    span: pos.map(&mut span),
+                    related: related
+                        .iter()
+                        .map(|(pos, message)| Related {
+                            span: span(*pos),
+                            message: message.clone(),
+                        })
+                        .collect(),
+                });
+            }
+        }
+        diagnostics
+    }
+
     /// Returns a boolean indicating whether the set is empty.
     pub fn is_empty(&self) -> bool {
         self.errors.is_empty()
@@ -162,20 +233,34 @@ impl ErrorSet {
 impl error::Error for ErrorSet {
     fn cause(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.first_error()?.1 {
+            Error::ArgumentMissing { .. } => None,
+            Error::ArgumentUnused(_) => None,
             Error::Bad2ExpNumber(..) => None,
+            Error::BadWitnessValue { .. } => None,
             Error::BadWordLength { .. } => None,
+            Error::ConstantType { .. } => None,
+            Error::ConstantUntyped(_) => None,
             Error::EntropyInsufficient { .. } => None,
             Error::EntropyTooMuch { .. } => None,
             Error::HoleAtCommitTime { .. } => None,
             Error::HoleFilledAtCommitTime => None,
+            Error::ImportCycle(_) => None,
+            Error::ImportFailed { .. } => None,
             Error::NameIllegal(_) => None,
             Error::NameIncomplete(_) => None,
             Error::NameMissing(_) => None,
             Error::NameRepeated(_) => None,
+            Error::NestingTooDeep => None,
             Error::NoMain => None,
-            Error::ParseFailed(_) => None,
+            Error::NotWitness(_) => None,
+            Error::NotWitnessAssignment(_) => None,
+            Error::ParseFailed { .. } => None,
             Error::LexFailed(_) => None,
             Error::NumberOutOfRange(_) => None,
+            Error::TemplateArity { .. } => None,
+            Error::TemplateMissing(_) => None,
+            Error::TemplateRecursive(_) => None,
+            Error::TypeAliasCycle(_) => None,
             Error::TypeCheck(ref e) => Some(e),
             Error::Undefined(_) => None,
             Error::UnknownJet(_) => None,
@@ -186,23 +271,32 @@ impl error::Error for ErrorSet {
 
 impl fmt::Display for ErrorSet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut line_map = self.line_map.lock().unwrap();
-        if line_map.is_empty() {
-            if let Some(ref s) = self.context {
-                *line_map = iter::repeat(0)
-                    .take(2)
-                    .chain(
-                        s.char_indices()
-                            .filter_map(|(n, ch)| if ch == '\n' { Some(n) } else { None }),
-                    )
-                    .collect();
-            }
-        }
+        let mut line_maps = self.line_maps.lock().unwrap();
 
         for (pos, errs) in &self.errors {
             if let Some(pos) = pos {
-                for err in errs {
-                    if let Some(ref s) = self.context {
+                let (name, source) = match self.files.get(&pos.file) {
+                    Some((name, source)) => (Some(name), Some(source)),
+                    None if pos.file == 0 => (None, self.context.as_ref()),
+                    None => (None, None),
+                };
+                for (err, _) in errs {
+                    if let Some(s) = source {
+                        let line_map = line_maps.entry(pos.file).or_insert_with(|| {
+                            iter::repeat(0)
+                                .take(2)
+                                .chain(s.char_indices().filter_map(|(n, ch)| {
+                                    if ch == '\n' {
+                                        Some(n)
+                                    } else {
+                                        None
+                                    }
+                                }))
+                                .collect()
+                        });
+                        if let Some(name) = name {
+                            writeln!(f, "{}:{}:{}:", name, pos.line, pos.column)?;
+                        }
                         let end = line_map.get(pos.line + 1).copied().unwrap_or(s.len());
                         let line = &s[line_map[pos.line] + 1..end];
                         writeln!(f, "{:5} | {}", pos.line, line)?;
@@ -210,12 +304,15 @@ impl fmt::Display for ErrorSet {
                         writeln!(f, "      \\-- {}", err)?;
                         writeln!(f)?;
                     } else {
+                        if let Some(name) = name {
+                            write!(f, "{}:", name)?;
+                        }
                         writeln!(f, "{:4}:{:2}: {}", pos.line, pos.column, err,)?;
                         writeln!(f)?;
                     }
                 }
             } else {
-                for err in errs {
+                for (err, _) in errs {
                     writeln!(f, "Error: {}", err)?;
                 }
             }
@@ -224,6 +321,54 @@ impl fmt::Display for ErrorSet {
     }
 }
 
+/// How serious a diagnostic is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Severity {
+    /// The program cannot be assembled
+    Error,
+}
+
+/// A span of source text which a diagnostic refers to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    /// The name of the file, if it is known
+    pub file: Option<String>,
+    /// The line number, counting from 1
+    pub line: usize,
+    /// The column number in characters, counting from 1
+    pub column: usize,
+    /// The length of the span in characters, or 0 if the source is not known
+    pub length: usize,
+}
+
+/// Another position which is relevant to a diagnostic.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Related {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A machine-readable form of an error, for use by tools such as editors
+/// and continuous integration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    /// The stable code of the error, as returned by [`Error::code`]
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the error occurred, if it occurred at a particular position
+    pub span: Option<Span>,
+    pub related: Vec<Related>,
+}
+
 /// An individual error.
 ///
 /// Generally this structure should not be used on its own, but only wrapped in an
@@ -232,10 +377,28 @@ impl fmt::Display for ErrorSet {
 /// many as possible at once.
 #[derive(Clone, Debug)]
 pub enum Error {
+    /// A parameter of the `main` template was not given a value
+    ArgumentMissing {
+        template: Arc<str>,
+        parameter: Arc<str>,
+    },
+    /// A value was given for a name which is not a parameter of the `main` template
+    ArgumentUnused(Arc<str>),
     /// A number of the form 2^y was used as a type but y was not an allowed value
     Bad2ExpNumber(u32),
+    /// A value assigned to a witness node was not a valid compact encoding of
+    /// a value of the node's type
+    BadWitnessValue {
+        name: Arc<str>,
+        ty: Arc<types::Final>,
+        bit_length: usize,
+    },
     /// A constant word had a length which was not an allowable power of 2
     BadWordLength { bit_length: usize },
+    /// A constant was not a value of the type given for it
+    ConstantType { constant: Arc<str>, ty: Arc<str> },
+    /// A decimal constant was not given a word type, so its width is unknown
+    ConstantUntyped(Arc<str>),
     /// A "fail" node was provided with less than 128 bits of entropy
     EntropyInsufficient { bit_length: usize },
     /// A "fail" node was provided with more than 512 bits of entropy
@@ -249,6 +412,10 @@ pub enum Error {
     /// When converting to a `CommitNode`, a disconnect node had an actual node rather
     /// than a hole.
     HoleFilledAtCommitTime,
+    /// A file imported itself, directly or through other imports.
+    ImportCycle(Arc<str>),
+    /// An imported file could not be read.
+    ImportFailed { path: Arc<str>, reason: String },
     /// An expression name was not allowed to be used as a name.
     NameIllegal(Arc<str>),
     /// An expression was given a type, but no actual expression was provided.
@@ -257,15 +424,37 @@ pub enum Error {
     NameMissing(Arc<str>),
     /// An expression name was used for multiple expressions.
     NameRepeated(Arc<str>),
+    /// Expressions, types or constants were nested too deeply
+    NestingTooDeep,
     /// Program did not have a `main` expression
     NoMain,
-    /// Parsing failed (the parser provides us some extra information, but beyond
-    /// the line and column, it does not seem very useful to a user, so we drop it).
-    ParseFailed(Option<Lexeme>),
-    /// Lexing failed; here santiago provides us an error message which is useful
+    /// A value was assigned to a name which is not a witness node
+    NotWitness(Arc<str>),
+    /// A definition, type bound, import or type alias was given where only
+    /// witness values may be
+    NotWitnessAssignment(Arc<str>),
+    /// Parsing failed: the parser expected one thing but found another token
+    /// (or `None`, if it reached the end of the input).
+    ParseFailed {
+        expected: &'static str,
+        found: Option<Arc<str>>,
+    },
+    /// Lexing failed; the string describes the unrecognized input
     LexFailed(String),
     /// A number was parsed in some context but was out of range.
     NumberOutOfRange(String),
+    /// A template was instantiated with the wrong number of arguments
+    TemplateArity {
+        name: Arc<str>,
+        expected: usize,
+        found: usize,
+    },
+    /// A name which is not a template was instantiated as one
+    TemplateMissing(Arc<str>),
+    /// A template was instantiated, directly or indirectly, from its own body
+    TemplateRecursive(Arc<str>),
+    /// A type alias was defined, directly or through other aliases, in terms of itself
+    TypeAliasCycle(Arc<str>),
     /// Simplicity type-checking error
     TypeCheck(types::Error),
     /// Expression referred to an undefined symbol
@@ -276,6 +465,49 @@ pub enum Error {
     WitnessDisconnectRepeated { name: Arc<str>, count: usize },
 }
 
+impl Error {
+    /// A short code identifying the kind of error.
+    ///
+    /// Unlike error messages, which may be reworded, codes are stable: each
+    /// code always refers to the same kind of error, and is never reused.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Error::ArgumentMissing { .. } => "E0001",
+            Error::ArgumentUnused(_) => "E0002",
+            Error::Bad2ExpNumber(..) => "E0003",
+            Error::BadWitnessValue { .. } => "E0004",
+            Error::BadWordLength { .. } => "E0005",
+            Error::ConstantType { .. } => "E0006",
+            Error::ConstantUntyped(_) => "E0007",
+            Error::EntropyInsufficient { .. } => "E0008",
+            Error::EntropyTooMuch { .. } => "E0009",
+            Error::HoleAtCommitTime { .. } => "E0010",
+            Error::HoleFilledAtCommitTime => "E0011",
+            Error::ImportCycle(_) => "E0012",
+            Error::ImportFailed { .. } => "E0013",
+            Error::NameIllegal(_) => "E0014",
+            Error::NameIncomplete(_) => "E0015",
+            Error::NameMissing(_) => "E0016",
+            Error::NameRepeated(_) => "E0017",
+            Error::NoMain => "E0018",
+            Error::NotWitness(_) => "E0019",
+            Error::ParseFailed { .. } => "E0020",
+            Error::LexFailed(_) => "E0021",
+            Error::NumberOutOfRange(_) => "E0022",
+            Error::TemplateArity { .. } => "E0023",
+            Error::TemplateMissing(_) => "E0024",
+            Error::TemplateRecursive(_) => "E0025",
+            Error::TypeAliasCycle(_) => "E0026",
+            Error::TypeCheck(_) => "E0027",
+            Error::Undefined(_) => "E0028",
+            Error::UnknownJet(_) => "E0029",
+            Error::WitnessDisconnectRepeated { .. } => "E0030",
+            Error::NotWitnessAssignment(_) => "E0031",
+            Error::NestingTooDeep => "E0032",
+        }
+    }
+}
+
 impl From<types::Error> for Error {
     fn from(e: types::Error) -> Self {
         Error::TypeCheck(e)
@@ -285,9 +517,40 @@ impl From<types::Error> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::ArgumentMissing {
+                ref template,
+                ref parameter,
+            } => write!(
+                f,
+                "no value was given for parameter `{}` of template `{}`",
+                parameter, template
+            ),
+            Error::ArgumentUnused(ref s) => write!(
+                f,
+                "a value was given for `{}`, which is not a parameter of `main`",
+                s
+            ),
             Error::BadWordLength { bit_length } => {
                 write!(f, "word length {} is not a valid power of 2", bit_length)
             }
+            Error::ConstantType {
+                ref constant,
+                ref ty,
+            } => write!(f, "constant `{}` is not a value of type `{}`", constant, ty),
+            Error::ConstantUntyped(ref s) => write!(
+                f,
+                "the width of decimal constant `{}` is unknown; give it a word type such as `2^32`",
+                s
+            ),
+            Error::BadWitnessValue {
+                ref name,
+                ref ty,
+                bit_length,
+            } => write!(
+                f,
+                "{}-bit value is not a valid value of type {} for witness `{}`",
+                bit_length, ty, name
+            ),
             Error::Bad2ExpNumber(exp) => {
                 write!(f, "types may be 2^n for n a power of 2, but not 2^{}", exp)
             }
@@ -312,6 +575,13 @@ impl fmt::Display for Error {
             Error::HoleFilledAtCommitTime => {
                 f.write_str("disconnect node has a non-hole child at commit time")
             }
+            Error::ImportCycle(ref path) => {
+                write!(f, "import of `{}` forms a cycle", path)
+            }
+            Error::ImportFailed {
+                ref path,
+                ref reason,
+            } => write!(f, "failed to import `{}`: {}", path, reason),
             Error::NameIllegal(ref s) => {
                 write!(f, "name `{}` is not allowed in this context", s)
             }
@@ -320,13 +590,48 @@ impl fmt::Display for Error {
                 write!(f, "name `{}` is referred to but does not exist", s)
             }
             Error::NameRepeated(ref s) => write!(f, "name `{}` occured mulitple times", s),
+            Error::NestingTooDeep => f.write_str("expression is nested too deeply"),
             Error::NoMain => f.write_str("program does not define `main`"),
+            Error::NotWitnessAssignment(ref s) => write!(
+                f,
+                "only witness values may be given here, but `{}` is not assigned one",
+                s
+            ),
+            Error::NotWitness(ref s) => {
+                write!(
+                    f,
+                    "name `{}` is assigned a value but is not a witness node",
+                    s
+                )
+            }
             Error::NumberOutOfRange(ref n) => {
                 write!(f, "number {} was out of allowable range", n)
             }
-            Error::ParseFailed(None) => f.write_str("could not parse"),
-            Error::ParseFailed(Some(ref lex)) => write!(f, "could not parse `{}`", lex.raw),
+            Error::ParseFailed {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            Error::ParseFailed {
+                expected,
+                found: Some(ref found),
+            } => write!(f, "expected {}, found `{}`", expected, found),
             Error::LexFailed(ref msg) => write!(f, "could not parse: {}", msg),
+            Error::TemplateArity {
+                ref name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "template `{}` takes {} argument(s) but was given {}",
+                name, expected, found
+            ),
+            Error::TemplateMissing(ref s) => write!(f, "`{}` is not a template", s),
+            Error::TemplateRecursive(ref s) => {
+                write!(f, "template `{}` is instantiated within itself", s)
+            }
+            Error::TypeAliasCycle(ref s) => {
+                write!(f, "type alias `{}` is defined in terms of itself", s)
+            }
             Error::TypeCheck(ref e) => fmt::Display::fmt(e, f),
             Error::Undefined(ref s) => write!(f, "reference to undefined symbol `{}`", s),
             Error::UnknownJet(ref s) => write!(f, "unknown jet `{}`", s),
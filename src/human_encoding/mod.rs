@@ -6,47 +6,65 @@
 //! in a human-readable format.
 //!
 
+mod debug_info;
 mod error;
 mod named_node;
 mod parse;
+mod source_map;
 
-use crate::dag::{DagLike, MaxSharing};
+use crate::dag::{DagLike, InternalSharing, MaxSharing};
 use crate::jet::Jet;
 use crate::node::{self, CommitNode, NoWitness};
-use crate::{Cmr, ConstructNode, Imr, Value};
+use crate::types::Final;
+use crate::{Cmr, ConstructNode, Imr, RedeemNode, Value, Word};
 
-use std::collections::HashMap;
-use std::str;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
+use std::{fs, io, str};
 
-pub use self::error::{Error, ErrorSet};
+pub use self::debug_info::{DebugInfo, NodeInfo, ParseDebugInfoError};
+pub use self::error::{Diagnostic, Error, ErrorSet, Related, Severity, Span};
 pub use self::named_node::NamedCommitNode;
+pub use self::parse::format as format_source;
+pub use self::parse::{symbols, Symbol, SymbolKind, SymbolRole};
+pub use self::source_map::{SourceLocation, SourceMap};
 
-/// Line/column pair
+/// File/line/column triple
 ///
-/// There is a similar type provided by the `santiago` library but it does not implement
-/// `Copy`, among many other traits, which makes it unergonomic to use. Santiago positions
-/// can be converted using `.into()`.
+/// Both lines and columns are counted from 1; columns count characters, not bytes.
+/// Files are numbered from 0, which is the file being parsed; imported files are
+/// numbered in the order that they are loaded.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
 pub struct Position {
+    file: usize,
     line: usize,
     column: usize,
 }
 
-impl<'a> From<&'a santiago::lexer::Position> for Position {
-    fn from(position: &'a santiago::lexer::Position) -> Position {
-        Position {
-            line: position.line,
-            column: position.column,
-        }
+impl Position {
+    /// The index of the file that this position is in.
+    pub fn file(&self) -> usize {
+        self.file
+    }
+
+    /// The line number of this position, counting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column number of this position, counting characters from 1.
+    pub fn column(&self) -> usize {
+        self.column
     }
-}
 
-impl From<santiago::lexer::Position> for Position {
-    fn from(position: santiago::lexer::Position) -> Position {
-        Position {
-            line: position.line,
-            column: position.column,
+    /// Advances the position past the given character.
+    fn advance(&mut self, ch: char) {
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
     }
 }
@@ -81,15 +99,84 @@ impl From<&'_ NoWitness> for WitnessOrHole {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct Forest<J: Jet> {
     roots: HashMap<Arc<str>, Arc<NamedCommitNode<J>>>,
+    witness: HashMap<Arc<str>, Value>,
+    /// Type aliases for complete types, sorted by name
+    aliases: Vec<(Arc<str>, Arc<Final>)>,
+    /// Comments describing each name, in order
+    ///
+    /// Not considered by equality, like the source locations of nodes.
+    comments: HashMap<Arc<str>, Vec<Arc<str>>>,
+}
+
+impl<J: Jet> PartialEq for Forest<J> {
+    fn eq(&self, other: &Self) -> bool {
+        self.roots == other.roots && self.witness == other.witness && self.aliases == other.aliases
+    }
 }
+impl<J: Jet> Eq for Forest<J> {}
 
 impl<J: Jet> Forest<J> {
     /// Parses a forest from a string
+    ///
+    /// Since there is no file to resolve paths relative to, programs which use
+    /// `import` must be parsed with [`Forest::parse_file`] instead.
     pub fn parse(s: &str) -> Result<Self, ErrorSet> {
-        parse::parse(s).map(|roots| Forest { roots })
+        parse::parse(s)
+    }
+
+    /// Parses a forest from a string, as [`Forest::parse`] does, instantiating
+    /// the `main` template with the given arguments
+    ///
+    /// Each parameter of `main` is replaced by `const` followed by the value
+    /// given for it. It is an error for a parameter to have no value, or for a
+    /// value to be given for a name which is not a parameter.
+    pub fn parse_with_arguments(
+        s: &str,
+        arguments: &HashMap<Arc<str>, Word>,
+    ) -> Result<Self, ErrorSet> {
+        parse::parse_with_arguments(s, arguments)
+    }
+
+    /// Parses a forest from a file, along with every file that it imports
+    ///
+    /// Imports are resolved relative to the directory of the importing file.
+    /// The returned errors carry the source of every file that was read.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Self, ErrorSet> {
+        parse::parse_file(
+            path.as_ref(),
+            &mut |path| fs::read_to_string(path),
+            &HashMap::new(),
+        )
+    }
+
+    /// Parses a forest from a file, as [`Forest::parse_file`] does, instantiating
+    /// the `main` template with the given arguments, as [`Forest::parse_with_arguments`]
+    /// does
+    pub fn parse_file_with_arguments<P: AsRef<Path>>(
+        path: P,
+        arguments: &HashMap<Arc<str>, Word>,
+    ) -> Result<Self, ErrorSet> {
+        parse::parse_file(
+            path.as_ref(),
+            &mut |path| fs::read_to_string(path),
+            arguments,
+        )
+    }
+
+    /// Parses a forest from a file, as [`Forest::parse_file`] does, but reading
+    /// the file and its imports with the given function
+    ///
+    /// This allows, for example, an editor to parse files whose latest contents
+    /// have not been saved to disk.
+    pub fn parse_file_with<P, F>(path: P, mut read: F) -> Result<Self, ErrorSet>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Path) -> io::Result<String>,
+    {
+        parse::parse_file(path.as_ref(), &mut read, &HashMap::new())
     }
 
     /// Parses a program from a bytestring
@@ -97,7 +184,35 @@ impl<J: Jet> Forest<J> {
         let root = NamedCommitNode::from_node(&root);
         let mut roots = HashMap::new();
         roots.insert("main".into(), root);
-        Forest { roots }
+        Forest {
+            roots,
+            witness: HashMap::new(),
+            aliases: vec![],
+            comments: HashMap::new(),
+        }
+    }
+
+    /// Parses a program from a bytestring, as [`Forest::from_program`] does,
+    /// restoring the names, source locations and comments recorded in the given
+    /// debug information.
+    pub fn from_program_with_debug_info(root: Arc<CommitNode<J>>, debug_info: &DebugInfo) -> Self {
+        let root = NamedCommitNode::from_node_with_debug_info(&root, debug_info);
+        let mut comments = HashMap::new();
+        for data in root.as_ref().post_order_iter::<MaxSharing<_>>() {
+            let name = data.node.name();
+            let texts = debug_info.comments(name);
+            if !texts.is_empty() {
+                comments.insert(Arc::clone(name), texts.to_vec());
+            }
+        }
+        let mut roots = HashMap::new();
+        roots.insert("main".into(), root);
+        Forest {
+            roots,
+            witness: HashMap::new(),
+            aliases: vec![],
+            comments,
+        }
     }
 
     /// Accessor for the map of roots of this forest
@@ -105,11 +220,42 @@ impl<J: Jet> Forest<J> {
         &self.roots
     }
 
+    /// Accessor for the witness values assigned in this forest, by witness node name
+    pub fn witness(&self) -> &HashMap<Arc<str>, Value> {
+        &self.witness
+    }
+
+    /// Parses values for the witness nodes of this forest from a string
+    ///
+    /// The string has the same syntax as a program, but may only contain
+    /// witness assignments such as `w <- 0x2a`. Values are decoded against the
+    /// types of the witness nodes they are assigned to, as they are when they
+    /// are assigned within the program itself. This allows a program to be
+    /// committed to first, and the witness values to be given separately.
+    pub fn parse_witness_values(&self, s: &str) -> Result<HashMap<Arc<str>, Value>, ErrorSet> {
+        parse::witness_values(self, s)
+    }
+
+    /// Accessor for the type aliases of this forest which stand for complete
+    /// types, sorted by name
+    pub fn type_aliases(&self) -> &[(Arc<str>, Arc<Final>)] {
+        &self.aliases
+    }
+
+    /// Formats a type as it is written in the human encoding, using the forest's
+    /// type aliases wherever they apply, as [`Forest::string_serialize`] does.
+    pub fn type_string(&self, ty: &Final) -> String {
+        type_str(ty, &self.aliases, true)
+    }
+
     /// Serialize the program in human-readable form
+    ///
+    /// Types are printed using the forest's type aliases wherever they apply.
     pub fn string_serialize(&self) -> String {
-        struct Print {
+        struct Print<'c> {
             cmr: Cmr,
             imr: Option<Imr>,
+            comments: &'c [Arc<str>],
             expr_str: String,  // The X = Y part
             arrow_str: String, // The :: A -> B part
         }
@@ -129,6 +275,10 @@ impl<J: Jet> Forest<J> {
                 } else {
                     ret += "-- IMR: [undetermined]\n";
                 }
+                for comment in line.comments {
+                    ret += comment;
+                    ret += "\n";
+                }
                 ret += &format!(
                     "{0:1$} {2:3$}\n",
                     line.expr_str, expr_width, line.arrow_str, arrow_width,
@@ -138,6 +288,8 @@ impl<J: Jet> Forest<J> {
         }
 
         let mut witness_lines = vec![];
+        let mut witness_values: Vec<_> = self.witness.iter().collect();
+        witness_values.sort_by(|a, b| a.0.cmp(b.0));
         let mut const_lines = vec![];
         let mut program_lines = vec![];
         // Pass 1: compute string data for every node
@@ -169,11 +321,16 @@ impl<J: Jet> Forest<J> {
                 }
 
                 let arrow = node.arrow();
-                let arrow_str = format!(": {} -> {}", arrow.source, arrow.target).replace('×', "*"); // for human-readable encoding stick with ASCII
+                let arrow_str = format!(
+                    ": {} -> {}",
+                    type_str(&arrow.source, &self.aliases, true),
+                    type_str(&arrow.target, &self.aliases, true),
+                );
 
                 let print = Print {
                     cmr: node.cmr(),
                     imr: node.imr(),
+                    comments: self.comments.get(name).map_or(&[], Vec::as_slice),
                     expr_str,
                     arrow_str,
                 };
@@ -189,11 +346,33 @@ impl<J: Jet> Forest<J> {
 
         // Pass 2: actually print everything
         let mut ret = String::new();
+        if !self.aliases.is_empty() {
+            ret += "--------------\n-- Type aliases\n--------------\n";
+            for (name, ty) in &self.aliases {
+                // An alias must not be printed in terms of itself, or of any
+                // other alias for the same type.
+                let others: Vec<_> = self
+                    .aliases
+                    .iter()
+                    .filter(|(_, other)| other.tmr() != ty.tmr())
+                    .cloned()
+                    .collect();
+                ret += &format!("type {} := {}\n", name, type_str(ty, &others, true));
+            }
+            ret += "\n";
+        }
         if !witness_lines.is_empty() {
             ret += "--------------\n-- Witnesses\n--------------\n";
             ret += &print_lines(&witness_lines, false);
             ret += "\n";
         }
+        if !witness_values.is_empty() {
+            ret += "--------------\n-- Witness values\n--------------\n";
+            for (name, value) in witness_values {
+                ret += &format!("{} <- {}\n", name, value_literal(value));
+            }
+            ret += "\n";
+        }
         if !const_lines.is_empty() {
             // FIXME detect scribes
             ret += "--------------\n-- Constants\n--------------\n";
@@ -217,14 +396,176 @@ impl<J: Jet> Forest<J> {
         let main = self.roots.get("main")?;
         Some(main.to_construct_node(witness, self.roots()))
     }
+
+    /// Convert the forest into a redeem node, using the witness values assigned
+    /// in the forest itself.
+    ///
+    /// The program is pruned with respect to the given environment. Returns `None`
+    /// if the forest does not contain a "main" root.
+    pub fn to_redeem_node(
+        &self,
+        env: &J::Environment,
+    ) -> Option<Result<Arc<RedeemNode<J>>, crate::Error>> {
+        let witness_node = self.to_witness_node(&self.witness)?;
+        Some(witness_node.finalize_pruned(env))
+    }
+
+    /// Map the nodes of every root of the forest back to their source.
+    ///
+    /// Nodes are keyed by CMR and, where they have no witnesses or disconnected
+    /// expressions below them, by IMR. To look up the nodes of a program which
+    /// has been finalized, use [`Forest::redeem_source_map`] instead.
+    ///
+    /// Forests which were not parsed from source have an empty source map.
+    pub fn source_map(&self) -> SourceMap {
+        let mut map = SourceMap::default();
+        for root in self.roots.values() {
+            for data in root.as_ref().post_order_iter::<InternalSharing>() {
+                if let Some(location) = data.node.source() {
+                    map.insert(data.node.cmr(), data.node.imr(), location);
+                }
+            }
+        }
+        map
+    }
+
+    /// The debug information of this forest: the name, source location and
+    /// comments of every node, which the bit encoding of its roots does not keep.
+    pub fn debug_info(&self) -> DebugInfo {
+        let mut info = DebugInfo::new();
+        let mut names: Vec<_> = self.roots.keys().collect();
+        names.sort();
+        let mut seen = HashSet::new();
+        for name in names {
+            for data in self.roots[name].as_ref().post_order_iter::<MaxSharing<_>>() {
+                let node = data.node;
+                if !seen.insert(Arc::clone(node.name())) {
+                    continue;
+                }
+                info.add_node(NodeInfo {
+                    cmr: node.cmr(),
+                    imr: node.imr(),
+                    name: Arc::clone(node.name()),
+                    source: node.source().cloned(),
+                });
+                for text in self.comments.get(node.name()).into_iter().flatten() {
+                    info.add_comment(Arc::clone(node.name()), Arc::clone(text));
+                }
+            }
+        }
+        info
+    }
+
+    /// Map the nodes of a program, which was finalized from the "main" root of
+    /// this forest, back to their source.
+    ///
+    /// Unlike [`Forest::source_map`], every node is keyed by its IMR in the
+    /// finalized program, including nodes whose IMR depends on witness data,
+    /// disconnected expressions or pruning.
+    pub fn redeem_source_map(&self, program: &RedeemNode<J>) -> SourceMap {
+        let mut map = SourceMap::default();
+        let main = match self.roots.get("main") {
+            Some(main) => main,
+            None => return map,
+        };
+
+        let mut visited = HashSet::new();
+        let mut stack: Vec<(&RedeemNode<J>, &NamedCommitNode<J>)> = vec![(program, main)];
+        while let Some((redeem, named)) = stack.pop() {
+            if !visited.insert((redeem.imr(), named as *const NamedCommitNode<J>)) {
+                continue;
+            }
+            if let Some(location) = named.source() {
+                map.insert(redeem.cmr(), Some(redeem.imr()), location);
+            }
+
+            // Pruning replaces `case` nodes with assertions, and disconnected
+            // expressions are given by name in the forest.
+            match (redeem.inner(), named.inner()) {
+                (node::Inner::InjL(r), node::Inner::InjL(n))
+                | (node::Inner::InjR(r), node::Inner::InjR(n))
+                | (node::Inner::Take(r), node::Inner::Take(n))
+                | (node::Inner::Drop(r), node::Inner::Drop(n))
+                | (node::Inner::AssertL(r, _), node::Inner::Case(n, _))
+                | (node::Inner::AssertL(r, _), node::Inner::AssertL(n, _))
+                | (node::Inner::AssertR(_, r), node::Inner::Case(_, n))
+                | (node::Inner::AssertR(_, r), node::Inner::AssertR(_, n)) => stack.push((r, n)),
+                (node::Inner::Comp(rl, rr), node::Inner::Comp(nl, nr))
+                | (node::Inner::Case(rl, rr), node::Inner::Case(nl, nr))
+                | (node::Inner::Pair(rl, rr), node::Inner::Pair(nl, nr)) => {
+                    stack.push((rl, nl));
+                    stack.push((rr, nr));
+                }
+                (node::Inner::Disconnect(rl, rr), node::Inner::Disconnect(nl, name)) => {
+                    stack.push((rl, nl));
+                    if let Some(nr) = self.roots.get(name) {
+                        stack.push((rr, nr));
+                    }
+                }
+                _ => {}
+            }
+        }
+        map
+    }
+}
+
+/// Formats a type as it is written in the human encoding, with the name of an
+/// alias in place of any part of the type that it stands for.
+///
+/// Unlike the `Display` implementation of [`Final`], this uses only ASCII and
+/// writes `1 + A` rather than `A?`, so that the output can be parsed again.
+/// Compound types are parenthesized unless they are at the top level.
+fn type_str(ty: &Final, aliases: &[(Arc<str>, Arc<Final>)], top_level: bool) -> String {
+    if let Some((name, _)) = aliases.iter().find(|(_, alias)| alias.tmr() == ty.tmr()) {
+        return name.to_string();
+    }
+    let (left, right, op) = match (ty.as_sum(), ty.as_product()) {
+        _ if ty.is_unit() || ty.as_word().is_some() => return ty.to_string(),
+        (Some((left, right)), _) => (left, right, "+"),
+        (_, Some((left, right))) => (left, right, "*"),
+        (None, None) => unreachable!("every type is a unit, a sum or a product"),
+    };
+    let s = format!(
+        "{} {} {}",
+        type_str(left, aliases, false),
+        op,
+        type_str(right, aliases, false),
+    );
+    if top_level {
+        s
+    } else {
+        format!("({})", s)
+    }
+}
+
+/// Formats the compact bit encoding of a value as a literal, using hex where possible.
+fn value_literal(value: &Value) -> String {
+    let bits: Vec<bool> = value.iter_compact().collect();
+    if bits.is_empty() {
+        "_".to_owned()
+    } else if bits.len() % 4 == 0 {
+        let mut ret = String::from("0x");
+        for nybble in bits.chunks(4) {
+            let n = nybble
+                .iter()
+                .fold(0, |acc, &bit| (acc << 1) | u32::from(bit));
+            ret.push(char::from_digit(n, 16).unwrap());
+        }
+        ret
+    } else {
+        let mut ret = String::from("0b");
+        ret.extend(bits.iter().map(|&bit| if bit { '1' } else { '0' }));
+        ret
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::human_encoding::Forest;
+    use crate::dag::{DagLike, InternalSharing, MaxSharing};
+    use crate::human_encoding::{Forest, SourceMap};
     use crate::jet::{Core, Jet};
-    use crate::{BitMachine, Value};
-    use std::collections::HashMap;
+    use crate::{BitMachine, Imr, Value};
+    use std::collections::{HashMap, HashSet};
     use std::sync::Arc;
 
     fn assert_finalize_ok<J: Jet>(
@@ -372,4 +713,223 @@ mod tests {
         let wit2_populated = HashMap::from([(Arc::from("wit2"), Value::u1(1))]);
         assert_finalize_ok::<Core>(s, &wit2_populated, &());
     }
+
+    #[test]
+    fn assigned_witness_values() {
+        let s = "
+            a := witness
+            b := witness
+            main := comp
+                comp
+                    pair a b
+                    jet_lt_8
+                jet_verify
+
+            a <- 0x00
+            b <- 0b00000001
+        ";
+        let forest = Forest::<Core>::parse(s).expect("Failed to parse human encoding");
+        assert_eq!(forest.witness()[&Arc::from("a")], Value::u8(0x00));
+        assert_eq!(forest.witness()[&Arc::from("b")], Value::u8(0x01));
+
+        let program = forest
+            .to_redeem_node(&())
+            .expect("Forest is missing expected root")
+            .expect("Failed to finalize");
+        let mut mac = BitMachine::for_program(&program).expect("program has reasonable bounds");
+        mac.exec(&program, &()).expect("Failed to run program");
+
+        // Assigned values survive a round trip through the serialization
+        let reparsed = Forest::<Core>::parse(&forest.string_serialize())
+            .expect("Failed to parse serialized forest");
+        assert_eq!(reparsed.witness(), forest.witness());
+    }
+
+    #[test]
+    fn debug_info_round_trip() {
+        let s = "
+            -- The value to check
+            -- against
+            a := witness : 1 -> 2^8
+            check := comp (pair a (const 0x2a)) jet_eq_8 -- equal?
+            main := comp check jet_verify
+        ";
+        let forest = Forest::<Core>::parse(s).expect("Failed to parse human encoding");
+        let serialized = forest.string_serialize();
+        assert!(serialized.contains("-- The value to check\n-- against\na := witness"));
+        assert!(serialized.contains("-- equal?\ncheck := comp"));
+
+        let info = forest.debug_info();
+        assert_eq!(info.comments("check"), [Arc::from("-- equal?")]);
+        let info = info
+            .to_string()
+            .parse()
+            .expect("Failed to parse debug info");
+        assert_eq!(forest.debug_info(), info);
+
+        // The encoded program keeps no names, but debug info restores them
+        let commit = forest.roots()["main"].to_commit_node();
+        let decoded = Forest::from_program_with_debug_info(Arc::clone(&commit), &info);
+        assert_eq!(decoded, forest);
+        assert_eq!(decoded.string_serialize(), serialized);
+        assert_eq!(decoded.source_map(), forest.source_map());
+        assert_ne!(Forest::from_program(commit).string_serialize(), serialized);
+
+        // Comments printed by the serialization are kept when it is parsed again,
+        // but the CMR and IMR comments are not.
+        let reparsed =
+            Forest::<Core>::parse(&serialized).expect("Failed to parse serialized forest");
+        assert_eq!(reparsed.string_serialize(), serialized);
+    }
+
+    #[test]
+    fn debug_info_for_another_program() {
+        // Names are only restored for the nodes that the debug info knows about,
+        // and never given to two different nodes.
+        let info = Forest::<Core>::parse("x := unit : 1 -> 1\nmain := comp x x")
+            .unwrap()
+            .debug_info();
+        let other =
+            Forest::<Core>::parse("main := comp (comp unit unit) (comp unit iden)").unwrap();
+        let decoded =
+            Forest::from_program_with_debug_info(other.roots()["main"].to_commit_node(), &info);
+        let names: HashSet<_> = decoded.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .map(|data| Arc::clone(data.node.name()))
+            .collect();
+        assert!(names.contains("x"));
+        assert!(names.contains("main"));
+        assert_eq!(decoded.roots()["main"].cmr(), other.roots()["main"].cmr());
+    }
+
+    #[test]
+    fn serialize_with_type_aliases() {
+        let s = "
+            type Byte := 2^8
+            type Flag := 1 + Byte
+            a := witness : 1 -> Byte
+            b := witness
+            main := comp (comp (pair a b) jet_eq_8) jet_verify
+            flag := injr (const 0x2a)
+            a <- 0x2a
+            b <- 0x2a
+        ";
+        let forest = Forest::<Core>::parse(s).expect("Failed to parse human encoding");
+        let serialized = forest.string_serialize();
+        assert!(serialized.contains("type Byte := 2^8\n"));
+        assert!(serialized.contains("type Flag := 1 + Byte\n"));
+        assert!(serialized.contains("a := witness : 1 -> Byte\n"));
+        assert!(serialized.contains(": 1 -> Flag"));
+        // Aliases are not used within types that are printed more compactly
+        assert!(serialized.contains(": 1 -> 2^16"));
+
+        let reparsed =
+            Forest::<Core>::parse(&serialized).expect("Failed to parse serialized forest");
+        assert_eq!(reparsed, forest);
+    }
+
+    #[test]
+    fn serialize_templates() {
+        let s = "
+            ident(X) := comp X iden
+            main := comp (ident(unit)) (ident(iden))
+        ";
+        let forest = Forest::<Core>::parse(s).expect("Failed to parse human encoding");
+        let serialized = forest.string_serialize();
+        assert!(serialized.contains("-- ident(unit)\nident'1 := comp"));
+
+        let reparsed =
+            Forest::<Core>::parse(&serialized).expect("Failed to parse serialized forest");
+        assert_eq!(reparsed.roots()["main"].cmr(), forest.roots()["main"].cmr());
+        assert_eq!(reparsed.string_serialize(), serialized);
+    }
+
+    #[test]
+    fn assigned_witness_errors() {
+        let prefix = "
+            a := witness : 1 -> 2^8
+            main := comp (comp a jet_is_zero_8) jet_verify : 1 -> 1
+        ";
+        let bad_length = format!("{}\na <- 0x000", prefix);
+        let err = Forest::<Core>::parse(&bad_length).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("12-bit value is not a valid value of type 2^8 for witness `a`"));
+
+        let not_witness = format!("{}\nmain <- _", prefix);
+        let err = Forest::<Core>::parse(&not_witness).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("name `main` is assigned a value but is not a witness node"));
+
+        let repeated = format!("{}\na <- 0x00\na <- 0x01", prefix);
+        let err = Forest::<Core>::parse(&repeated).unwrap_err();
+        assert!(err.to_string().contains("name `a` occured mulitple times"));
+    }
+
+    #[test]
+    fn source_map_locates_failure() {
+        use crate::bit_machine::ExecTracker;
+        use crate::node::RedeemNode;
+
+        struct LastNode(Option<Imr>);
+
+        impl<J: Jet> ExecTracker<J> for LastNode {
+            fn visit_node(&mut self, node: &RedeemNode<J>) {
+                self.0 = Some(node.imr());
+            }
+        }
+
+        let s = "
+            bit := witness : 1 -> 2
+            check := comp (injl unit) jet_verify : 1 -> 1
+            branch := case (drop check) (drop unit) : 2 * 1 -> 1
+            main := comp (pair bit unit) branch : 1 -> 1
+        ";
+        let forest = Forest::<Core>::parse(&format!("{}\nbit <- 0b0", s))
+            .expect("Failed to parse human encoding");
+        let program = forest
+            .to_witness_node(forest.witness())
+            .expect("Forest has a main root")
+            .finalize_unpruned()
+            .expect("Failed to finalize");
+
+        let mut mac = BitMachine::for_program(&program).expect("program has reasonable bounds");
+        let mut tracker = LastNode(None);
+        mac.exec_with_tracker(&program, &(), &mut tracker)
+            .expect_err("Execution is expected to fail");
+
+        let map = forest.redeem_source_map(&program);
+        let locations = map.by_imr(tracker.0.unwrap());
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].definition.as_ref(), "check");
+        assert_eq!(locations[0].position.line(), 3);
+        assert_eq!(locations[0].to_string(), "`check` at line 3, column 39");
+
+        // Pruning changes the IMR of `main`, but every node of the pruned
+        // program can be mapped back to the source all the same.
+        let forest = Forest::<Core>::parse(&format!("{}\nbit <- 0b1", s))
+            .expect("Failed to parse human encoding");
+        let program = forest
+            .to_redeem_node(&())
+            .expect("Forest has a main root")
+            .expect("Failed to finalize");
+        let map = forest.redeem_source_map(&program);
+        for data in program.as_ref().post_order_iter::<InternalSharing>() {
+            assert!(!map.lookup(data.node).is_empty());
+        }
+        assert_eq!(map.lookup(&program)[0].definition.as_ref(), "main");
+        assert!(forest.source_map().by_imr(program.imr()).is_empty());
+        assert_eq!(
+            forest.source_map().by_cmr(program.cmr())[0]
+                .definition
+                .as_ref(),
+            "main"
+        );
+
+        // Forests which do not come from source have no source map
+        let decoded = Forest::from_program(forest.roots()["main"].to_commit_node());
+        assert_eq!(decoded.source_map(), SourceMap::default());
+    }
 }
@@ -3,7 +3,7 @@
 //! Human-readable Nodes
 
 use crate::dag::{InternalSharing, MaxSharing, PostOrderIterItem};
-use crate::human_encoding::{Error, ErrorSet, Position, WitnessOrHole};
+use crate::human_encoding::{DebugInfo, Error, ErrorSet, Position, SourceLocation, WitnessOrHole};
 use crate::jet::Jet;
 use crate::node::{
     self, Commit, CommitData, CommitNode, Construct, ConstructData, Constructible as _, Converter,
@@ -14,7 +14,7 @@ use crate::types::arrow::{Arrow, FinalArrow};
 use crate::{encode, ConstructNode, Value};
 use crate::{BitWriter, Cmr, Imr};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::marker::PhantomData;
 use std::sync::Arc;
@@ -41,12 +41,31 @@ impl<J: Jet> node::Marker for Named<Commit<J>> {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug)]
 pub struct NamedCommitData<J> {
     /// Data related to the node itself
     internal: Arc<CommitData<J>>,
     /// Name assigned to the node.
     name: Arc<str>,
+    /// Location of the node, if it comes from source code.
+    ///
+    /// Not considered by equality or hashing, so that reformatting the source
+    /// does not change the program.
+    source: Option<SourceLocation>,
+}
+
+impl<J: Jet> PartialEq for NamedCommitData<J> {
+    fn eq(&self, other: &Self) -> bool {
+        self.internal == other.internal && self.name == other.name
+    }
+}
+impl<J: Jet> Eq for NamedCommitData<J> {}
+
+impl<J: Jet> std::hash::Hash for NamedCommitData<J> {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        self.internal.hash(hasher);
+        self.name.hash(hasher);
+    }
 }
 
 impl<J: Jet> NamedCommitNode<J> {
@@ -56,6 +75,21 @@ impl<J: Jet> NamedCommitNode<J> {
             .unwrap()
     }
 
+    /// Names the nodes of a program using debug information, so that they get
+    /// back the names and source locations that they had before it was encoded.
+    ///
+    /// Nodes which have no debug information, or whose name is already taken by
+    /// another node, are given made-up names as [`NamedCommitNode::from_node`] does.
+    pub fn from_node_with_debug_info(root: &CommitNode<J>, debug_info: &DebugInfo) -> Arc<Self> {
+        let mut namer = DebugNamer {
+            namer: Namer::new_rooted(root.cmr()),
+            debug_info,
+            used: HashSet::from([Arc::from("main")]),
+        };
+        root.convert::<MaxSharing<Commit<J>>, _, _>(&mut namer)
+            .unwrap()
+    }
+
     /// Accessor for the node's name
     pub fn name(&self) -> &Arc<str> {
         &self.cached_data().name
@@ -66,6 +100,11 @@ impl<J: Jet> NamedCommitNode<J> {
         self.cached_data().internal.imr()
     }
 
+    /// Accessor for the node's location in the source, if it was parsed from source
+    pub fn source(&self) -> Option<&SourceLocation> {
+        self.cached_data().source.as_ref()
+    }
+
     /// Accessor for the node's type arrow
     pub fn arrow(&self) -> &FinalArrow {
         self.cached_data().internal.arrow()
@@ -233,8 +272,8 @@ pub struct NamedConstructData<J> {
     internal: ConstructData<J>,
     /// Name assigned to the node
     name: Arc<str>,
-    /// Position of the node, if it comes from source code.
-    position: Position,
+    /// Location of the node in the source code.
+    source: SourceLocation,
     /// User-provided type bounds on the source (will be checked for consistency
     /// but only after the type checking has completed.)
     user_source_types: Arc<[types::Type]>,
@@ -248,7 +287,7 @@ impl<J: Jet> NamedConstructNode<J> {
     pub fn new(
         inference_context: &types::Context,
         name: Arc<str>,
-        position: Position,
+        source: SourceLocation,
         user_source_types: Arc<[types::Type]>,
         user_target_types: Arc<[types::Type]>,
         inner: node::Inner<Arc<Self>, J, Arc<Self>, WitnessOrHole>,
@@ -264,7 +303,7 @@ impl<J: Jet> NamedConstructNode<J> {
         let named_data = NamedConstructData {
             internal: construct_data,
             name,
-            position,
+            source,
             user_source_types,
             user_target_types,
         };
@@ -278,7 +317,35 @@ impl<J: Jet> NamedConstructNode<J> {
             user_source_types: Arc::clone(&self.cached_data().user_source_types),
             user_target_types: Arc::clone(&self.cached_data().user_target_types),
             name: new_name,
-            position: self.position(),
+            source: self.cached_data().source.clone(),
+        };
+        Self::from_parts(self.inner().clone(), data)
+    }
+
+    /// Creates a copy of a node with additional user-provided type bounds.
+    pub fn with_type_bounds(
+        &self,
+        user_source_types: &[types::Type],
+        user_target_types: &[types::Type],
+    ) -> Self {
+        let data = NamedConstructData {
+            internal: self.cached_data().internal.clone(),
+            user_source_types: self
+                .cached_data()
+                .user_source_types
+                .iter()
+                .chain(user_source_types)
+                .map(types::Type::shallow_clone)
+                .collect(),
+            user_target_types: self
+                .cached_data()
+                .user_target_types
+                .iter()
+                .chain(user_target_types)
+                .map(types::Type::shallow_clone)
+                .collect(),
+            name: Arc::clone(&self.cached_data().name),
+            source: self.cached_data().source.clone(),
         };
         Self::from_parts(self.inner().clone(), data)
     }
@@ -290,7 +357,7 @@ impl<J: Jet> NamedConstructNode<J> {
 
     /// Accessor for the node's position
     pub fn position(&self) -> Position {
-        self.cached_data().position
+        self.cached_data().source.position
     }
 
     /// Accessor for the node's arrow
@@ -448,6 +515,7 @@ impl<J: Jet> NamedConstructNode<J> {
                 Ok(NamedCommitData {
                     name: Arc::clone(&data.node.cached_data().name),
                     internal: commit_data,
+                    source: Some(data.node.cached_data().source.clone()),
                 })
             }
         }
@@ -583,6 +651,7 @@ impl<J: Jet> Converter<Commit<J>, Named<Commit<J>>> for Namer {
             return Ok(NamedCommitData {
                 internal: Arc::clone(data.node.cached_data()),
                 name: Arc::from("main"),
+                source: None,
             });
         }
 
@@ -592,6 +661,70 @@ impl<J: Jet> Converter<Commit<J>, Named<Commit<J>>> for Namer {
                 self.assign_name(inner.map_witness(WitnessOrHole::from).as_ref())
                     .as_str(),
             ),
+            source: None,
+        })
+    }
+}
+
+/// Names nodes using debug information where it is available, and a [`Namer`]
+/// where it is not.
+struct DebugNamer<'d> {
+    namer: Namer,
+    debug_info: &'d DebugInfo,
+    /// Every name given out so far
+    used: HashSet<Arc<str>>,
+}
+
+impl<J: Jet> Converter<Commit<J>, Named<Commit<J>>> for DebugNamer<'_> {
+    type Error = ();
+    fn convert_witness(
+        &mut self,
+        _: &PostOrderIterItem<&CommitNode<J>>,
+        _: &NoWitness,
+    ) -> Result<NoWitness, Self::Error> {
+        Ok(NoWitness)
+    }
+
+    fn convert_disconnect(
+        &mut self,
+        data: &PostOrderIterItem<&CommitNode<J>>,
+        right: Option<&Arc<NamedCommitNode<J>>>,
+        disc: &NoDisconnect,
+    ) -> Result<Arc<str>, Self::Error> {
+        self.namer.convert_disconnect(data, right, disc)
+    }
+
+    fn convert_data(
+        &mut self,
+        data: &PostOrderIterItem<&CommitNode<J>>,
+        inner: node::Inner<&Arc<NamedCommitNode<J>>, J, &Arc<str>, &NoWitness>,
+    ) -> Result<NamedCommitData<J>, Self::Error> {
+        let node = data.node;
+        let info = self.debug_info.lookup(node.cmr(), node.imr());
+        let source = info.and_then(|info| info.source.clone());
+        if Some(node.cmr()) == self.namer.root_cmr {
+            return Ok(NamedCommitData {
+                internal: Arc::clone(node.cached_data()),
+                name: Arc::from("main"),
+                source,
+            });
+        }
+
+        let inner = inner.map_witness(WitnessOrHole::from);
+        let name = match info {
+            Some(info) if !self.used.contains(&info.name) => Arc::clone(&info.name),
+            _ => loop {
+                let name = self.namer.assign_name(inner.as_ref());
+                if !self.used.contains(name.as_str()) {
+                    break Arc::from(name);
+                }
+            },
+        };
+        self.used.insert(Arc::clone(&name));
+        Ok(NamedCommitData {
+            internal: Arc::clone(node.cached_data()),
+            name,
+            source,
         })
     }
 }
@@ -2,7 +2,8 @@
 
 //! Parsing
 
-use std::mem;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::human_encoding::{Error, ErrorSet, Position, WitnessOrHole};
@@ -10,8 +11,15 @@ use crate::jet::Jet;
 use crate::value::Word;
 use crate::{node, types};
 use crate::{BitIter, Cmr, FailEntropy};
-use santiago::grammar::{Associativity, Grammar};
-use santiago::lexer::{Lexeme, LexerRules};
+
+/// Maximum nesting depth of expressions, types and constants, so that parsing
+/// cannot exhaust the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Maximum depth of an expression, counting the combinators of chains such as
+/// `comp a (comp b c)`, which the parser does not recurse on. The passes after
+/// parsing do recurse on them, so this keeps those from exhausting the stack.
+const MAX_EXPRESSION_DEPTH: usize = 1024;
 
 /// A single non-empty line of a program, of the form x = y :: t
 ///
@@ -22,10 +30,154 @@ pub struct Line<J> {
     pub position: Position,
     /// The name of the expression being named on the line.
     pub name: Arc<str>,
+    /// The parameters of a template, of the form `x(p, q) := y`; empty for
+    /// ordinary definitions and type bounds.
+    pub parameters: Vec<Parameter>,
     /// The actual expression, if present (missing for type declarations).
     pub expression: Option<Expression<J>>,
     /// The type of the expression, if given (inferred if missing).
     pub arrow: (Option<Type>, Option<Type>),
+    /// The definitions and type bounds in the `where` block of a definition,
+    /// which are only visible within the definition.
+    pub locals: Vec<Line<J>>,
+}
+
+/// A parameter of a template
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Parameter {
+    /// Position of the first character of the parameter.
+    pub position: Position,
+    /// The name of the parameter, which may be referred to in the template.
+    pub name: Arc<str>,
+}
+
+/// A witness assignment, of the form `x <- v`
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct WitnessAssignment {
+    /// Position of the first character of the line.
+    pub position: Position,
+    /// The name of the witness node being assigned.
+    pub name: Arc<str>,
+    /// The compact bit encoding of the assigned value.
+    pub data: Vec<u8>,
+    /// The number of bits in the encoding.
+    pub bit_length: usize,
+}
+
+/// An import, of the form `import "path"` or `import "path" as x`
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Import {
+    /// Position of the first character of the line.
+    pub position: Position,
+    /// The path of the imported file, relative to the importing file.
+    pub path: Arc<str>,
+    /// The qualifier for names in the imported file, if any.
+    pub alias: Option<Arc<str>>,
+}
+
+/// A comment describing a definition or type bound
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct DefinitionComment {
+    /// The name being defined or bounded.
+    pub name: Arc<str>,
+    /// The text of the comment, including the leading `--`.
+    pub text: Arc<str>,
+}
+
+/// A type alias, of the form `type X := t`
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct TypeAlias {
+    /// Position of the first character of the line.
+    pub position: Position,
+    /// The name of the alias, which may be used in place of the type.
+    pub name: Arc<str>,
+    /// The type that the alias stands for.
+    pub ty: Type,
+}
+
+/// A parsed program
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Program<J> {
+    /// Definitions and type bounds
+    pub lines: Vec<Line<J>>,
+    /// Names for types
+    pub aliases: Vec<TypeAlias>,
+    /// Values assigned to witness nodes
+    pub witnesses: Vec<WitnessAssignment>,
+    /// Other files whose definitions are used by this one
+    pub imports: Vec<Import>,
+    /// Comments on definitions and type bounds, in order
+    pub comments: Vec<DefinitionComment>,
+}
+
+/// Qualifies a name with the given prefix, which ends in a `.`.
+///
+/// The reserved name `_` is left alone, so that it is still rejected later.
+pub fn qualify(prefix: &str, name: &str) -> Arc<str> {
+    if name == "_" {
+        Arc::from(name)
+    } else {
+        Arc::from(format!("{}{}", prefix, name))
+    }
+}
+
+impl<J: Jet> Line<J> {
+    /// Qualifies the name of the line and every name and hole that it refers to.
+    pub fn qualify(self, prefix: &str) -> Self {
+        Line {
+            position: self.position,
+            name: qualify(prefix, &self.name),
+            parameters: self
+                .parameters
+                .into_iter()
+                .map(|param| Parameter {
+                    position: param.position,
+                    name: qualify(prefix, &param.name),
+                })
+                .collect(),
+            expression: self.expression.map(|expr| expr.qualify(prefix)),
+            arrow: (
+                self.arrow.0.map(|ty| ty.qualify(prefix)),
+                self.arrow.1.map(|ty| ty.qualify(prefix)),
+            ),
+            locals: self
+                .locals
+                .into_iter()
+                .map(|local| local.qualify(prefix))
+                .collect(),
+        }
+    }
+}
+
+impl TypeAlias {
+    /// Qualifies the name of the alias and every name in its type.
+    pub fn qualify(self, prefix: &str) -> Self {
+        TypeAlias {
+            position: self.position,
+            name: qualify(prefix, &self.name),
+            ty: self.ty.qualify(prefix),
+        }
+    }
+}
+
+impl DefinitionComment {
+    /// Qualifies the name that the comment describes.
+    pub fn qualify(self, prefix: &str) -> Self {
+        DefinitionComment {
+            name: qualify(prefix, &self.name),
+            ..self
+        }
+    }
+}
+
+impl WitnessAssignment {
+    /// Qualifies the name of the assigned witness node.
+    pub fn qualify(self, prefix: &str) -> Self {
+        WitnessAssignment {
+            name: qualify(prefix, &self.name),
+            ..self
+        }
+    }
 }
 
 /// An expression, as represented in the AST
@@ -42,6 +194,53 @@ impl<J: Jet> Expression<J> {
             position,
         }
     }
+
+    fn qualify(&self, prefix: &str) -> Self {
+        let qualify_child = |child: &Arc<Expression<J>>| Arc::new(child.qualify(prefix));
+        let qualify_cmr = |cmr: &AstCmr<J>| match cmr {
+            AstCmr::Expr(expr) => AstCmr::Expr(qualify_child(expr)),
+            AstCmr::Literal(cmr) => AstCmr::Literal(*cmr),
+        };
+        let inner = match self.inner {
+            ExprInner::Reference(ref name) => ExprInner::Reference(qualify(prefix, name)),
+            ExprInner::Instance(ref name, ref arguments) => ExprInner::Instance(
+                qualify(prefix, name),
+                arguments.iter().map(qualify_child).collect(),
+            ),
+            ExprInner::AssertL(ref left, ref cmr) => {
+                ExprInner::AssertL(qualify_child(left), qualify_cmr(cmr))
+            }
+            ExprInner::AssertR(ref cmr, ref right) => {
+                ExprInner::AssertR(qualify_cmr(cmr), qualify_child(right))
+            }
+            ExprInner::Constant(ref constant) => {
+                ExprInner::Constant(constant.map_cmrs(&mut |cmr| qualify_cmr(cmr)))
+            }
+            ExprInner::Ascribed(ref child, ref arrow) => ExprInner::Ascribed(
+                qualify_child(child),
+                (
+                    arrow.0.clone().map(|ty| ty.qualify(prefix)),
+                    arrow.1.clone().map(|ty| ty.qualify(prefix)),
+                ),
+            ),
+            ExprInner::Inline(ref inner) => ExprInner::Inline(
+                inner
+                    .as_ref()
+                    .map(qualify_child)
+                    .map_disconnect(qualify_child)
+                    .map_witness(|witness| match witness {
+                        WitnessOrHole::Witness => WitnessOrHole::Witness,
+                        WitnessOrHole::TypedHole(name) => {
+                            WitnessOrHole::TypedHole(qualify(prefix, name))
+                        }
+                    }),
+            ),
+        };
+        Expression {
+            inner,
+            position: self.position,
+        }
+    }
 }
 
 /// An expression, as represented in the AST
@@ -49,10 +248,16 @@ impl<J: Jet> Expression<J> {
 pub enum ExprInner<J> {
     /// A reference to another expression
     Reference(Arc<str>),
+    /// An instance of a template, with the given arguments
+    Instance(Arc<str>, Vec<Arc<Expression<J>>>),
     /// A left assertion (referring to the CMR of an expression on the right)
     AssertL(Arc<Expression<J>>, AstCmr<J>),
     /// A right assertion (referring to the CMR of an expression on the left)
     AssertR(AstCmr<J>, Arc<Expression<J>>),
+    /// A structured constant, such as `const (0x02, L(()))`
+    Constant(Constant<J>),
+    /// An expression with a type annotation, of the form `(x : A -> B)`
+    Ascribed(Arc<Expression<J>>, (Option<Type>, Option<Type>)),
     /// An inline expression
     Inline(node::Inner<Arc<Expression<J>>, J, Arc<Expression<J>>, WitnessOrHole>),
 }
@@ -61,7 +266,59 @@ pub enum ExprInner<J> {
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum AstCmr<J> {
     Expr(Arc<Expression<J>>),
-    Literal,
+    Literal(Cmr),
+}
+
+/// The value of a `const` expression, as represented in the AST
+///
+/// A constant which is a single binary or hex literal is parsed directly as a
+/// word, and is never represented this way.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Constant<J> {
+    /// The unit value `()`
+    Unit,
+    /// A pair of values `(a, b)`
+    Pair(Box<Constant<J>>, Box<Constant<J>>),
+    /// A left injection `L(a)`
+    Left(Box<Constant<J>>),
+    /// A right injection `R(a)`
+    Right(Box<Constant<J>>),
+    /// A binary or hex literal, whose length is a power of two
+    Word(Word),
+    /// A decimal integer, whose width is given by the type of the constant
+    Decimal(Arc<str>),
+    /// A CMR, as a 256-bit word
+    Cmr(AstCmr<J>),
+}
+
+impl<J: Jet> Constant<J> {
+    /// Replaces every CMR within the constant.
+    pub fn map_cmrs<F: FnMut(&AstCmr<J>) -> AstCmr<J>>(&self, f: &mut F) -> Self {
+        match self {
+            Constant::Unit => Constant::Unit,
+            Constant::Pair(left, right) => {
+                Constant::Pair(Box::new(left.map_cmrs(f)), Box::new(right.map_cmrs(f)))
+            }
+            Constant::Left(inner) => Constant::Left(Box::new(inner.map_cmrs(f))),
+            Constant::Right(inner) => Constant::Right(Box::new(inner.map_cmrs(f))),
+            Constant::Word(word) => Constant::Word(word.shallow_clone()),
+            Constant::Decimal(digits) => Constant::Decimal(Arc::clone(digits)),
+            Constant::Cmr(cmr) => Constant::Cmr(f(cmr)),
+        }
+    }
+
+    /// Calls the given function on every CMR within the constant.
+    pub fn for_each_cmr<'c, F: FnMut(&'c AstCmr<J>)>(&'c self, f: &mut F) {
+        match self {
+            Constant::Pair(left, right) => {
+                left.for_each_cmr(f);
+                right.for_each_cmr(f);
+            }
+            Constant::Left(inner) | Constant::Right(inner) => inner.for_each_cmr(f),
+            Constant::Cmr(cmr) => f(cmr),
+            Constant::Unit | Constant::Word(_) | Constant::Decimal(_) => {}
+        }
+    }
 }
 
 /// A type, as represented in the AST
@@ -82,6 +339,69 @@ pub enum Type {
 }
 
 impl Type {
+    /// Qualifies every type variable in the type.
+    fn qualify(self, prefix: &str) -> Self {
+        match self {
+            Type::Name(s) => Type::Name(format!("{}{}", prefix, s)),
+            Type::Product(left, right) => Type::Product(
+                Box::new(left.qualify(prefix)),
+                Box::new(right.qualify(prefix)),
+            ),
+            Type::Sum(left, right) => Type::Sum(
+                Box::new(left.qualify(prefix)),
+                Box::new(right.qualify(prefix)),
+            ),
+            ty => ty,
+        }
+    }
+
+    /// Replaces every name which is a type alias by the type it stands for.
+    ///
+    /// The aliases must already have been expanded within each other.
+    pub fn expand_aliases(self, aliases: &HashMap<Arc<str>, Type>) -> Self {
+        match self {
+            Type::Name(s) => match aliases.get(s.as_str()) {
+                Some(ty) => ty.clone(),
+                None => Type::Name(s),
+            },
+            Type::Product(left, right) => Type::Product(
+                Box::new(left.expand_aliases(aliases)),
+                Box::new(right.expand_aliases(aliases)),
+            ),
+            Type::Sum(left, right) => Type::Sum(
+                Box::new(left.expand_aliases(aliases)),
+                Box::new(right.expand_aliases(aliases)),
+            ),
+            ty => ty,
+        }
+    }
+
+    /// Calls the given function on every name in the type.
+    pub fn for_each_name<F: FnMut(&str)>(&self, f: &mut F) {
+        match self {
+            Type::Name(s) => f(s),
+            Type::Product(left, right) | Type::Sum(left, right) => {
+                left.for_each_name(f);
+                right.for_each_name(f);
+            }
+            _ => {}
+        }
+    }
+
+    /// Converts to a complete type, if the type has no free type variables.
+    pub fn to_final(&self) -> Option<Arc<types::Final>> {
+        match self {
+            Type::Name(_) => None,
+            Type::One => Some(types::Final::unit()),
+            Type::Two => Some(types::Final::two_two_n(0)),
+            Type::Product(left, right) => {
+                Some(types::Final::product(left.to_final()?, right.to_final()?))
+            }
+            Type::Sum(left, right) => Some(types::Final::sum(left.to_final()?, right.to_final()?)),
+            Type::TwoTwoN(n) => Some(types::Final::two_two_n(*n as usize)),
+        }
+    }
+
     /// Convert to a Simplicity type
     pub fn reify(self, ctx: &types::Context) -> types::Type {
         match self {
@@ -107,693 +427,1226 @@ impl Type {
 }
 
 /// Takes a program as a string and parses it into an AST (actually, a vector
-/// of lines, each of which is individually an AST)
-pub fn parse_line_vector<J: Jet + 'static>(input: &str) -> Result<Vec<Line<J>>, ErrorSet> {
-    let lexer_rules = lexer_rules();
-    let grammar = grammar::<J>();
-
-    let lexemes = match santiago::lexer::lex(&lexer_rules, input) {
-        Ok(lexemes) => lexemes,
-        Err(err) => return Err(err.into()),
+/// of lines, each of which is individually an AST, and a vector of witness
+/// assignments)
+///
+/// Parsing does not stop at the first error. After a syntax error the parser
+/// skips ahead to the next line, so that every error in the input is reported
+/// in a single pass.
+///
+/// Imports are not followed.
+pub fn parse_program<J: Jet + 'static>(input: &str) -> Result<Program<J>, ErrorSet> {
+    parse_source(input, 0)
+}
+
+/// Parses one source file of a program, as [`parse_program`] does, with every
+/// position in the returned program and errors referring to the given file index.
+pub fn parse_source<J: Jet + 'static>(input: &str, file: usize) -> Result<Program<J>, ErrorSet> {
+    parse_with_layout(input, file).map(|(program, _)| program)
+}
+
+/// Returns the length in characters of each token in a source file, keyed by
+/// the position at which it starts.
+pub fn token_lengths(input: &str, file: usize) -> HashMap<Position, usize> {
+    lex(input, file, &mut vec![], &mut ErrorSet::new())
+        .into_iter()
+        .map(|tok| (tok.position, tok.raw.chars().count()))
+        .collect()
+}
+
+/// A comment, which has no meaning to the parser but is kept for formatting
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Comment {
+    /// Position of the leading `--`.
+    pub position: Position,
+    /// The text of the comment, including the leading `--`.
+    pub text: Arc<str>,
+}
+
+/// The parts of the source text which are not represented in the AST
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Layout {
+    /// Every comment, in order.
+    pub comments: Vec<Comment>,
+    /// The position of every token, in order.
+    pub token_positions: Vec<Position>,
+}
+
+/// Parses one source file of a program, as [`parse_source`] does, also returning
+/// the comments and token positions that a formatter needs to reproduce it.
+pub fn parse_with_layout<J: Jet + 'static>(
+    input: &str,
+    file: usize,
+) -> Result<(Program<J>, Layout), ErrorSet> {
+    let mut errors = ErrorSet::new();
+    let mut comments = vec![];
+    let tokens = lex(input, file, &mut comments, &mut errors);
+    let token_positions = tokens.iter().map(|tok| tok.position).collect();
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end_position: end_position(input, file),
+        errors,
+        depth: 0,
+        expression_depth: 0,
     };
-    let trees = santiago::parser::parse(&grammar, &lexemes)?;
-    assert_eq!(trees.len(), 1, "ambiguous parse (this is a bug)");
-    match trees[0].as_abstract_syntax_tree() {
-        Ast::Program(lines) => Ok(lines),
-        Ast::Error(errs) => Err(errs),
-        x => unreachable!(
-            "Parsed program into non-program non-error {:?}; this is a bug.",
-            x
-        ),
+
+    let mut program = parser.program();
+    let layout = Layout {
+        comments,
+        token_positions,
+    };
+    program.comments = definition_comments(&program.lines, &layout);
+    parser.errors.into_result((program, layout))
+}
+
+/// Finds the comments describing each definition and type bound: the comments
+/// on their own lines just before it, and the comment at the end of its first
+/// line.
+///
+/// The `-- CMR:` and `-- IMR:` comments which
+/// [`crate::human_encoding::Forest::string_serialize`] prints before each
+/// definition are skipped, since they are not written by hand.
+fn definition_comments<J>(lines: &[Line<J>], layout: &Layout) -> Vec<DefinitionComment> {
+    let token_lines: HashSet<usize> = layout.token_positions.iter().map(|pos| pos.line).collect();
+    let comments: HashMap<usize, &Comment> = layout
+        .comments
+        .iter()
+        .filter(|comment| {
+            !comment.text.starts_with("-- CMR: ") && !comment.text.starts_with("-- IMR: ")
+        })
+        .map(|comment| (comment.position.line, comment))
+        .collect();
+
+    let mut ret = vec![];
+    for line in lines {
+        let mut start = line.position.line;
+        while start > 1
+            && comments.contains_key(&(start - 1))
+            && !token_lines.contains(&(start - 1))
+        {
+            start -= 1;
+        }
+        for n in start..=line.position.line {
+            if let Some(comment) = comments.get(&n) {
+                ret.push(DefinitionComment {
+                    name: Arc::clone(&line.name),
+                    text: Arc::clone(&comment.text),
+                });
+            }
+        }
     }
+    ret
 }
 
-/// Check a list of AST elements for errors; if any are errors, combine them and return the result
-fn propagate_errors<J: Jet>(ast: &[Ast<J>]) -> Option<Ast<J>> {
-    let mut e = ErrorSet::new();
-    for elem in ast {
-        if let Ast::Error(errs) = elem {
-            e.merge(errs);
+/// The kind of a lexical token
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    /// `const`
+    Const,
+    /// A combinator or jet without children
+    Nullary,
+    /// A combinator with a single child
+    Unary,
+    /// A combinator with two children
+    Binary,
+    /// `assertl`
+    AssertL,
+    /// `assertr`
+    AssertR,
+    /// `fail`
+    Fail,
+    /// `_`
+    Underscore,
+    /// A binary or hex literal
+    Literal,
+    /// A name which is not a reserved symbol
+    Symbol,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `+`
+    Plus,
+    /// `*`
+    Star,
+    /// `->`
+    Arrow,
+    /// `:`
+    Colon,
+    /// `:=`
+    Define,
+    /// `<-`
+    Assign,
+    /// `1`
+    One,
+    /// `2`
+    Two,
+    /// `2^n`
+    TwoExp,
+    /// A decimal number other than `1` or `2`
+    Number,
+    /// `#` followed by 64 hex digits
+    CmrLiteral,
+    /// `#{`
+    CmrOpen,
+    /// `}`
+    CmrClose,
+    /// `?`
+    Question,
+    /// `,`
+    Comma,
+    /// `{`
+    LBrace,
+    /// `;`
+    Semicolon,
+    /// A double-quoted string
+    String,
+}
+
+/// A lexical token, borrowing its text from the input
+#[derive(Copy, Clone, Debug)]
+struct Token<'s> {
+    kind: TokenKind,
+    raw: &'s str,
+    position: Position,
+}
+
+fn is_symbol_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || matches!(ch, '_' | '-' | '.' | '\'')
+}
+
+fn is_symbol_char(ch: char) -> bool {
+    ch.is_ascii_digit() || is_symbol_start(ch)
+}
+
+/// Classifies a symbol-like word as either a reserved symbol or a name.
+fn symbol_kind(word: &str) -> TokenKind {
+    match word {
+        "_" => TokenKind::Underscore,
+        "const" => TokenKind::Const,
+        "unit" | "iden" | "witness" => TokenKind::Nullary,
+        "injl" | "injr" | "take" | "drop" => TokenKind::Unary,
+        "case" | "comp" | "pair" | "disconnect" => TokenKind::Binary,
+        "assertl" => TokenKind::AssertL,
+        "assertr" => TokenKind::AssertR,
+        "fail" => TokenKind::Fail,
+        _ if word.starts_with("jet_")
+            && word[4..]
+                .chars()
+                .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_') =>
+        {
+            TokenKind::Nullary
         }
+        _ => TokenKind::Symbol,
     }
-    if e.is_empty() {
-        None
-    } else {
-        Some(Ast::Error(e))
+}
+
+/// Returns the position just after the last character of the input.
+fn end_position(input: &str, file: usize) -> Position {
+    let mut position = Position {
+        file,
+        line: 1,
+        column: 1,
+    };
+    for ch in input.chars() {
+        position.advance(ch);
     }
+    position
 }
 
-/// Main AST structure
+/// Splits the input into tokens, in a single linear pass.
 ///
-/// This structure is never really instantiated; during construction it is
-/// continually collapsed until in the end it will be in either the `Program`
-/// or `Error` variant.
-#[derive(Debug, Clone)]
-enum Ast<J: Jet> {
-    Combinator {
-        comb: node::Inner<(), J, (), WitnessOrHole>,
-        position: Position,
-    },
-    /// A type->type arrow
-    Arrow(Option<Type>, Option<Type>),
-    /// A #{expr} or #abcd CMR
-    Cmr(AstCmr<J>),
-    /// An error occurred during parsing
-    Error(ErrorSet),
-    /// An expression
-    Expression(Expression<J>),
-    /// A full expression line
-    Line(Line<J>),
-    /// A hex or binary literal
-    Literal {
-        data: Vec<u8>,
-        bit_length: usize,
-        position: Position,
-    },
-    /// The top-level program
-    Program(Vec<Line<J>>),
-    /// A symbol
-    Symbol { value: Arc<str>, position: Position },
-    /// A type
-    Type(Option<Type>),
-    /// Dummy value used internally in the parser when building the tree.
-    ///
-    /// Any parse objects which have no information in themselves (e.g. the
-    /// plus or star symbols) but which are just used to shape the parse
-    /// tree, get mapped to this value and then discarded.
-    Dummy { position: Position },
-    /// Dummy value used when manipulating the tree in-place, to replace
-    /// data that we move out of the tree
-    Replaced,
-}
-
-impl<J: Jet> Ast<J> {
-    /// Creates an `Ast` from a single sub-AST
-    fn from_1<T, F1, F>(toks: &mut [Self], convert: F1, unconvert: F) -> Self
-    where
-        F1: FnOnce(&mut Self) -> T,
-        F: FnOnce(T) -> Self,
-    {
-        if let Some(e) = propagate_errors(toks) {
-            return e;
-        }
-        assert_eq!(toks.len(), 1);
-        unconvert(convert(&mut toks[0]))
+/// Whitespace is dropped and comments are collected into `comments`. Characters
+/// which cannot start any token are reported in `errors` and skipped.
+fn lex<'s>(
+    input: &'s str,
+    file: usize,
+    comments: &mut Vec<Comment>,
+    errors: &mut ErrorSet,
+) -> Vec<Token<'s>> {
+    // Returns the length of the prefix of `s` consisting of `pred` characters.
+    fn prefix_len(s: &str, pred: fn(char) -> bool) -> usize {
+        s.find(|ch: char| !pred(ch)).unwrap_or(s.len())
     }
-
-    /// Creates an `Ast` from two sub-`Ast`s with a dummy in between
-    fn from_2<T, U, F1, F2, F>(toks: &mut [Self], convert1: F1, convert2: F2, unconvert: F) -> Self
-    where
-        F1: FnOnce(&mut Self) -> T,
-        F2: FnOnce(&mut Self) -> U,
-        F: FnOnce(T, U) -> Self,
-    {
-        if let Some(e) = propagate_errors(toks) {
-            return e;
-        }
-        assert_eq!(toks.len(), 3);
-        toks[1].expect_position();
-        unconvert(convert1(&mut toks[0]), convert2(&mut toks[2]))
-    }
-
-    /// Creates an `Ast` from three sub-`Ast`s with dummies in between
-    fn from_3<T, U, V, F1, F2, F3, F>(
-        toks: &mut [Self],
-        convert1: F1,
-        convert2: F2,
-        convert3: F3,
-        unconvert: F,
-    ) -> Self
-    where
-        F1: FnOnce(&mut Self) -> T,
-        F2: FnOnce(&mut Self) -> U,
-        F3: FnOnce(&mut Self) -> V,
-        F: FnOnce(T, U, V) -> Self,
-    {
-        if let Some(e) = propagate_errors(toks) {
-            return e;
-        }
-        assert_eq!(toks.len(), 5);
-        toks[1].expect_position();
-        toks[3].expect_position();
-        unconvert(
-            convert1(&mut toks[0]),
-            convert2(&mut toks[2]),
-            convert3(&mut toks[4]),
-        )
+    fn is_bin_digit(ch: char) -> bool {
+        ch == '0' || ch == '1'
+    }
+    fn is_hex_digit(ch: char) -> bool {
+        ch.is_ascii_digit() || ('a'..='f').contains(&ch)
     }
 
-    /// Creates an AST from a combinator with no arguments
-    fn from_combinator(mut toks: Vec<Self>) -> Self {
-        if let Some(e) = propagate_errors(&toks) {
-            return e;
-        }
+    let mut tokens = vec![];
+    let mut rest = input;
+    let mut position = Position {
+        file,
+        line: 1,
+        column: 1,
+    };
 
-        let (comb, position) = match mem::replace(&mut toks[0], Ast::Replaced) {
-            Ast::Combinator { comb, position } => (comb, position),
-            _ => unreachable!(),
+    while let Some(ch) = rest.chars().next() {
+        // Whitespace is not significant except to separate other tokens, and
+        // comments run until the end of the line.
+        let skip_len = if ch.is_whitespace() {
+            ch.len_utf8()
+        } else if rest.starts_with("--") {
+            let len = rest.find('\n').map_or(rest.len(), |n| n + 1);
+            comments.push(Comment {
+                position,
+                text: Arc::from(rest[..len].trim_end()),
+            });
+            len
+        } else {
+            0
         };
+        if skip_len > 0 {
+            rest[..skip_len].chars().for_each(|ch| position.advance(ch));
+            rest = &rest[skip_len..];
+            continue;
+        }
 
-        // This stupid construction is needed to avoid borrowck rules
-        // around borrowing tok[1] and tok[2] mutably at the same time.
-        let arcs: Vec<Arc<_>> = toks[1..]
-            .iter_mut()
-            .map(Ast::expect_expression)
-            .map(Arc::new)
-            .collect();
-        let inner = comb
-            .map_left_right(|_| Arc::clone(&arcs[0]), |_| Arc::clone(&arcs[1]))
-            .map_disconnect(|_| Arc::clone(&arcs[1]));
-        Ast::Expression(Expression {
-            inner: ExprInner::Inline(inner),
-            position,
-        })
-    }
-
-    /// Creates an AST from a dummy lexeme
-    fn from_dummy_lexeme(lexemes: &[&std::rc::Rc<Lexeme>]) -> Self {
-        assert_eq!(lexemes.len(), 1);
-        let position = (&lexemes[0].position).into();
-        Ast::Dummy { position }
-    }
-
-    /// Creates an AST from a lexeme which forms a complete type
-    fn from_type_lexeme(lexemes: &[&std::rc::Rc<Lexeme>]) -> Self {
-        assert_eq!(lexemes.len(), 1);
-        let position = &lexemes[0].position;
-        match lexemes[0].raw.as_str() {
-            "1" => Ast::Type(Some(Type::One)),
-            "2" => Ast::Type(Some(Type::Two)),
-            other => {
-                assert_eq!(&other[..2], "2^");
-                match str::parse::<u32>(&other[2..]) {
-                    // TODO how many of these should we support?
-                    Ok(0) => Ast::Type(Some(Type::One)),
-                    Ok(1) => Ast::Type(Some(Type::Two)),
-                    Ok(2) => Ast::Type(Some(Type::TwoTwoN(1))),
-                    Ok(4) => Ast::Type(Some(Type::TwoTwoN(2))),
-                    Ok(8) => Ast::Type(Some(Type::TwoTwoN(3))),
-                    Ok(16) => Ast::Type(Some(Type::TwoTwoN(4))),
-                    Ok(32) => Ast::Type(Some(Type::TwoTwoN(5))),
-                    Ok(64) => Ast::Type(Some(Type::TwoTwoN(6))),
-                    Ok(128) => Ast::Type(Some(Type::TwoTwoN(7))),
-                    Ok(256) => Ast::Type(Some(Type::TwoTwoN(8))),
-                    Ok(512) => Ast::Type(Some(Type::TwoTwoN(9))),
-                    Ok(y) => Ast::Error(ErrorSet::single(position, Error::Bad2ExpNumber(y))),
-                    Err(_) => Ast::Error(ErrorSet::single(
+        // Strings are the only tokens which may contain non-ASCII characters,
+        // so they are handled separately. They have no escapes and may not span
+        // multiple lines.
+        if ch == '"' {
+            let len = match rest[1..].find(['"', '\n']) {
+                Some(n) if rest[1 + n..].starts_with('"') => {
+                    tokens.push(Token {
+                        kind: TokenKind::String,
+                        raw: &rest[..n + 2],
                         position,
-                        Error::NumberOutOfRange(other.to_owned()),
-                    )),
+                    });
+                    n + 2
                 }
-            }
+                _ => {
+                    errors.add(position, Error::LexFailed("unterminated string".into()));
+                    rest.find('\n').unwrap_or(rest.len())
+                }
+            };
+            rest[..len].chars().for_each(|ch| position.advance(ch));
+            rest = &rest[len..];
+            continue;
         }
-    }
 
-    /// Creates an AST from a combinator lexeme
-    fn from_combinator_lexeme(lexemes: &[&std::rc::Rc<Lexeme>]) -> Self {
-        assert_eq!(lexemes.len(), 1);
-        let position = (&lexemes[0].position).into();
-        let comb = match lexemes[0].raw.as_str() {
-            "unit" => node::Inner::Unit,
-            "iden" => node::Inner::Iden,
-            "injl" => node::Inner::InjL(()),
-            "injr" => node::Inner::InjR(()),
-            "take" => node::Inner::Take(()),
-            "drop" => node::Inner::Drop(()),
-            "comp" => node::Inner::Comp((), ()),
-            "case" => node::Inner::Case((), ()),
-            "assertl" => node::Inner::AssertL((), Cmr::unit()),
-            "assertr" => node::Inner::AssertR(Cmr::unit(), ()),
-            "pair" => node::Inner::Pair((), ()),
-            "disconnect" => node::Inner::Disconnect((), ()),
-            "witness" => node::Inner::Witness(WitnessOrHole::Witness),
-            "fail" => node::Inner::Fail(FailEntropy::ZERO),
-            other => {
-                assert_eq!(&other[..4], "jet_");
-                if let Ok(jet) = J::from_str(&other[4..]) {
-                    node::Inner::Jet(jet)
-                } else {
-                    return Ast::Error(ErrorSet::single(
+        // All other tokens are ASCII and none of them contain newlines, so the
+        // token length in bytes is also its length in columns.
+        let (kind, len) = if rest.starts_with("->") {
+            (TokenKind::Arrow, 2)
+        } else if rest.starts_with(":=") {
+            (TokenKind::Define, 2)
+        } else if rest.starts_with("<-") {
+            (TokenKind::Assign, 2)
+        } else if rest.starts_with("#{") {
+            (TokenKind::CmrOpen, 2)
+        } else if ch == '#' && prefix_len(&rest[1..], |ch| ch.is_ascii_hexdigit()) >= 64 {
+            (TokenKind::CmrLiteral, 65)
+        } else if rest.starts_with("0b") && prefix_len(&rest[2..], is_bin_digit) > 0 {
+            (TokenKind::Literal, 2 + prefix_len(&rest[2..], is_bin_digit))
+        } else if rest.starts_with("0x") && prefix_len(&rest[2..], is_hex_digit) > 0 {
+            (TokenKind::Literal, 2 + prefix_len(&rest[2..], is_hex_digit))
+        } else if rest.starts_with("2^") && rest[2..].starts_with(|ch| ('1'..='9').contains(&ch)) {
+            (
+                TokenKind::TwoExp,
+                2 + prefix_len(&rest[2..], |ch| ch.is_ascii_digit()),
+            )
+        } else if is_symbol_start(ch) {
+            let len = prefix_len(rest, is_symbol_char);
+            (symbol_kind(&rest[..len]), len)
+        } else if ch.is_ascii_digit() {
+            let len = prefix_len(rest, |ch| ch.is_ascii_digit());
+            let kind = match &rest[..len] {
+                "1" => TokenKind::One,
+                "2" => TokenKind::Two,
+                _ => TokenKind::Number,
+            };
+            (kind, len)
+        } else {
+            let kind = match ch {
+                '(' => TokenKind::LParen,
+                ')' => TokenKind::RParen,
+                '+' => TokenKind::Plus,
+                '*' => TokenKind::Star,
+                ':' => TokenKind::Colon,
+                '{' => TokenKind::LBrace,
+                '}' => TokenKind::CmrClose,
+                '?' => TokenKind::Question,
+                ',' => TokenKind::Comma,
+                ';' => TokenKind::Semicolon,
+                _ => {
+                    errors.add(
                         position,
-                        Error::UnknownJet(other.to_owned()),
-                    ));
+                        Error::LexFailed(format!("unexpected character `{}`", ch)),
+                    );
+                    position.advance(ch);
+                    rest = &rest[ch.len_utf8()..];
+                    continue;
                 }
-            }
+            };
+            (kind, 1)
         };
-        Ast::Combinator { comb, position }
+
+        tokens.push(Token {
+            kind,
+            raw: &rest[..len],
+            position,
+        });
+        position.column += len;
+        rest = &rest[len..];
     }
 
-    /// Creates an AST from a literal lexeme
-    fn from_literal_lexeme(lexemes: &[&std::rc::Rc<Lexeme>]) -> Self {
-        assert_eq!(lexemes.len(), 1);
-        let position = (&lexemes[0].position).into();
+    tokens
+}
 
-        if lexemes[0].raw == "_" {
-            return Ast::Literal {
-                data: vec![],
-                bit_length: 0,
-                position,
-            };
+/// Marker indicating that the parser hit a syntax error and needs to skip ahead
+/// to a point where it can resume parsing.
+///
+/// The error itself has already been recorded when this is returned.
+struct Desync;
+
+/// A combinator in a chain of expressions, each of which is the last child of
+/// the one before, whose last child has yet to be parsed.
+enum ChainLink<'s, J: Jet> {
+    /// A unary combinator
+    Unary(Token<'s>),
+    /// A binary combinator, with its left child
+    Binary(Token<'s>, Option<Expression<J>>),
+}
+
+impl<'s, J: Jet> ChainLink<'s, J> {
+    /// The expression of the combinator with the given last child, if neither
+    /// it nor the left child contained an error.
+    fn complete(self, child: Option<Expression<J>>) -> Option<Expression<J>> {
+        let child = child.map(Arc::new);
+        let (tok, inner) = match self {
+            ChainLink::Unary(tok) => (
+                tok,
+                child.map(|child| match tok.raw {
+                    "injl" => node::Inner::InjL(child),
+                    "injr" => node::Inner::InjR(child),
+                    "take" => node::Inner::Take(child),
+                    "drop" => node::Inner::Drop(child),
+                    _ => unreachable!("unary combinators are lexed by name"),
+                }),
+            ),
+            ChainLink::Binary(tok, left) => (
+                tok,
+                left.map(Arc::new)
+                    .zip(child)
+                    .map(|(left, right)| match tok.raw {
+                        "comp" => node::Inner::Comp(left, right),
+                        "case" => node::Inner::Case(left, right),
+                        "pair" => node::Inner::Pair(left, right),
+                        "disconnect" => node::Inner::Disconnect(left, right),
+                        _ => unreachable!("binary combinators are lexed by name"),
+                    }),
+            ),
+        };
+        inner.map(|inner| Expression {
+            inner: ExprInner::Inline(inner),
+            position: tok.position,
+        })
+    }
+}
+
+/// Recursive-descent parser over a vector of tokens.
+struct Parser<'s> {
+    tokens: Vec<Token<'s>>,
+    index: usize,
+    end_position: Position,
+    errors: ErrorSet,
+    /// Number of expressions, types and constants being parsed
+    depth: usize,
+    /// Depth of the expression being parsed, including chains
+    expression_depth: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&self) -> Option<Token<'s>> {
+        self.tokens.get(self.index).copied()
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.peek().map(|tok| tok.kind)
+    }
+
+    /// Records a syntax error at the current token and returns [`Desync`].
+    fn unexpected<T>(&mut self, expected: &'static str) -> Result<T, Desync> {
+        match self.peek() {
+            Some(tok) => self.errors.add(
+                tok.position,
+                Error::ParseFailed {
+                    expected,
+                    found: Some(Arc::from(tok.raw)),
+                },
+            ),
+            None => self.errors.add(
+                self.end_position,
+                Error::ParseFailed {
+                    expected,
+                    found: None,
+                },
+            ),
         }
+        Err(Desync)
+    }
 
-        let s = &lexemes[0].raw[2..];
-        if &lexemes[0].raw[..2] == "0x" {
-            let bit_length = s.len() * 4;
-            let mut data = Vec::with_capacity((s.len() + 1) / 2);
-            for idx in 0..s.len() / 2 {
-                data.push(u8::from_str_radix(&s[2 * idx..2 * idx + 2], 16).unwrap());
-            }
-            if s.len() % 2 == 1 {
-                data.push(u8::from_str_radix(&s[s.len() - 1..], 16).unwrap() << 4);
-            }
+    /// Parses something nested one level deeper, or records an error if the
+    /// nesting is too deep.
+    fn nested<T, F>(&mut self, parse: F) -> Result<T, Desync>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Desync>,
+    {
+        if self.depth >= MAX_DEPTH {
+            let position = self.peek().map_or(self.end_position, |tok| tok.position);
+            self.errors.add(position, Error::NestingTooDeep);
+            return Err(Desync);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
 
-            Ast::Literal {
-                data,
-                bit_length,
-                position,
+    /// Consumes a token of the given kind, or records a syntax error.
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<Token<'s>, Desync> {
+        match self.peek() {
+            Some(tok) if tok.kind == kind => {
+                self.index += 1;
+                Ok(tok)
             }
-        } else {
-            assert_eq!(&lexemes[0].raw[..2], "0b");
+            _ => self.unexpected(expected),
+        }
+    }
 
-            let bit_length = s.len();
-            let mut data = Vec::with_capacity((s.len() + 7) / 8);
-            let mut x = 0;
-            for (n, ch) in s.chars().enumerate() {
-                match ch {
-                    '0' => {}
-                    '1' => x |= 1 << (7 - (n % 8)),
-                    _ => unreachable!(),
-                }
-                if n % 8 == 7 {
-                    data.push(x);
-                    x = 0;
+    /// Whether the current token is a name (or template name and parameters)
+    /// followed by `:=` or `<-`, is `import` followed by a string, or is `type`
+    /// followed by a name and `:=`.
+    ///
+    /// This always starts a new definition, witness assignment, import or type
+    /// alias, since an expression is never followed by `:=`, `<-` or a string.
+    fn at_definition(&self) -> bool {
+        if self.at_import() || self.at_type_alias() {
+            return true;
+        }
+        let mut index = self.index + 1;
+        if self.at_call() {
+            // Skip the parameters, which must all be names.
+            index += 1;
+            loop {
+                match (self.tokens.get(index), self.tokens.get(index + 1)) {
+                    (Some(tok), Some(next)) if tok.kind == TokenKind::Symbol => match next.kind {
+                        TokenKind::Comma => index += 2,
+                        TokenKind::RParen => {
+                            index += 2;
+                            break;
+                        }
+                        _ => return false,
+                    },
+                    _ => return false,
                 }
             }
-            if s.len() % 8 != 0 {
-                data.push(x);
-            }
-
-            Ast::Literal {
-                data,
-                bit_length,
-                position,
+        }
+        match (self.peek(), self.tokens.get(index)) {
+            (Some(tok), Some(next)) => {
+                matches!(tok.kind, TokenKind::Symbol | TokenKind::Underscore)
+                    && matches!(next.kind, TokenKind::Define | TokenKind::Assign)
             }
+            _ => false,
         }
     }
 
-    /// Creates an AST from a CMR literal lexeme
-    fn from_cmr_literal_lexeme(lexemes: &[&std::rc::Rc<Lexeme>]) -> Self {
-        assert_eq!(lexemes.len(), 1);
-        assert_eq!(lexemes[0].raw.len(), 65);
-
-        Ast::Cmr(AstCmr::Literal)
+    /// Whether the current token is a name immediately followed by `(`, with
+    /// no space between them, which starts a template or an instance of one.
+    fn at_call(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.index + 1)) {
+            (Some(tok), Some(next)) => {
+                tok.kind == TokenKind::Symbol
+                    && next.kind == TokenKind::LParen
+                    && next.position.line == tok.position.line
+                    && next.position.column == tok.position.column + tok.raw.len()
+            }
+            _ => false,
+        }
     }
 
-    fn expect_arrow(&mut self) -> (Option<Type>, Option<Type>) {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Arrow(a, b) = replaced {
-            (a, b)
-        } else {
-            panic!("Expected arrow, got {:?}", self);
+    /// Whether the current token is `import` followed by a string.
+    ///
+    /// `import` is not a reserved word, so that it may still be used as a name.
+    fn at_import(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.index + 1)) {
+            (Some(tok), Some(next)) => {
+                tok.kind == TokenKind::Symbol
+                    && tok.raw == "import"
+                    && next.kind == TokenKind::String
+            }
+            _ => false,
         }
     }
 
-    /// Checks that a given AST element is a dummy value
-    fn expect_position(&self) -> Position {
-        match self {
-            Ast::Combinator { position, .. } => *position,
-            Ast::Dummy { position } => *position,
-            Ast::Literal { position, .. } => *position,
-            Ast::Symbol { position, .. } => *position,
-            _ => panic!("Expected some element with position, got {:?}", self),
+    /// Whether the current token is `type` followed by a name and `:=`.
+    ///
+    /// Like `import`, `type` is not a reserved word.
+    fn at_type_alias(&self) -> bool {
+        match (
+            self.peek(),
+            self.tokens.get(self.index + 1),
+            self.tokens.get(self.index + 2),
+        ) {
+            (Some(tok), Some(name), Some(define)) => {
+                tok.kind == TokenKind::Symbol
+                    && tok.raw == "type"
+                    && matches!(name.kind, TokenKind::Symbol | TokenKind::Underscore)
+                    && define.kind == TokenKind::Define
+            }
+            _ => false,
         }
     }
 
-    fn expect_cmr(&mut self) -> AstCmr<J> {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Cmr(cmr) = replaced {
-            cmr
-        } else {
-            panic!("Expected CMR, got {:?}", replaced);
+    /// Whether the current token is a reasonable place to resume parsing after
+    /// a syntax error.
+    ///
+    /// This is the case for the start of any definition, witness assignment,
+    /// import or type alias, and for a name followed
+    /// by `:` which is the first token on its line. (The latter might also be the
+    /// tail of an expression with a type ascription, so we rely on the layout to
+    /// guess that it is a type bound.)
+    fn at_line_start(&self) -> bool {
+        if self.at_definition() {
+            return true;
+        }
+        match (self.peek(), self.tokens.get(self.index + 1)) {
+            (Some(tok), Some(next)) => {
+                matches!(tok.kind, TokenKind::Symbol | TokenKind::Underscore)
+                    && next.kind == TokenKind::Colon
+                    && (self.index == 0
+                        || self.tokens[self.index - 1].position.line < tok.position.line)
+            }
+            _ => false,
         }
     }
 
-    fn expect_expression(&mut self) -> Expression<J> {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Expression(exp) = replaced {
-            exp
-        } else {
-            panic!("Expected expression, got {:?}", replaced);
+    /// Skips tokens until the start of the next definition or type bound.
+    fn recover(&mut self, line_start: usize) {
+        if self.index == line_start {
+            self.index += 1;
+        }
+        while self.index < self.tokens.len() && !self.at_line_start() {
+            self.index += 1;
         }
     }
 
-    fn expect_line(&mut self) -> Line<J> {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Line(ell) = replaced {
-            ell
-        } else {
-            panic!("Expected line, got {:?}", replaced);
+    fn program<J: Jet>(&mut self) -> Program<J> {
+        let mut program = Program {
+            lines: vec![],
+            aliases: vec![],
+            witnesses: vec![],
+            imports: vec![],
+            comments: vec![],
+        };
+        while self.index < self.tokens.len() {
+            let line_start = self.index;
+            let result = if self.at_import() {
+                self.import(&mut program)
+            } else if self.at_type_alias() {
+                self.type_alias(&mut program)
+            } else {
+                self.line(&mut program)
+            };
+            if let Err(Desync) = result {
+                self.recover(line_start);
+            }
         }
+        program
     }
 
-    fn expect_literal(&mut self) -> (Vec<u8>, usize, Position) {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Literal {
-            data,
-            bit_length,
+    fn import<J: Jet>(&mut self, program: &mut Program<J>) -> Result<(), Desync> {
+        let position = self.tokens[self.index].position;
+        let path = self.tokens[self.index + 1].raw;
+        self.index += 2;
+        let alias = match self.peek() {
+            Some(tok) if tok.kind == TokenKind::Symbol && tok.raw == "as" => {
+                self.index += 1;
+                Some(self.name()?.0)
+            }
+            _ => None,
+        };
+        program.imports.push(Import {
             position,
-        } = replaced
-        {
-            (data, bit_length, position)
-        } else {
-            panic!("Expected literal, got {:?}", replaced);
+            path: Arc::from(&path[1..path.len() - 1]),
+            alias,
+        });
+        Ok(())
+    }
+
+    fn type_alias<J: Jet>(&mut self, program: &mut Program<J>) -> Result<(), Desync> {
+        let position = self.tokens[self.index].position;
+        let name = Arc::from(self.tokens[self.index + 1].raw);
+        self.index += 3;
+        // `_` is not a type, only the absence of a bound.
+        if self.peek_kind() == Some(TokenKind::Underscore) {
+            return self.unexpected("type");
+        }
+        if let Some(ty) = self.ty()? {
+            program.aliases.push(TypeAlias { position, name, ty });
         }
+        Ok(())
     }
 
-    fn expect_program(&mut self) -> Vec<Line<J>> {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Program(lines) = replaced {
-            lines
-        } else {
-            panic!("Expected program, got {:?}", replaced);
+    fn name(&mut self) -> Result<(Arc<str>, Position), Desync> {
+        match self.peek() {
+            Some(tok) if matches!(tok.kind, TokenKind::Symbol | TokenKind::Underscore) => {
+                self.index += 1;
+                Ok((Arc::from(tok.raw), tok.position))
+            }
+            _ => self.unexpected("name"),
         }
     }
 
-    fn expect_symbol(&mut self) -> (Arc<str>, Position) {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Symbol { value, position } = replaced {
-            (value, position)
+    fn line<J: Jet>(&mut self, program: &mut Program<J>) -> Result<(), Desync> {
+        let is_template = self.at_call();
+        let (name, position) = self.name()?;
+        let parameters = if is_template {
+            let parameters = self.parenthesized(|parser| {
+                parser
+                    .name()
+                    .map(|(name, position)| Parameter { position, name })
+            })?;
+            if self.peek_kind() != Some(TokenKind::Define) {
+                return self.unexpected("`:=`");
+            }
+            parameters
         } else {
-            panic!("Expected symbol, got {:?}", replaced);
+            vec![]
+        };
+        match self.peek_kind() {
+            Some(TokenKind::Colon) => {
+                self.index += 1;
+                let arrow = self.arrow()?;
+                program.lines.push(Line {
+                    position,
+                    name,
+                    parameters,
+                    expression: None,
+                    arrow,
+                    locals: vec![],
+                });
+            }
+            Some(TokenKind::Assign) => {
+                self.index += 1;
+                let (data, bit_length, _) = self.literal()?;
+                program.witnesses.push(WitnessAssignment {
+                    position,
+                    name,
+                    data,
+                    bit_length,
+                });
+            }
+            Some(TokenKind::Define) => {
+                self.index += 1;
+                let expression = self.expr()?;
+                let arrow = if self.peek_kind() == Some(TokenKind::Colon) {
+                    self.index += 1;
+                    self.arrow()?
+                } else {
+                    (None, None)
+                };
+                let locals = if self.at_where() {
+                    self.where_block()?
+                } else {
+                    vec![]
+                };
+                if let Some(expression) = expression {
+                    program.lines.push(Line {
+                        position,
+                        name,
+                        parameters,
+                        expression: Some(expression),
+                        arrow,
+                        locals,
+                    });
+                }
+            }
+            _ => return self.unexpected("`:=`, `:` or `<-`"),
         }
+        Ok(())
     }
 
-    /// Checks that a given AST element is a type, and returns it if so
+    /// Whether the current token is `where` followed by `{`.
     ///
-    /// Replaces the original value with a dummy, on the assumption that
-    /// it lives in a vector and therefore can't be simply moved.
-    fn expect_type(&mut self) -> Option<Type> {
-        let replaced = mem::replace(self, Ast::Replaced);
-        if let Ast::Type(ty) = replaced {
-            ty
-        } else {
-            panic!("Expected type, got {:?}", replaced);
-        }
-    }
-}
-
-fn lexer_rules() -> LexerRules {
-    santiago::lexer_rules!(
-        // Base combinators and jets
-        "DEFAULT" | "CONST"  = string "const";
-        "DEFAULT" | "NULLARY" = pattern "unit|iden|witness|jet_[a-z0-9_]*";
-        "DEFAULT" | "UNARY"   = pattern "injl|injr|take|drop";
-        "DEFAULT" | "BINARY"  = pattern "case|comp|pair|disconnect";
-        // Assertions
-        "DEFAULT" | "ASSERTL" = string "assertl";
-        "DEFAULT" | "ASSERTR" = string "assertr";
-        "DEFAULT" | "FAIL" = string "fail";
-        // Literals
-        "DEFAULT" | "_" = string "_";
-        "DEFAULT" | "LITERAL" = pattern r"0b[01]+|0x[0-9a-f]+";
-
-        // Symbols (expression names). Essentially any alphanumeric that does not
-        // start with a numbera and isn't a reserved symbol (i.e. one of the above)
-        // patterns. Dash, underscore and dot are also allowed anywhere in a symbol.
-        "DEFAULT" | "SYMBOL" = pattern r"[a-zA-Z_\-.'][0-9a-zA-Z_\-.']*";
-
-        // Type/arrow symbols
-        "DEFAULT" | "(" = string "(";
-        "DEFAULT" | ")" = string ")";
-        "DEFAULT" | "+" = string "+";
-        "DEFAULT" | "*" = string "*";
-        "DEFAULT" | "->" = string "->";
-        "DEFAULT" | ":" = string ":";
-        "DEFAULT" | "1" = string "1";
-        "DEFAULT" | "2" = string "2";
-        "DEFAULT" | "2EXP" = pattern "2\\^[1-9][0-9]*";
-
-        // Assignment
-        "DEFAULT" | ":=" = string ":=";
-
-        // CMR and holes
-        "DEFAULT" | "CMRLIT" = pattern "#[a-fA-F0-9]{64}";
-        "DEFAULT" | "#{" = string "#{";
-        "DEFAULT" | "}" = string "}";
-        "DEFAULT" | "?" = string "?";
-
-        // Comments (single-line comments only).
-        "DEFAULT" | "LINE_COMMENT" = pattern r"--.*\n" => |lexer| lexer.skip();
-
-        // No whitespace is significant except to separate other tokens.
-        "DEFAULT" | "WS" = pattern r"\s" => |lexer| lexer.skip();
-    )
-}
-
-fn grammar<J: Jet + 'static>() -> Grammar<Ast<J>> {
-    santiago::grammar!(
-        "program" => empty => |_| Ast::Program(vec![]);
-        "program" => rules "line" "program" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            let line = toks[0].expect_line();
-            let prog = toks[1].expect_program();
-
-            let mut new_prog = Vec::with_capacity(1 + prog.len());
-            new_prog.push(line);
-            new_prog.extend(prog);
-            Ast::Program(new_prog)
+    /// Like `import`, `where` is not a reserved word.
+    fn at_where(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.index + 1)) {
+            (Some(tok), Some(next)) => {
+                tok.kind == TokenKind::Symbol
+                    && tok.raw == "where"
+                    && next.kind == TokenKind::LBrace
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a `where` block: definitions and type bounds between `{` and `}`,
+    /// optionally separated by `;`.
+    fn where_block<J: Jet>(&mut self) -> Result<Vec<Line<J>>, Desync> {
+        self.index += 2;
+        let mut block = Program {
+            lines: vec![],
+            aliases: vec![],
+            witnesses: vec![],
+            imports: vec![],
+            comments: vec![],
         };
+        loop {
+            match self.peek_kind() {
+                Some(TokenKind::CmrClose) => break,
+                Some(TokenKind::Semicolon) => self.index += 1,
+                None => return self.unexpected("`}`"),
+                // Witness assignments are only allowed at the top level, where
+                // local witness nodes are referred to by their qualified names.
+                Some(_)
+                    if self.tokens.get(self.index + 1).map(|tok| tok.kind)
+                        == Some(TokenKind::Assign) =>
+                {
+                    self.index += 1;
+                    return self.unexpected("`:=` or `:`");
+                }
+                Some(_) => self.line(&mut block)?,
+            }
+        }
+        self.index += 1;
+        Ok(block.lines)
+    }
 
-        "line" => rules "symbol" ":" "arrow" => |mut toks| Ast::from_2(
-            &mut toks,
-            Ast::expect_symbol,
-            Ast::expect_arrow,
-            |symb, arrow| Ast::Line(Line {
-                position: symb.1,
-                name: symb.0,
-                expression: None,
-                arrow,
-            })
-        );
-        "line" => rules "symbol" ":=" "expr" => |mut toks| Ast::from_2(
-            &mut toks,
-            Ast::expect_symbol,
-            Ast::expect_expression,
-            |symb, expr| Ast::Line(Line {
-                position: symb.1,
-                name: symb.0,
-                expression: Some(expr),
-                arrow: (None, None),
-            })
-        );
-        "line" => rules "symbol" ":=" "expr" ":" "arrow" => |mut toks| Ast::from_3(
-            &mut toks,
-            Ast::expect_symbol,
-            Ast::expect_expression,
-            Ast::expect_arrow,
-            |symb, expr, arrow| Ast::Line(Line {
-                position: symb.1,
-                name: symb.0,
-                expression: Some(expr),
-                arrow,
-            }),
-        );
+    /// Parses a parenthesized, comma-separated and non-empty list of items.
+    fn parenthesized<T, F>(&mut self, mut item: F) -> Result<Vec<T>, Desync>
+    where
+        F: FnMut(&mut Self) -> Result<T, Desync>,
+    {
+        self.expect(TokenKind::LParen, "`(`")?;
+        let mut items = vec![item(self)?];
+        while self.peek_kind() == Some(TokenKind::Comma) {
+            self.index += 1;
+            items.push(item(self)?);
+        }
+        self.expect(TokenKind::RParen, "`,` or `)`")?;
+        Ok(items)
+    }
 
-        "arrow" => rules "type" "->" "type" => |mut toks| Ast::from_2(
-            &mut toks,
-            Ast::expect_type,
-            Ast::expect_type,
-            Ast::Arrow,
-        );
+    /// Parses an expression.
+    ///
+    /// Returns `Ok(None)` if the expression was syntactically valid but
+    /// contained some other error, which has been recorded.
+    fn expr<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        self.nested(Self::unnested_expr)
+    }
 
-        "expr" => rules "symbol" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 1);
-            if let Ast::Symbol { value, position } = mem::replace(&mut toks[0], Ast::Replaced) {
-                Ast::Expression(Expression::reference(value, position))
-            } else {
-                unreachable!("expected string, got something else")
+    fn unnested_expr<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        let expression_depth = self.expression_depth;
+        let result = self.chain();
+        self.expression_depth = expression_depth;
+        result
+    }
+
+    /// Parses an expression and the chain of last children below it.
+    fn chain<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        // The last child of a combinator is parsed by this loop rather than by
+        // recursion, so that long chains such as `comp a (comp b (comp c d))`
+        // are limited only by `MAX_EXPRESSION_DEPTH` rather than `MAX_DEPTH`.
+        let mut chain = vec![];
+        let mut expr = loop {
+            // A name followed by `:=` is the start of the next definition, which
+            // means that the current expression is missing.
+            if self.at_definition() {
+                return self.unexpected("expression");
             }
-        };
-        "expr" => rules "?" "symbol" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 2);
-            if let Ast::Symbol { value, position } = mem::replace(&mut toks[1], Ast::Replaced) {
-                Ast::Expression(Expression {
-                    inner: ExprInner::Inline(node::Inner::Witness(WitnessOrHole::TypedHole(value))),
-                    position,
-                })
-            } else {
-                unreachable!("expected string, got something else")
+            let tok = match self.peek() {
+                Some(tok) => tok,
+                None => return self.unexpected("expression"),
+            };
+            self.expression_depth += 1;
+            if self.expression_depth > MAX_EXPRESSION_DEPTH {
+                self.errors.add(tok.position, Error::NestingTooDeep);
+                return Err(Desync);
+            }
+            match tok.kind {
+                TokenKind::Unary => {
+                    self.index += 1;
+                    chain.push(ChainLink::Unary(tok));
+                }
+                TokenKind::Binary => {
+                    self.index += 1;
+                    let left = self.expr()?;
+                    chain.push(ChainLink::Binary(tok, left));
+                }
+                _ => break self.chain_end(tok)?,
             }
-        };
-        "expr" => rules "(" "expr" ")" => |toks| toks[1].clone();
-        "expr" => rules "nullary" => Ast::from_combinator;
-        "expr" => rules "unary" "expr" => Ast::from_combinator;
-        "expr" => rules "binary" "expr" "expr" => Ast::from_combinator;
-
-        // TODO should we allow CMRs as literals for constant words?
-        "expr" => rules "const" "literal" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 2);
-            let (data, bit_length, position) = toks[1].expect_literal();
-            let mut iter = BitIter::from(data);
-
-            if bit_length.count_ones() != 1 || bit_length > 1 << 31 {
-                return Ast::Error(ErrorSet::single(
-                    position,
-                    Error::BadWordLength { bit_length },
-                ));
-            }
-            // unwrap ok here since literally every sequence of bits is a valid
-            // value for the given type
-            let word = Word::from_bits(&mut iter, bit_length.trailing_zeros()).unwrap();
-            Ast::Expression(Expression {
-                inner: ExprInner::Inline(node::Inner::Word(word)),
-                position,
-            })
         };
 
-        "expr" => rules "assertl" "expr" "cmr" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 3);
-            let exp1 = toks[1].expect_expression();
-            let cmr2 = toks[2].expect_cmr();
-            Ast::Expression(Expression {
-                inner: ExprInner::AssertL(Arc::new(exp1), cmr2),
-                position: toks[0].expect_position(),
-            })
-        };
-        "expr" => rules "assertr" "cmr" "expr" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 3);
-            let cmr1 = toks[1].expect_cmr();
-            let exp2 = toks[2].expect_expression();
-            Ast::Expression(Expression {
-                inner: ExprInner::AssertR(cmr1, Arc::new(exp2)),
-                position: toks[0].expect_position(),
-            })
-        };
+        for link in chain.into_iter().rev() {
+            expr = link.complete(expr);
+        }
+        Ok(expr)
+    }
 
-        "expr" => rules "fail" "literal" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 2);
-            let (value, bit_length, position) = toks[1].expect_literal();
-            if bit_length < 128 {
-                Ast::Error(ErrorSet::single(
-                    position,
-                    Error::EntropyInsufficient { bit_length },
-                ))
-            } else if bit_length > 512 {
-                Ast::Error(ErrorSet::single(
-                    position,
-                    Error::EntropyTooMuch { bit_length },
-                ))
-            } else {
-                let mut entropy = [0; 64];
-                entropy[..value.len()].copy_from_slice(&value[..]);
-                let entropy = FailEntropy::from_byte_array(entropy);
-                Ast::Expression(Expression {
-                    inner: ExprInner::Inline(node::Inner::Fail(entropy)),
-                    position,
-                })
+    /// Parses an expression which is not a combinator with children, starting
+    /// at the given token.
+    ///
+    /// Each kind of expression is parsed by its own function, so that the stack
+    /// used by each level of nesting stays small.
+    fn chain_end<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        match tok.kind {
+            TokenKind::Symbol if self.at_call() => self.instance(tok),
+            TokenKind::Symbol | TokenKind::Underscore => {
+                self.index += 1;
+                Ok(Some(Expression::reference(
+                    Arc::from(tok.raw),
+                    tok.position,
+                )))
             }
-        };
+            TokenKind::LParen => self.parenthesized_expr(),
+            TokenKind::Question => self.typed_hole(),
+            TokenKind::Nullary => self.nullary(tok),
+            TokenKind::Const => self.const_expr(tok),
+            TokenKind::AssertL => self.assertl(tok),
+            TokenKind::AssertR => self.assertr(tok),
+            TokenKind::Fail => self.fail(),
+            _ => self.unexpected("expression"),
+        }
+    }
 
-        "cmr" => rules "#{" "expr" "}" => |mut toks| {
-            if let Some(e) = propagate_errors(&toks) { return e; }
-            assert_eq!(toks.len(), 3);
-            let exp = toks[1].expect_expression();
-            Ast::Cmr(AstCmr::Expr(Arc::new(exp)))
-        };
-        "cmr" => rules "CMRLIT";
+    /// Parses an instance of a template, `name(arg, ...)`.
+    fn instance<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let arguments = self.parenthesized(|parser| parser.expr())?;
+        Ok(arguments
+            .into_iter()
+            .map(|arg| arg.map(Arc::new))
+            .collect::<Option<_>>()
+            .map(|arguments| Expression {
+                inner: ExprInner::Instance(Arc::from(tok.raw), arguments),
+                position: tok.position,
+            }))
+    }
 
+    /// Parses an expression in parentheses, which may have a type ascription.
+    fn parenthesized_expr<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let expr = self.expr()?;
+        if self.peek_kind() == Some(TokenKind::Colon) {
+            self.index += 1;
+            let arrow = self.arrow()?;
+            self.expect(TokenKind::RParen, "`)`")?;
+            return Ok(expr.map(|expr| Expression {
+                position: expr.position,
+                inner: ExprInner::Ascribed(Arc::new(expr), arrow),
+            }));
+        }
+        self.expect(TokenKind::RParen, "`:` or `)`")?;
+        Ok(expr)
+    }
 
-        "type" => rules "symbol" => |mut toks| Ast::from_1(
-            &mut toks,
-            Ast::expect_symbol,
-            |name| {
-                if name.0.as_ref() == "_" {
-                    Ast::Type(None)
-                } else {
-                    // Type names are stored as Strings, but we normally use Arc<str>
-                    // in the parser. So we need to do an extra conversion.
-                    Ast::Type(Some(Type::Name(name.0.as_ref().to_owned())))
+    /// Parses a typed hole, `?name`.
+    fn typed_hole<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let (name, position) = self.name()?;
+        Ok(Some(Expression {
+            inner: ExprInner::Inline(node::Inner::Witness(WitnessOrHole::TypedHole(name))),
+            position,
+        }))
+    }
+
+    /// Parses a combinator without children, or a jet.
+    fn nullary<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let inner = match tok.raw {
+            "unit" => node::Inner::Unit,
+            "iden" => node::Inner::Iden,
+            "witness" => node::Inner::Witness(WitnessOrHole::Witness),
+            jet => match J::from_str(&jet[4..]) {
+                Ok(jet) => node::Inner::Jet(jet),
+                Err(_) => {
+                    self.errors
+                        .add(tok.position, Error::UnknownJet(jet.to_owned()));
+                    return Ok(None);
                 }
             },
-        );
-        "type" => rules "1";
-        "type" => rules "2";
-        "type" => rules "2EXP";
-        "type" => rules "(" "type" ")" => |mut toks| Ast::from_1(
-            &mut toks[1..2],
-            Ast::expect_type,
-            Ast::Type,
-        );
-        "type" => rules "type" "+" "type" => |mut toks| Ast::from_2(
-            &mut toks,
-            Ast::expect_type,
-            Ast::expect_type,
-            |t1, t2| Ast::Type(t1.zip(t2).map(|(t1, t2)| Type::Sum(Box::new(t1), Box::new(t2)))),
-        );
-        "type" => rules "type" "*" "type" => |mut toks| Ast::from_2(
-            &mut toks,
-            Ast::expect_type,
-            Ast::expect_type,
-            |t1, t2| Ast::Type(t1.zip(t2).map(|(t1, t2)| Type::Product(Box::new(t1), Box::new(t2)))),
-        );
+        };
+        Ok(Some(Expression {
+            inner: ExprInner::Inline(inner),
+            position: tok.position,
+        }))
+    }
 
-        // Turn lexemes into rules
-        "nullary" => lexemes "NULLARY" => Ast::from_combinator_lexeme;
-        "unary" => lexemes "UNARY" => Ast::from_combinator_lexeme;
-        "binary" => lexemes "BINARY" => Ast::from_combinator_lexeme;
-        "const" => lexemes "CONST" => Ast::from_dummy_lexeme;
-        "assertl" => lexemes "ASSERTL" => Ast::from_combinator_lexeme;
-        "assertr" => lexemes "ASSERTR" => Ast::from_combinator_lexeme;
-        "fail" => lexemes "FAIL" => Ast::from_combinator_lexeme;
+    /// Parses a `const` expression.
+    fn const_expr<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let position = self.peek().map_or(tok.position, |tok| tok.position);
+        Ok(self.constant()?.map(|constant| Expression {
+            inner: match constant {
+                Constant::Word(word) => ExprInner::Inline(node::Inner::Word(word)),
+                constant => ExprInner::Constant(constant),
+            },
+            position,
+        }))
+    }
+
+    /// Parses a left assertion, `assertl expr cmr`.
+    fn assertl<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let left = self.expr()?;
+        let cmr = self.cmr()?;
+        Ok(left.zip(cmr).map(|(left, cmr)| Expression {
+            inner: ExprInner::AssertL(Arc::new(left), cmr),
+            position: tok.position,
+        }))
+    }
 
-        "literal" => lexemes "LITERAL" => Ast::from_literal_lexeme;
-        "literal" => lexemes "_" => Ast::from_literal_lexeme;
+    /// Parses a right assertion, `assertr cmr expr`.
+    fn assertr<J: Jet>(&mut self, tok: Token<'s>) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let cmr = self.cmr()?;
+        let right = self.expr()?;
+        Ok(cmr.zip(right).map(|(cmr, right)| Expression {
+            inner: ExprInner::AssertR(cmr, Arc::new(right)),
+            position: tok.position,
+        }))
+    }
 
-        "#{" => lexemes "#{" => Ast::from_dummy_lexeme;
-        "}" => lexemes "}" => Ast::from_dummy_lexeme;
-        "CMRLIT" => lexemes "CMRLIT" => Ast::from_cmr_literal_lexeme;
+    /// Parses a `fail` expression and its entropy.
+    fn fail<J: Jet>(&mut self) -> Result<Option<Expression<J>>, Desync> {
+        self.index += 1;
+        let (value, bit_length, position) = self.literal()?;
+        if bit_length < 128 {
+            self.errors
+                .add(position, Error::EntropyInsufficient { bit_length });
+            Ok(None)
+        } else if bit_length > 512 {
+            self.errors
+                .add(position, Error::EntropyTooMuch { bit_length });
+            Ok(None)
+        } else {
+            let mut entropy = [0; 64];
+            entropy[..value.len()].copy_from_slice(&value[..]);
+            let entropy = FailEntropy::from_byte_array(entropy);
+            Ok(Some(Expression {
+                inner: ExprInner::Inline(node::Inner::Fail(entropy)),
+                position,
+            }))
+        }
+    }
 
-        "symbol" => lexemes "_" => |lexemes| {
-            assert_eq!(lexemes.len(), 1);
-            Ast::Symbol {
-                value: Arc::from(lexemes[0].raw.as_str()),
-                position: (&lexemes[0].position).into(),
-            }
+    /// Parses the value of a `const` expression.
+    ///
+    /// Returns `Ok(None)` if the value contained an error, which has been recorded.
+    fn constant<J: Jet>(&mut self) -> Result<Option<Constant<J>>, Desync> {
+        self.nested(Self::unnested_constant)
+    }
+
+    fn unnested_constant<J: Jet>(&mut self) -> Result<Option<Constant<J>>, Desync> {
+        let tok = match self.peek() {
+            Some(tok) => tok,
+            None => return self.unexpected("constant"),
         };
-        "symbol" => lexemes "SYMBOL" => |lexemes| {
-            assert_eq!(lexemes.len(), 1);
-            Ast::Symbol {
-                value: Arc::from(lexemes[0].raw.as_str()),
-                position: (&lexemes[0].position).into(),
+        match tok.kind {
+            TokenKind::Literal | TokenKind::Underscore => {
+                let (data, bit_length, position) = self.literal()?;
+                if bit_length.count_ones() != 1 || bit_length > 1 << 31 {
+                    self.errors
+                        .add(position, Error::BadWordLength { bit_length });
+                    Ok(None)
+                } else {
+                    let mut iter = BitIter::from(data);
+                    // unwrap ok here since literally every sequence of bits is a valid
+                    // value for the given type
+                    let word = Word::from_bits(&mut iter, bit_length.trailing_zeros()).unwrap();
+                    Ok(Some(Constant::Word(word)))
+                }
+            }
+            TokenKind::Number | TokenKind::One | TokenKind::Two => {
+                self.index += 1;
+                Ok(Some(Constant::Decimal(Arc::from(tok.raw))))
+            }
+            TokenKind::CmrLiteral | TokenKind::CmrOpen => Ok(self.cmr()?.map(Constant::Cmr)),
+            TokenKind::Symbol
+                if matches!(tok.raw, "L" | "R")
+                    && self.tokens.get(self.index + 1).map(|tok| tok.kind)
+                        == Some(TokenKind::LParen) =>
+            {
+                self.index += 2;
+                let inner = self.constant()?;
+                self.expect(TokenKind::RParen, "`)`")?;
+                Ok(inner.map(|inner| match tok.raw {
+                    "L" => Constant::Left(Box::new(inner)),
+                    _ => Constant::Right(Box::new(inner)),
+                }))
+            }
+            TokenKind::LParen => {
+                self.index += 1;
+                if self.peek_kind() == Some(TokenKind::RParen) {
+                    self.index += 1;
+                    return Ok(Some(Constant::Unit));
+                }
+                let left = self.constant()?;
+                if self.peek_kind() == Some(TokenKind::Comma) {
+                    self.index += 1;
+                    let right = self.constant()?;
+                    self.expect(TokenKind::RParen, "`)`")?;
+                    return Ok(left
+                        .zip(right)
+                        .map(|(left, right)| Constant::Pair(Box::new(left), Box::new(right))));
+                }
+                self.expect(TokenKind::RParen, "`,` or `)`")?;
+                Ok(left)
+            }
+            _ => self.unexpected("constant"),
+        }
+    }
+
+    fn cmr<J: Jet>(&mut self) -> Result<Option<AstCmr<J>>, Desync> {
+        match self.peek_kind() {
+            Some(TokenKind::CmrLiteral) => {
+                let tok = self.peek().unwrap();
+                self.index += 1;
+                let cmr = Cmr::from_str(&tok.raw[1..]).expect("lexer only accepts 64 hex digits");
+                Ok(Some(AstCmr::Literal(cmr)))
+            }
+            Some(TokenKind::CmrOpen) => {
+                self.index += 1;
+                let expr = self.expr()?;
+                self.expect(TokenKind::CmrClose, "`}`")?;
+                Ok(expr.map(|expr| AstCmr::Expr(Arc::new(expr))))
             }
+            _ => self.unexpected("CMR"),
+        }
+    }
+
+    /// Parses a hex or binary literal, returning its data, bit length and position.
+    fn literal(&mut self) -> Result<(Vec<u8>, usize, Position), Desync> {
+        let tok = match self.peek() {
+            Some(tok) if matches!(tok.kind, TokenKind::Literal | TokenKind::Underscore) => tok,
+            _ => return self.unexpected("literal"),
         };
+        self.index += 1;
+
+        if tok.kind == TokenKind::Underscore {
+            return Ok((vec![], 0, tok.position));
+        }
+
+        let s = &tok.raw[2..];
+        if &tok.raw[..2] == "0x" {
+            let bit_length = s.len() * 4;
+            let mut data = Vec::with_capacity((s.len() + 1) / 2);
+            for idx in 0..s.len() / 2 {
+                data.push(u8::from_str_radix(&s[2 * idx..2 * idx + 2], 16).unwrap());
+            }
+            if s.len() % 2 == 1 {
+                data.push(u8::from_str_radix(&s[s.len() - 1..], 16).unwrap() << 4);
+            }
+            Ok((data, bit_length, tok.position))
+        } else {
+            let bit_length = s.len();
+            let mut data = Vec::with_capacity((s.len() + 7) / 8);
+            let mut x = 0;
+            for (n, ch) in s.chars().enumerate() {
+                if ch == '1' {
+                    x |= 1 << (7 - (n % 8));
+                }
+                if n % 8 == 7 {
+                    data.push(x);
+                    x = 0;
+                }
+            }
+            if s.len() % 8 != 0 {
+                data.push(x);
+            }
+            Ok((data, bit_length, tok.position))
+        }
+    }
 
-        "(" => lexemes "(" => Ast::from_dummy_lexeme;
-        ")" => lexemes ")" => Ast::from_dummy_lexeme;
-        "+" => lexemes "+" => Ast::from_dummy_lexeme;
-        "*" => lexemes "*" => Ast::from_dummy_lexeme;
-        ":" => lexemes ":" => Ast::from_dummy_lexeme;
-        "->" => lexemes "->" => Ast::from_dummy_lexeme;
+    fn arrow(&mut self) -> Result<(Option<Type>, Option<Type>), Desync> {
+        let source = self.ty()?;
+        self.expect(TokenKind::Arrow, "`->`")?;
+        let target = self.ty()?;
+        Ok((source, target))
+    }
 
-        "1" => lexemes "1" => Ast::from_type_lexeme;
-        "2" => lexemes "2" => Ast::from_type_lexeme;
-        "2EXP" => lexemes "2EXP" => Ast::from_type_lexeme;
+    /// Parses a type; `*` binds more tightly than `+` and both are left-associative.
+    ///
+    /// Returns `Ok(None)` for the type `_`, and for types containing errors,
+    /// which have been recorded.
+    fn ty(&mut self) -> Result<Option<Type>, Desync> {
+        let mut ret = self.product_ty()?;
+        while self.peek_kind() == Some(TokenKind::Plus) {
+            self.index += 1;
+            let right = self.product_ty()?;
+            ret = ret
+                .zip(right)
+                .map(|(left, right)| Type::Sum(Box::new(left), Box::new(right)));
+        }
+        Ok(ret)
+    }
 
-        ":=" => lexemes ":=" => Ast::from_dummy_lexeme;
+    fn product_ty(&mut self) -> Result<Option<Type>, Desync> {
+        let mut ret = self.atom_ty()?;
+        while self.peek_kind() == Some(TokenKind::Star) {
+            self.index += 1;
+            let right = self.atom_ty()?;
+            ret = ret
+                .zip(right)
+                .map(|(left, right)| Type::Product(Box::new(left), Box::new(right)));
+        }
+        Ok(ret)
+    }
 
-        "#" => lexemes "#" => Ast::from_dummy_lexeme;
-        "?" => lexemes "?" => Ast::from_dummy_lexeme;
+    fn atom_ty(&mut self) -> Result<Option<Type>, Desync> {
+        self.nested(Self::unnested_atom_ty)
+    }
 
-        // Define associativity rules for type constructors
-        Associativity::Left => rules "+";
-        Associativity::Left => rules "*";
-    )
+    fn unnested_atom_ty(&mut self) -> Result<Option<Type>, Desync> {
+        let tok = match self.peek() {
+            Some(tok) => tok,
+            None => return self.unexpected("type"),
+        };
+        let ty = match tok.kind {
+            TokenKind::Underscore => None,
+            // Type names are stored as Strings, but we normally use Arc<str>
+            // in the parser. So we need to do an extra conversion.
+            TokenKind::Symbol => Some(Type::Name(tok.raw.to_owned())),
+            TokenKind::One => Some(Type::One),
+            TokenKind::Two => Some(Type::Two),
+            TokenKind::TwoExp => match str::parse::<u32>(&tok.raw[2..]) {
+                // TODO how many of these should we support?
+                Ok(2) => Some(Type::TwoTwoN(1)),
+                Ok(4) => Some(Type::TwoTwoN(2)),
+                Ok(8) => Some(Type::TwoTwoN(3)),
+                Ok(16) => Some(Type::TwoTwoN(4)),
+                Ok(32) => Some(Type::TwoTwoN(5)),
+                Ok(64) => Some(Type::TwoTwoN(6)),
+                Ok(128) => Some(Type::TwoTwoN(7)),
+                Ok(256) => Some(Type::TwoTwoN(8)),
+                Ok(512) => Some(Type::TwoTwoN(9)),
+                Ok(1) => Some(Type::Two),
+                Ok(y) => {
+                    self.errors.add(tok.position, Error::Bad2ExpNumber(y));
+                    None
+                }
+                Err(_) => {
+                    self.errors
+                        .add(tok.position, Error::NumberOutOfRange(tok.raw.to_owned()));
+                    None
+                }
+            },
+            TokenKind::LParen => {
+                self.index += 1;
+                let ty = self.ty()?;
+                self.expect(TokenKind::RParen, "`)`")?;
+                return Ok(ty);
+            }
+            _ => return self.unexpected("type"),
+        };
+        self.index += 1;
+        Ok(ty)
+    }
 }
 
 #[cfg(test)]
@@ -805,47 +1658,82 @@ mod tests {
     #[test]
     fn fixed_vectors() {
         // Single line
-        parse_line_vector::<Core>("a := b").unwrap();
+        parse_program::<Core>("a := b").unwrap();
         // Bad lex
-        parse_line_vector::<Core>("?P<").unwrap_err();
+        parse_program::<Core>("?P<").unwrap_err();
         // Witness
-        parse_line_vector::<Core>("U := witness").unwrap();
+        parse_program::<Core>("U := witness").unwrap();
         // Name with type
-        parse_line_vector::<Core>("U : T -> 1").unwrap();
-        parse_line_vector::<Core>("U : 2 -> 1").unwrap();
-        parse_line_vector::<Core>("U : 2^2 -> 1").unwrap();
-        parse_line_vector::<Core>("U : 2^512 -> 1").unwrap();
-        parse_line_vector::<Core>("U : (2^512) -> 1").unwrap();
-        parse_line_vector::<Core>("U : (2^512 * 2^512) -> 1").unwrap();
-        parse_line_vector::<Core>("U : 1 -> (2^512 * 2^512)").unwrap();
+        parse_program::<Core>("U : T -> 1").unwrap();
+        parse_program::<Core>("U : 2 -> 1").unwrap();
+        parse_program::<Core>("U : 2^2 -> 1").unwrap();
+        parse_program::<Core>("U : 2^512 -> 1").unwrap();
+        parse_program::<Core>("U : (2^512) -> 1").unwrap();
+        parse_program::<Core>("U : (2^512 * 2^512) -> 1").unwrap();
+        parse_program::<Core>("U : 1 -> (2^512 * 2^512)").unwrap();
         // Witness with type and expression
-        parse_line_vector::<Core>("U := witness : 1 -> 1").unwrap();
-        parse_line_vector::<Core>("U := witness : _ -> 1").unwrap();
-        parse_line_vector::<Core>("U := witness : 1 -> _").unwrap();
-        parse_line_vector::<Core>("U := witness : _ -> _").unwrap();
+        parse_program::<Core>("U := witness : 1 -> 1").unwrap();
+        parse_program::<Core>("U := witness : _ -> 1").unwrap();
+        parse_program::<Core>("U := witness : 1 -> _").unwrap();
+        parse_program::<Core>("U := witness : _ -> _").unwrap();
         // Case with nested unit
-        parse_line_vector::<Core>("ABC := case unit injl DEF").unwrap();
+        parse_program::<Core>("ABC := case unit injl DEF").unwrap();
         // word hex
-        parse_line_vector::<Core>("U := const 0xabcd").unwrap();
+        parse_program::<Core>("U := const 0xabcd").unwrap();
+        parse_program::<Core>("U := const 0xABCD").unwrap_err();
         // word bin
-        parse_line_vector::<Core>("U := const 0b0101001011111000").unwrap();
+        parse_program::<Core>("U := const 0b0101001011111000").unwrap();
 
         // asserts
-        parse_line_vector::<Core>(
+        parse_program::<Core>(
             "U := assertl unit #abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234",
         )
         .unwrap();
-        parse_line_vector::<Core>("U := assertl unit #{comp iden iden}").unwrap();
-        parse_line_vector::<Core>(
+        parse_program::<Core>("U := assertl unit #{comp iden iden}").unwrap();
+        parse_program::<Core>(
             "U := assertr #abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234abcd1234 unit",
         )
         .unwrap();
-        parse_line_vector::<Core>("U := assertr #{comp iden iden} unit").unwrap();
+        parse_program::<Core>("U := assertr #{comp iden iden} unit").unwrap();
+
+        // type aliases and annotations
+        let program =
+            parse_program::<Core>("type Sig := 2^512\ntype := comp (unit : 1 -> _) type").unwrap();
+        assert_eq!(program.aliases.len(), 1);
+        assert_eq!(program.aliases[0].ty, Type::TwoTwoN(9));
+        assert_eq!(program.lines.len(), 1);
+        parse_program::<Core>("type Sig := _").unwrap_err();
+        parse_program::<Core>("U := (unit : 1)").unwrap_err();
+
+        // structured constants
+        let program =
+            parse_program::<Core>("U := const (42, (L(()), (R(0x0f), #{unit})))").unwrap();
+        match program.lines[0].expression.as_ref().unwrap().inner {
+            ExprInner::Constant(Constant::Pair(ref left, ref right)) => {
+                assert_eq!(**left, Constant::Decimal(Arc::from("42")));
+                assert!(matches!(**right, Constant::Pair(..)));
+            }
+            ref inner => panic!("expected a pair constant, got {:?}", inner),
+        }
+        parse_program::<Core>("U := const ((1), 2)").unwrap();
+        parse_program::<Core>("U := const (1, )").unwrap_err();
+        parse_program::<Core>("U := const L 1").unwrap_err();
+
+        // imports
+        let program = parse_program::<Core>(
+            "import \"a.simpl\"\nimport \"dir/b c.simpl\" as b\nimport := import",
+        )
+        .unwrap();
+        assert_eq!(program.imports.len(), 2);
+        assert_eq!(&*program.imports[1].path, "dir/b c.simpl");
+        assert_eq!(program.imports[1].alias.as_deref(), Some("b"));
+        assert_eq!(program.lines.len(), 1);
+        parse_program::<Core>("import \"a.simpl\nmain := unit").unwrap_err();
     }
 
     #[test]
     fn simple_program() {
-        parse_line_vector::<Core>(
+        parse_program::<Core>(
             "
             v2 := unit : B -> 1                -- 62274a89
             v1 := pair v2 v2 : B -> (1 * 1)    -- 822d5a17
@@ -853,4 +1741,141 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn error_recovery() {
+        // Every broken line is reported, not just the first one
+        let errs = parse_program::<Core>(
+            "
+            a := comp unit
+            b := injl : 1 -> 1
+            c := unit
+            d := pair unit ) unit
+            e : 1 -> -> 1
+            f := jet_nonexistent
+        ",
+        )
+        .unwrap_err();
+        let messages: Vec<_> = errs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "expected expression, found `b`",
+                "expected expression, found `:`",
+                "expected expression, found `)`",
+                "expected type, found `->`",
+                "unknown jet `jet_nonexistent`",
+            ],
+        );
+        assert_eq!(
+            errs.first_error().unwrap().0,
+            Some(Position {
+                file: 0,
+                line: 3,
+                column: 13
+            }),
+        );
+
+        // Lexer errors do not stop parsing either
+        let errs = parse_program::<Core>("a := unit <\nb := comp").unwrap_err();
+        let messages: Vec<_> = errs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "could not parse: unexpected character `<`",
+                "expected expression, found end of input",
+            ],
+        );
+        assert_eq!(
+            errs.first_error().unwrap().0,
+            Some(Position {
+                file: 0,
+                line: 1,
+                column: 11
+            }),
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // Deeply nested input is an error rather than a stack overflow
+        let n = 10_000;
+        for s in [
+            format!("main := {}unit{}", "(".repeat(n), ")".repeat(n)),
+            format!("main := {}{}", "comp ".repeat(n), "unit ".repeat(n + 1)),
+            format!("main := unit : {}1{} -> 1", "(".repeat(n), ")".repeat(n)),
+            format!("main := const {}(){}", "L(".repeat(n), ")".repeat(n)),
+        ] {
+            let errs = parse_program::<Core>(&s).unwrap_err();
+            let messages: Vec<_> = errs.iter().map(ToString::to_string).collect();
+            assert_eq!(messages, ["expression is nested too deeply"]);
+            assert_eq!(errs.first_error().unwrap().1.code(), "E0032");
+        }
+
+        // Nesting up to the limit is allowed
+        let s = format!(
+            "main := {}{}",
+            "comp ".repeat(MAX_DEPTH - 1),
+            "unit ".repeat(MAX_DEPTH)
+        );
+        crate::human_encoding::Forest::<Core>::parse(&s).unwrap();
+    }
+
+    #[test]
+    fn long_chains() {
+        // Chains in which each expression is the last child of the one before
+        // are limited by the depth of the expression rather than the nesting
+        // depth of the parser.
+        let n = MAX_EXPRESSION_DEPTH - 1;
+        let s = format!("main := {}unit", "comp unit ".repeat(n));
+        crate::human_encoding::Forest::<Core>::parse(&s).unwrap();
+        let s = format!("main := {}unit", "injl ".repeat(n));
+        parse_program::<Core>(&s).unwrap();
+        let s = format!("main := comp ({}unit) unit", "pair iden ".repeat(n / 2));
+        crate::human_encoding::Forest::<Core>::parse(&s).unwrap();
+
+        // Left children count towards the depth of the chain.
+        let s = format!("main := {}comp iden iden", "comp iden ".repeat(n - 1));
+        parse_program::<Core>(&s).unwrap();
+        let s = format!(
+            "main := {}comp (comp iden iden) iden",
+            "comp iden ".repeat(n - 1)
+        );
+        let errs = parse_program::<Core>(&s).unwrap_err();
+        let messages: Vec<_> = errs.iter().map(ToString::to_string).collect();
+        assert_eq!(messages, ["expression is nested too deeply"]);
+
+        for s in [
+            format!("main := {}unit", "comp unit ".repeat(10_000)),
+            format!("main := {}unit", "injl ".repeat(10_000)),
+        ] {
+            let errs = parse_program::<Core>(&s).unwrap_err();
+            let messages: Vec<_> = errs.iter().map(ToString::to_string).collect();
+            assert_eq!(messages, ["expression is nested too deeply"]);
+        }
+    }
+
+    #[test]
+    fn comment_at_end_of_input() {
+        parse_program::<Core>("main := unit -- no trailing newline").unwrap();
+    }
+
+    #[test]
+    fn large_program() {
+        // Generated programs with many thousands of lines should parse quickly
+        let mut s = String::from("n0 := unit : 1 -> 1\n");
+        for i in 1..20_000 {
+            s += &format!("n{} := comp n{} iden : 1 -> 1 -- line {}\n", i, i - 1, i);
+        }
+        let lines = parse_program::<Core>(&s).unwrap().lines;
+        assert_eq!(lines.len(), 20_000);
+        assert_eq!(
+            lines[19_999].position,
+            Position {
+                file: 0,
+                line: 20_000,
+                column: 1
+            },
+        );
+    }
 }
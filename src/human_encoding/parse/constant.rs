@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Constants
+//!
+//! Replaces each structured constant, such as `const (0x02, L(()))`, by the
+//! combinators which construct it. A constant is checked against the target
+//! type given for it, if there is one, which also gives the width of decimal
+//! integers.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::human_encoding::{Error, ErrorSet, Position, WitnessOrHole};
+use crate::jet::Jet;
+use crate::node;
+use crate::value::Word;
+use crate::BitIter;
+
+use super::ast::{self, AstCmr, Constant, ExprInner, Expression, Type};
+use super::format;
+
+/// Replaces every structured constant in the program by the combinators which
+/// construct it.
+///
+/// The type of a constant is the target type of the innermost annotation around
+/// it or, if it is the whole expression of a definition, the target type given
+/// for that definition. Constants which are the CMR of an expression are left
+/// alone, since the CMR is only known once names are resolved, as are constants
+/// which are not values of their type, after the error has been recorded.
+pub fn desugar<J: Jet>(
+    program: ast::Program<J>,
+    aliases: &HashMap<Arc<str>, Type>,
+    errors: &mut ErrorSet,
+) -> ast::Program<J> {
+    let mut bounds = HashMap::<Arc<str>, &Type>::new();
+    for line in &program.lines {
+        if let (None, Some(ty)) = (&line.expression, &line.arrow.1) {
+            bounds.entry(Arc::clone(&line.name)).or_insert(ty);
+        }
+    }
+
+    let mut desugarer = Desugarer { aliases, errors };
+    let mut lines = Vec::with_capacity(program.lines.len());
+    for line in &program.lines {
+        let expression = line.expression.as_ref().map(|expression| {
+            let ty = line
+                .arrow
+                .1
+                .as_ref()
+                .or_else(|| bounds.get(&line.name).copied())
+                .map(|ty| ty.clone().expand_aliases(aliases));
+            desugarer.expression(expression, ty.as_ref())
+        });
+        lines.push(ast::Line {
+            expression,
+            ..line.clone()
+        });
+    }
+    ast::Program { lines, ..program }
+}
+
+struct Desugarer<'a, 'e> {
+    aliases: &'a HashMap<Arc<str>, Type>,
+    errors: &'e mut ErrorSet,
+}
+
+impl Desugarer<'_, '_> {
+    /// Replaces the constants within an expression whose target type is `ty`.
+    fn expression<J: Jet>(&mut self, expr: &Expression<J>, ty: Option<&Type>) -> Expression<J> {
+        let inner = match expr.inner {
+            ExprInner::Constant(ref constant) => self
+                .constant(constant, ty, expr.position)
+                .unwrap_or_else(|| expr.inner.clone()),
+            ExprInner::Ascribed(ref child, ref arrow) => {
+                let child_ty = arrow
+                    .1
+                    .as_ref()
+                    .map(|ty| ty.clone().expand_aliases(self.aliases));
+                ExprInner::Ascribed(
+                    Arc::new(self.expression(child, child_ty.as_ref().or(ty))),
+                    arrow.clone(),
+                )
+            }
+            ExprInner::Reference(..) | ExprInner::Instance(..) => expr.inner.clone(),
+            ExprInner::AssertL(ref left, ref cmr) => {
+                ExprInner::AssertL(self.child(left), self.cmr(cmr))
+            }
+            ExprInner::AssertR(ref cmr, ref right) => {
+                ExprInner::AssertR(self.cmr(cmr), self.child(right))
+            }
+            ExprInner::Inline(ref inner) => ExprInner::Inline(
+                inner
+                    .as_ref()
+                    .map(|child| self.child(child))
+                    .map_disconnect(|child| self.child(child))
+                    .map_witness(WitnessOrHole::shallow_clone),
+            ),
+        };
+        Expression {
+            inner,
+            position: expr.position,
+        }
+    }
+
+    fn child<J: Jet>(&mut self, child: &Arc<Expression<J>>) -> Arc<Expression<J>> {
+        Arc::new(self.expression(child, None))
+    }
+
+    fn cmr<J: Jet>(&mut self, cmr: &AstCmr<J>) -> AstCmr<J> {
+        match cmr {
+            AstCmr::Expr(expr) => AstCmr::Expr(self.child(expr)),
+            AstCmr::Literal(cmr) => AstCmr::Literal(*cmr),
+        }
+    }
+
+    /// Converts a constant of type `ty` into the expression which constructs it,
+    /// or records an error if it is not a value of that type.
+    fn constant<J: Jet>(
+        &mut self,
+        constant: &Constant<J>,
+        ty: Option<&Type>,
+        position: Position,
+    ) -> Option<ExprInner<J>> {
+        let mismatch = |errors: &mut ErrorSet| {
+            errors.add(
+                position,
+                Error::ConstantType {
+                    constant: Arc::from(format::constant(constant)),
+                    ty: Arc::from(format::type_string(ty.unwrap(), 0)),
+                },
+            );
+            None
+        };
+        let child = |this: &mut Self, constant: &Constant<J>, ty: Option<Type>| {
+            this.constant(constant, ty.as_ref(), position)
+                .map(|inner| Arc::new(Expression { inner, position }))
+        };
+
+        let word = match constant {
+            Constant::Unit => {
+                return match ty {
+                    None | Some(Type::Name(_)) | Some(Type::One) => {
+                        Some(ExprInner::Inline(node::Inner::Unit))
+                    }
+                    Some(_) => mismatch(self.errors),
+                };
+            }
+            Constant::Pair(left, right) => {
+                let (left_ty, right_ty) = match split_product(ty) {
+                    Some(types) => types,
+                    None => return mismatch(self.errors),
+                };
+                let left = child(self, left, left_ty);
+                let right = child(self, right, right_ty);
+                return Some(ExprInner::Inline(node::Inner::Pair(left?, right?)));
+            }
+            Constant::Left(inner) => {
+                let (inner_ty, _) = match split_sum(ty) {
+                    Some(types) => types,
+                    None => return mismatch(self.errors),
+                };
+                let inner = child(self, inner, inner_ty)?;
+                return Some(ExprInner::Inline(node::Inner::InjL(inner)));
+            }
+            Constant::Right(inner) => {
+                let (_, inner_ty) = match split_sum(ty) {
+                    Some(types) => types,
+                    None => return mismatch(self.errors),
+                };
+                let inner = child(self, inner, inner_ty)?;
+                return Some(ExprInner::Inline(node::Inner::InjR(inner)));
+            }
+            Constant::Cmr(AstCmr::Expr(expr)) => {
+                if !ty.map_or(true, |ty| is_word_type(8, ty)) {
+                    return mismatch(self.errors);
+                }
+                let expr = AstCmr::Expr(self.child(expr));
+                return Some(ExprInner::Constant(Constant::Cmr(expr)));
+            }
+            Constant::Cmr(AstCmr::Literal(cmr)) => Word::u256(cmr.to_byte_array()),
+            Constant::Word(word) => word.shallow_clone(),
+            Constant::Decimal(digits) => {
+                let n = match ty.map(word_width) {
+                    Some(Some(n)) => n,
+                    None | Some(None) if ty.map_or(true, |ty| matches!(ty, Type::Name(_))) => {
+                        self.errors
+                            .add(position, Error::ConstantUntyped(Arc::clone(digits)));
+                        return None;
+                    }
+                    _ => return mismatch(self.errors),
+                };
+                match decimal_word(digits, n) {
+                    Some(word) => word,
+                    None => return mismatch(self.errors),
+                }
+            }
+        };
+        if !ty.map_or(true, |ty| is_word_type(word.n(), ty)) {
+            return mismatch(self.errors);
+        }
+        Some(ExprInner::Inline(node::Inner::Word(word)))
+    }
+}
+
+/// The types of the components of a pair of the given type, or `None` if the
+/// type is not a product.
+fn split_product(ty: Option<&Type>) -> Option<(Option<Type>, Option<Type>)> {
+    match ty {
+        None | Some(Type::Name(_)) => Some((None, None)),
+        Some(Type::Product(left, right)) => {
+            Some((Some(Type::clone(left)), Some(Type::clone(right))))
+        }
+        Some(Type::TwoTwoN(1)) => Some((Some(Type::Two), Some(Type::Two))),
+        Some(Type::TwoTwoN(n)) if *n > 1 => {
+            Some((Some(Type::TwoTwoN(n - 1)), Some(Type::TwoTwoN(n - 1))))
+        }
+        Some(_) => None,
+    }
+}
+
+/// The types of the variants of a sum of the given type, or `None` if the type
+/// is not a sum.
+fn split_sum(ty: Option<&Type>) -> Option<(Option<Type>, Option<Type>)> {
+    match ty {
+        None | Some(Type::Name(_)) => Some((None, None)),
+        Some(Type::Sum(left, right)) => Some((Some(Type::clone(left)), Some(Type::clone(right)))),
+        Some(Type::Two) | Some(Type::TwoTwoN(0)) => Some((Some(Type::One), Some(Type::One))),
+        Some(_) => None,
+    }
+}
+
+/// Whether a word of 2^n bits may have the given type.
+fn is_word_type(n: u32, ty: &Type) -> bool {
+    let is_unit_type = |ty: &Type| matches!(ty, Type::Name(_) | Type::One);
+    match ty {
+        Type::Name(_) => true,
+        Type::One => false,
+        Type::Two => n == 0,
+        Type::TwoTwoN(k) => *k == n,
+        Type::Sum(left, right) => n == 0 && is_unit_type(left) && is_unit_type(right),
+        Type::Product(left, right) => {
+            n > 0 && is_word_type(n - 1, left) && is_word_type(n - 1, right)
+        }
+    }
+}
+
+/// If the type is that of a word of 2^n bits, returns `n`.
+fn word_width(ty: &Type) -> Option<u32> {
+    match ty {
+        Type::Two => Some(0),
+        Type::TwoTwoN(n) => Some(*n),
+        Type::Sum(left, right) if **left == Type::One && **right == Type::One => Some(0),
+        Type::Product(left, right) => {
+            let n = word_width(left)?;
+            (word_width(right)? == n).then_some(n + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Converts a decimal integer into a word of 2^n bits, if it fits.
+fn decimal_word(digits: &str, n: u32) -> Option<Word> {
+    if n >= 32 {
+        return None;
+    }
+    let bit_length = 1usize << n;
+    let mut bytes = vec![0u8; bit_length.div_ceil(8)];
+    for digit in digits.bytes() {
+        let mut carry = u32::from(digit - b'0');
+        for byte in bytes.iter_mut().rev() {
+            let x = u32::from(*byte) * 10 + carry;
+            *byte = x as u8;
+            carry = x >> 8;
+        }
+        if carry > 0 {
+            return None;
+        }
+    }
+    if bit_length < 8 {
+        if bytes[0] >> bit_length != 0 {
+            return None;
+        }
+        bytes[0] <<= 8 - bit_length;
+    }
+    Word::from_bits(&mut BitIter::from(bytes), n).ok()
+}
@@ -0,0 +1,757 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Formatting
+//!
+//! Re-prints a single source file in a canonical layout. Unlike
+//! [`crate::human_encoding::Forest::string_serialize`], which prints a program
+//! from its DAG, this works on the AST, so names, the order of definitions
+//! and comments are all kept.
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use crate::human_encoding::{ErrorSet, Position, WitnessOrHole};
+use crate::jet::Jet;
+use crate::node;
+
+use super::ast::{self, AstCmr, Constant, ExprInner, Expression, Type};
+
+/// Lines longer than this are broken up, where possible.
+const MAX_WIDTH: usize = 100;
+/// Indentation of each level of a broken-up expression.
+const INDENT: &str = "    ";
+
+/// Formats the source of a single file in the canonical layout.
+///
+/// The output has imports first, sorted by path, then definitions, type
+/// aliases and type bounds in their original order, then witness assignments, sorted by name.
+/// Within each run of lines not separated by a blank line, type ascriptions
+/// and trailing comments are aligned. Expressions which do not fit on a line
+/// are broken up with one child per line.
+///
+/// Every comment is kept. Comments on their own line stay attached to the
+/// line that follows them, and comments at the end of a line stay at the end
+/// of that line. Comments inside an expression which spans several lines are
+/// moved to just before it.
+///
+/// Imports are not followed, and names are not resolved; the input only needs
+/// to be syntactically valid.
+pub fn format<J: Jet + 'static>(input: &str) -> Result<String, ErrorSet> {
+    let (program, layout) = ast::parse_with_layout::<J>(input, 0)?;
+
+    let mut items = Vec::with_capacity(
+        program.imports.len()
+            + program.lines.len()
+            + program.aliases.len()
+            + program.witnesses.len(),
+    );
+    items.extend(program.imports.iter().map(|import| Item {
+        position: import.position,
+        section: Section::Imports,
+        sort_key: import.path.to_string(),
+        text: Text::single(match import.alias {
+            Some(ref alias) => format!("import \"{}\" as {}", import.path, alias),
+            None => format!("import \"{}\"", import.path),
+        }),
+    }));
+    items.extend(program.lines.iter().map(|line| Item {
+        position: line.position,
+        section: Section::Definitions,
+        sort_key: String::new(),
+        text: line_text(line),
+    }));
+    items.extend(program.aliases.iter().map(|alias| Item {
+        position: alias.position,
+        section: Section::Definitions,
+        sort_key: String::new(),
+        text: Text::single(format!(
+            "type {} := {}",
+            alias.name,
+            type_string(&alias.ty, 0)
+        )),
+    }));
+    items.extend(program.witnesses.iter().map(|witness| Item {
+        position: witness.position,
+        section: Section::Witnesses,
+        sort_key: witness.name.to_string(),
+        text: Text {
+            head: witness.name.to_string(),
+            ascription: Some(format!("<- {}", literal(&witness.data, witness.bit_length))),
+            tail: None,
+        },
+    }));
+    items.sort_by_key(|item| item.position);
+
+    let (header, mut entries, footer) = attach_comments(items, &layout);
+    // Sorting is stable, so definitions keep their original order.
+    entries.sort_by(|a, b| {
+        (a.item.section, &a.item.sort_key).cmp(&(b.item.section, &b.item.sort_key))
+    });
+
+    let mut out = String::new();
+    for comment in &header {
+        if comment.blank_before && !out.is_empty() {
+            out.push('\n');
+        }
+        writeln!(out, "{}", comment.text).unwrap();
+    }
+    let mut section = None;
+    let mut group: Vec<&Entry> = vec![];
+    for entry in &entries {
+        // Blank lines are kept between definitions, but the other sections are
+        // sorted, so there blank lines would no longer separate anything.
+        let new_section = section != Some(entry.item.section);
+        let blank = if new_section {
+            !out.is_empty() || !group.is_empty()
+        } else {
+            entry.item.section == Section::Definitions && entry.blank_before
+        };
+        if blank {
+            print_group(&mut out, &group);
+            group.clear();
+            out.push('\n');
+        }
+        section = Some(entry.item.section);
+        group.push(entry);
+    }
+    print_group(&mut out, &group);
+    for comment in &footer {
+        if comment.blank_before && !out.is_empty() {
+            out.push('\n');
+        }
+        writeln!(out, "{}", comment.text).unwrap();
+    }
+    Ok(out)
+}
+
+/// The sections of a formatted file, in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Imports,
+    Definitions,
+    Witnesses,
+}
+
+/// A top-level item of the source: an import, definition, type alias, type
+/// bound or witness assignment.
+struct Item {
+    position: Position,
+    section: Section,
+    sort_key: String,
+    text: Text,
+}
+
+/// The printed form of an item.
+struct Text {
+    /// Everything before the type ascription (or `<-`), on one line.
+    head: String,
+    /// The type ascription, or the assigned value of a witness, which is
+    /// aligned with those of neighbouring lines.
+    ascription: Option<String>,
+    /// If the item does not fit on one line, the remaining lines of the
+    /// expression after `head`, including any type ascription.
+    tail: Option<Vec<String>>,
+}
+
+impl Text {
+    fn single(head: String) -> Self {
+        Text {
+            head,
+            ascription: None,
+            tail: None,
+        }
+    }
+
+    /// The lines of the text, without aligning its ascription.
+    fn lines(self) -> Vec<String> {
+        match (self.ascription, self.tail) {
+            (_, Some(tail)) => std::iter::once(self.head).chain(tail).collect(),
+            (Some(ascription), None) => vec![format!("{} {}", self.head, ascription)],
+            (None, None) => vec![self.head],
+        }
+    }
+}
+
+/// A comment on its own line.
+struct OwnLineComment<'a> {
+    text: &'a str,
+    line: usize,
+    /// Whether there is a blank line before the comment in the input.
+    blank_before: bool,
+}
+
+/// An item along with the comments attached to it.
+struct Entry<'a> {
+    item: Item,
+    leading: Vec<OwnLineComment<'a>>,
+    trailing: Option<&'a str>,
+    /// Whether there is a blank line before the entry (including its leading
+    /// comments) in the input.
+    blank_before: bool,
+}
+
+/// Attaches each comment to an item, returning the comments before the first
+/// item which are separated from it by a blank line, the items with their
+/// comments, and the comments after the last item.
+fn attach_comments(
+    items: Vec<Item>,
+    layout: &ast::Layout,
+) -> (
+    Vec<OwnLineComment<'_>>,
+    Vec<Entry<'_>>,
+    Vec<OwnLineComment<'_>>,
+) {
+    // Each item runs up to the last token before the next item.
+    let positions = &layout.token_positions;
+    let ends: Vec<Position> = (0..items.len())
+        .map(|k| {
+            let n_tokens = match items.get(k + 1) {
+                Some(next) => positions.partition_point(|pos| *pos < next.position),
+                None => positions.len(),
+            };
+            positions[n_tokens - 1]
+        })
+        .collect();
+
+    let mut entries: Vec<Entry> = items
+        .into_iter()
+        .map(|item| Entry {
+            item,
+            leading: vec![],
+            trailing: None,
+            blank_before: false,
+        })
+        .collect();
+    // Comments on their own lines after each item (or before the first item,
+    // at index 0), which are the leading comments of the following item
+    let mut pending: Vec<Vec<OwnLineComment>> = (0..=entries.len()).map(|_| vec![]).collect();
+
+    for comment in &layout.comments {
+        let n_before = entries.partition_point(|entry| entry.item.position < comment.position);
+        if n_before > 0 {
+            let end = ends[n_before - 1];
+            if comment.position < end {
+                // Inside a multi-line item; hoist it above the item.
+                entries[n_before - 1].leading.push(OwnLineComment {
+                    text: &comment.text,
+                    line: comment.position.line,
+                    blank_before: false,
+                });
+                continue;
+            } else if comment.position.line == end.line {
+                entries[n_before - 1].trailing = Some(&comment.text);
+                continue;
+            }
+        }
+        let prev_line = match pending[n_before].last() {
+            Some(prev) => Some(prev.line),
+            None => n_before.checked_sub(1).map(|k| ends[k].line),
+        };
+        pending[n_before].push(OwnLineComment {
+            text: &comment.text,
+            line: comment.position.line,
+            blank_before: prev_line.is_some_and(|line| comment.position.line > line + 1),
+        });
+    }
+
+    let footer = pending.pop().unwrap_or_default();
+    let mut header = vec![];
+    for (k, comments) in pending.into_iter().enumerate() {
+        let entry = &mut entries[k];
+        let first_line = comments
+            .first()
+            .map_or(entry.item.position.line, |comment| comment.line);
+        entry.blank_before = k > 0 && first_line > ends[k - 1].line + 1;
+
+        let mut comments = comments;
+        if k == 0 {
+            // Comments at the start of the file which are separated from the
+            // first item by a blank line are a header, and stay at the top.
+            let split = match comments.last() {
+                Some(last) if entry.item.position.line > last.line + 1 => comments.len(),
+                _ => comments.iter().rposition(|c| c.blank_before).unwrap_or(0),
+            };
+            header = comments.drain(..split).collect();
+        }
+        let hoisted = std::mem::replace(&mut entry.leading, comments);
+        entry.leading.extend(hoisted);
+    }
+    (header, entries, footer)
+}
+
+/// Prints a run of entries not separated by blank lines, aligning their type
+/// ascriptions and trailing comments.
+fn print_group(out: &mut String, group: &[&Entry]) {
+    // Blank lines between comments are only kept where the order is.
+    let keep_blank_lines = group
+        .first()
+        .is_some_and(|entry| entry.item.section == Section::Definitions);
+    let single_line = |entry: &&&Entry| entry.item.text.tail.is_none();
+    let head_width = group
+        .iter()
+        .filter(single_line)
+        .filter(|entry| entry.item.text.ascription.is_some())
+        .map(|entry| entry.item.text.head.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let lines: Vec<Vec<String>> = group
+        .iter()
+        .map(|entry| {
+            let text = &entry.item.text;
+            match (&text.ascription, &text.tail) {
+                (_, Some(tail)) => {
+                    let mut lines = vec![text.head.clone()];
+                    lines.extend(tail.iter().cloned());
+                    lines
+                }
+                (Some(ascription), None) => {
+                    let padding = head_width - text.head.chars().count();
+                    vec![format!(
+                        "{}{} {}",
+                        text.head,
+                        " ".repeat(padding),
+                        ascription
+                    )]
+                }
+                (None, None) => vec![text.head.clone()],
+            }
+        })
+        .collect();
+    let comment_column = group
+        .iter()
+        .zip(&lines)
+        .filter(|(entry, _)| single_line(entry) && entry.trailing.is_some())
+        .map(|(_, lines)| lines[0].chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (entry, lines) in group.iter().zip(lines) {
+        for (n, comment) in entry.leading.iter().enumerate() {
+            if keep_blank_lines && n > 0 && comment.blank_before {
+                out.push('\n');
+            }
+            writeln!(out, "{}", comment.text).unwrap();
+        }
+        let n_lines = lines.len();
+        for (n, line) in lines.into_iter().enumerate() {
+            match entry.trailing {
+                Some(comment) if n + 1 == n_lines => {
+                    let width = if n_lines == 1 {
+                        comment_column
+                    } else {
+                        line.chars().count()
+                    };
+                    writeln!(out, "{:width$} {}", line, comment, width = width).unwrap();
+                }
+                _ => writeln!(out, "{}", line).unwrap(),
+            }
+        }
+    }
+}
+
+/// Prints a definition or type bound.
+fn line_text<J: Jet>(line: &ast::Line<J>) -> Text {
+    let ascription = match line.arrow {
+        (None, None) => None,
+        (ref source, ref target) => Some(format!(": {} -> {}", ty(source), ty(target))),
+    };
+    let expression = match line.expression {
+        Some(ref expression) => expression,
+        None => {
+            return Text {
+                head: line.name.to_string(),
+                ascription,
+                tail: None,
+            }
+        }
+    };
+
+    let head = if line.parameters.is_empty() {
+        format!("{} := ", line.name)
+    } else {
+        let parameters: Vec<&str> = line.parameters.iter().map(|p| p.name.as_ref()).collect();
+        format!("{}({}) := ", line.name, parameters.join(", "))
+    };
+    let mut lines = layout(expression, head.chars().count(), 0);
+    let head = head + &lines.remove(0);
+    let text = if lines.is_empty() {
+        Text {
+            head,
+            ascription,
+            tail: None,
+        }
+    } else {
+        lines.extend(ascription.map(|ascription| format!("{}{}", INDENT, ascription)));
+        Text {
+            head,
+            ascription: None,
+            tail: Some(lines),
+        }
+    };
+    if line.locals.is_empty() {
+        return text;
+    }
+
+    // A `where` block starts at the end of the definition, and has one local
+    // per line, indented by one level.
+    let mut lines = text.lines();
+    let last = lines.pop().expect("text has at least one line");
+    lines.push(format!("{} where {{", last));
+    for local in &line.locals {
+        lines.extend(
+            line_text(local)
+                .lines()
+                .into_iter()
+                .map(|line| format!("{}{}", INDENT, line)),
+        );
+    }
+    lines.push("}".into());
+    Text {
+        head: lines.remove(0),
+        ascription: None,
+        tail: Some(lines),
+    }
+}
+
+/// A child of an expression, for printing.
+enum Child<'a, J: Jet> {
+    Expr(&'a Expression<J>),
+    Cmr(&'a AstCmr<J>),
+}
+
+/// Splits an expression into the keyword (or name) which starts it and its
+/// children, in the order they are written.
+fn parts<J: Jet>(expr: &Expression<J>) -> (String, Vec<Child<'_, J>>) {
+    match expr.inner {
+        ExprInner::Reference(ref name) => (name.to_string(), vec![]),
+        // Arguments are never broken across lines.
+        ExprInner::Instance(ref name, ref arguments) => (call(name, arguments), vec![]),
+        // Nor are constants and annotated expressions, which are self-delimiting.
+        ExprInner::Constant(ref value) => (format!("const {}", constant(value)), vec![]),
+        ExprInner::Ascribed(ref child, ref arrow) => (
+            format!("({} : {} -> {})", inline(child), ty(&arrow.0), ty(&arrow.1)),
+            vec![],
+        ),
+        ExprInner::AssertL(ref left, ref cmr) => {
+            ("assertl".into(), vec![Child::Expr(left), Child::Cmr(cmr)])
+        }
+        ExprInner::AssertR(ref cmr, ref right) => {
+            ("assertr".into(), vec![Child::Cmr(cmr), Child::Expr(right)])
+        }
+        ExprInner::Inline(ref inner) => {
+            let keyword = match inner {
+                node::Inner::Iden => "iden".into(),
+                node::Inner::Unit => "unit".into(),
+                node::Inner::InjL(_) => "injl".into(),
+                node::Inner::InjR(_) => "injr".into(),
+                node::Inner::Take(_) => "take".into(),
+                node::Inner::Drop(_) => "drop".into(),
+                node::Inner::Comp(..) => "comp".into(),
+                node::Inner::Case(..) => "case".into(),
+                node::Inner::AssertL(..) | node::Inner::AssertR(..) => {
+                    unreachable!("assertions are parsed as ExprInner::AssertL/AssertR")
+                }
+                node::Inner::Pair(..) => "pair".into(),
+                node::Inner::Disconnect(..) => "disconnect".into(),
+                node::Inner::Witness(WitnessOrHole::Witness) => "witness".into(),
+                node::Inner::Witness(WitnessOrHole::TypedHole(name)) => format!("?{}", name),
+                node::Inner::Fail(entropy) => {
+                    // Entropy is zero-padded, so trailing zero bytes beyond the
+                    // minimum of 128 bits carry no information.
+                    let bytes = entropy.as_ref();
+                    let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |n| n + 1);
+                    let bytes = &bytes[..len.max(16)];
+                    format!("fail {}", literal(bytes, 8 * bytes.len()))
+                }
+                node::Inner::Jet(jet) => format!("jet_{}", jet),
+                node::Inner::Word(word) => format!("const {}", word),
+            };
+            let children = match inner {
+                node::Inner::InjL(child)
+                | node::Inner::InjR(child)
+                | node::Inner::Take(child)
+                | node::Inner::Drop(child) => vec![Child::Expr(child)],
+                node::Inner::Comp(left, right)
+                | node::Inner::Case(left, right)
+                | node::Inner::Pair(left, right)
+                | node::Inner::Disconnect(left, right) => {
+                    vec![Child::Expr(left), Child::Expr(right)]
+                }
+                _ => vec![],
+            };
+            (keyword, children)
+        }
+    }
+}
+
+/// Prints an instance of a template on a single line.
+pub(super) fn call<J: Jet>(name: &str, arguments: &[Arc<Expression<J>>]) -> String {
+    let arguments: Vec<String> = arguments.iter().map(|arg| inline(arg)).collect();
+    format!("{}({})", name, arguments.join(", "))
+}
+
+/// Prints an expression on a single line.
+///
+/// Children which have children of their own are parenthesized.
+fn inline<J: Jet>(expr: &Expression<J>) -> String {
+    let (mut ret, children) = parts(expr);
+    for child in children {
+        ret.push(' ');
+        match child {
+            Child::Expr(child) => {
+                if parts(child).1.is_empty() {
+                    ret += &inline(child);
+                } else {
+                    ret += &format!("({})", inline(child));
+                }
+            }
+            Child::Cmr(cmr) => ret += &inline_cmr(cmr),
+        }
+    }
+    ret
+}
+
+/// Prints the value of a `const` expression.
+pub(super) fn constant<J: Jet>(value: &Constant<J>) -> String {
+    match value {
+        Constant::Unit => "()".into(),
+        Constant::Pair(left, right) => format!("({}, {})", constant(left), constant(right)),
+        Constant::Left(inner) => format!("L({})", constant(inner)),
+        Constant::Right(inner) => format!("R({})", constant(inner)),
+        Constant::Word(word) => word.to_string(),
+        Constant::Decimal(digits) => digits.to_string(),
+        Constant::Cmr(cmr) => inline_cmr(cmr),
+    }
+}
+
+fn inline_cmr<J: Jet>(cmr: &AstCmr<J>) -> String {
+    match cmr {
+        AstCmr::Expr(expr) => format!("#{{{}}}", inline(expr)),
+        AstCmr::Literal(cmr) => format!("#{}", cmr),
+    }
+}
+
+/// Prints an expression whose first line starts at `column`, breaking it up
+/// if it does not fit. Children of a broken-up expression are printed one per
+/// line, indented one level more than `indent`.
+///
+/// The first line is returned without indentation; other lines include it.
+fn layout<J: Jet>(expr: &Expression<J>, column: usize, indent: usize) -> Vec<String> {
+    let single = inline(expr);
+    let (keyword, children) = parts(expr);
+    if column + single.chars().count() <= MAX_WIDTH || children.is_empty() {
+        return vec![single];
+    }
+
+    let child_indent = INDENT.repeat(indent + 1);
+    let child_column = child_indent.len();
+    let mut lines = vec![keyword];
+    for child in children {
+        let child_lines = match child {
+            Child::Expr(child) => layout(child, child_column, indent + 1),
+            Child::Cmr(cmr) => {
+                let single = inline_cmr(cmr);
+                match cmr {
+                    AstCmr::Expr(expr) if child_column + single.chars().count() > MAX_WIDTH => {
+                        let mut expr_lines = layout(expr, child_column + INDENT.len(), indent + 2);
+                        expr_lines[0] = format!("{}{}", INDENT.repeat(indent + 2), expr_lines[0]);
+                        let mut cmr_lines = vec!["#{".to_owned()];
+                        cmr_lines.extend(expr_lines);
+                        cmr_lines.push(format!("{}}}", child_indent));
+                        cmr_lines
+                    }
+                    _ => vec![single],
+                }
+            }
+        };
+        let mut child_lines = child_lines.into_iter();
+        lines.push(format!("{}{}", child_indent, child_lines.next().unwrap()));
+        lines.extend(child_lines);
+    }
+    lines
+}
+
+/// Prints an optional type, with `_` for a missing one.
+fn ty(ty: &Option<Type>) -> String {
+    match ty {
+        Some(ty) => type_string(ty, 0),
+        None => "_".into(),
+    }
+}
+
+/// Prints a type, parenthesizing it if its operator binds less tightly than
+/// `precedence` (0 for none, 1 for `+`, 2 for `*`).
+pub(super) fn type_string(ty: &Type, precedence: u8) -> String {
+    let (s, own_precedence) = match ty {
+        Type::Name(name) => (name.clone(), 3),
+        Type::One => ("1".into(), 3),
+        Type::Two => ("2".into(), 3),
+        Type::TwoTwoN(n) => (format!("2^{}", 1u64 << n), 3),
+        // Both operators are left-associative, so only the right operand needs
+        // parentheses at the same precedence.
+        Type::Sum(left, right) => (
+            format!("{} + {}", type_string(left, 1), type_string(right, 2)),
+            1,
+        ),
+        Type::Product(left, right) => (
+            format!("{} * {}", type_string(left, 2), type_string(right, 3)),
+            2,
+        ),
+    };
+    if own_precedence < precedence {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+/// Prints a literal of the given length, as hex if the length is a multiple
+/// of 4 and otherwise as binary.
+fn literal(data: &[u8], bit_length: usize) -> String {
+    if bit_length == 0 {
+        return "_".into();
+    }
+    let bit = |n: usize| data[n / 8] & (0x80 >> (n % 8)) != 0;
+    if bit_length % 4 == 0 {
+        let mut ret = String::from("0x");
+        for n in 0..bit_length / 4 {
+            let nybble = (0..4).fold(0, |acc, i| (acc << 1) | u32::from(bit(4 * n + i)));
+            ret.push(char::from_digit(nybble, 16).unwrap());
+        }
+        ret
+    } else {
+        let mut ret = String::from("0b");
+        ret.extend((0..bit_length).map(|n| if bit(n) { '1' } else { '0' }));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::human_encoding::Forest;
+    use crate::jet::Core;
+
+    #[test]
+    fn canonical_layout() {
+        let input = "-- header
+
+-- about main
+main   :=  comp (comp (pair a b) jet_lt_8)   jet_verify  -- check
+b <- 0b00000001
+  a := witness:1->2^8
+b:=witness -- second
+
+
+-- imports may come anywhere
+import \"z.simpl\"
+import \"a.simpl\" as a
+a <- 0x00
+long := comp comp comp comp comp pair iden iden take iden drop iden injl iden injr pair unit unit case iden iden : 2 * 2 -> 1 + (1 * 1)
+x := assertl unit #{comp iden iden} : (2 + 1) * A -> _
+-- the end
+";
+        let expected = "-- header
+
+import \"a.simpl\" as a
+-- imports may come anywhere
+import \"z.simpl\"
+
+-- about main
+main := comp (comp (pair a b) jet_lt_8) jet_verify -- check
+a := witness                        : 1 -> 2^8
+b := witness                                       -- second
+long := comp
+    comp
+        comp (comp (comp (pair iden iden) (take iden)) (drop iden)) (injl iden)
+        injr (pair unit unit)
+    case iden iden
+    : 2 * 2 -> 1 + 1 * 1
+x := assertl unit #{comp iden iden} : (2 + 1) * A -> _
+
+a <- 0x00
+b <- 0x01
+-- the end
+";
+        let formatted = format::<Core>(input).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format::<Core>(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let input = "
+-- doc for a
+a := unit  -- a
+bb := iden : A -> A -- bb
+
+c := comp
+    -- inside
+    a -- also inside
+    bb
+";
+        let expected = "-- doc for a
+a := unit           -- a
+bb := iden : A -> A -- bb
+
+-- inside
+-- also inside
+c := comp a bb
+";
+        assert_eq!(format::<Core>(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn where_blocks() {
+        let input = "
+main := comp check unit : 1 -> 1 where { check := comp eq jet_verify
+  eq := comp (pair w (const 0x2a)) jet_eq_8 where{w:=witness} ; eq : 1 -> 2 }
+";
+        let expected = "main := comp check unit : 1 -> 1 where {
+    check := comp eq jet_verify
+    eq := comp (pair w const 0x2a) jet_eq_8 where {
+        w := witness
+    }
+    eq : 1 -> 2
+}
+";
+        let formatted = format::<Core>(input).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format::<Core>(&formatted).unwrap(), formatted);
+        assert_eq!(
+            Forest::<Core>::parse(input).unwrap(),
+            Forest::<Core>::parse(&formatted).unwrap(),
+        );
+    }
+
+    #[test]
+    fn formatting_preserves_meaning() {
+        let input = "
+            type  Word:=2^32
+            wit1 := witness : 1 -> Word
+            wit2 := witness : 1 -> 2^32
+            eq(X,Y) := comp (pair (X:_ -> Word) Y) jet_eq_32 : 1 -> 2
+            check := comp eq(wit1,wit2) jet_verify : 1 -> 1
+            id1 := iden : 2^256 * 1 -> 2^256 * 1
+            disc := comp (disconnect id1 ?hole) unit
+            hole := unit
+            lit := assertr #0000000000000000000000000000000000000000000000000000000000000000 (const 0b01)
+            boom := fail 0x000102030405060708090a0b0c0d0e0f10
+            point := const (  42,L( ( ) ) ) : 1 -> Word * 2
+            main := comp check disc
+            wit1 <- 0x00000001
+            wit2 <- 0x00000001
+        ";
+        let formatted = format::<Core>(input).unwrap();
+        assert!(formatted.contains("type Word := 2^32\n"));
+        assert!(formatted.contains("eq(X, Y) := comp (pair (X : _ -> Word) Y) jet_eq_32"));
+        assert!(formatted.contains("comp eq(wit1, wit2) jet_verify"));
+        assert!(formatted.contains("point := const (42, L(()))"));
+        assert_eq!(format::<Core>(&formatted).unwrap(), formatted);
+        assert_eq!(
+            Forest::<Core>::parse(input).unwrap(),
+            Forest::<Core>::parse(&formatted).unwrap(),
+        );
+    }
+}
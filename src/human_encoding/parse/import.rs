@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Imports
+//!
+//! Follows the `import` lines of a program, collecting the lines of every
+//! imported file into a single program which can then be resolved as though
+//! it had been written in one file.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::human_encoding::{Error, ErrorSet, Position};
+use crate::jet::Jet;
+
+use super::{ast, scope};
+
+/// Reads the contents of a file, given its path.
+pub type ReadFn<'a> = dyn FnMut(&Path) -> io::Result<String> + 'a;
+
+/// Loads the file at `path` and everything it imports, combining them into a
+/// single program.
+///
+/// Each file is read at most once, no matter how many times it is imported,
+/// and its lines are included once for each distinct qualifier it is imported
+/// under. Positions in the returned program and errors refer to files by the
+/// order in which they were read, starting with 0 for `path` itself.
+///
+/// The name and source of every file read are attached to `errors`, so that
+/// errors found later can be displayed in context. The names are also returned,
+/// indexed by file.
+pub fn load<J: Jet + 'static>(
+    path: &Path,
+    read: &mut ReadFn,
+    errors: &mut ErrorSet,
+) -> (ast::Program<J>, Vec<Arc<str>>) {
+    let mut loader = Loader {
+        read,
+        programs: HashMap::new(),
+        stack: vec![],
+        included: HashSet::new(),
+        files: vec![],
+        errors,
+        output: ast::Program {
+            lines: vec![],
+            aliases: vec![],
+            witnesses: vec![],
+            imports: vec![],
+            comments: vec![],
+        },
+    };
+    loader.include(normalize(path), "", None);
+    (loader.output, loader.files)
+}
+
+struct Loader<'r, 'e, 'a, J: Jet> {
+    read: &'r mut ReadFn<'a>,
+    /// Parsed contents of each file which has been read, or `None` if reading
+    /// or parsing it failed
+    programs: HashMap<PathBuf, Option<ast::Program<J>>>,
+    /// Files which are currently being included, used to detect cycles
+    stack: Vec<PathBuf>,
+    /// Files and qualifiers whose lines have already been included
+    included: HashSet<(PathBuf, String)>,
+    /// Names of the files which have been read, by file index
+    files: Vec<Arc<str>>,
+    errors: &'e mut ErrorSet,
+    output: ast::Program<J>,
+}
+
+impl<J: Jet + 'static> Loader<'_, '_, '_, J> {
+    /// Includes the lines of the file at `path`, after those of its imports,
+    /// qualifying every name with `prefix`.
+    ///
+    /// `position` is the position of the import line, or `None` for the
+    /// top-level file.
+    fn include(&mut self, path: PathBuf, prefix: &str, position: Option<Position>) {
+        let display = Arc::<str>::from(path.display().to_string());
+        if self.stack.contains(&path) {
+            self.add_error(position, Error::ImportCycle(display));
+            return;
+        }
+        if !self.included.insert((path.clone(), prefix.to_owned())) {
+            return;
+        }
+
+        if !self.programs.contains_key(&path) {
+            let program = match (self.read)(&path) {
+                Ok(source) => {
+                    let file = self.files.len();
+                    self.files.push(Arc::clone(&display));
+                    self.errors
+                        .add_file(file, Arc::clone(&display), Arc::from(source.as_str()));
+                    // Locals are hoisted before names are qualified, so that
+                    // they are named after the definition they belong to.
+                    match ast::parse_source(&source, file) {
+                        Ok(program) => Some(scope::hoist(program)),
+                        Err(errs) => {
+                            self.errors.merge(&errs);
+                            None
+                        }
+                    }
+                }
+                Err(e) => {
+                    let error = Error::ImportFailed {
+                        path: display,
+                        reason: e.to_string(),
+                    };
+                    self.add_error(position, error);
+                    None
+                }
+            };
+            self.programs.insert(path.clone(), program);
+        }
+        let program = match self.programs[&path] {
+            Some(ref program) => program.clone(),
+            None => return,
+        };
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        self.stack.push(path.clone());
+        for import in &program.imports {
+            let prefix = match import.alias {
+                Some(ref alias) => format!("{}{}.", prefix, alias),
+                None => prefix.to_owned(),
+            };
+            let path = normalize(&dir.join(&*import.path));
+            self.include(path, &prefix, Some(import.position));
+        }
+        self.stack.pop();
+
+        if prefix.is_empty() {
+            self.output.lines.extend(program.lines);
+            self.output.aliases.extend(program.aliases);
+            self.output.witnesses.extend(program.witnesses);
+            self.output.comments.extend(program.comments);
+        } else {
+            let lines = program.lines.into_iter().map(|line| line.qualify(prefix));
+            let aliases = program
+                .aliases
+                .into_iter()
+                .map(|alias| alias.qualify(prefix));
+            let witnesses = program.witnesses.into_iter().map(|wit| wit.qualify(prefix));
+            let comments = program
+                .comments
+                .into_iter()
+                .map(|comment| comment.qualify(prefix));
+            self.output.lines.extend(lines);
+            self.output.aliases.extend(aliases);
+            self.output.witnesses.extend(witnesses);
+            self.output.comments.extend(comments);
+        }
+    }
+
+    fn add_error(&mut self, position: Option<Position>, error: Error) {
+        match position {
+            Some(position) => self.errors.add(position, error),
+            None => self.errors.add_no_position(error),
+        }
+    }
+}
+
+/// Lexically removes `.` and `..` components from a path, so that different
+/// spellings of the same relative path are recognized as the same file.
+fn normalize(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(ret.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                ret.pop();
+            }
+            component => ret.push(component),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::{DagLike, MaxSharing};
+    use crate::human_encoding::Forest;
+    use crate::jet::Core;
+
+    fn parse_files(files: &[(&str, &str)]) -> Result<Forest<Core>, ErrorSet> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+        super::super::parse_file(
+            Path::new("dir/main.simpl"),
+            &mut |path| {
+                files
+                    .get(path)
+                    .cloned()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"))
+            },
+            &HashMap::new(),
+        )
+    }
+
+    fn error_messages(errs: &ErrorSet) -> Vec<String> {
+        errs.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn unqualified_import() {
+        let forest = parse_files(&[
+            (
+                "dir/main.simpl",
+                "import \"lib.simpl\"\nmain := comp (comp unit not_true) jet_verify",
+            ),
+            (
+                "dir/lib.simpl",
+                "
+                    not_true := comp (injr unit) not
+                    not := comp (pair iden unit) (case (injr unit) (injl unit)) : 2 -> 2
+                    unused := iden
+                ",
+            ),
+        ])
+        .unwrap();
+        // Unused definitions from the imported file are not roots
+        assert_eq!(forest.roots().len(), 1);
+        assert!(forest.roots().contains_key("main"));
+
+        // Nodes from the imported file are located by its name
+        let locations: Vec<_> = forest.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .filter_map(|data| data.node.source().map(ToString::to_string))
+            .collect();
+        assert!(locations.contains(&"`main` at line 2, column 15".to_string()));
+        assert!(
+            locations.contains(&"`not_true` at line 2, column 21 of `dir/lib.simpl`".to_string())
+        );
+    }
+
+    #[test]
+    fn qualified_import() {
+        let forest = parse_files(&[
+            (
+                "dir/main.simpl",
+                "
+                    import \"lib/bits.simpl\" as bits
+                    import \"./lib/../lib/bits.simpl\" as other
+                    main := comp (comp (pair bits.false other.false) jet_eq_1) jet_verify
+                ",
+            ),
+            (
+                "dir/lib/bits.simpl",
+                "
+                    import \"../common.simpl\" as common
+                    false := comp common.unit' left : 1 -> B where { left := injl unit }
+                ",
+            ),
+            ("dir/common.simpl", "unit' := unit : A -> 1"),
+        ])
+        .unwrap();
+        assert_eq!(forest.roots().len(), 1);
+        assert!(forest.roots().contains_key("main"));
+
+        // Locals are named after their definition, before it is qualified
+        let names: HashSet<_> = forest.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .map(|data| data.node.name().to_string())
+            .collect();
+        assert!(names.contains("bits.false.left"));
+    }
+
+    #[test]
+    fn shared_import() {
+        // Importing the same file twice without a qualifier does not cause
+        // its names to be defined twice.
+        parse_files(&[
+            (
+                "dir/main.simpl",
+                "
+                    import \"a.simpl\"
+                    import \"b.simpl\"
+                    main := comp a b
+                ",
+            ),
+            ("dir/a.simpl", "import \"common.simpl\"\na := comp one unit"),
+            ("dir/b.simpl", "import \"common.simpl\"\nb := comp one unit"),
+            ("dir/common.simpl", "one := unit"),
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn import_errors() {
+        let errs = parse_files(&[
+            ("dir/main.simpl", "import \"a.simpl\"\nmain := a"),
+            ("dir/a.simpl", "import \"main.simpl\" as m\na := unit"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error_messages(&errs),
+            ["import of `dir/main.simpl` forms a cycle"]
+        );
+
+        let errs = parse_files(&[("dir/main.simpl", "import \"missing.simpl\"\nmain := unit")])
+            .unwrap_err();
+        assert_eq!(
+            error_messages(&errs),
+            ["failed to import `dir/missing.simpl`: file not found"]
+        );
+        assert_eq!(errs.first_error().unwrap().0.unwrap().file(), 0);
+
+        let errs = Forest::<Core>::parse("import \"lib.simpl\"\nmain := unit").unwrap_err();
+        assert_eq!(
+            error_messages(&errs),
+            ["failed to import `lib.simpl`: imports are only supported when parsing a file"]
+        );
+    }
+
+    #[test]
+    fn error_in_imported_file() {
+        let errs = parse_files(&[
+            (
+                "dir/main.simpl",
+                "import \"lib.simpl\" as lib\nmain := lib.a",
+            ),
+            ("dir/lib.simpl", "a := comp unit\n  b\n"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error_messages(&errs),
+            ["name `lib.b` is referred to but does not exist"]
+        );
+        let display = errs.to_string();
+        assert!(display.contains("dir/lib.simpl:2:3:"), "{}", display);
+        assert!(display.contains("    2 |   b"), "{}", display);
+    }
+
+    #[test]
+    fn diagnostics() {
+        use crate::human_encoding::{Related, Severity, Span};
+
+        let span = |file: &str, line, column, length| Span {
+            file: Some(file.to_owned()),
+            line,
+            column,
+            length,
+        };
+        let errs = parse_files(&[
+            (
+                "dir/main.simpl",
+                "import \"lib.simpl\" as lib\nmain := lib.a",
+            ),
+            ("dir/lib.simpl", "a := comp unit\n  missing\na := unit\n"),
+        ])
+        .unwrap_err();
+        let diagnostics = errs.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, "E0016");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].span,
+            Some(span("dir/lib.simpl", 2, 3, "missing".len()))
+        );
+        assert!(diagnostics[0].related.is_empty());
+        assert_eq!(diagnostics[1].code, "E0017");
+        assert_eq!(diagnostics[1].span, Some(span("dir/lib.simpl", 3, 6, 4)));
+        assert_eq!(
+            diagnostics[1].related,
+            [Related {
+                span: span("dir/lib.simpl", 1, 1, 1),
+                message: "`lib.a` first occurs here".to_owned(),
+            }]
+        );
+    }
+}
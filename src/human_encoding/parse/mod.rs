@@ -3,21 +3,33 @@
 //! Parsing
 
 mod ast;
+mod constant;
+mod format;
+mod import;
+mod scope;
+mod symbols;
+mod template;
 
 use crate::dag::{Dag, DagLike, InternalSharing};
 use crate::jet::Jet;
 use crate::node;
-use crate::types::{self, Type};
-use std::collections::HashMap;
+use crate::types::{self, Final, Type};
+use crate::{BitIter, Value, Word};
+use std::collections::{HashMap, HashSet};
 use std::mem;
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use super::named_node::{NamedCommitNode, NamedConstructNode, Namer};
-use super::{Position, WitnessOrHole};
+use super::{Forest, Position, SourceLocation, WitnessOrHole};
 
 use super::{Error, ErrorSet};
 
+pub(super) use self::ast::token_lengths;
+pub use self::format::format;
+pub use self::symbols::{symbols, Symbol, SymbolKind, SymbolRole};
+
 #[derive(Clone)]
 struct UnresolvedExpression<J: Jet> {
     inner: UnresolvedInner<J>,
@@ -36,9 +48,18 @@ impl<J: Jet> UnresolvedExpression<J> {
         }
     }
 
-    fn from_inline_expression(expr_inner: ast::ExprInner<J>, position: Position) -> Self {
+    fn from_inline_expression(
+        expr_inner: ast::ExprInner<J>,
+        position: Position,
+        user_source_types: Vec<Type>,
+        user_target_types: Vec<Type>,
+    ) -> Self {
         UnresolvedExpression {
-            inner: UnresolvedInner::Inline { expr_inner },
+            inner: UnresolvedInner::Inline {
+                expr_inner,
+                user_source_types,
+                user_target_types,
+            },
             position,
         }
     }
@@ -63,10 +84,15 @@ impl<J: Jet> UnresolvedExpression<J> {
                 Ok(())
             }
             UnresolvedInner::Inline { .. } => unreachable!(),
-            UnresolvedInner::Named { ref name, .. } => Err(ErrorSet::single(
-                position,
-                Error::NameRepeated(Arc::clone(name)),
-            )),
+            UnresolvedInner::Named { ref name, .. } => {
+                let mut errors = ErrorSet::new();
+                errors.add_with_related(
+                    position,
+                    Error::NameRepeated(Arc::clone(name)),
+                    vec![(self.position, format!("`{}` first occurs here", name))],
+                );
+                Err(errors)
+            }
         }
     }
 
@@ -116,6 +142,8 @@ enum UnresolvedInner<J: Jet> {
     },
     Inline {
         expr_inner: ast::ExprInner<J>,
+        user_source_types: Vec<Type>,
+        user_target_types: Vec<Type>,
     },
     Named {
         name: Arc<str>,
@@ -144,8 +172,12 @@ impl<J: Jet> DagLike for &'_ ResolvedExpression<J> {
 
     fn as_dag_node(&self) -> Dag<Self> {
         match self.inner {
-            ResolvedInner::Missing { .. } | ResolvedInner::NoExpr { .. } => Dag::Nullary,
-            ResolvedInner::Reference(ref child) => Dag::Unary(child),
+            ResolvedInner::Missing { .. }
+            | ResolvedInner::NoExpr { .. }
+            | ResolvedInner::Invalid => Dag::Nullary,
+            ResolvedInner::Reference(ref child) | ResolvedInner::CmrWord(ref child) => {
+                Dag::Unary(child)
+            }
             ResolvedInner::AssertL(ref left, ResolvedCmr::Expr(ref right))
             | ResolvedInner::AssertR(ResolvedCmr::Expr(ref left), ref right) => {
                 Dag::Binary(left, right)
@@ -167,8 +199,12 @@ enum ResolvedInner<J: Jet> {
     Missing { name: Arc<str> },
     /// A reference to a name with no associated expression
     NoExpr { name: Arc<str> },
+    /// A constant which was not a value of its type
+    Invalid,
     /// A reference to another expression
     Reference(Arc<ResolvedExpression<J>>),
+    /// A constant word equal to the CMR of an expression
+    CmrWord(Arc<ResolvedExpression<J>>),
     /// A left assertion (referring to the CMR of an expression on the right)
     AssertL(Arc<ResolvedExpression<J>>, ResolvedCmr<J>),
     /// A right assertion (referring to the CMR of an expression on the left)
@@ -177,16 +213,93 @@ enum ResolvedInner<J: Jet> {
     Inline(node::Inner<Arc<ResolvedExpression<J>>, J, Arc<ResolvedExpression<J>>, WitnessOrHole>),
 }
 
-pub fn parse<J: Jet + 'static>(
+pub fn parse<J: Jet + 'static>(program: &str) -> Result<Forest<J>, ErrorSet> {
+    parse_with_arguments(program, &HashMap::new())
+}
+
+pub fn parse_with_arguments<J: Jet + 'static>(
     program: &str,
-) -> Result<HashMap<Arc<str>, Arc<NamedCommitNode<J>>>, ErrorSet> {
+    arguments: &HashMap<Arc<str>, Word>,
+) -> Result<Forest<J>, ErrorSet> {
+    let program = scope::hoist(ast::parse_program(program)?);
+    // Without a file, there is nothing to resolve import paths against.
+    let mut errors = ErrorSet::new();
+    for import in &program.imports {
+        errors.add(
+            import.position,
+            Error::ImportFailed {
+                path: Arc::clone(&import.path),
+                reason: "imports are only supported when parsing a file".into(),
+            },
+        );
+    }
+    errors.into_result(())?;
+    resolve(program, &[], arguments)
+}
+
+pub fn parse_file<J: Jet + 'static>(
+    path: &Path,
+    read: &mut import::ReadFn,
+    arguments: &HashMap<Arc<str>, Word>,
+) -> Result<Forest<J>, ErrorSet> {
+    let mut errors = ErrorSet::new();
+    let (program, files) = import::load(path, read, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    resolve(program, &files, arguments).map_err(|resolve_errors| {
+        errors.merge(&resolve_errors);
+        errors
+    })
+}
+
+/// Resolves a program, given the names of the files it was read from, by file
+/// index.
+fn resolve<J: Jet + 'static>(
+    program: ast::Program<J>,
+    files: &[Arc<str>],
+    arguments: &HashMap<Arc<str>, Word>,
+) -> Result<Forest<J>, ErrorSet> {
     let mut errors = ErrorSet::new();
     let inference_context = types::Context::new();
+    // Positions of every definition, so that each node can be mapped back to the
+    // definition which contains it (the last one which starts before the node).
+    let mut definitions: Vec<(Position, Arc<str>)> = program
+        .lines
+        .iter()
+        .filter(|line| line.expression.is_some())
+        .map(|line| (line.position, Arc::clone(&line.name)))
+        .collect();
+    definitions.sort();
+    let source_location = |position: Position| {
+        let idx = definitions.partition_point(|(start, _)| *start <= position);
+        SourceLocation {
+            definition: idx
+                .checked_sub(1)
+                .map(|idx| Arc::clone(&definitions[idx].1))
+                .unwrap_or_else(|| Arc::from("")),
+            position,
+            file: match position.file {
+                0 => None,
+                file => files.get(file).cloned(),
+            },
+        }
+    };
+
+    let program = template::expand(program, arguments, &mut errors);
+    let aliases = expand_aliases(&program.aliases, &mut errors);
+    let program = constant::desugar(program, &aliases, &mut errors);
+    let reify = |ty: &ast::Type| {
+        ty.clone()
+            .expand_aliases(&aliases)
+            .reify(&inference_context)
+    };
+
     // **
     // Step 1: Read expressions into HashMap, checking for dupes and illegal names.
     // **
     let mut unresolved_map = HashMap::<Arc<str>, UnresolvedExpression<J>>::new();
-    for line in ast::parse_line_vector(program)? {
+    for line in program.lines {
         if line.name.as_ref() == "_" || line.name.starts_with("prim") {
             errors.add(line.position, Error::NameIllegal(Arc::clone(&line.name)));
             continue;
@@ -201,15 +314,24 @@ pub fn parse<J: Jet + 'static>(
                 ));
 
         if let Some(expr) = line.expression {
-            if let Err(eset) = entry.add_expression(expr.inner, expr.position) {
+            let (expr, arrows) = strip_ascriptions(&expr);
+            if let Err(eset) = entry.add_expression(expr.inner.clone(), expr.position) {
                 errors.merge(&eset)
             }
+            for (source, target) in arrows {
+                if let Some(ty) = source {
+                    entry.add_source_type(reify(ty));
+                }
+                if let Some(ty) = target {
+                    entry.add_target_type(reify(ty));
+                }
+            }
         }
-        if let Some(ty) = line.arrow.0 {
-            entry.add_source_type(ty.reify(&inference_context));
+        if let Some(ref ty) = line.arrow.0 {
+            entry.add_source_type(reify(ty));
         }
-        if let Some(ty) = line.arrow.1 {
-            entry.add_target_type(ty.reify(&inference_context));
+        if let Some(ref ty) = line.arrow.1 {
+            entry.add_target_type(reify(ty));
         }
     }
 
@@ -264,10 +386,15 @@ pub fn parse<J: Jet + 'static>(
                 stack.push(stack_item.clone());
 
                 let push_ast_expr = |stack: &mut Vec<_>, expr: &ast::Expression<_>| {
+                    let (expr, arrows) = strip_ascriptions(expr);
+                    let source_types = arrows.iter().filter_map(|arrow| arrow.0.as_ref());
+                    let target_types = arrows.iter().filter_map(|arrow| arrow.1.as_ref());
                     stack.push(StackItem {
                         expr: UnresolvedExpression::from_inline_expression(
                             expr.inner.clone(),
                             expr.position,
+                            source_types.map(reify).collect(),
+                            target_types.map(reify).collect(),
                         ),
                         name: None,
                         done_children: false,
@@ -287,6 +414,18 @@ pub fn parse<J: Jet + 'static>(
                                 });
                             }
                         }
+                        ast::ExprInner::Instance(..) => {
+                            unreachable!("templates are expanded before names are resolved")
+                        }
+                        ast::ExprInner::Ascribed(..) => {
+                            unreachable!("type annotations are removed before names are resolved")
+                        }
+                        ast::ExprInner::Constant(ast::Constant::Cmr(ast::AstCmr::Expr(
+                            ref expr,
+                        ))) => push_ast_expr(&mut stack, expr),
+                        // Any other constant was not a value of its type, which
+                        // has already been reported.
+                        ast::ExprInner::Constant(..) => {}
                         ast::ExprInner::AssertL(ref left, ref cmr) => {
                             push_ast_expr(&mut stack, left);
                             if let ast::AstCmr::Expr(ref right) = cmr {
@@ -323,6 +462,18 @@ pub fn parse<J: Jet + 'static>(
                         }
                     }
                 }
+                ast::ExprInner::Instance(..) => {
+                    unreachable!("templates are expanded before names are resolved")
+                }
+                ast::ExprInner::Ascribed(..) => {
+                    unreachable!("type annotations are removed before names are resolved")
+                }
+                ast::ExprInner::Constant(ast::Constant::Cmr(ast::AstCmr::Expr(..))) => {
+                    let child = inline_stack.pop().unwrap();
+                    child.in_degree.fetch_add(1, Ordering::SeqCst);
+                    ResolvedInner::CmrWord(child)
+                }
+                ast::ExprInner::Constant(..) => ResolvedInner::Invalid,
                 ast::ExprInner::AssertL(_, ref cmr) => {
                     let left = inline_stack.pop().unwrap();
                     left.in_degree.fetch_add(1, Ordering::SeqCst);
@@ -333,7 +484,7 @@ pub fn parse<J: Jet + 'static>(
                             right.in_degree.fetch_add(1, Ordering::SeqCst);
                             ResolvedCmr::Expr(right)
                         }
-                        ast::AstCmr::Literal => ResolvedCmr::Literal,
+                        ast::AstCmr::Literal(_) => ResolvedCmr::Literal,
                     };
                     ResolvedInner::AssertL(left, right)
                 }
@@ -344,7 +495,7 @@ pub fn parse<J: Jet + 'static>(
                             left.in_degree.fetch_add(1, Ordering::SeqCst);
                             ResolvedCmr::Expr(left)
                         }
-                        ast::AstCmr::Literal => ResolvedCmr::Literal,
+                        ast::AstCmr::Literal(_) => ResolvedCmr::Literal,
                     };
 
                     let right = inline_stack.pop().unwrap();
@@ -386,12 +537,16 @@ pub fn parse<J: Jet + 'static>(
                     user_target_types: Arc::from(&user_target_types[..]),
                     in_degree: AtomicUsize::new(0),
                 },
-                UnresolvedInner::Inline { ref expr_inner } => ResolvedExpression {
+                UnresolvedInner::Inline {
+                    ref expr_inner,
+                    ref user_source_types,
+                    ref user_target_types,
+                } => ResolvedExpression {
                     inner: convert_expr_inner(expr_inner),
                     position: stack_item.expr.position,
                     name: None,
-                    user_source_types: Arc::from([]),
-                    user_target_types: Arc::from([]),
+                    user_source_types: Arc::from(&user_source_types[..]),
+                    user_target_types: Arc::from(&user_target_types[..]),
                     in_degree: AtomicUsize::new(0),
                 },
                 UnresolvedInner::Named {
@@ -445,15 +600,32 @@ pub fn parse<J: Jet + 'static>(
                     errors.add(data.node.position, Error::NameIncomplete(Arc::clone(name)));
                     None
                 }
+                ResolvedInner::Invalid => None,
+                ResolvedInner::CmrWord(..) => {
+                    left.map(|expr| node::Inner::Word(Word::u256(expr.cmr().to_byte_array())))
+                }
                 ResolvedInner::Reference(..) => {
                     // For chains of references we make an effort to preserve the name.
                     // So if you have main := a; a := b; b := c, then the `main` node
                     // will retain the name `main` (and absent any other references,
                     // the `a` and `b` names will simply be dropped).
+                    //
+                    // Type bounds on the reference are added to those of the node
+                    // that it refers to.
                     let mut child = left;
                     if let Some(name) = data.node.name.as_ref() {
                         child = child.map(|node| Arc::new(node.renamed(Arc::clone(name))));
                     }
+                    if !data.node.user_source_types.is_empty()
+                        || !data.node.user_target_types.is_empty()
+                    {
+                        child = child.map(|node| {
+                            Arc::new(node.with_type_bounds(
+                                &data.node.user_source_types,
+                                &data.node.user_target_types,
+                            ))
+                        });
+                    }
                     converted.push(child);
                     continue;
                 }
@@ -488,7 +660,7 @@ pub fn parse<J: Jet + 'static>(
             let node = NamedConstructNode::new(
                 &inference_context,
                 Arc::clone(&name),
-                data.node.position,
+                source_location(data.node.position),
                 Arc::clone(&data.node.user_source_types),
                 Arc::clone(&data.node.user_target_types),
                 inner,
@@ -522,6 +694,25 @@ pub fn parse<J: Jet + 'static>(
         }
     }
 
+    // Definitions from imported files are only roots if they are unused, so drop
+    // them, unless they fill a disconnect hole of some root that is kept.
+    let mut stack: Vec<Arc<str>> = roots
+        .keys()
+        .filter(|name| resolved_map[*name].position.file == 0)
+        .cloned()
+        .collect();
+    let mut kept: HashSet<Arc<str>> = stack.iter().cloned().collect();
+    while let Some(name) = stack.pop() {
+        for data in roots[&name].as_ref().post_order_iter::<InternalSharing>() {
+            if let node::Inner::Disconnect(_, hole_name) = data.node.inner() {
+                if roots.contains_key(hole_name) && kept.insert(Arc::clone(hole_name)) {
+                    stack.push(Arc::clone(hole_name));
+                }
+            }
+        }
+    }
+    roots.retain(|name, _| kept.contains(name));
+
     // From each root, count the number of ways that each witness node can be reached
     for root in roots.values() {
         let mut counts: Vec<HashMap<Arc<str>, usize>> = vec![];
@@ -560,11 +751,208 @@ pub fn parse<J: Jet + 'static>(
         }
     }
 
+    // ** Step 4: decode witness values, now that the types of witness nodes are known.
+    let mut witness = HashMap::new();
     if errors.is_empty() {
-        Ok(roots)
-    } else {
-        Err(errors)
+        let types = witness_types(&roots);
+        witness = decode_witnesses(program.witnesses, &types, &mut errors);
+    }
+
+    // Aliases for complete types are kept so that the forest can be printed
+    // using them.
+    let mut aliases: Vec<_> = aliases
+        .into_iter()
+        .filter_map(|(name, ty)| ty.to_final().map(|ty| (name, ty)))
+        .collect();
+    aliases.sort();
+
+    let mut comments = HashMap::<Arc<str>, Vec<Arc<str>>>::new();
+    for comment in program.comments {
+        comments.entry(comment.name).or_default().push(comment.text);
     }
+
+    errors.into_result(Forest {
+        roots,
+        witness,
+        aliases,
+        comments,
+    })
+}
+
+/// Expands every type alias within the others, so that the types of the
+/// returned aliases do not refer to any alias.
+///
+/// Aliases with illegal or repeated names, and aliases which are defined in
+/// terms of themselves, are reported in `errors`.
+/// Parses a file of witness values, which assigns values to the witness nodes
+/// of the given forest and contains nothing else.
+pub fn witness_values<J: Jet + 'static>(
+    forest: &Forest<J>,
+    s: &str,
+) -> Result<HashMap<Arc<str>, Value>, ErrorSet> {
+    let program = ast::parse_program::<J>(s)?;
+    let mut errors = ErrorSet::new();
+    let others = program
+        .lines
+        .iter()
+        .map(|line| (line.position, &line.name))
+        .chain(
+            program
+                .aliases
+                .iter()
+                .map(|alias| (alias.position, &alias.name)),
+        )
+        .chain(
+            program
+                .imports
+                .iter()
+                .map(|import| (import.position, &import.path)),
+        );
+    for (position, name) in others {
+        errors.add(position, Error::NotWitnessAssignment(Arc::clone(name)));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let types = witness_types(forest.roots());
+    let witness = decode_witnesses(program.witnesses, &types, &mut errors);
+    errors.into_result(witness)
+}
+
+/// The types of the witness nodes reachable from the given roots, by name
+fn witness_types<J: Jet>(
+    roots: &HashMap<Arc<str>, Arc<NamedCommitNode<J>>>,
+) -> HashMap<Arc<str>, Arc<Final>> {
+    let mut types = HashMap::new();
+    for root in roots.values() {
+        for data in root.as_ref().post_order_iter::<InternalSharing>() {
+            if let node::Inner::Witness(_) = data.node.inner() {
+                types
+                    .entry(Arc::clone(data.node.name()))
+                    .or_insert_with(|| Arc::clone(&data.node.arrow().target));
+            }
+        }
+    }
+    types
+}
+
+/// Decodes witness assignments against the types of the witness nodes they
+/// assign to, recording an error for each one which cannot be decoded.
+fn decode_witnesses(
+    assignments: Vec<ast::WitnessAssignment>,
+    types: &HashMap<Arc<str>, Arc<Final>>,
+    errors: &mut ErrorSet,
+) -> HashMap<Arc<str>, Value> {
+    let mut witness = HashMap::new();
+    for assignment in assignments {
+        let ty = match types.get(&assignment.name) {
+            Some(ty) => ty,
+            None => {
+                errors.add(assignment.position, Error::NotWitness(assignment.name));
+                continue;
+            }
+        };
+        if witness.contains_key(&assignment.name) {
+            errors.add(assignment.position, Error::NameRepeated(assignment.name));
+            continue;
+        }
+
+        let mut iter = BitIter::from(assignment.data);
+        match Value::from_compact_bits(&mut iter, ty) {
+            Ok(value) if iter.n_total_read() == assignment.bit_length => {
+                witness.insert(assignment.name, value);
+            }
+            _ => errors.add(
+                assignment.position,
+                Error::BadWitnessValue {
+                    name: assignment.name,
+                    ty: Arc::clone(ty),
+                    bit_length: assignment.bit_length,
+                },
+            ),
+        }
+    }
+    witness
+}
+
+fn expand_aliases(
+    aliases: &[ast::TypeAlias],
+    errors: &mut ErrorSet,
+) -> HashMap<Arc<str>, ast::Type> {
+    let mut defined = HashMap::<Arc<str>, &ast::TypeAlias>::new();
+    for alias in aliases {
+        if alias.name.as_ref() == "_" {
+            errors.add(alias.position, Error::NameIllegal(Arc::clone(&alias.name)));
+        } else if let Some(first) = defined.get(&alias.name) {
+            errors.add_with_related(
+                alias.position,
+                Error::NameRepeated(Arc::clone(&alias.name)),
+                vec![(
+                    first.position,
+                    format!("`{}` first occurs here", alias.name),
+                )],
+            );
+        } else {
+            defined.insert(Arc::clone(&alias.name), alias);
+        }
+    }
+
+    // Expand the aliases that each alias refers to before expanding it, so
+    // that a single substitution is enough.
+    fn visit(
+        name: &Arc<str>,
+        defined: &HashMap<Arc<str>, &ast::TypeAlias>,
+        stack: &mut Vec<Arc<str>>,
+        expanded: &mut HashMap<Arc<str>, ast::Type>,
+        errors: &mut ErrorSet,
+    ) {
+        if expanded.contains_key(name) {
+            return;
+        }
+        let alias = defined[name];
+        if stack.contains(name) {
+            errors.add(alias.position, Error::TypeAliasCycle(Arc::clone(name)));
+            return;
+        }
+        let mut references = vec![];
+        alias.ty.for_each_name(&mut |s| {
+            if let Some((reference, _)) = defined.get_key_value(s) {
+                references.push(Arc::clone(reference));
+            }
+        });
+        stack.push(Arc::clone(name));
+        for reference in &references {
+            visit(reference, defined, stack, expanded, errors);
+        }
+        stack.pop();
+        let ty = alias.ty.clone().expand_aliases(expanded);
+        expanded.insert(Arc::clone(name), ty);
+    }
+
+    let mut names: Vec<&Arc<str>> = defined.keys().collect();
+    names.sort();
+    let mut expanded = HashMap::with_capacity(defined.len());
+    for name in names {
+        visit(name, &defined, &mut vec![], &mut expanded, errors);
+    }
+    expanded
+}
+
+/// Removes the type annotations from around an expression, returning the
+/// annotated expression and each annotation, outermost first.
+fn strip_ascriptions<J: Jet>(
+    mut expr: &ast::Expression<J>,
+) -> (
+    &ast::Expression<J>,
+    Vec<&(Option<ast::Type>, Option<ast::Type>)>,
+) {
+    let mut arrows = vec![];
+    while let ast::ExprInner::Ascribed(ref child, ref arrow) = expr.inner {
+        arrows.push(arrow);
+        expr = child;
+    }
+    (expr, arrows)
 }
 
 #[cfg(test)]
@@ -586,12 +974,12 @@ mod tests {
     ) {
         match parse::<J>(s) {
             Ok(forest) => {
-                assert_eq!(forest.len(), 1);
-                let main = &forest["main"];
+                assert_eq!(forest.roots().len(), 1);
+                let main = &forest.roots()["main"];
                 assert_eq!(main.cmr().to_string(), cmr);
 
                 let program = main
-                    .to_construct_node(witness, &forest)
+                    .to_construct_node(witness, forest.roots())
                     .finalize_unpruned()
                     .expect("finalize");
 
@@ -624,8 +1012,8 @@ mod tests {
     fn assert_const<J: Jet>(s: &str, word: Word) {
         match parse::<J>(s) {
             Ok(forest) => {
-                assert_eq!(forest.len(), 1);
-                let main = &forest["main"];
+                assert_eq!(forest.roots().len(), 1);
+                let main = &forest.roots()["main"];
 
                 for data in main.clone().post_order_iter::<MaxSharing<_>>() {
                     if let Inner::Word(parsed_word) = data.node.inner() {
@@ -791,4 +1179,351 @@ mod tests {
             "name `wit1` occured mulitple times",
         );
     }
+
+    #[test]
+    fn type_aliases() {
+        let s = "
+            type Byte := 2^8
+            type Bytes := Byte * Byte
+            bytes := pair (const 0x2a) (const 0x2a) : 1 -> Bytes
+            main := comp bytes (comp jet_eq_8 jet_verify : Bytes -> 1)
+        ";
+        let forest = parse::<Core>(s).unwrap();
+        forest.to_redeem_node(&()).unwrap().expect("execute");
+        let aliases: Vec<_> = forest
+            .type_aliases()
+            .iter()
+            .map(|(name, ty)| (name.as_ref(), ty.to_string()))
+            .collect();
+        assert_eq!(aliases, [("Byte", "2^8".into()), ("Bytes", "2^16".into())]);
+
+        error_contains(
+            parse::<Core>("type Byte := 2^4\nmain := comp (const 0x2a : 1 -> Byte) unit"),
+            "failed to apply bound",
+        );
+        error_contains(
+            parse::<Core>("type A := B * 1\ntype B := A + 1\nmain := unit"),
+            "type alias `A` is defined in terms of itself",
+        );
+        error_contains(
+            parse::<Core>("type A := 1\ntype A := 2\nmain := unit"),
+            "name `A` occured mulitple times",
+        );
+        error_contains(
+            parse::<Core>("type _ := 1\nmain := unit"),
+            "name `_` is not allowed in this context",
+        );
+    }
+
+    #[test]
+    fn inline_annotations() {
+        parse::<Core>("main := comp (unit : 1 -> 1) (iden : 1 -> 1)").unwrap();
+        parse::<Core>("main := comp ((unit : _ -> 1) : 1 -> _) iden").unwrap();
+        error_contains(
+            parse::<Core>("main := comp (unit : 1 -> 2) unit"),
+            "failed to apply bound",
+        );
+        error_contains(
+            parse::<Core>("main := comp ((unit : _ -> 1) : _ -> 2) unit"),
+            "failed to apply bound",
+        );
+        // Bounds apply to references, including chains of them
+        error_contains(
+            parse::<Core>("u := unit\nmain := comp (u : 1 -> 2) unit"),
+            "failed to apply bound",
+        );
+        error_contains(
+            parse::<Core>("b := unit\na := b : 1 -> 2\nmain := comp a unit"),
+            "failed to apply bound",
+        );
+    }
+
+    #[test]
+    fn structured_constants() {
+        let cmr = |s: &str| parse::<Core>(s).unwrap().roots()["main"].cmr();
+
+        assert_eq!(
+            cmr("main := comp (const (0x02, L(())) : _ -> 2^8 * 2) unit"),
+            cmr("main := comp (pair (const 0x02) (injl unit)) unit"),
+        );
+        assert_eq!(
+            cmr("main := comp (const (R(()), 5) : _ -> (1 + 1) * 2^4) unit"),
+            cmr("main := comp (pair (injr unit) (const 0x5)) unit"),
+        );
+        assert_eq!(
+            cmr("type Byte := 2^8\nmain := comp (const 42 : _ -> Byte) unit"),
+            cmr("main := comp const 0x2a unit"),
+        );
+        assert_const::<Core>(
+            "main := comp (const 65535 : _ -> 2^16) unit",
+            Word::u16(0xffff),
+        );
+        assert_const::<Core>("main := comp (const 1 : _ -> 2) unit", Word::u1(1));
+        assert_const::<Core>(
+            "main := comp (const #{unit} : _ -> 2^256) unit",
+            Word::u256(
+                parse::<Core>("main := unit").unwrap().roots()["main"]
+                    .cmr()
+                    .to_byte_array(),
+            ),
+        );
+        assert_const::<Core>(
+            "u := unit\nmain := comp const #{u} unit",
+            Word::u256(
+                parse::<Core>("main := unit").unwrap().roots()["main"]
+                    .cmr()
+                    .to_byte_array(),
+            ),
+        );
+
+        error_contains(
+            parse::<Core>("main := comp (const 256 : _ -> 2^8) unit"),
+            "constant `256` is not a value of type `2^8`",
+        );
+        error_contains(
+            parse::<Core>("main := comp (const L(()) : _ -> 2^8) unit"),
+            "constant `L(())` is not a value of type `2^8`",
+        );
+        error_contains(
+            parse::<Core>("main := comp (const (0x02, 0xf) : _ -> 2^8 * 2^8) unit"),
+            "constant `0b1111` is not a value of type `2^8`",
+        );
+        error_contains(
+            parse::<Core>("main := comp const 42 unit"),
+            "the width of decimal constant `42` is unknown",
+        );
+    }
+
+    #[test]
+    fn templates() {
+        let forest = parse::<Core>(
+            "
+            eq(X, Y) := comp (pair X Y) jet_eq_8 : 1 -> 2
+            check(X) := comp eq(X, const 0x2a) jet_verify
+            main := comp check(const 0x2a) comp check(const 0x2a) check(comp unit const 0x2a)
+        ",
+        )
+        .unwrap();
+        let expanded = parse::<Core>(
+            "
+            check := comp (comp (pair (const 0x2a) (const 0x2a)) jet_eq_8) jet_verify
+            check' := comp (comp (pair (comp unit const 0x2a) (const 0x2a)) jet_eq_8) jet_verify
+            main := comp check comp check check'
+        ",
+        )
+        .unwrap();
+        let main = &forest.roots()["main"];
+        assert_eq!(forest.roots().len(), 1);
+        assert_eq!(main.cmr(), expanded.roots()["main"].cmr());
+
+        // Each distinct instance is a single named node, described by a comment
+        let names: Vec<_> = main
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .map(|data| data.node.name().to_string())
+            .filter(|name| name.contains('\''))
+            .collect();
+        assert_eq!(names, ["eq'1", "check'1", "eq'2", "check'2"]);
+        assert_eq!(
+            forest.debug_info().comments("eq'2"),
+            [Arc::from("-- eq(comp unit const 0x2a, const 0x2a)")]
+        );
+
+        // Instances are not given names which are already defined
+        let forest = parse::<Core>(
+            "id(X) := X
+id'1 := unit
+main := comp id'1 id(iden)",
+        )
+        .unwrap();
+        assert!(forest.debug_info().comments("id'1").is_empty());
+        assert_eq!(
+            forest.debug_info().comments("id'2"),
+            [Arc::from("-- id(iden)")]
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        error_contains(parse::<Core>("main := f(unit)"), "`f` is not a template");
+        error_contains(
+            parse::<Core>("f(X) := X\nmain := f(unit, unit)"),
+            "template `f` takes 1 argument(s) but was given 2",
+        );
+        error_contains(
+            parse::<Core>("f(X) := X\nmain := f"),
+            "template `f` takes 1 argument(s) but was given 0",
+        );
+        error_contains(
+            parse::<Core>("f(X) := g(X)\ng(X) := f(X)\nmain := f(unit)"),
+            "template `f` is instantiated within itself",
+        );
+        error_contains(
+            parse::<Core>("f(X, X) := X\nmain := f(unit, unit)"),
+            "name `X` occured mulitple times",
+        );
+    }
+
+    #[test]
+    fn where_blocks() {
+        let forest = parse::<Core>(
+            "
+            a := unit : 1 -> 1
+            check := comp a ok where {
+                -- Shadows the outer `a`, and is named `check.a` outside the block
+                a := pair w (const 0x2a); w := witness
+                ok := jet_eq_8
+            }
+            main := comp (comp check jet_verify) a where { a := iden }
+            check.w <- 0x2a
+        ",
+        )
+        .unwrap();
+        let expanded = parse::<Core>(
+            "
+            a := unit : 1 -> 1
+            check := comp (pair w (const 0x2a)) jet_eq_8
+            main := comp (comp check jet_verify) iden
+            w := witness
+            w <- 0x2a
+        ",
+        )
+        .unwrap();
+        // The outer `a` is unused, so it is a root of its own
+        assert_eq!(forest.roots().len(), 2);
+        assert_eq!(forest.roots()["a"].cmr(), expanded.roots()["a"].cmr());
+        assert_eq!(forest.roots()["main"].cmr(), expanded.roots()["main"].cmr());
+        assert_eq!(forest.witness()["check.w"], expanded.witness()["w"]);
+        forest.to_redeem_node(&()).unwrap().expect("execute");
+
+        let names: HashSet<_> = forest.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .map(|data| data.node.name().to_string())
+            .collect();
+        assert!(names.contains("check.a"));
+        assert!(names.contains("check.w"));
+        assert!(names.contains("main.a"));
+        assert!(!names.contains("a"));
+
+        // Locals are not visible outside their block, but nested blocks see
+        // the locals of every enclosing block.
+        error_contains(
+            parse::<Core>("f := unit where { x := unit }\nmain := comp f x"),
+            "name `x` is referred to but does not exist",
+        );
+        parse::<Core>("main := comp x unit where { x := y where { z := iden }; y := iden }")
+            .unwrap();
+        error_contains(
+            parse::<Core>("main := comp x unit where { x := z; y := iden where { z := iden } }"),
+            "name `z` is referred to but does not exist",
+        );
+        error_contains(
+            parse::<Core>("main := x where { x := unit; x := iden }"),
+            "name `main.x` occured mulitple times",
+        );
+        error_contains(
+            parse::<Core>("main := x where { w <- 0x00 }"),
+            "expected `:=` or `:`, found `<-`",
+        );
+    }
+
+    #[test]
+    fn witness_values() {
+        let forest = parse::<Core>(
+            "
+            w := witness
+            main := comp (pair w (const 0x2a)) (comp jet_eq_8 jet_verify)
+        ",
+        )
+        .unwrap();
+        assert!(forest.witness().is_empty());
+
+        let witness = forest.parse_witness_values("w <- 0x2a").unwrap();
+        let program = forest.to_witness_node(&witness).unwrap();
+        program.finalize_pruned(&()).expect("execute");
+
+        error_contains(
+            forest.parse_witness_values("w <- 0x2a\nw <- 0x2b"),
+            "name `w` occured mulitple times",
+        );
+        error_contains(
+            forest.parse_witness_values("v <- 0x2a"),
+            "name `v` is assigned a value but is not a witness node",
+        );
+        error_contains(
+            forest.parse_witness_values("w <- 0x2a2a"),
+            "16-bit value is not a valid value of type 2^8 for witness `w`",
+        );
+        error_contains(
+            forest.parse_witness_values("w <- 0x2a\nmain := unit"),
+            "only witness values may be given here, but `main` is not assigned one",
+        );
+    }
+
+    #[test]
+    fn where_blocks_in_templates() {
+        let forest = parse::<Core>(
+            "
+            check(X) := comp ok jet_verify where {
+                ok := comp (pair X expected) jet_eq_8
+                expected := const 0x2a : 1 -> 2^8
+            }
+            twice(X) := comp check(X) again(X) where { again(Y) := check(Y) }
+            main := twice(const 0x2a)
+        ",
+        )
+        .unwrap();
+        let expanded = parse::<Core>(
+            "
+            check := comp (comp (pair (const 0x2a) (const 0x2a)) jet_eq_8) jet_verify
+            main := comp check check
+        ",
+        )
+        .unwrap();
+        assert_eq!(forest.roots()["main"].cmr(), expanded.roots()["main"].cmr());
+        forest.to_redeem_node(&()).unwrap().expect("execute");
+
+        // Each instance has its own locals, named after the instance.
+        let names: HashSet<_> = forest.roots()["main"]
+            .as_ref()
+            .post_order_iter::<MaxSharing<_>>()
+            .map(|data| data.node.name().to_string())
+            .collect();
+        assert!(names.contains("check.ok'1"));
+        assert!(names.contains("twice.again'1"));
+    }
+
+    #[test]
+    fn main_template() {
+        let s = "main(X) := comp (comp (pair X const 0x2a) jet_eq_8) jet_verify";
+        let arguments = |args: &[(&str, Word)]| -> HashMap<Arc<str>, Word> {
+            args.iter()
+                .map(|(name, word)| (Arc::from(*name), word.shallow_clone()))
+                .collect()
+        };
+
+        let forest =
+            parse_with_arguments::<Core>(s, &arguments(&[("X", Word::u8(0x2a))])).expect("parse");
+        forest.to_redeem_node(&()).unwrap().expect("execute");
+        let forest =
+            parse_with_arguments::<Core>(s, &arguments(&[("X", Word::u8(0x2b))])).expect("parse");
+        assert!(forest.to_redeem_node(&()).unwrap().is_err());
+
+        error_contains(
+            parse::<Core>(s),
+            "no value was given for parameter `X` of template `main`",
+        );
+        error_contains(
+            parse_with_arguments::<Core>(
+                s,
+                &arguments(&[("X", Word::u8(0x2a)), ("Y", Word::u8(0x2a))]),
+            ),
+            "a value was given for `Y`, which is not a parameter of `main`",
+        );
+        error_contains(
+            parse_with_arguments::<Core>("main := unit", &arguments(&[("X", Word::u8(0x2a))])),
+            "a value was given for `X`, which is not a parameter of `main`",
+        );
+    }
 }
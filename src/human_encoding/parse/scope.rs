@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Scopes
+//!
+//! Moves the definitions and type bounds in every `where` block to the top
+//! level of the program. A name defined in the `where` block of `x` becomes
+//! `x.name`, and every reference to it from within the definition of `x` is
+//! renamed to match, so that local names shadow outer ones without being
+//! visible outside of their block.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::human_encoding::WitnessOrHole;
+use crate::jet::Jet;
+
+use super::ast::{self, AstCmr, ExprInner, Expression};
+
+/// What a name refers to within a scope
+enum Binding {
+    /// A parameter of an enclosing template, which is substituted later.
+    Parameter,
+    /// A local definition, with the name it is moved to and the parameters of
+    /// the enclosing templates, which it takes as its first parameters.
+    Local {
+        name: Arc<str>,
+        parameters: Arc<[Arc<str>]>,
+    },
+}
+
+/// Moves every local definition and type bound to the top level.
+///
+/// Within a template, a local definition may refer to the template's
+/// parameters, so it becomes a template itself which takes the same
+/// parameters, followed by any of its own. References to it are replaced by
+/// instances which pass the parameters along. For example
+///
+/// ```text
+/// check(PK) := comp sig-ok jet_verify where { sig-ok := pair PK sig }
+/// ```
+///
+/// becomes `check(PK) := comp check.sig-ok(PK) jet_verify` along with
+/// `check.sig-ok(PK) := pair PK sig`.
+pub fn hoist<J: Jet>(program: ast::Program<J>) -> ast::Program<J> {
+    let mut hoister = Hoister {
+        scopes: vec![],
+        lines: Vec::with_capacity(program.lines.len()),
+    };
+    for line in program.lines {
+        hoister.line(line, &[]);
+    }
+    ast::Program {
+        lines: hoister.lines,
+        ..program
+    }
+}
+
+struct Hoister<J> {
+    /// The names visible at the current point, innermost scope last
+    scopes: Vec<HashMap<Arc<str>, Binding>>,
+    /// Lines which have been moved to the top level
+    lines: Vec<ast::Line<J>>,
+}
+
+impl<J: Jet> Hoister<J> {
+    /// Moves a line and its locals to the top level, after renaming the names
+    /// that it refers to. `implicit` are the parameters of the enclosing
+    /// templates, which a definition takes before its own.
+    fn line(&mut self, line: ast::Line<J>, implicit: &[Arc<str>]) {
+        // Type bounds apply to every instance of a template, so never take
+        // parameters of their own.
+        let parameters: Vec<ast::Parameter> = match line.expression {
+            Some(_) => implicit
+                .iter()
+                .map(|name| ast::Parameter {
+                    position: line.position,
+                    name: Arc::clone(name),
+                })
+                .chain(line.parameters.iter().cloned())
+                .collect(),
+            None => vec![],
+        };
+        let parameter_names: Arc<[Arc<str>]> = parameters
+            .iter()
+            .map(|param| Arc::clone(&param.name))
+            .collect();
+
+        // Parameters are visible in the definition and its `where` block, and
+        // locals shadow them.
+        self.scopes.push(
+            line.parameters
+                .iter()
+                .map(|param| (Arc::clone(&param.name), Binding::Parameter))
+                .collect(),
+        );
+        let prefix = format!("{}.", line.name);
+        self.scopes.push(
+            line.locals
+                .iter()
+                .map(|local| {
+                    let binding = Binding::Local {
+                        name: ast::qualify(&prefix, &local.name),
+                        parameters: Arc::clone(&parameter_names),
+                    };
+                    (Arc::clone(&local.name), binding)
+                })
+                .collect(),
+        );
+
+        let expression = line.expression.as_ref().map(|expr| self.expression(expr));
+        self.lines.push(ast::Line {
+            position: line.position,
+            name: line.name,
+            parameters,
+            expression,
+            arrow: line.arrow,
+            locals: vec![],
+        });
+        for local in line.locals {
+            let local = ast::Line {
+                name: ast::qualify(&prefix, &local.name),
+                ..local
+            };
+            self.line(local, &parameter_names);
+        }
+
+        self.scopes.pop();
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Renames every reference to a local definition within an expression.
+    fn expression(&self, expr: &Expression<J>) -> Expression<J> {
+        // References to a local pass along the parameters it takes implicitly.
+        let implicit_arguments = |parameters: &[Arc<str>]| {
+            parameters
+                .iter()
+                .map(|name| {
+                    Arc::new(Expression {
+                        inner: ExprInner::Reference(Arc::clone(name)),
+                        position: expr.position,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let inner = match expr.inner {
+            ExprInner::Reference(ref name) => match self.lookup(name) {
+                Some(Binding::Local { name, parameters }) if parameters.is_empty() => {
+                    ExprInner::Reference(Arc::clone(name))
+                }
+                Some(Binding::Local { name, parameters }) => {
+                    ExprInner::Instance(Arc::clone(name), implicit_arguments(parameters))
+                }
+                Some(Binding::Parameter) | None => expr.inner.clone(),
+            },
+            ExprInner::Instance(ref name, ref arguments) => {
+                let arguments = arguments.iter().map(|arg| self.child(arg));
+                match self.lookup(name) {
+                    Some(Binding::Local { name, parameters }) => {
+                        let mut all_arguments = implicit_arguments(parameters);
+                        all_arguments.extend(arguments);
+                        ExprInner::Instance(Arc::clone(name), all_arguments)
+                    }
+                    Some(Binding::Parameter) | None => {
+                        ExprInner::Instance(Arc::clone(name), arguments.collect())
+                    }
+                }
+            }
+            ExprInner::AssertL(ref left, ref cmr) => {
+                ExprInner::AssertL(self.child(left), self.cmr(cmr))
+            }
+            ExprInner::AssertR(ref cmr, ref right) => {
+                ExprInner::AssertR(self.cmr(cmr), self.child(right))
+            }
+            ExprInner::Constant(ref constant) => {
+                ExprInner::Constant(constant.map_cmrs(&mut |cmr| self.cmr(cmr)))
+            }
+            ExprInner::Ascribed(ref child, ref arrow) => {
+                ExprInner::Ascribed(self.child(child), arrow.clone())
+            }
+            ExprInner::Inline(ref inner) => ExprInner::Inline(
+                inner
+                    .as_ref()
+                    .map(|child| self.child(child))
+                    .map_disconnect(|child| self.child(child))
+                    .map_witness(WitnessOrHole::shallow_clone),
+            ),
+        };
+        Expression {
+            inner,
+            position: expr.position,
+        }
+    }
+
+    fn child(&self, child: &Arc<Expression<J>>) -> Arc<Expression<J>> {
+        Arc::new(self.expression(child))
+    }
+
+    fn cmr(&self, cmr: &AstCmr<J>) -> AstCmr<J> {
+        match cmr {
+            AstCmr::Expr(expr) => AstCmr::Expr(self.child(expr)),
+            AstCmr::Literal(cmr) => AstCmr::Literal(*cmr),
+        }
+    }
+}
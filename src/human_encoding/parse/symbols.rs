@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Symbols
+//!
+//! Lists every occurrence of a name or hole in a single source file, for
+//! tools which need to map between names and their positions in the source.
+
+use std::sync::Arc;
+
+use crate::human_encoding::{ErrorSet, Position, WitnessOrHole};
+use crate::jet::Jet;
+use crate::node;
+
+use super::ast::{self, AstCmr, ExprInner, Expression};
+
+/// The namespace that a symbol belongs to
+///
+/// The hole on the right of a `disconnect` is filled by the expression of the
+/// same name, so it is listed as a reference to that name rather than as a hole.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolKind {
+    /// The name of an expression
+    Name,
+    /// The name of a hole
+    Hole,
+}
+
+/// The way in which a symbol occurs in the source
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolRole {
+    /// The name on the left of `:=`, a parameter of a template, or a hole (holes
+    /// have no separate definition)
+    Definition,
+    /// The name on the left of a type bound `name : A -> B`
+    TypeBound,
+    /// The name on the left of a witness assignment `name <- v`
+    WitnessAssignment,
+    /// A name used within an expression
+    Reference,
+}
+
+/// A single occurrence of a name or hole in the source
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol {
+    /// Position of the first character of the name (not of the `?` of a hole).
+    pub position: Position,
+    /// The name, exactly as it is written.
+    pub name: Arc<str>,
+    /// Whether this is the name of an expression or a hole.
+    pub kind: SymbolKind,
+    /// How the name is used at this position.
+    pub role: SymbolRole,
+}
+
+/// Lists every occurrence of a name or hole in the given source, ordered by position.
+///
+/// Imports are not followed, and names are not resolved; the input only needs
+/// to be syntactically valid. Qualified names which refer to imported files are
+/// listed like any other name.
+pub fn symbols<J: Jet + 'static>(input: &str) -> Result<Vec<Symbol>, ErrorSet> {
+    let program = ast::parse_program::<J>(input)?;
+
+    let mut symbols = vec![];
+    for line in &program.lines {
+        line_symbols(line, &mut symbols);
+    }
+    for witness in &program.witnesses {
+        symbols.push(Symbol {
+            position: witness.position,
+            name: Arc::clone(&witness.name),
+            kind: SymbolKind::Name,
+            role: SymbolRole::WitnessAssignment,
+        });
+    }
+    symbols.sort();
+    Ok(symbols)
+}
+
+/// Adds every name and hole within a definition or type bound, including its
+/// `where` block, to `symbols`.
+///
+/// Local names are listed as they are written, not as the qualified names
+/// that they are known by outside their block.
+fn line_symbols<J: Jet>(line: &ast::Line<J>, symbols: &mut Vec<Symbol>) {
+    let role = match line.expression {
+        Some(_) => SymbolRole::Definition,
+        None => SymbolRole::TypeBound,
+    };
+    symbols.push(Symbol {
+        position: line.position,
+        name: Arc::clone(&line.name),
+        kind: SymbolKind::Name,
+        role,
+    });
+    symbols.extend(line.parameters.iter().map(|param| Symbol {
+        position: param.position,
+        name: Arc::clone(&param.name),
+        kind: SymbolKind::Name,
+        role: SymbolRole::Definition,
+    }));
+    if let Some(ref expression) = line.expression {
+        expression_symbols(expression, symbols);
+    }
+    for local in &line.locals {
+        line_symbols(local, symbols);
+    }
+}
+
+/// Adds every name and hole within an expression to `symbols`.
+fn expression_symbols<J: Jet>(expression: &Expression<J>, symbols: &mut Vec<Symbol>) {
+    let mut stack = vec![expression];
+    while let Some(expression) = stack.pop() {
+        match expression.inner {
+            ExprInner::Reference(ref name) => symbols.push(Symbol {
+                position: expression.position,
+                name: Arc::clone(name),
+                kind: SymbolKind::Name,
+                role: SymbolRole::Reference,
+            }),
+            ExprInner::Instance(ref name, ref arguments) => {
+                symbols.push(Symbol {
+                    position: expression.position,
+                    name: Arc::clone(name),
+                    kind: SymbolKind::Name,
+                    role: SymbolRole::Reference,
+                });
+                stack.extend(arguments.iter().map(Arc::as_ref));
+            }
+            ExprInner::AssertL(ref left, ref cmr) => {
+                stack.push(left);
+                stack.extend(cmr_expression(cmr));
+            }
+            ExprInner::AssertR(ref cmr, ref right) => {
+                stack.extend(cmr_expression(cmr));
+                stack.push(right);
+            }
+            ExprInner::Constant(ref constant) => {
+                constant.for_each_cmr(&mut |cmr| stack.extend(cmr_expression(cmr)));
+            }
+            ExprInner::Ascribed(ref child, _) => stack.push(child),
+            ExprInner::Inline(ref inner) => {
+                if let node::Inner::Witness(WitnessOrHole::TypedHole(ref name)) = inner {
+                    symbols.push(Symbol {
+                        position: expression.position,
+                        name: Arc::clone(name),
+                        kind: SymbolKind::Hole,
+                        role: SymbolRole::Definition,
+                    });
+                }
+                match inner {
+                    node::Inner::InjL(child)
+                    | node::Inner::InjR(child)
+                    | node::Inner::Take(child)
+                    | node::Inner::Drop(child) => stack.push(child),
+                    node::Inner::Disconnect(left, right) => {
+                        stack.push(left);
+                        match right.inner {
+                            ExprInner::Inline(node::Inner::Witness(WitnessOrHole::TypedHole(
+                                ref name,
+                            ))) => symbols.push(Symbol {
+                                position: right.position,
+                                name: Arc::clone(name),
+                                kind: SymbolKind::Name,
+                                role: SymbolRole::Reference,
+                            }),
+                            _ => stack.push(right),
+                        }
+                    }
+                    node::Inner::Comp(left, right)
+                    | node::Inner::Case(left, right)
+                    | node::Inner::Pair(left, right) => {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The expression whose CMR is asserted, if it is not a literal.
+fn cmr_expression<J>(cmr: &AstCmr<J>) -> Option<&Expression<J>> {
+    match cmr {
+        AstCmr::Expr(expr) => Some(expr),
+        AstCmr::Literal(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jet::Core;
+
+    #[test]
+    fn lists_symbols() {
+        let source = "
+            main := comp (disconnect iden ?dis) (comp helper ?todo) : 1 -> 1
+            dis := unit
+            helper : B -> 1
+            helper := assertl unit #{ comp wit unit }
+            wit := witness
+            wit <- 0x00
+        ";
+        let symbols = symbols::<Core>(source).unwrap();
+        let summary: Vec<_> = symbols
+            .iter()
+            .map(|sym| (sym.name.as_ref(), sym.kind, sym.role, sym.position.line))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("main", SymbolKind::Name, SymbolRole::Definition, 2),
+                ("dis", SymbolKind::Name, SymbolRole::Reference, 2),
+                ("helper", SymbolKind::Name, SymbolRole::Reference, 2),
+                ("todo", SymbolKind::Hole, SymbolRole::Definition, 2),
+                ("dis", SymbolKind::Name, SymbolRole::Definition, 3),
+                ("helper", SymbolKind::Name, SymbolRole::TypeBound, 4),
+                ("helper", SymbolKind::Name, SymbolRole::Definition, 5),
+                ("wit", SymbolKind::Name, SymbolRole::Reference, 5),
+                ("wit", SymbolKind::Name, SymbolRole::Definition, 6),
+                ("wit", SymbolKind::Name, SymbolRole::WitnessAssignment, 7),
+            ]
+        );
+
+        // Positions are those of the names, not of the `?` before them
+        assert_eq!(symbols[1].position.column, 44);
+        assert_eq!(symbols[3].position.column, 63);
+    }
+}
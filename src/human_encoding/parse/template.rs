@@ -0,0 +1,357 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Templates
+//!
+//! Expands every instance of a template, such as `checksig(const 0x01)`, into
+//! an ordinary definition in which the template's parameters are replaced by
+//! the instance's arguments. After expansion a program contains no templates
+//! and can be resolved like any other.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::human_encoding::{Error, ErrorSet, Position, WitnessOrHole};
+use crate::jet::Jet;
+use crate::node;
+use crate::value::Word;
+
+use super::ast::{self, AstCmr, ExprInner, Expression, Type};
+use super::format;
+
+/// A definition of the form `name(PARAM, ...) := expression`, along with any
+/// type bounds for `name`.
+struct Template<J> {
+    position: Position,
+    parameters: Vec<ast::Parameter>,
+    expression: Arc<Expression<J>>,
+    arrows: Vec<(Option<Type>, Option<Type>)>,
+}
+
+/// Replaces every template in the program by a definition for each distinct
+/// instance of it.
+///
+/// Identical instances are defined once and share a node. Each is named after
+/// its template and numbered, for example `checksig'1`, skipping any names
+/// which are already defined, and is described by a comment giving its
+/// arguments, such as `-- checksig(const 0x01)`. Templates which are never
+/// instantiated are dropped without being checked.
+///
+/// If `main` is a template, it is instantiated with `arguments`, which must
+/// give a value for each of its parameters.
+pub fn expand<J: Jet>(
+    program: ast::Program<J>,
+    arguments: &HashMap<Arc<str>, Word>,
+    errors: &mut ErrorSet,
+) -> ast::Program<J> {
+    let mut templates = HashMap::<Arc<str>, Template<J>>::new();
+    let mut lines = Vec::with_capacity(program.lines.len());
+    for line in program.lines {
+        if line.parameters.is_empty() {
+            lines.push(line);
+            continue;
+        }
+        let mut seen = HashSet::new();
+        for param in &line.parameters {
+            if !seen.insert(Arc::clone(&param.name)) {
+                errors.add(param.position, Error::NameRepeated(Arc::clone(&param.name)));
+            }
+        }
+        if templates.contains_key(&line.name) {
+            errors.add(line.position, Error::NameRepeated(line.name));
+            continue;
+        }
+        let expression = line
+            .expression
+            .expect("templates always have an expression");
+        templates.insert(
+            line.name,
+            Template {
+                position: line.position,
+                parameters: line.parameters,
+                expression: Arc::new(expression),
+                arrows: vec![line.arrow],
+            },
+        );
+    }
+
+    // Type bounds for a template apply to each of its instances.
+    lines.retain(|line| match templates.get_mut(&line.name) {
+        Some(template) => {
+            if line.expression.is_some() {
+                errors.add(line.position, Error::NameRepeated(Arc::clone(&line.name)));
+            } else {
+                template.arrows.push(line.arrow.clone());
+            }
+            false
+        }
+        None => true,
+    });
+
+    let mut expander = Expander {
+        templates: &templates,
+        names: lines
+            .iter()
+            .map(|line| Arc::clone(&line.name))
+            .chain(templates.keys().cloned())
+            .collect(),
+        counts: HashMap::new(),
+        instances: HashMap::new(),
+        stack: vec![],
+        lines: vec![],
+        comments: vec![],
+        errors,
+    };
+    for line in &mut lines {
+        if let Some(ref expression) = line.expression {
+            line.expression = Some(expander.expand(expression, &HashMap::new()));
+        }
+    }
+    expander.instantiate_main(arguments);
+
+    lines.append(&mut expander.lines);
+    let mut comments = program.comments;
+    comments.append(&mut expander.comments);
+    ast::Program {
+        lines,
+        comments,
+        ..program
+    }
+}
+
+struct Expander<'t, 'e, J> {
+    templates: &'t HashMap<Arc<str>, Template<J>>,
+    /// Names which are defined, including those of instances
+    names: HashSet<Arc<str>>,
+    /// Number of instances of each template which have been defined
+    counts: HashMap<Arc<str>, usize>,
+    /// Name of each instance which has been defined, by its description
+    instances: HashMap<String, Arc<str>>,
+    /// Templates which are currently being instantiated, used to detect cycles
+    stack: Vec<Arc<str>>,
+    /// Definitions and type bounds of the instances
+    lines: Vec<ast::Line<J>>,
+    /// Comments describing the instances
+    comments: Vec<ast::DefinitionComment>,
+    errors: &'e mut ErrorSet,
+}
+
+impl<J: Jet> Expander<'_, '_, J> {
+    /// Replaces each parameter in the expression by its argument, and each
+    /// instance by a reference to its definition.
+    fn expand(
+        &mut self,
+        expr: &Expression<J>,
+        params: &HashMap<Arc<str>, Arc<Expression<J>>>,
+    ) -> Expression<J> {
+        let inner = match expr.inner {
+            ExprInner::Reference(ref name) => {
+                if let Some(argument) = params.get(name) {
+                    return Expression::clone(argument);
+                }
+                if let Some(template) = self.templates.get(name) {
+                    self.errors.add(
+                        expr.position,
+                        Error::TemplateArity {
+                            name: Arc::clone(name),
+                            expected: template.parameters.len(),
+                            found: 0,
+                        },
+                    );
+                }
+                ExprInner::Reference(Arc::clone(name))
+            }
+            ExprInner::Instance(ref name, ref arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|arg| self.expand_child(arg, params))
+                    .collect();
+                ExprInner::Reference(self.instantiate(name, arguments, expr.position))
+            }
+            ExprInner::AssertL(ref left, ref cmr) => ExprInner::AssertL(
+                self.expand_child(left, params),
+                self.expand_cmr(cmr, params),
+            ),
+            ExprInner::AssertR(ref cmr, ref right) => ExprInner::AssertR(
+                self.expand_cmr(cmr, params),
+                self.expand_child(right, params),
+            ),
+            ExprInner::Constant(ref constant) => {
+                ExprInner::Constant(constant.map_cmrs(&mut |cmr| self.expand_cmr(cmr, params)))
+            }
+            ExprInner::Ascribed(ref child, ref arrow) => {
+                ExprInner::Ascribed(self.expand_child(child, params), arrow.clone())
+            }
+            ExprInner::Inline(ref inner) => ExprInner::Inline(
+                inner
+                    .as_ref()
+                    .map(|child| self.expand_child(child, params))
+                    .map_disconnect(|child| self.expand_child(child, params))
+                    .map_witness(WitnessOrHole::shallow_clone),
+            ),
+        };
+        Expression {
+            inner,
+            position: expr.position,
+        }
+    }
+
+    fn expand_child(
+        &mut self,
+        child: &Arc<Expression<J>>,
+        params: &HashMap<Arc<str>, Arc<Expression<J>>>,
+    ) -> Arc<Expression<J>> {
+        Arc::new(self.expand(child, params))
+    }
+
+    fn expand_cmr(
+        &mut self,
+        cmr: &AstCmr<J>,
+        params: &HashMap<Arc<str>, Arc<Expression<J>>>,
+    ) -> AstCmr<J> {
+        match cmr {
+            AstCmr::Expr(expr) => AstCmr::Expr(self.expand_child(expr, params)),
+            AstCmr::Literal(cmr) => AstCmr::Literal(*cmr),
+        }
+    }
+
+    /// Defines the instance of a template with the given (expanded) arguments,
+    /// if it has not been defined already, returning its name.
+    ///
+    /// If the instance cannot be defined, an error is recorded and the name of
+    /// the template is returned.
+    fn instantiate(
+        &mut self,
+        name: &Arc<str>,
+        arguments: Vec<Arc<Expression<J>>>,
+        position: Position,
+    ) -> Arc<str> {
+        let template = match self.templates.get(name) {
+            Some(template) => template,
+            None => {
+                self.errors
+                    .add(position, Error::TemplateMissing(Arc::clone(name)));
+                return Arc::clone(name);
+            }
+        };
+        if template.parameters.len() != arguments.len() {
+            self.errors.add(
+                position,
+                Error::TemplateArity {
+                    name: Arc::clone(name),
+                    expected: template.parameters.len(),
+                    found: arguments.len(),
+                },
+            );
+            return Arc::clone(name);
+        }
+        if self.stack.contains(name) {
+            self.errors
+                .add(position, Error::TemplateRecursive(Arc::clone(name)));
+            return Arc::clone(name);
+        }
+
+        let description = format::call(name, &arguments);
+        if let Some(instance_name) = self.instances.get(&description) {
+            return Arc::clone(instance_name);
+        }
+
+        let params = template
+            .parameters
+            .iter()
+            .map(|param| Arc::clone(&param.name))
+            .zip(arguments)
+            .collect();
+        self.stack.push(Arc::clone(name));
+        let expression = self.expand(&template.expression, &params);
+        self.stack.pop();
+
+        let count = self.counts.entry(Arc::clone(name)).or_default();
+        let instance_name = loop {
+            *count += 1;
+            let instance_name = Arc::<str>::from(format!("{}'{}", name, count));
+            if self.names.insert(Arc::clone(&instance_name)) {
+                break instance_name;
+            }
+        };
+
+        for (n, arrow) in template.arrows.iter().enumerate() {
+            self.lines.push(ast::Line {
+                position: template.position,
+                name: Arc::clone(&instance_name),
+                parameters: vec![],
+                expression: if n == 0 {
+                    Some(expression.clone())
+                } else {
+                    None
+                },
+                arrow: arrow.clone(),
+                locals: vec![],
+            });
+        }
+        self.comments.push(ast::DefinitionComment {
+            name: Arc::clone(&instance_name),
+            text: Arc::from(format!("-- {}", description)),
+        });
+        self.instances
+            .insert(description, Arc::clone(&instance_name));
+        instance_name
+    }
+
+    /// Defines `main` as the instance of the `main` template with the given
+    /// arguments, if there is such a template.
+    fn instantiate_main(&mut self, arguments: &HashMap<Arc<str>, Word>) {
+        let mut names: Vec<&Arc<str>> = arguments.keys().collect();
+        names.sort();
+
+        let template = match self.templates.get("main") {
+            Some(template) => template,
+            None => {
+                for name in names {
+                    self.errors
+                        .add_no_position(Error::ArgumentUnused(Arc::clone(name)));
+                }
+                return;
+            }
+        };
+
+        for name in names {
+            if !template.parameters.iter().any(|param| param.name == *name) {
+                self.errors
+                    .add(template.position, Error::ArgumentUnused(Arc::clone(name)));
+            }
+        }
+        let mut main_arguments = Vec::with_capacity(template.parameters.len());
+        for param in &template.parameters {
+            match arguments.get(&param.name) {
+                Some(word) => main_arguments.push(Arc::new(Expression {
+                    inner: ExprInner::Inline(node::Inner::Word(word.shallow_clone())),
+                    position: param.position,
+                })),
+                None => self.errors.add(
+                    param.position,
+                    Error::ArgumentMissing {
+                        template: Arc::from("main"),
+                        parameter: Arc::clone(&param.name),
+                    },
+                ),
+            }
+        }
+        if main_arguments.len() < template.parameters.len() {
+            return;
+        }
+
+        let position = template.position;
+        let instance_name = self.instantiate(&Arc::from("main"), main_arguments, position);
+        self.lines.push(ast::Line {
+            position,
+            name: Arc::from("main"),
+            parameters: vec![],
+            expression: Some(Expression {
+                inner: ExprInner::Reference(instance_name),
+                position,
+            }),
+            arrow: (None, None),
+            locals: vec![],
+        });
+    }
+}
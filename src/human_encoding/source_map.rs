@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Source Maps
+//!
+//! Maps the nodes of a program back to the positions in the human-readable
+//! source that they were parsed from.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::human_encoding::Position;
+use crate::jet::Jet;
+use crate::{Cmr, Imr, RedeemNode};
+
+/// A position in the source, along with the definition that contains it
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLocation {
+    /// The name on the left of the `:=` whose expression contains the node.
+    pub definition: Arc<str>,
+    /// The position of the node within the source.
+    pub position: Position,
+    /// The name of the imported file which contains the node, or `None` if it
+    /// is in the file being parsed.
+    pub file: Option<Arc<str>>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` at line {}, column {}",
+            self.definition, self.position.line, self.position.column,
+        )?;
+        if let Some(ref file) = self.file {
+            write!(f, " of `{}`", file)?;
+        }
+        Ok(())
+    }
+}
+
+/// A map from the nodes of a program to their locations in the source
+///
+/// Nodes are identified by CMR and by IMR. A CMR does not change when a program
+/// is finalized, but many different expressions may share one, for example
+/// every `unit` in the program. An IMR is far more specific, but is only known
+/// once witness data is populated and pruning is done, so a map which is keyed
+/// on IMRs must be built for a specific [`RedeemNode`], using
+/// [`super::Forest::redeem_source_map`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    by_cmr: HashMap<Cmr, Vec<SourceLocation>>,
+    by_imr: HashMap<Imr, Vec<SourceLocation>>,
+}
+
+impl SourceMap {
+    /// The locations of every node with the given CMR, ordered by position.
+    pub fn by_cmr(&self, cmr: Cmr) -> &[SourceLocation] {
+        self.by_cmr.get(&cmr).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The locations of every node with the given IMR, ordered by position.
+    pub fn by_imr(&self, imr: Imr) -> &[SourceLocation] {
+        self.by_imr.get(&imr).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The locations of the given node of a finalized program.
+    ///
+    /// Nodes are looked up by IMR, falling back to looking up by CMR if the
+    /// IMR is unknown.
+    pub fn lookup<J: Jet>(&self, node: &RedeemNode<J>) -> &[SourceLocation] {
+        match self.by_imr(node.imr()) {
+            [] => self.by_cmr(node.cmr()),
+            locations => locations,
+        }
+    }
+
+    /// Records the location of a node with the given CMR and, if known, IMR.
+    pub(super) fn insert(&mut self, cmr: Cmr, imr: Option<Imr>, location: &SourceLocation) {
+        insert_sorted(self.by_cmr.entry(cmr).or_default(), location);
+        if let Some(imr) = imr {
+            insert_sorted(self.by_imr.entry(imr).or_default(), location);
+        }
+    }
+}
+
+/// Inserts a location into a sorted list, unless it is already present.
+fn insert_sorted(locations: &mut Vec<SourceLocation>, location: &SourceLocation) {
+    if let Err(idx) = locations.binary_search(location) {
+        locations.insert(idx, location.clone());
+    }
+}
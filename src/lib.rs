@@ -33,6 +33,8 @@ mod macros;
 mod analysis;
 mod bit_encoding;
 pub mod bit_machine;
+#[cfg(feature = "elements")]
+pub mod consensus;
 pub mod dag;
 pub mod human_encoding;
 pub mod jet;
@@ -55,7 +57,9 @@ pub use crate::policy::{
     sighash, Policy, Preimage32, Satisfier, SimplicityKey, ToXOnlyPubkey, Translator,
 };
 
-pub use crate::analysis::{Cost, NodeBounds};
+#[cfg(feature = "elements")]
+pub use crate::analysis::compare_c_bounds;
+pub use crate::analysis::{BoundsMismatch, Cost, NodeBounds, ProgramBounds};
 pub use crate::bit_machine::BitMachine;
 pub use crate::encode::{encode_natural, encode_value, encode_witness};
 pub use crate::merkle::{
@@ -66,7 +70,7 @@ pub use crate::merkle::{
     FailEntropy, HasCmr,
 };
 pub use crate::node::{CommitNode, ConstructNode, Hiding, RedeemNode};
-pub use crate::value::{Value, Word};
+pub use crate::value::{ParseWordError, Value, Word};
 pub use simplicity_sys as ffi;
 use std::fmt;
 
@@ -39,6 +39,12 @@ pub enum Policy<Pk: SimplicityKey> {
     Older(u16),
     /// Provide the preimage of the given SHA256 hash image
     Sha256(Pk::Sha256),
+    /// Provide the preimage of the given double SHA256 hash image
+    Hash256(Pk::Hash256),
+    /// Provide the preimage of the given RIPEMD160 hash image
+    Ripemd160(Pk::Ripemd160),
+    /// Provide the preimage of the given SHA256 followed by RIPEMD160 hash image
+    Hash160(Pk::Hash160),
     /// Satisfy both of the given sub-policies
     And {
         left: Arc<Policy<Pk>>,
@@ -51,6 +57,8 @@ pub enum Policy<Pk: SimplicityKey> {
     },
     /// Satisfy exactly `k` of the given sub-policies
     Threshold(usize, Vec<Policy<Pk>>),
+    /// Provide signatures for exactly `k` of the given public keys
+    Multi(usize, Vec<Pk>),
     /// Satisfy the program with the given CMR
     Assembly(Cmr),
 }
@@ -75,6 +83,21 @@ impl<Pk: ToXOnlyPubkey> Policy<Pk> {
             Policy::Sha256(ref hash) => {
                 Some(serialize::sha256::<Pk, _, _>(inference_context, hash, None))
             }
+            Policy::Hash256(ref hash) => Some(serialize::hash256::<Pk, _, _>(
+                inference_context,
+                hash,
+                None,
+            )),
+            Policy::Ripemd160(ref hash) => Some(serialize::ripemd160::<Pk, _, _>(
+                inference_context,
+                hash,
+                None,
+            )),
+            Policy::Hash160(ref hash) => Some(serialize::hash160::<Pk, _, _>(
+                inference_context,
+                hash,
+                None,
+            )),
             Policy::And {
                 ref left,
                 ref right,
@@ -100,6 +123,11 @@ impl<Pk: ToXOnlyPubkey> Policy<Pk> {
                 let wits = iter::repeat(None).take(subs.len()).collect::<Vec<_>>();
                 Some(serialize::threshold(k, &subs, &wits))
             }
+            Policy::Multi(k, ref keys) => {
+                let k = u32::try_from(k).expect("can have k at most 2^32 in a multi");
+                let wits = iter::repeat(None).take(keys.len()).collect::<Vec<_>>();
+                Some(serialize::multi(inference_context, k, keys, &wits))
+            }
             Policy::Assembly(cmr) => N::assembly(inference_context, cmr),
         }
     }
@@ -133,6 +161,9 @@ impl<Pk: SimplicityKey> Policy<Pk> {
             Policy::Trivial => Ok(Policy::Trivial),
             Policy::Key(ref pk) => translator.pk(pk).map(Policy::Key),
             Policy::Sha256(ref h) => translator.sha256(h).map(Policy::Sha256),
+            Policy::Hash256(ref h) => translator.hash256(h).map(Policy::Hash256),
+            Policy::Ripemd160(ref h) => translator.ripemd160(h).map(Policy::Ripemd160),
+            Policy::Hash160(ref h) => translator.hash160(h).map(Policy::Hash160),
             Policy::After(n) => Ok(Policy::After(n)),
             Policy::Older(n) => Ok(Policy::Older(n)),
             Policy::Threshold(k, ref subs) => {
@@ -140,6 +171,10 @@ impl<Pk: SimplicityKey> Policy<Pk> {
                     subs.iter().map(|sub| sub.translate(translator)).collect();
                 new_subs.map(|ok| Policy::Threshold(k, ok))
             }
+            Policy::Multi(k, ref keys) => {
+                let new_keys: Result<Vec<Q>, _> = keys.iter().map(|pk| translator.pk(pk)).collect();
+                new_keys.map(|ok| Policy::Multi(k, ok))
+            }
             Policy::And {
                 ref left,
                 ref right,
@@ -227,6 +262,7 @@ impl<Pk: SimplicityKey> Policy<Pk> {
                 }
                 subs.sort();
             }
+            Policy::Multi(_, ref mut keys) => keys.sort(),
             _ => {}
         }
     }
@@ -238,10 +274,13 @@ impl<Pk: SimplicityKey> Policy<Pk> {
 
     /// Return an iterator over the public keys of the policy.
     pub fn iter_pk(&self) -> impl Iterator<Item = Pk> + '_ {
-        self.iter().filter_map(|fragment| match fragment {
-            Policy::Key(key) => Some(key.clone()),
-            _ => None,
-        })
+        self.iter()
+            .flat_map(|fragment| match fragment {
+                Policy::Key(key) => std::slice::from_ref(key),
+                Policy::Multi(_, keys) => keys.as_slice(),
+                _ => &[],
+            })
+            .cloned()
     }
 }
 
@@ -254,6 +293,9 @@ impl<Pk: SimplicityKey> fmt::Debug for Policy<Pk> {
             Policy::After(n) => write!(f, "after({})", n),
             Policy::Older(n) => write!(f, "older({})", n),
             Policy::Sha256(h) => write!(f, "sha256({})", h),
+            Policy::Hash256(h) => write!(f, "hash256({})", h),
+            Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
+            Policy::Hash160(h) => write!(f, "hash160({})", h),
             Policy::And { left, right } => write!(f, "and({},{})", left, right),
             Policy::Or { left, right } => write!(f, "or({},{})", left, right),
             Policy::Threshold(k, sub_policies) => {
@@ -263,6 +305,13 @@ impl<Pk: SimplicityKey> fmt::Debug for Policy<Pk> {
                 }
                 f.write_str(")")
             }
+            Policy::Multi(k, keys) => {
+                write!(f, "multi({}", k)?;
+                for key in keys {
+                    write!(f, ",{}", key)?;
+                }
+                f.write_str(")")
+            }
             Policy::Assembly(cmr) => write!(f, "asm({})", cmr),
         }
     }
@@ -0,0 +1,965 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Policy Compiler
+//!
+//! Policies are compiled to a taproot tree of Simplicity programs, choosing
+//! among equivalent encodings the one with the least expected spend weight.
+//! The branches of a disjunction may be given relative probabilities, as in
+//! `or(9@pk(A),1@pk(B))`; a branch without one has probability 1.
+//!
+//! The compiler chooses
+//!
+//! * whether to encode a threshold as a sum over its sub-policies, as a
+//!   `multi` if its sub-policies are keys, or as a disjunction over every
+//!   choice of `k` sub-policies;
+//! * how to nest the branches of disjunctions, so that likely branches are
+//!   close to the root and cheap to reveal;
+//! * which disjunctions to split into separate taproot leaves.
+//!
+//! The weight of a spend is estimated as the size of the pruned program and
+//! its witness data, plus the cost of executing it, plus the size of the
+//! control block which reveals its leaf. The sizes and costs of fragments are
+//! measured on the programs that the `serialize` module produces for them.
+
+use std::sync::{Arc, OnceLock};
+use std::{fmt, io, ops};
+
+use bitcoin_miniscript::hash256;
+use elements::bitcoin::key::XOnlyPublicKey;
+use elements::secp256k1_zkp::{constants, Secp256k1};
+use elements::taproot::{TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_MAX_NODE_COUNT};
+use hashes::{hash160, ripemd160, sha256, Hash};
+
+use crate::analysis::NodeBounds;
+use crate::dag::{DagLike, NoSharing};
+use crate::jet::Elements;
+use crate::node::{CoreConstructible, Inner, WitnessConstructible};
+use crate::policy::{serialize, Error, Policy, SimplicityKey, ToXOnlyPubkey};
+use crate::value::Word;
+use crate::{leaf_version, types, BitWriter, Cmr, CommitNode, ConstructNode, FailEntropy, Value};
+
+/// Weight of each level of the merkle path in a taproot control block
+const TAP_LEVEL_WEIGHT: f64 = 32.0;
+
+/// Weight of the leaf script, which is a CMR, and of the control block
+/// without its merkle path, including their length prefixes
+const LEAF_WEIGHT: f64 = 1.0 + 32.0 + 1.0 + 33.0;
+
+/// Maximum number of choices of sub-policies for which a threshold may be
+/// encoded as a disjunction
+const MAX_EXPANSION: usize = 64;
+
+/// Policy whose disjunctions have branches with relative probabilities
+#[derive(Clone, PartialEq, Eq)]
+pub enum WeightedPolicy<Pk: SimplicityKey> {
+    /// Satisfy the given policy, whose disjunctions have equally likely branches
+    Unweighted(Policy<Pk>),
+    /// Satisfy both of the given sub-policies
+    And {
+        left: Arc<WeightedPolicy<Pk>>,
+        right: Arc<WeightedPolicy<Pk>>,
+    },
+    /// Satisfy one of the given sub-policies, which are used with the given
+    /// relative probabilities
+    Or(Vec<(usize, WeightedPolicy<Pk>)>),
+    /// Satisfy `k` of the given sub-policies
+    Threshold(usize, Vec<WeightedPolicy<Pk>>),
+}
+
+impl<Pk: SimplicityKey> From<Policy<Pk>> for WeightedPolicy<Pk> {
+    fn from(policy: Policy<Pk>) -> Self {
+        match policy {
+            Policy::And { left, right } => WeightedPolicy::And {
+                left: Arc::new(Self::from(left.as_ref().clone())),
+                right: Arc::new(Self::from(right.as_ref().clone())),
+            },
+            Policy::Or { left, right } => WeightedPolicy::Or(vec![
+                (1, Self::from(left.as_ref().clone())),
+                (1, Self::from(right.as_ref().clone())),
+            ]),
+            Policy::Threshold(k, subs) => {
+                WeightedPolicy::Threshold(k, subs.into_iter().map(Self::from).collect())
+            }
+            policy => WeightedPolicy::Unweighted(policy),
+        }
+    }
+}
+
+impl<Pk: SimplicityKey> fmt::Debug for WeightedPolicy<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeightedPolicy::Unweighted(policy) => fmt::Debug::fmt(policy, f),
+            WeightedPolicy::And { left, right } => write!(f, "and({:?},{:?})", left, right),
+            WeightedPolicy::Or(branches) => {
+                f.write_str("or(")?;
+                for (i, (weight, branch)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    if *weight != 1 {
+                        write!(f, "{}@", weight)?;
+                    }
+                    write!(f, "{:?}", branch)?;
+                }
+                f.write_str(")")
+            }
+            WeightedPolicy::Threshold(k, subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs {
+                    write!(f, ",{:?}", sub)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl<Pk: SimplicityKey> fmt::Display for WeightedPolicy<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Leaf of a compiled taproot tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapLeaf<Pk: SimplicityKey> {
+    policy: Policy<Pk>,
+    depth: u8,
+    probability: f64,
+}
+
+impl<Pk: SimplicityKey> TapLeaf<Pk> {
+    /// Accessor for the policy of the leaf, which is serialized as it is.
+    pub fn policy(&self) -> &Policy<Pk> {
+        &self.policy
+    }
+
+    /// Accessor for the depth of the leaf in the taproot tree.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Accessor for the probability that a spend uses the leaf.
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+}
+
+/// Result of compiling a policy: the leaves of a taproot tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct Compilation<Pk: SimplicityKey> {
+    leaves: Vec<TapLeaf<Pk>>,
+    expected_weight: f64,
+}
+
+impl<Pk: SimplicityKey> Compilation<Pk> {
+    /// Accessor for the leaves of the taproot tree, in depth-first order.
+    pub fn leaves(&self) -> &[TapLeaf<Pk>] {
+        &self.leaves
+    }
+
+    /// Accessor for the estimated expected weight of the witness of a spend.
+    pub fn expected_weight(&self) -> f64 {
+        self.expected_weight
+    }
+}
+
+impl<Pk: ToXOnlyPubkey> Compilation<Pk> {
+    /// Build the taproot tree with the given internal key.
+    ///
+    /// The script of each leaf is the CMR of its policy.
+    pub fn spend_info(&self, internal_key: XOnlyPublicKey) -> TaprootSpendInfo {
+        let mut builder = TaprootBuilder::new();
+        for leaf in &self.leaves {
+            let script = elements::Script::from(leaf.policy.cmr().as_ref().to_vec());
+            builder = builder
+                .add_leaf_with_ver(usize::from(leaf.depth), script, leaf_version())
+                .expect("leaves are in depth-first order");
+        }
+        builder
+            .finalize(&Secp256k1::verification_only(), internal_key)
+            .expect("tree is complete")
+    }
+}
+
+impl<Pk: SimplicityKey> WeightedPolicy<Pk> {
+    /// Compile the policy to the taproot tree with the least expected spend weight.
+    pub fn compile(&self) -> Result<Compilation<Pk>, Error> {
+        let tree = self.expand().compile_tree(Model::get());
+        let leaves = tree
+            .leaves
+            .into_iter()
+            .map(|(compiled, depth, probability)| {
+                if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+                    return Err(Error::MaxTaprootDepthExceeded);
+                }
+                Ok(TapLeaf {
+                    policy: compiled.policy,
+                    depth: depth as u8, // cast safety: checked above
+                    probability,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Compilation {
+            leaves,
+            expected_weight: tree.weight + LEAF_WEIGHT,
+        })
+    }
+
+    /// Replace unweighted sub-policies with fragments of the weighted policy,
+    /// so that only fragments without sub-policies are unweighted.
+    fn expand(&self) -> Self {
+        match self {
+            WeightedPolicy::Unweighted(policy) => Self::from(policy.clone()),
+            WeightedPolicy::And { left, right } => WeightedPolicy::And {
+                left: Arc::new(left.expand()),
+                right: Arc::new(right.expand()),
+            },
+            WeightedPolicy::Or(branches) => WeightedPolicy::Or(
+                branches
+                    .iter()
+                    .map(|(weight, branch)| (*weight, branch.expand()))
+                    .collect(),
+            ),
+            WeightedPolicy::Threshold(k, subs) => {
+                WeightedPolicy::Threshold(*k, subs.iter().map(Self::expand).collect())
+            }
+        }
+    }
+
+    /// Check whether the policy can never be satisfied.
+    fn is_unsatisfiable(&self) -> bool {
+        match self {
+            WeightedPolicy::Unweighted(policy) => matches!(policy, Policy::Unsatisfiable(..)),
+            WeightedPolicy::And { left, right } => {
+                left.is_unsatisfiable() || right.is_unsatisfiable()
+            }
+            WeightedPolicy::Or(branches) => {
+                branches.iter().all(|(_, branch)| branch.is_unsatisfiable())
+            }
+            WeightedPolicy::Threshold(k, subs) => {
+                subs.iter().filter(|sub| !sub.is_unsatisfiable()).count() < *k
+            }
+        }
+    }
+
+    /// Return the branches of a disjunction with their probabilities, with the
+    /// branches of nested disjunctions in place of the nested disjunctions.
+    ///
+    /// Unsatisfiable branches are never used, so they are left out.
+    fn disjuncts(&self) -> Vec<(f64, &Self)> {
+        let branches = match self {
+            WeightedPolicy::Or(branches) => branches
+                .iter()
+                .filter(|(_, branch)| !branch.is_unsatisfiable())
+                .collect::<Vec<_>>(),
+            _ => return vec![(1.0, self)],
+        };
+        let total = branches
+            .iter()
+            .map(|(weight, _)| *weight as f64)
+            .sum::<f64>();
+        let mut disjuncts = vec![];
+        for (weight, branch) in &branches {
+            let p = if total > 0.0 {
+                *weight as f64 / total
+            } else {
+                1.0 / branches.len() as f64
+            };
+            disjuncts.extend(
+                branch
+                    .disjuncts()
+                    .into_iter()
+                    .map(|(q, disjunct)| (p * q, disjunct)),
+            );
+        }
+        disjuncts
+    }
+
+    /// Compile the policy to the taproot tree with the least expected spend
+    /// weight, splitting disjunctions into separate leaves where that is cheaper.
+    ///
+    /// Unsatisfiable disjuncts and choices of sub-policies get no leaves, unless
+    /// nothing else is left, in which case the tree is a single unsatisfiable leaf.
+    fn compile_tree(&self, model: &Model) -> Tree<Pk> {
+        if self.is_unsatisfiable() && !matches!(self, WeightedPolicy::Unweighted(..)) {
+            let unsatisfiable = Policy::Unsatisfiable(FailEntropy::ZERO);
+            return Tree::leaf(Compiled::fragment(unsatisfiable, model));
+        }
+        let whole = Tree::leaf(self.compile_script(model));
+        let split = match self {
+            WeightedPolicy::Or(..) => {
+                let mut disjuncts = self.disjuncts();
+                if disjuncts.len() < 2 {
+                    let (_, disjunct) = disjuncts.pop().expect("policy is satisfiable");
+                    return disjunct.compile_tree(model);
+                }
+                Tree::join(
+                    disjuncts
+                        .into_iter()
+                        .map(|(p, disjunct)| (p, disjunct.compile_tree(model)))
+                        .collect(),
+                )
+            }
+            WeightedPolicy::Threshold(k, subs) if 0 < *k && *k < subs.len() => {
+                let subs = subs
+                    .iter()
+                    .filter(|sub| !sub.is_unsatisfiable())
+                    .map(|sub| sub.compile_script(model))
+                    .collect::<Vec<_>>();
+                match choices(subs.len(), *k) {
+                    Some(choices) => Tree::join(
+                        choices
+                            .iter()
+                            .map(|choice| {
+                                (1.0, Tree::leaf(Compiled::and_all(&subs, choice, model)))
+                            })
+                            .collect(),
+                    ),
+                    None => return whole,
+                }
+            }
+            _ => return whole,
+        };
+
+        if split.weight < whole.weight {
+            split
+        } else {
+            whole
+        }
+    }
+
+    /// Compile the policy to the single program with the least expected spend weight.
+    fn compile_script(&self, model: &Model) -> Compiled<Pk> {
+        match self {
+            WeightedPolicy::Unweighted(policy) => Compiled::fragment(policy.clone(), model),
+            WeightedPolicy::And { left, right } => Compiled::and(
+                left.compile_script(model),
+                right.compile_script(model),
+                model,
+            ),
+            WeightedPolicy::Or(..) => Compiled::or(
+                self.disjuncts()
+                    .into_iter()
+                    .map(|(p, disjunct)| (p, disjunct.compile_script(model)))
+                    .collect(),
+                model,
+            ),
+            WeightedPolicy::Threshold(k, subs) => {
+                let subs = subs
+                    .iter()
+                    .map(|sub| sub.compile_script(model))
+                    .collect::<Vec<_>>();
+                Compiled::threshold(*k, subs, model)
+            }
+        }
+    }
+}
+
+/// Expected size and cost of a spend
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Stats {
+    program_bits: f64,
+    witness_bits: f64,
+    /// Cost in milliweight units
+    cost: f64,
+}
+
+impl Stats {
+    /// Measure a program, which must not contain any `case` nodes, so that
+    /// all of it is executed.
+    fn of(program: &CommitNode<Elements>) -> Self {
+        let program_bits = program
+            .encode(&mut BitWriter::new(io::sink()))
+            .expect("write to sink never fails");
+        let witness_bits = program
+            .post_order_iter::<NoSharing>()
+            .filter(|data| matches!(data.node.inner(), Inner::Witness(_)))
+            .map(|data| data.node.arrow().target.bit_width())
+            .sum::<usize>();
+        Stats {
+            program_bits: program_bits as f64,
+            witness_bits: witness_bits as f64,
+            cost: f64::from(bounds(program).cost.to_milliweight()),
+        }
+    }
+
+    /// Estimated weight of a spend
+    fn weight(&self) -> f64 {
+        (self.program_bits + self.witness_bits) / 8.0 + self.cost / 1000.0
+    }
+}
+
+impl ops::Add for Stats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Stats {
+            program_bits: self.program_bits + rhs.program_bits,
+            witness_bits: self.witness_bits + rhs.witness_bits,
+            cost: self.cost + rhs.cost,
+        }
+    }
+}
+
+impl ops::Sub for Stats {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + rhs * -1.0
+    }
+}
+
+impl ops::Mul<f64> for Stats {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Stats {
+            program_bits: self.program_bits * rhs,
+            witness_bits: self.witness_bits * rhs,
+            cost: self.cost * rhs,
+        }
+    }
+}
+
+/// Compute the bounds of a program without witness data.
+fn bounds(node: &CommitNode<Elements>) -> NodeBounds {
+    let arrow = node.arrow();
+    match node.inner() {
+        Inner::Iden => NodeBounds::iden(arrow.source.bit_width()),
+        Inner::Unit => NodeBounds::unit(),
+        Inner::InjL(child) => NodeBounds::injl(bounds(child)),
+        Inner::InjR(child) => NodeBounds::injr(bounds(child)),
+        Inner::Take(child) => NodeBounds::take(bounds(child)),
+        Inner::Drop(child) => NodeBounds::drop(bounds(child)),
+        Inner::Comp(left, right) => {
+            NodeBounds::comp(bounds(left), bounds(right), left.arrow().target.bit_width())
+        }
+        Inner::Case(left, right) => NodeBounds::case(bounds(left), bounds(right)),
+        Inner::AssertL(left, _) => NodeBounds::assertl(bounds(left)),
+        Inner::AssertR(_, right) => NodeBounds::assertr(bounds(right)),
+        Inner::Pair(left, right) => NodeBounds::pair(bounds(left), bounds(right)),
+        Inner::Disconnect(..) => unreachable!("policy fragments have no disconnect"),
+        Inner::Witness(_) => NodeBounds::witness(arrow.target.bit_width()),
+        Inner::Fail(_) => NodeBounds::fail(),
+        Inner::Jet(jet) => NodeBounds::jet(*jet),
+        Inner::Word(word) => NodeBounds::const_word(word),
+    }
+}
+
+/// Stats of the fragments of a policy
+///
+/// The stats of fragments with sub-policies are those of the fragment itself,
+/// pruned for a spend, without the stats of its sub-policies.
+struct Model {
+    unsatisfiable: Stats,
+    trivial: Stats,
+    key: Stats,
+    after: Stats,
+    older: Stats,
+    sha256: Stats,
+    hash256: Stats,
+    ripemd160: Stats,
+    hash160: Stats,
+    /// `multi` without keys
+    multi: Stats,
+    /// Step of `multi` whose key has a signature
+    multi_signed: Stats,
+    /// Step of `multi` whose key has no signature
+    multi_unsigned: Stats,
+    and: Stats,
+    /// `or` with one branch taken and the other pruned
+    or: Stats,
+    /// Threshold summand whose sub-policy is satisfied
+    summand_satisfied: Stats,
+    /// Threshold summand whose sub-policy is not satisfied
+    summand_unsatisfied: Stats,
+    /// Addition of a summand to a threshold sum
+    add: Stats,
+    /// Comparison of a threshold sum with `k`
+    verify: Stats,
+}
+
+impl Model {
+    /// Stats of the fragments, which are measured once
+    ///
+    /// Measuring the hash fragments takes a while, because RIPEMD160 is not a jet.
+    fn get() -> &'static Self {
+        static MODEL: OnceLock<Model> = OnceLock::new();
+        MODEL.get_or_init(Model::new)
+    }
+
+    fn new() -> Self {
+        type N = Arc<ConstructNode<Elements>>;
+
+        let key = XOnlyPublicKey::from_slice(&constants::GENERATOR_X).expect("generator is a key");
+        let fragment = |policy: Policy<XOnlyPublicKey>| {
+            Stats::of(&policy.commit().expect("fragment is not assembly"))
+        };
+
+        // Fragments with sub-policies are measured with `fail` nodes in place
+        // of their sub-policies, which are never executed.
+        let ctx = types::Context::new();
+        let hole = |i: u8| N::fail(&ctx, FailEntropy::from_byte_array([i; 64]));
+        let measure = |node: N| {
+            Stats::of(
+                &node
+                    .finalize_types_non_program()
+                    .expect("fragment has sound types"),
+            )
+        };
+        let hole_stats = measure(hole(0));
+        let glue = |node: N, holes: usize| measure(node) - hole_stats * holes as f64;
+
+        let selector = || serialize::selector::<N, _>(&ctx, None);
+        let word = |n: u32| N::const_word(&ctx, Word::u32(n));
+        let pruned = Cmr::unit();
+
+        let or = N::comp(
+            &selector(),
+            &N::assertl(&N::drop_(&hole(1)), pruned).expect("consistent types"),
+        )
+        .expect("consistent types");
+        let child_one = N::comp(&hole(1), &word(1)).expect("consistent types");
+        let summand_satisfied = N::comp(
+            &selector(),
+            &N::assertr(pruned, &N::drop_(&child_one)).expect("consistent types"),
+        )
+        .expect("consistent types");
+        let summand_unsatisfied = N::comp(
+            &selector(),
+            &N::assertl(&N::drop_(&word(0)), pruned).expect("consistent types"),
+        )
+        .expect("consistent types");
+        let multi_step = |branch: N| {
+            let pair_witness_count = N::pair(&N::witness(&ctx, None::<Value>), &N::iden(&ctx))
+                .expect("consistent types");
+            let step = N::comp(&pair_witness_count, &branch).expect("consistent types");
+            N::comp(&hole(1), &step).expect("consistent types")
+        };
+        let multi_signed = multi_step(
+            N::assertr(pruned, &serialize::multi_verify(&ctx, &key)).expect("consistent types"),
+        );
+        let multi_unsigned =
+            multi_step(N::assertl(&N::drop_(&N::iden(&ctx)), pruned).expect("consistent types"));
+
+        Model {
+            unsatisfiable: fragment(Policy::Unsatisfiable(FailEntropy::ZERO)),
+            trivial: fragment(Policy::Trivial),
+            key: fragment(Policy::Key(key)),
+            after: fragment(Policy::After(0)),
+            older: fragment(Policy::Older(0)),
+            sha256: fragment(Policy::Sha256(sha256::Hash::all_zeros())),
+            hash256: fragment(Policy::Hash256(hash256::Hash::all_zeros())),
+            ripemd160: fragment(Policy::Ripemd160(ripemd160::Hash::all_zeros())),
+            hash160: fragment(Policy::Hash160(hash160::Hash::all_zeros())),
+            multi: fragment(Policy::Multi(0, vec![])),
+            multi_signed: glue(multi_signed, 1),
+            multi_unsigned: glue(multi_unsigned, 1),
+            and: glue(serialize::and(&hole(1), &hole(2)), 2),
+            or: glue(or, 1),
+            summand_satisfied: glue(summand_satisfied, 1),
+            summand_unsatisfied: measure(summand_unsatisfied),
+            add: glue(serialize::thresh_add(&hole(1), &hole(2)), 2),
+            verify: glue(serialize::thresh_verify(&hole(1), 0), 1),
+        }
+    }
+
+    /// Stats of a fragment without sub-policies
+    ///
+    /// The program of an assembly fragment is unknown, so it is counted as free.
+    fn fragment<Pk: SimplicityKey>(&self, policy: &Policy<Pk>) -> Stats {
+        match policy {
+            Policy::Unsatisfiable(..) => self.unsatisfiable,
+            Policy::Trivial => self.trivial,
+            Policy::Key(..) => self.key,
+            Policy::After(..) => self.after,
+            Policy::Older(..) => self.older,
+            Policy::Sha256(..) => self.sha256,
+            Policy::Hash256(..) => self.hash256,
+            Policy::Ripemd160(..) => self.ripemd160,
+            Policy::Hash160(..) => self.hash160,
+            Policy::Multi(k, keys) => {
+                let unsigned = keys.len().saturating_sub(*k);
+                self.multi + self.multi_signed * *k as f64 + self.multi_unsigned * unsigned as f64
+            }
+            Policy::Assembly(..) => Stats::default(),
+            Policy::And { .. } | Policy::Or { .. } | Policy::Threshold(..) => {
+                unreachable!("fragment has sub-policies")
+            }
+        }
+    }
+}
+
+/// Policy with the expected stats of a spend
+#[derive(Clone)]
+struct Compiled<Pk: SimplicityKey> {
+    policy: Policy<Pk>,
+    stats: Stats,
+}
+
+impl<Pk: SimplicityKey> Compiled<Pk> {
+    fn fragment(policy: Policy<Pk>, model: &Model) -> Self {
+        let stats = model.fragment(&policy);
+        Compiled { policy, stats }
+    }
+
+    fn and(left: Self, right: Self, model: &Model) -> Self {
+        Compiled {
+            stats: left.stats + right.stats + model.and,
+            policy: Policy::And {
+                left: Arc::new(left.policy),
+                right: Arc::new(right.policy),
+            },
+        }
+    }
+
+    /// Conjunction of the sub-policies with the given indices
+    fn and_all(subs: &[Self], indices: &[usize], model: &Model) -> Self {
+        let (last, rest) = indices.split_last().expect("at least one sub-policy");
+        rest.iter().rev().fold(subs[*last].clone(), |acc, &i| {
+            Self::and(subs[i].clone(), acc, model)
+        })
+    }
+
+    /// Disjunction of the branches, which have the given relative probabilities
+    ///
+    /// Each `or` fragment costs the same, so the nesting with the least
+    /// expected cost is a Huffman tree.
+    fn or(branches: Vec<(f64, Self)>, model: &Model) -> Self {
+        if branches.is_empty() {
+            return Self::fragment(Policy::Unsatisfiable(FailEntropy::ZERO), model);
+        }
+        huffman(branches, |(p, left), (q, right)| {
+            let share = share(p, q);
+            Compiled {
+                stats: left.stats * share + right.stats * (1.0 - share) + model.or,
+                policy: Policy::Or {
+                    left: Arc::new(left.policy),
+                    right: Arc::new(right.policy),
+                },
+            }
+        })
+    }
+
+    /// Threshold of the sub-policies, assuming that every choice of `k`
+    /// sub-policies is equally likely
+    fn threshold(k: usize, subs: Vec<Self>, model: &Model) -> Self {
+        let n = subs.len();
+        if k == 0 {
+            return Self::fragment(Policy::Trivial, model);
+        }
+        if k > n {
+            return Self::fragment(Policy::Unsatisfiable(FailEntropy::ZERO), model);
+        }
+
+        let mut candidates = vec![];
+        if let Some(choices) = choices(n, k) {
+            let branches = choices
+                .iter()
+                .map(|choice| (1.0, Self::and_all(&subs, choice, model)))
+                .collect();
+            candidates.push(Self::or(branches, model));
+        }
+
+        // Each sub-policy is satisfied with probability k / n.
+        let p = k as f64 / n as f64;
+        let summands = subs.iter().fold(Stats::default(), |acc, sub| {
+            acc + (model.summand_satisfied + sub.stats) * p + model.summand_unsatisfied * (1.0 - p)
+        });
+        // A threshold of keys may check their signatures with `multi`.
+        let keys = subs
+            .iter()
+            .map(|sub| match sub.policy {
+                Policy::Key(ref key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(keys) = keys {
+            candidates.push(Self::fragment(Policy::Multi(k, keys), model));
+        }
+
+        candidates.push(Compiled {
+            stats: summands + model.add * (n - 1) as f64 + model.verify,
+            policy: Policy::Threshold(k, subs.into_iter().map(|sub| sub.policy).collect()),
+        });
+
+        candidates
+            .into_iter()
+            .min_by(|a, b| a.stats.weight().total_cmp(&b.stats.weight()))
+            .expect("at least one candidate")
+    }
+}
+
+/// Taproot tree with the expected weight of a spend, not counting the parts
+/// of the control block that are the same for every leaf
+struct Tree<Pk: SimplicityKey> {
+    /// Leaves with their depth and probability
+    leaves: Vec<(Compiled<Pk>, usize, f64)>,
+    weight: f64,
+}
+
+impl<Pk: SimplicityKey> Tree<Pk> {
+    fn leaf(compiled: Compiled<Pk>) -> Self {
+        Tree {
+            weight: compiled.stats.weight(),
+            leaves: vec![(compiled, 0, 1.0)],
+        }
+    }
+
+    /// Join the trees, which are used with the given relative probabilities,
+    /// into a Huffman tree.
+    fn join(trees: Vec<(f64, Self)>) -> Self {
+        huffman(trees, |(p, left), (q, right)| {
+            let share = share(p, q);
+            let leaves = left
+                .leaves
+                .into_iter()
+                .map(|(leaf, depth, prob)| (leaf, depth + 1, prob * share))
+                .chain(
+                    right
+                        .leaves
+                        .into_iter()
+                        .map(|(leaf, depth, prob)| (leaf, depth + 1, prob * (1.0 - share))),
+                )
+                .collect();
+            Tree {
+                leaves,
+                weight: left.weight * share + right.weight * (1.0 - share) + TAP_LEVEL_WEIGHT,
+            }
+        })
+    }
+}
+
+/// Combine items, which have the given relative probabilities, into a binary
+/// tree that minimizes the expected depth of an item.
+fn huffman<T, F>(mut items: Vec<(f64, T)>, mut merge: F) -> T
+where
+    F: FnMut((f64, T), (f64, T)) -> T,
+{
+    assert!(!items.is_empty(), "cannot combine zero items");
+    while items.len() > 1 {
+        let mut order = (0..items.len()).collect::<Vec<_>>();
+        order.sort_by(|&i, &j| items[i].0.total_cmp(&items[j].0));
+        let (i, j) = (order[0].min(order[1]), order[0].max(order[1]));
+
+        let right = items.remove(j);
+        let left = items.remove(i);
+        let p = left.0 + right.0;
+        items.insert(i, (p, merge(left, right)));
+    }
+    items.pop().expect("one item").1
+}
+
+/// Return the share of the left of two branches with the given relative
+/// probabilities.
+fn share(p: f64, q: f64) -> f64 {
+    if p + q > 0.0 {
+        p / (p + q)
+    } else {
+        0.5
+    }
+}
+
+/// Return every choice of `k` out of `n` indices, or `None` if there are more
+/// than [`MAX_EXPANSION`] choices.
+fn choices(n: usize, k: usize) -> Option<Vec<Vec<usize>>> {
+    let mut count = 1usize;
+    for i in 0..k {
+        // The product of i + 1 consecutive numbers is divisible by (i + 1)!.
+        count = count.checked_mul(n - i)? / (i + 1);
+        if count > MAX_EXPANSION {
+            return None;
+        }
+    }
+
+    let mut choices = Vec::with_capacity(count);
+    let mut choice = (0..k).collect::<Vec<_>>();
+    loop {
+        choices.push(choice.clone());
+        // Advance the last index which is not at its maximum.
+        match (0..k).rev().find(|&i| choice[i] < n - k + i) {
+            Some(i) => {
+                choice[i] += 1;
+                for j in i + 1..k {
+                    choice[j] = choice[j - 1] + 1;
+                }
+            }
+            None => return Some(choices),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn compile(s: &str) -> Compilation<String> {
+        WeightedPolicy::<String>::from_str(s)
+            .unwrap()
+            .compile()
+            .unwrap()
+    }
+
+    fn leaves(compilation: &Compilation<String>) -> Vec<(String, u8)> {
+        compilation
+            .leaves()
+            .iter()
+            .map(|leaf| (leaf.policy().to_string(), leaf.depth()))
+            .collect()
+    }
+
+    #[test]
+    fn choices() {
+        assert_eq!(super::choices(3, 1), Some(vec![vec![0], vec![1], vec![2]]));
+        assert_eq!(
+            super::choices(4, 2),
+            Some(vec![
+                vec![0, 1],
+                vec![0, 2],
+                vec![0, 3],
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 3]
+            ])
+        );
+        assert_eq!(super::choices(2, 2), Some(vec![vec![0, 1]]));
+        assert_eq!(super::choices(20, 10), None);
+    }
+
+    #[test]
+    fn split_disjunctions() {
+        let compilation = compile("or(1@pk(A),9@or(1@pk(B),8@pk(C)))");
+        assert_eq!(
+            leaves(&compilation),
+            [
+                ("pk(A)".to_owned(), 2),
+                ("pk(B)".to_owned(), 2),
+                ("pk(C)".to_owned(), 1)
+            ]
+        );
+        for (leaf, p) in compilation.leaves().iter().zip([0.1, 0.1, 0.8]) {
+            assert!((leaf.probability() - p).abs() < 1e-9);
+        }
+
+        // The nesting of disjunctions does not matter, only the probabilities.
+        let flat = compile("or(1@pk(A),1@pk(B),8@pk(C))");
+        assert_eq!(leaves(&flat), leaves(&compilation));
+    }
+
+    #[test]
+    fn disjunctions_in_script() {
+        // A disjunction below a conjunction cannot be split into leaves.
+        let compilation = compile("and(older(144),or(1@pk(A),1@pk(B),8@pk(C)))");
+        assert_eq!(
+            leaves(&compilation),
+            [("and(older(144),or(or(pk(A),pk(B)),pk(C)))".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn thresholds() {
+        let script = |s: &str| {
+            let policy = WeightedPolicy::<String>::from_str(s).unwrap();
+            policy.compile_script(Model::get()).policy.to_string()
+        };
+        assert_eq!(script("thresh(0,pk(A),pk(B))"), "TRIVIAL");
+        let empty = WeightedPolicy::<String>::Or(vec![]);
+        assert_eq!(
+            empty.compile().unwrap().leaves()[0].policy(),
+            &Policy::Unsatisfiable(FailEntropy::ZERO),
+        );
+        let too_high =
+            WeightedPolicy::Threshold(3, vec![WeightedPolicy::Unweighted(Policy::Trivial)]);
+        assert_eq!(
+            too_high.compile_script(Model::get()).policy,
+            Policy::<String>::Unsatisfiable(FailEntropy::ZERO),
+        );
+        assert_eq!(script("thresh(2,pk(A),pk(B))"), "and(pk(A),pk(B))");
+        assert_eq!(script("thresh(1,pk(A),pk(B))"), "or(pk(A),pk(B))");
+
+        // Large thresholds are summed, since they have too many choices of
+        // sub-policies.
+        let subs = (0..20)
+            .map(|i| format!(",after({})", i))
+            .collect::<String>();
+        let policy = format!("thresh(10{})", subs);
+        assert_eq!(script(&policy), policy);
+
+        // Large thresholds of keys check their signatures with `multi`.
+        let keys = (0..20).map(|i| format!(",pk(K{})", i)).collect::<String>();
+        let multi = (0..20).map(|i| format!(",K{}", i)).collect::<String>();
+        assert_eq!(
+            script(&format!("thresh(10{})", keys)),
+            format!("multi(10{})", multi),
+        );
+
+        // At the top, each choice of sub-policies gets its own leaf.
+        let compilation = compile("thresh(2,pk(A),pk(B),pk(C))");
+        assert_eq!(compilation.leaves().len(), 3);
+        for leaf in compilation.leaves() {
+            assert!(matches!(leaf.policy(), Policy::And { .. }));
+        }
+    }
+
+    #[test]
+    fn unsatisfiable_branches() {
+        // Unsatisfiable disjuncts and choices of sub-policies get no leaves.
+        let compilation = compile("or(9@pk(K),1@UNSATISFIABLE)");
+        assert_eq!(leaves(&compilation), [("pk(K)".to_owned(), 0)]);
+        assert!((compilation.leaves()[0].probability() - 1.0).abs() < 1e-9);
+        let compilation = compile("or(pk(A),or(pk(B),UNSATISFIABLE))");
+        assert_eq!(
+            leaves(&compilation),
+            [("pk(A)".to_owned(), 1), ("pk(B)".to_owned(), 1)]
+        );
+        let compilation = compile("thresh(2,pk(A),pk(B),UNSATISFIABLE)");
+        assert_eq!(leaves(&compilation), [("and(pk(A),pk(B))".to_owned(), 0)]);
+
+        // If nothing is left, the tree is a single unsatisfiable leaf.
+        let unsatisfiable = || vec![("UNSATISFIABLE".to_owned(), 0)];
+        assert_eq!(
+            leaves(&compile("or(UNSATISFIABLE,UNSATISFIABLE)")),
+            unsatisfiable()
+        );
+        assert_eq!(
+            leaves(&compile("thresh(2,pk(A),UNSATISFIABLE,UNSATISFIABLE)")),
+            unsatisfiable()
+        );
+        assert_eq!(
+            leaves(&compile("or(and(pk(A),UNSATISFIABLE),UNSATISFIABLE)")),
+            unsatisfiable()
+        );
+    }
+
+    #[test]
+    fn splitting_is_cheaper() {
+        let split = compile("or(pk(A),pk(B))");
+        assert_eq!(split.leaves().len(), 2);
+        let script = WeightedPolicy::<String>::from_str("or(pk(A),pk(B))")
+            .unwrap()
+            .compile_script(Model::get());
+        assert!(split.expected_weight() < script.stats.weight() + LEAF_WEIGHT);
+    }
+
+    #[test]
+    fn spend_info() {
+        let policy = Policy::<XOnlyPublicKey>::from_str(&format!(
+            "or(older(144),thresh(2,pk({0}),after(100),pk({0})))",
+            XOnlyPublicKey::from_slice(&constants::GENERATOR_X).unwrap(),
+        ))
+        .unwrap();
+        let compilation = WeightedPolicy::from(policy).compile().unwrap();
+        let key = XOnlyPublicKey::from_slice(&constants::GENERATOR_X).unwrap();
+        let spend_info = compilation.spend_info(key);
+        for leaf in compilation.leaves() {
+            let script = elements::Script::from(leaf.policy().cmr().as_ref().to_vec());
+            let control_block = spend_info.control_block(&(script, leaf_version())).unwrap();
+            assert_eq!(
+                control_block.merkle_branch.as_inner().len(),
+                leaf.depth() as usize
+            );
+        }
+    }
+}
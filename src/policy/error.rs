@@ -5,14 +5,49 @@ use std::fmt;
 pub enum Error {
     InvalidSequence,
     PublicKeyHash,
+    #[deprecated(since = "0.3.0", note = "the hash256 fragment is supported")]
     Sha256d,
+    #[deprecated(since = "0.3.0", note = "the ripemd160 fragment is supported")]
     Ripemd160,
+    #[deprecated(since = "0.3.0", note = "the multi fragment is supported")]
     Multisig,
     Extensions,
     CouldNotSatisfy,
+    /// The policy string ended in the middle of a fragment
+    UnexpectedEnd,
+    /// The policy string has an unexpected character at the given byte offset
+    UnexpectedChar {
+        position: usize,
+        ch: char,
+    },
+    /// The policy string has no fragment at the given byte offset
+    ExpectedFragment {
+        position: usize,
+    },
+    /// The policy string is nested too deeply
+    MaxDepthExceeded,
+    /// The compiled taproot tree is deeper than consensus allows
+    MaxTaprootDepthExceeded,
+    /// The fragment at the given byte offset has an unknown name
+    UnknownFragment {
+        position: usize,
+        name: String,
+    },
+    /// The fragment at the given byte offset has the wrong number of arguments
+    WrongArguments {
+        position: usize,
+        name: String,
+        expected: &'static str,
+    },
+    /// The argument at the given byte offset could not be parsed
+    BadArgument {
+        position: usize,
+        message: String,
+    },
 }
 
 impl fmt::Debug for Error {
+    #[allow(deprecated)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::InvalidSequence => writeln!(f, "Sequence does not encode relative locktime"),
@@ -22,6 +57,32 @@ impl fmt::Debug for Error {
             Error::Multisig => writeln!(f, "Multisig is not supported"),
             Error::Extensions => writeln!(f, "Extensions are not supported"),
             Error::CouldNotSatisfy => writeln!(f, "Could not satisfy the given policy"),
+            Error::UnexpectedEnd => writeln!(f, "Policy ends in the middle of a fragment"),
+            Error::UnexpectedChar { position, ch } => {
+                writeln!(f, "Unexpected character `{}` at byte {}", ch, position)
+            }
+            Error::ExpectedFragment { position } => {
+                writeln!(f, "Expected a fragment at byte {}", position)
+            }
+            Error::MaxDepthExceeded => writeln!(f, "Policy is nested too deeply"),
+            Error::MaxTaprootDepthExceeded => {
+                writeln!(f, "Compiled taproot tree is deeper than 128 levels")
+            }
+            Error::UnknownFragment { position, name } => {
+                writeln!(f, "Unknown fragment `{}` at byte {}", name, position)
+            }
+            Error::WrongArguments {
+                position,
+                name,
+                expected,
+            } => writeln!(
+                f,
+                "Fragment `{}` at byte {} takes {}",
+                name, position, expected
+            ),
+            Error::BadArgument { position, message } => {
+                writeln!(f, "Bad argument at byte {}: {}", position, message)
+            }
         }
     }
 }
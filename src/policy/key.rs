@@ -1,18 +1,31 @@
 // SPDX-License-Identifier: CC0-1.0
 
+use bitcoin_miniscript::hash256;
 use bitcoin_miniscript::{MiniscriptKey, ToPublicKey};
 use elements::bitcoin::key::XOnlyPublicKey;
-use hashes::sha256;
+use hashes::{hash160, ripemd160, sha256};
 use std::fmt::{Debug, Display};
 
 /// Public key which can be converted to a hash type.
 pub trait SimplicityKey: Clone + Eq + Ord + Debug + Display + std::hash::Hash {
     /// SHA 256 hash associated with this key, used in the sha256 fragment.
     type Sha256: Clone + Eq + Ord + Display + Debug + std::hash::Hash;
+
+    /// Double SHA 256 hash associated with this key, used in the hash256 fragment.
+    type Hash256: Clone + Eq + Ord + Display + Debug + std::hash::Hash;
+
+    /// RIPEMD160 hash associated with this key, used in the ripemd160 fragment.
+    type Ripemd160: Clone + Eq + Ord + Display + Debug + std::hash::Hash;
+
+    /// SHA 256 followed by RIPEMD160 hash associated with this key, used in the hash160 fragment.
+    type Hash160: Clone + Eq + Ord + Display + Debug + std::hash::Hash;
 }
 
 impl<Pk: MiniscriptKey> SimplicityKey for Pk {
     type Sha256 = <Pk as MiniscriptKey>::Sha256;
+    type Hash256 = <Pk as MiniscriptKey>::Hash256;
+    type Ripemd160 = <Pk as MiniscriptKey>::Ripemd160;
+    type Hash160 = <Pk as MiniscriptKey>::Hash160;
 }
 
 /// Public key which can be converted to a (x-only) public key which can be used in Simplicity.
@@ -22,6 +35,15 @@ pub trait ToXOnlyPubkey: SimplicityKey {
 
     /// Convert the generic associated [`SimplicityKey::Sha256`] to [`sha256::Hash`].
     fn to_sha256(hash: &Self::Sha256) -> sha256::Hash;
+
+    /// Convert the generic associated [`SimplicityKey::Hash256`] to [`hash256::Hash`].
+    fn to_hash256(hash: &Self::Hash256) -> hash256::Hash;
+
+    /// Convert the generic associated [`SimplicityKey::Ripemd160`] to [`ripemd160::Hash`].
+    fn to_ripemd160(hash: &Self::Ripemd160) -> ripemd160::Hash;
+
+    /// Convert the generic associated [`SimplicityKey::Hash160`] to [`hash160::Hash`].
+    fn to_hash160(hash: &Self::Hash160) -> hash160::Hash;
 }
 
 impl<Pk: ToPublicKey> ToXOnlyPubkey for Pk {
@@ -32,6 +54,18 @@ impl<Pk: ToPublicKey> ToXOnlyPubkey for Pk {
     fn to_sha256(hash: &Self::Sha256) -> sha256::Hash {
         <Pk as ToPublicKey>::to_sha256(hash)
     }
+
+    fn to_hash256(hash: &Self::Hash256) -> hash256::Hash {
+        <Pk as ToPublicKey>::to_hash256(hash)
+    }
+
+    fn to_ripemd160(hash: &Self::Ripemd160) -> ripemd160::Hash {
+        <Pk as ToPublicKey>::to_ripemd160(hash)
+    }
+
+    fn to_hash160(hash: &Self::Hash160) -> hash160::Hash {
+        <Pk as ToPublicKey>::to_hash160(hash)
+    }
 }
 
 /// Object which can translate one key type to another, including all associated hashes.
@@ -45,4 +79,13 @@ where
 
     /// Translates SHA 256 hashes `P::Sha256` → `Q::Sha256`.
     fn sha256(&mut self, sha256: &P::Sha256) -> Result<Q::Sha256, E>;
+
+    /// Translates double SHA 256 hashes `P::Hash256` → `Q::Hash256`.
+    fn hash256(&mut self, hash256: &P::Hash256) -> Result<Q::Hash256, E>;
+
+    /// Translates RIPEMD160 hashes `P::Ripemd160` → `Q::Ripemd160`.
+    fn ripemd160(&mut self, ripemd160: &P::Ripemd160) -> Result<Q::Ripemd160, E>;
+
+    /// Translates SHA 256 followed by RIPEMD160 hashes `P::Hash160` → `Q::Hash160`.
+    fn hash160(&mut self, hash160: &P::Hash160) -> Result<Q::Hash160, E>;
 }
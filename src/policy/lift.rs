@@ -0,0 +1,378 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Lifting Simplicity to Policies
+//!
+//! Programs are lifted by recognizing the fragments that are produced when a
+//! policy is serialized (see the `serialize` module). Any subexpression which
+//! is not one of these fragments is lifted to an assembly fragment, so that
+//! the CMR of the lifted policy is always the CMR of the program.
+
+use std::sync::Arc;
+
+use bitcoin_miniscript::hash256;
+use elements::bitcoin::key::XOnlyPublicKey;
+use hashes::{hash160, ripemd160, sha256, Hash};
+
+use crate::jet::Elements;
+use crate::node::Inner;
+use crate::policy::Policy;
+use crate::{Cmr, CommitNode};
+
+type Node = CommitNode<Elements>;
+
+impl Policy<XOnlyPublicKey> {
+    /// Lift a Simplicity program to the policy that it implements.
+    ///
+    /// Programs which are not of type `1 → 1` cannot be policies, and are
+    /// lifted as a whole to an assembly fragment.
+    pub fn lift(program: &CommitNode<Elements>) -> Self {
+        let arrow = program.arrow();
+        if arrow.source.is_unit() && arrow.target.is_unit() {
+            lift(program)
+        } else {
+            Policy::Assembly(program.cmr())
+        }
+    }
+}
+
+/// Lift a node of type `1 → 1`.
+fn lift(node: &Node) -> Policy<XOnlyPublicKey> {
+    let policy = match node.inner() {
+        Inner::Unit => Some(Policy::Trivial),
+        Inner::Fail(entropy) => Some(Policy::Unsatisfiable(*entropy)),
+        Inner::Comp(left, right) => key(left, right)
+            .or_else(|| after(left, right))
+            .or_else(|| older(left, right))
+            .or_else(|| sha256(left, right))
+            .or_else(|| hash256(left, right))
+            .or_else(|| ripemd160(left, right))
+            .or_else(|| or(left, right))
+            .or_else(|| multi(left, right))
+            .or_else(|| threshold(left, right))
+            .or_else(|| and(left, right)),
+        _ => None,
+    };
+    policy.unwrap_or_else(|| Policy::Assembly(node.cmr()))
+}
+
+/// Match `comp(pair(pair(const_word(key), jet_sig_all_hash), witness), jet_bip_0340_verify)`.
+fn key(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    is_jet(right, Elements::Bip0340Verify)?;
+    let (key_msg, witness) = pair(left)?;
+    is_witness(witness)?;
+    let (key, msg) = pair(key_msg)?;
+    is_jet(msg, Elements::SigAllHash)?;
+    let key = XOnlyPublicKey::from_slice(&word::<32>(key)?).ok()?;
+    Some(Policy::Key(key))
+}
+
+/// Match `comp(const_word(n), jet_check_lock_height)`.
+fn after(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    is_jet(right, Elements::CheckLockHeight)?;
+    Some(Policy::After(u32::from_be_bytes(word(left)?)))
+}
+
+/// Match `comp(const_word(n), jet_check_lock_distance)`.
+fn older(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    is_jet(right, Elements::CheckLockDistance)?;
+    Some(Policy::Older(u16::from_be_bytes(word(left)?)))
+}
+
+/// Match `verify_bexp(pair(const_word(hash), compute_sha256(witness)), jet_eq_256)`.
+fn sha256(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    let (hash, computed_hash) = verify_bexp(left, right, Elements::Eq256)?;
+    let hash = sha256::Hash::from_byte_array(word(hash)?);
+    is_witness(compute_sha256(computed_hash)?)?;
+    Some(Policy::Sha256(hash))
+}
+
+/// Match `verify_bexp(pair(const_word(hash), compute_sha256(compute_sha256(witness))), jet_eq_256)`.
+fn hash256(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    let (hash, computed_hash) = verify_bexp(left, right, Elements::Eq256)?;
+    let hash = hash256::Hash::from_byte_array(word(hash)?);
+    is_witness(compute_sha256(compute_sha256(computed_hash)?)?)?;
+    Some(Policy::Hash256(hash))
+}
+
+/// Match `verify_bexp(pair(const_word(hash), compute_ripemd160(input)), jet_eq_256)`,
+/// where the input is the witness for `ripemd160` and `compute_sha256(witness)`
+/// for `hash160`.
+fn ripemd160(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    let (hash, _) = verify_bexp(left, right, Elements::Eq256)?;
+    let hash = word::<32>(hash)?;
+    (hash[20..] == [0; 12]).then_some(())?;
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(hash.chunks_exact(4)) {
+        chunk.copy_from_slice(&[word[3], word[2], word[1], word[0]]);
+    }
+
+    // The hash is computed without a jet, so the computation is not matched
+    // node by node. The fragment is only lifted if it is exactly the fragment
+    // of the policy.
+    let cmr = Cmr::comp(left.cmr(), right.cmr());
+    [
+        Policy::Ripemd160(ripemd160::Hash::from_byte_array(digest)),
+        Policy::Hash160(hash160::Hash::from_byte_array(digest)),
+    ]
+    .into_iter()
+    .find(|policy| policy.cmr() == cmr)
+}
+
+/// Match `compute_sha256(input)` and return the input.
+fn compute_sha256(node: &Node) -> Option<&Node> {
+    let (digest_ctx, finalize) = comp(node)?;
+    is_jet(finalize, Elements::Sha256Ctx8Finalize)?;
+    let (ctx_input, add) = comp(digest_ctx)?;
+    is_jet(add, Elements::Sha256Ctx8Add32)?;
+    let (ctx, input) = pair(ctx_input)?;
+    is_jet(ctx, Elements::Sha256Ctx8Init)?;
+    Some(input)
+}
+
+/// Match `comp(selector, case(drop(left), drop(right)))`.
+fn or(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    selector(left)?;
+    let (drop_left, drop_right) = case(right)?;
+    Some(Policy::Or {
+        left: Arc::new(lift(drop(drop_left)?)),
+        right: Arc::new(lift(drop(drop_right)?)),
+    })
+}
+
+/// Match `verify_bexp(pair(const_word(k), sum), jet_eq_32)`, where the sum
+/// is a summand or `thresh_add(sum, summand)`.
+fn threshold(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    let (k, mut sum) = verify_bexp(left, right, Elements::Eq32)?;
+    let k = u32::from_be_bytes(word(k)?) as usize;
+
+    let mut subs = vec![];
+    // Summands are added from left to right, so they are found in reverse.
+    loop {
+        if let Some(child) = thresh_summand(sum) {
+            subs.push(lift(child));
+            break;
+        }
+        let (full_sum, drop_iden) = comp(sum)?;
+        matches!(drop(drop_iden)?.inner(), Inner::Iden).then_some(())?;
+        let (sum_summand, add) = comp(full_sum)?;
+        is_jet(add, Elements::Add32)?;
+        let (next_sum, summand) = pair(sum_summand)?;
+        subs.push(lift(thresh_summand(summand)?));
+        sum = next_sum;
+    }
+    subs.reverse();
+
+    // Thresholds of more than their number of sub-policies cannot be
+    // serialized, so they are not lifted.
+    (k <= subs.len()).then_some(Policy::Threshold(k, subs))
+}
+
+/// Match `thresh_verify(count, k)`, where the count is `const_word(0)`
+/// followed by a `multi_step` for each key.
+fn multi(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    let (k, mut count) = verify_bexp(left, right, Elements::Eq32)?;
+    let k = u32::from_be_bytes(word(k)?) as usize;
+
+    let mut keys = vec![];
+    // Steps are composed from left to right, so they are found in reverse.
+    while let Some((prev_count, step)) = comp(count) {
+        keys.push(multi_key(step)?);
+        count = prev_count;
+    }
+    keys.reverse();
+    (word::<4>(count)? == [0; 4] && k <= keys.len()).then_some(())?;
+
+    // The keys are found without matching the rest of each step, so the
+    // fragment is only lifted if it is exactly the fragment of the policy.
+    let policy = Policy::Multi(k, keys);
+    (policy.cmr() == Cmr::comp(left.cmr(), right.cmr())).then_some(policy)
+}
+
+/// Match the key of `comp(pair(witness, iden), case(drop(iden), multi_verify(key)))`.
+fn multi_key(step: &Node) -> Option<XOnlyPublicKey> {
+    let (_, skip_or_verify) = comp(step)?;
+    let (_, verify) = case(skip_or_verify)?;
+    let (pair_verify_count, _) = comp(verify)?;
+    let (verify_sig, _) = pair(pair_verify_count)?;
+    let (key_msg_sig, _) = comp(verify_sig)?;
+    let (scribe_key_msg, _) = pair(key_msg_sig)?;
+    let (_, key_msg) = comp(scribe_key_msg)?;
+    let (key, _) = pair(key_msg)?;
+    XOnlyPublicKey::from_slice(&word::<32>(key)?).ok()
+}
+
+/// Match `comp(selector, case(drop(const_word(0)), drop(comp(child, const_word(1)))))`.
+fn thresh_summand(node: &Node) -> Option<&Node> {
+    let (selector_, child_one_or_zero) = comp(node)?;
+    selector(selector_)?;
+    let (drop_zero, drop_one) = case(child_one_or_zero)?;
+    (word::<4>(drop(drop_zero)?)? == [0; 4]).then_some(())?;
+    let (child, one) = comp(drop(drop_one)?)?;
+    (word::<4>(one)? == [0, 0, 0, 1]).then_some(())?;
+    Some(child)
+}
+
+/// Match `comp(left, right)` where both sides are of type `1 → 1`.
+fn and(left: &Node, right: &Node) -> Option<Policy<XOnlyPublicKey>> {
+    left.arrow().target.is_unit().then_some(())?;
+    Some(Policy::And {
+        left: Arc::new(lift(left)),
+        right: Arc::new(lift(right)),
+    })
+}
+
+/// Match `comp(comp(input, bexp), jet_verify)` for the given `bexp` jet, where
+/// the input is a pair, and return the sides of the pair.
+fn verify_bexp<'a>(left: &'a Node, right: &Node, bexp: Elements) -> Option<(&'a Node, &'a Node)> {
+    is_jet(right, Elements::Verify)?;
+    let (input, jet) = comp(left)?;
+    is_jet(jet, bexp)?;
+    pair(input)
+}
+
+/// Match `pair(witness, unit)`.
+fn selector(node: &Node) -> Option<()> {
+    let (witness, unit) = pair(node)?;
+    is_witness(witness)?;
+    matches!(unit.inner(), Inner::Unit).then_some(())
+}
+
+fn comp(node: &Node) -> Option<(&Node, &Node)> {
+    match node.inner() {
+        Inner::Comp(left, right) => Some((left, right)),
+        _ => None,
+    }
+}
+
+fn pair(node: &Node) -> Option<(&Node, &Node)> {
+    match node.inner() {
+        Inner::Pair(left, right) => Some((left, right)),
+        _ => None,
+    }
+}
+
+fn case(node: &Node) -> Option<(&Node, &Node)> {
+    match node.inner() {
+        Inner::Case(left, right) => Some((left, right)),
+        _ => None,
+    }
+}
+
+fn drop(node: &Node) -> Option<&Node> {
+    match node.inner() {
+        Inner::Drop(child) => Some(child),
+        _ => None,
+    }
+}
+
+fn is_jet(node: &Node, jet: Elements) -> Option<()> {
+    matches!(node.inner(), Inner::Jet(j) if *j == jet).then_some(())
+}
+
+fn is_witness(node: &Node) -> Option<()> {
+    matches!(node.inner(), Inner::Witness(_)).then_some(())
+}
+
+/// Match a constant word of `N` bytes and return its bytes in big-endian order.
+fn word<const N: usize>(node: &Node) -> Option<[u8; N]> {
+    match node.inner() {
+        Inner::Word(word) if word.len() == 8 * N => {
+            let mut bytes = [0; N];
+            for (byte, value) in bytes.iter_mut().zip(word.as_value().raw_byte_iter()) {
+                *byte = value;
+            }
+            Some(bytes)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::CoreConstructible;
+    use crate::policy::serialize;
+    use crate::value::Word;
+    use crate::{types, ConstructNode, FailEntropy};
+    use std::str::FromStr;
+
+    /// The x-coordinates of the generator and its double
+    const KEY_A: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const KEY_B: &str = "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    fn policy(s: &str) -> Policy<XOnlyPublicKey> {
+        let s = s
+            .replace("pk(A)", &format!("pk({})", KEY_A))
+            .replace("pk(B)", &format!("pk({})", KEY_B))
+            .replace(",A", &format!(",{}", KEY_A))
+            .replace(",B", &format!(",{}", KEY_B));
+        Policy::from_str(&s).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let hash = sha256::Hash::hash(b"preimage");
+        let hash256 = hash256::Hash::hash(b"preimage");
+        for s in [
+            "UNSATISFIABLE".to_owned(),
+            "TRIVIAL".to_owned(),
+            "pk(A)".to_owned(),
+            "after(500000)".to_owned(),
+            "older(144)".to_owned(),
+            format!("sha256({})", hash),
+            format!("hash256({})", hash256),
+            format!("ripemd160({})", ripemd160::Hash::hash(b"preimage")),
+            format!("hash160({})", hash160::Hash::hash(b"preimage")),
+            "multi(1,A)".to_owned(),
+            "multi(0,B,A)".to_owned(),
+            "multi(2,A,B,A)".to_owned(),
+            format!("or(multi(2,A,B),and(pk(A),hash256({})))", hash256),
+            "and(pk(A),older(144))".to_owned(),
+            "and(TRIVIAL,and(after(1),TRIVIAL))".to_owned(),
+            "or(pk(A),and(pk(B),after(100)))".to_owned(),
+            "thresh(1,pk(A))".to_owned(),
+            "thresh(2,pk(A),pk(B),or(older(1),UNSATISFIABLE))".to_owned(),
+            "or(thresh(0,TRIVIAL,pk(B)),thresh(3,after(7),pk(A),older(9)))".to_owned(),
+        ] {
+            let policy = policy(&s);
+            let lifted = Policy::lift(&policy.commit().unwrap());
+            assert_eq!(lifted, policy, "{}", s);
+        }
+
+        let entropy = FailEntropy::from_byte_array([0xab; 64]);
+        let policy = Policy::<XOnlyPublicKey>::Unsatisfiable(entropy);
+        assert_eq!(Policy::lift(&policy.commit().unwrap()), policy);
+    }
+
+    #[test]
+    fn unknown_subtrees() {
+        let ctx = types::Context::new();
+        // iden : 1 → 1 is not a policy fragment
+        let unknown = Arc::<ConstructNode<Elements>>::iden(&ctx);
+
+        let program = unknown.finalize_types().unwrap();
+        assert_eq!(Policy::lift(&program), Policy::Assembly(unknown.cmr()));
+
+        let older: Arc<ConstructNode<Elements>> = serialize::older(&ctx, 144);
+        let program = serialize::and(&older, &unknown).finalize_types().unwrap();
+        let lifted = Policy::lift(&program);
+        assert_eq!(
+            lifted.to_string(),
+            format!("and(older(144),asm({}))", unknown.cmr()),
+        );
+        assert_eq!(lifted.cmr(), program.cmr());
+    }
+
+    #[test]
+    fn not_a_policy() {
+        // comp(const_word(7), iden) : 1 → 2^32 is not of type 1 → 1
+        let ctx = types::Context::new();
+        let word = Arc::<ConstructNode<Elements>>::const_word(&ctx, Word::u32(7));
+        let iden = Arc::<ConstructNode<Elements>>::iden(&ctx);
+        let program = Arc::<ConstructNode<Elements>>::comp(&word, &iden)
+            .unwrap()
+            .finalize_types_non_program()
+            .unwrap();
+        assert_eq!(Policy::lift(&program), Policy::Assembly(program.cmr()));
+    }
+}
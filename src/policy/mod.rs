@@ -7,20 +7,25 @@
 //! # Simplicity Script Policies
 //!
 //! Tools for representing simplicity programs as spending policies.
-//! These may be compiled to Simplicity programs.
+//! These may be compiled to Simplicity programs, and Simplicity programs may
+//! be lifted back to policies.
 //!
 //! The format represents public keys abstractly to allow wallets to replace
 //! these with BIP32 paths, pay-to-contract instructions, etc.
 //!
 
 mod ast;
+mod compiler;
 mod error;
 mod key;
+mod lift;
+mod parse;
 mod satisfy;
 mod serialize;
 pub mod sighash;
 
 pub use ast::Policy;
+pub use compiler::{Compilation, TapLeaf, WeightedPolicy};
 pub use error::Error;
 pub use key::{SimplicityKey, ToXOnlyPubkey, Translator};
 pub use satisfy::{Preimage32, Satisfier, SatisfierError};
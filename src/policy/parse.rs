@@ -0,0 +1,578 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Parsing Policies
+//!
+//! Policies are parsed from the format that their `Display` implementation
+//! emits, which is the format of miniscript policies: each fragment is either
+//! a name such as `TRIVIAL` or a call such as `and(pk(A),older(144))`.
+//! Whitespace is allowed around fragments and their arguments.
+//!
+//! Hash locks may use `sha256`, `hash256`, `ripemd160` or `hash160`.
+//!
+//! Weighted policies additionally allow the branches of `or` to be prefixed
+//! with their relative probability, as in `or(9@pk(A),1@pk(B))`, and allow
+//! `or` to have more than two branches.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::policy::{Error, Policy, SimplicityKey, WeightedPolicy};
+use crate::{Cmr, FailEntropy};
+
+/// Maximum nesting depth of a policy string, so that parsing cannot exhaust
+/// the stack.
+const MAX_DEPTH: usize = 256;
+
+/// An expression of the form `name` or `name(arg,...)`, which may be
+/// prefixed with `weight@`
+struct Tree<'a> {
+    name: &'a str,
+    /// Byte offset of the name in the policy string
+    position: usize,
+    /// Byte offset and text of the weight, up to the `@`
+    weight: Option<(usize, &'a str)>,
+    args: Vec<Tree<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    fn parse(s: &'a str) -> Result<Self, Error> {
+        let (tree, end) = Self::parse_at(s, 0, 0)?;
+        match s[end..].chars().next() {
+            None => Ok(tree),
+            Some(ch) => Err(Error::UnexpectedChar { position: end, ch }),
+        }
+    }
+
+    /// Parses the expression starting at byte `start`, returning it along with
+    /// the position just after it and any whitespace which follows.
+    fn parse_at(s: &'a str, start: usize, depth: usize) -> Result<(Self, usize), Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::MaxDepthExceeded);
+        }
+
+        let mut position = skip_whitespace(s, start);
+        let len = s[position..]
+            .find(['(', ')', ','])
+            .unwrap_or(s.len() - position);
+        let end = position + len;
+
+        let mut weight = None;
+        if let Some(at) = s[position..end].find('@') {
+            weight = Some((position, &s[position..position + at]));
+            position = skip_whitespace(s, position + at + 1);
+        }
+        let name = s[position..end].trim_end();
+        if name.is_empty() {
+            return Err(Error::ExpectedFragment { position });
+        }
+
+        let mut tree = Tree {
+            name,
+            position,
+            weight,
+            args: vec![],
+        };
+        let mut pos = end;
+        if s[pos..].starts_with('(') {
+            loop {
+                let (arg, end) = Self::parse_at(s, pos + 1, depth + 1)?;
+                tree.args.push(arg);
+                pos = end;
+                match s[pos..].chars().next() {
+                    Some(',') => {}
+                    Some(')') => break,
+                    Some(ch) => return Err(Error::UnexpectedChar { position: pos, ch }),
+                    None => return Err(Error::UnexpectedEnd),
+                }
+            }
+            pos = skip_whitespace(s, pos + 1);
+        }
+        Ok((tree, pos))
+    }
+
+    /// Parses an argument which is a single value.
+    fn value<T>(&self) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.check_unweighted()?;
+        if !self.args.is_empty() {
+            return Err(Error::BadArgument {
+                position: self.position,
+                message: format!("expected a value but found fragment `{}`", self.name),
+            });
+        }
+        T::from_str(self.name).map_err(|e| Error::BadArgument {
+            position: self.position,
+            message: format!("`{}`: {}", self.name, e),
+        })
+    }
+
+    /// Parses an argument which is a number written in decimal.
+    fn number<T>(&self) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        if !self.name.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::BadArgument {
+                position: self.position,
+                message: format!("`{}` is not a number", self.name),
+            });
+        }
+        self.value()
+    }
+
+    /// Parses the relative probability of a branch of `or`.
+    fn probability(&self) -> Result<usize, Error> {
+        let (position, weight) = match self.weight {
+            Some((position, weight)) => (position, weight.trim_end()),
+            None => return Ok(1),
+        };
+        let digits = weight.bytes().all(|b| b.is_ascii_digit());
+        match weight.parse() {
+            Ok(weight) if digits && weight > 0 => Ok(weight),
+            _ => Err(Error::BadArgument {
+                position,
+                message: format!("`{}` is not a positive probability", weight),
+            }),
+        }
+    }
+
+    /// Checks that the expression has no weight, which only branches of `or`
+    /// in weighted policies may have.
+    fn check_unweighted(&self) -> Result<(), Error> {
+        match self.weight {
+            Some((position, weight)) => Err(Error::UnexpectedChar {
+                position: position + weight.len(),
+                ch: '@',
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Parses the threshold `k` of a `thresh` or `multi` fragment, whose
+    /// other arguments are the given kind of item.
+    fn threshold(&self, items: &str) -> Result<usize, Error> {
+        let n = self.args.len() - 1;
+        let k = self.args[0].number()?;
+        if k > n {
+            return Err(Error::BadArgument {
+                position: self.args[0].position,
+                message: format!("threshold {} is more than {} {}", k, n, items),
+            });
+        }
+        Ok(k)
+    }
+
+    fn to_policy<Pk>(&self) -> Result<Policy<Pk>, Error>
+    where
+        Pk: SimplicityKey + FromStr,
+        <Pk as FromStr>::Err: fmt::Display,
+        Pk::Sha256: FromStr,
+        <Pk::Sha256 as FromStr>::Err: fmt::Display,
+        Pk::Hash256: FromStr,
+        <Pk::Hash256 as FromStr>::Err: fmt::Display,
+        Pk::Ripemd160: FromStr,
+        <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+        Pk::Hash160: FromStr,
+        <Pk::Hash160 as FromStr>::Err: fmt::Display,
+    {
+        self.check_unweighted()?;
+        self.to_fragment()
+    }
+
+    /// Parses a policy, ignoring the weight of the expression.
+    fn to_fragment<Pk>(&self) -> Result<Policy<Pk>, Error>
+    where
+        Pk: SimplicityKey + FromStr,
+        <Pk as FromStr>::Err: fmt::Display,
+        Pk::Sha256: FromStr,
+        <Pk::Sha256 as FromStr>::Err: fmt::Display,
+        Pk::Hash256: FromStr,
+        <Pk::Hash256 as FromStr>::Err: fmt::Display,
+        Pk::Ripemd160: FromStr,
+        <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+        Pk::Hash160: FromStr,
+        <Pk::Hash160 as FromStr>::Err: fmt::Display,
+    {
+        let args = &self.args;
+        match (self.name, args.len()) {
+            ("UNSATISFIABLE", 0) => Ok(Policy::Unsatisfiable(FailEntropy::ZERO)),
+            ("TRIVIAL", 0) => Ok(Policy::Trivial),
+            ("pk", 1) => args[0].value().map(Policy::Key),
+            ("after", 1) => args[0].number().map(Policy::After),
+            ("older", 1) => args[0].number().map(Policy::Older),
+            ("sha256", 1) => args[0].value().map(Policy::Sha256),
+            ("hash256", 1) => args[0].value().map(Policy::Hash256),
+            ("ripemd160", 1) => args[0].value().map(Policy::Ripemd160),
+            ("hash160", 1) => args[0].value().map(Policy::Hash160),
+            ("and", 2) => Ok(Policy::And {
+                left: Arc::new(args[0].to_policy()?),
+                right: Arc::new(args[1].to_policy()?),
+            }),
+            ("or", 2) => Ok(Policy::Or {
+                left: Arc::new(args[0].to_policy()?),
+                right: Arc::new(args[1].to_policy()?),
+            }),
+            ("thresh", n) if n > 1 => {
+                let k = self.threshold("sub-policies")?;
+                let subs = args[1..]
+                    .iter()
+                    .map(Tree::to_policy)
+                    .collect::<Result<_, _>>()?;
+                Ok(Policy::Threshold(k, subs))
+            }
+            ("multi", n) if n > 1 => {
+                let k = self.threshold("keys")?;
+                let keys = args[1..]
+                    .iter()
+                    .map(Tree::value)
+                    .collect::<Result<_, _>>()?;
+                Ok(Policy::Multi(k, keys))
+            }
+            ("asm", 1) => args[0].value::<Cmr>().map(Policy::Assembly),
+            ("UNSATISFIABLE" | "TRIVIAL", _) => Err(self.wrong_arguments("no arguments")),
+            (
+                "pk" | "after" | "older" | "sha256" | "hash256" | "ripemd160" | "hash160" | "asm",
+                _,
+            ) => Err(self.wrong_arguments("one argument")),
+            ("and" | "or", _) => Err(self.wrong_arguments("two arguments")),
+            ("thresh", _) => Err(self.wrong_arguments("a threshold and sub-policies")),
+            ("multi", _) => Err(self.wrong_arguments("a threshold and keys")),
+            (name, _) => Err(Error::UnknownFragment {
+                position: self.position,
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    fn to_weighted<Pk>(&self) -> Result<WeightedPolicy<Pk>, Error>
+    where
+        Pk: SimplicityKey + FromStr,
+        <Pk as FromStr>::Err: fmt::Display,
+        Pk::Sha256: FromStr,
+        <Pk::Sha256 as FromStr>::Err: fmt::Display,
+        Pk::Hash256: FromStr,
+        <Pk::Hash256 as FromStr>::Err: fmt::Display,
+        Pk::Ripemd160: FromStr,
+        <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+        Pk::Hash160: FromStr,
+        <Pk::Hash160 as FromStr>::Err: fmt::Display,
+    {
+        self.check_unweighted()?;
+        self.to_weighted_fragment()
+    }
+
+    /// Parses a weighted policy, ignoring the weight of the expression.
+    fn to_weighted_fragment<Pk>(&self) -> Result<WeightedPolicy<Pk>, Error>
+    where
+        Pk: SimplicityKey + FromStr,
+        <Pk as FromStr>::Err: fmt::Display,
+        Pk::Sha256: FromStr,
+        <Pk::Sha256 as FromStr>::Err: fmt::Display,
+        Pk::Hash256: FromStr,
+        <Pk::Hash256 as FromStr>::Err: fmt::Display,
+        Pk::Ripemd160: FromStr,
+        <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+        Pk::Hash160: FromStr,
+        <Pk::Hash160 as FromStr>::Err: fmt::Display,
+    {
+        let args = &self.args;
+        match (self.name, args.len()) {
+            ("and", 2) => Ok(WeightedPolicy::And {
+                left: Arc::new(args[0].to_weighted()?),
+                right: Arc::new(args[1].to_weighted()?),
+            }),
+            ("or", n) if n > 1 => {
+                let branches = args
+                    .iter()
+                    .map(|arg| Ok((arg.probability()?, arg.to_weighted_fragment()?)))
+                    .collect::<Result<_, Error>>()?;
+                Ok(WeightedPolicy::Or(branches))
+            }
+            ("or", _) => Err(self.wrong_arguments("two or more arguments")),
+            ("thresh", n) if n > 1 => {
+                let k = self.threshold("sub-policies")?;
+                let subs = args[1..]
+                    .iter()
+                    .map(Tree::to_weighted)
+                    .collect::<Result<_, _>>()?;
+                Ok(WeightedPolicy::Threshold(k, subs))
+            }
+            _ => self.to_fragment().map(WeightedPolicy::Unweighted),
+        }
+    }
+
+    fn wrong_arguments(&self, expected: &'static str) -> Error {
+        Error::WrongArguments {
+            position: self.position,
+            name: self.name.to_owned(),
+            expected,
+        }
+    }
+}
+
+/// Returns the position of the first character at or after `pos` which is not
+/// whitespace.
+fn skip_whitespace(s: &str, pos: usize) -> usize {
+    s.len() - s[pos..].trim_start().len()
+}
+
+/// Parses a policy from the format emitted by its `Display` implementation.
+///
+/// `UNSATISFIABLE` is parsed with zero entropy, since the entropy of an
+/// unsatisfiable policy is not displayed.
+impl<Pk> FromStr for Policy<Pk>
+where
+    Pk: SimplicityKey + FromStr,
+    <Pk as FromStr>::Err: fmt::Display,
+    Pk::Sha256: FromStr,
+    <Pk::Sha256 as FromStr>::Err: fmt::Display,
+    Pk::Hash256: FromStr,
+    <Pk::Hash256 as FromStr>::Err: fmt::Display,
+    Pk::Ripemd160: FromStr,
+    <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+    Pk::Hash160: FromStr,
+    <Pk::Hash160 as FromStr>::Err: fmt::Display,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Tree::parse(s)?.to_policy()
+    }
+}
+
+/// Parses a weighted policy, in which the branches of `or` may be prefixed
+/// with their relative probability.
+impl<Pk> FromStr for WeightedPolicy<Pk>
+where
+    Pk: SimplicityKey + FromStr,
+    <Pk as FromStr>::Err: fmt::Display,
+    Pk::Sha256: FromStr,
+    <Pk::Sha256 as FromStr>::Err: fmt::Display,
+    Pk::Hash256: FromStr,
+    <Pk::Hash256 as FromStr>::Err: fmt::Display,
+    Pk::Ripemd160: FromStr,
+    <Pk::Ripemd160 as FromStr>::Err: fmt::Display,
+    Pk::Hash160: FromStr,
+    <Pk::Hash160 as FromStr>::Err: fmt::Display,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Tree::parse(s)?.to_weighted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin_miniscript::hash256;
+    use elements::bitcoin::key::XOnlyPublicKey;
+    use elements::secp256k1_zkp::rand::{self, Rng};
+    use hashes::{hash160, ripemd160, sha256, Hash};
+
+    /// Generates a random policy with at most the given depth, using `key` to
+    /// generate keys and `hash` to generate SHA256, HASH256, RIPEMD160 and
+    /// HASH160 hashes.
+    fn random_policy<Pk, R, K, H>(rng: &mut R, depth: usize, key: &K, hash: &H) -> Policy<Pk>
+    where
+        Pk: SimplicityKey,
+        R: Rng,
+        K: Fn(&mut R) -> Pk,
+        H: Fn(&mut R) -> (Pk::Sha256, Pk::Hash256, Pk::Ripemd160, Pk::Hash160),
+    {
+        let leaves = 11;
+        let choice = if depth == 0 {
+            rng.gen_range(0..leaves)
+        } else {
+            rng.gen_range(0..leaves + 3)
+        };
+        match choice {
+            0 => Policy::Unsatisfiable(FailEntropy::ZERO),
+            1 => Policy::Trivial,
+            2 => Policy::Key(key(rng)),
+            3 => Policy::After(rng.gen()),
+            4 => Policy::Older(rng.gen()),
+            5 => Policy::Sha256(hash(rng).0),
+            6 => Policy::Hash256(hash(rng).1),
+            7 => Policy::Ripemd160(hash(rng).2),
+            8 => Policy::Hash160(hash(rng).3),
+            9 => Policy::Assembly(Cmr::from_byte_array(rng.gen())),
+            10 => {
+                let n = rng.gen_range(1..5);
+                let keys = (0..n).map(|_| key(rng)).collect();
+                Policy::Multi(rng.gen_range(0..=n), keys)
+            }
+            11 => Policy::And {
+                left: Arc::new(random_policy(rng, depth - 1, key, hash)),
+                right: Arc::new(random_policy(rng, depth - 1, key, hash)),
+            },
+            12 => Policy::Or {
+                left: Arc::new(random_policy(rng, depth - 1, key, hash)),
+                right: Arc::new(random_policy(rng, depth - 1, key, hash)),
+            },
+            _ => {
+                let n = rng.gen_range(1..5);
+                let subs = (0..n)
+                    .map(|_| random_policy(rng, depth - 1, key, hash))
+                    .collect();
+                Policy::Threshold(rng.gen_range(0..=n), subs)
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_string_keys() {
+        let mut rng = rand::thread_rng();
+        let key = |rng: &mut rand::rngs::ThreadRng| format!("key{}", rng.gen_range(0..10));
+        let hash = |rng: &mut rand::rngs::ThreadRng| {
+            let hash = format!("H{}", rng.gen_range(0..10));
+            (hash.clone(), hash.clone(), hash.clone(), hash)
+        };
+        for _ in 0..500 {
+            let policy = random_policy::<String, _, _, _>(&mut rng, 4, &key, &hash);
+            let s = policy.to_string();
+            assert_eq!(Policy::<String>::from_str(&s).unwrap(), policy, "{}", s);
+        }
+    }
+
+    #[test]
+    fn round_trip_xonly_keys() {
+        let mut rng = rand::thread_rng();
+        let secp = elements::secp256k1_zkp::Secp256k1::new();
+        let keys = (0..4)
+            .map(|_| {
+                elements::secp256k1_zkp::Keypair::new(&secp, &mut rng)
+                    .x_only_public_key()
+                    .0
+            })
+            .collect::<Vec<_>>();
+        let key = |rng: &mut rand::rngs::ThreadRng| keys[rng.gen_range(0..keys.len())];
+        let hash = |rng: &mut rand::rngs::ThreadRng| {
+            let preimage = [rng.gen()];
+            (
+                sha256::Hash::hash(&preimage),
+                hash256::Hash::hash(&preimage),
+                ripemd160::Hash::hash(&preimage),
+                hash160::Hash::hash(&preimage),
+            )
+        };
+        for _ in 0..200 {
+            let policy = random_policy::<XOnlyPublicKey, _, _, _>(&mut rng, 4, &key, &hash);
+            let s = policy.to_string();
+            assert_eq!(Policy::from_str(&s).unwrap(), policy, "{}", s);
+        }
+    }
+
+    #[test]
+    fn whitespace() {
+        let policy = Policy::<String>::from_str(" and( pk(A) ,\n thresh(1, older(144),TRIVIAL) ) ");
+        assert_eq!(
+            policy.unwrap().to_string(),
+            "and(pk(A),thresh(1,older(144),TRIVIAL))"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |s: &str| Policy::<String>::from_str(s).unwrap_err();
+        assert!(matches!(parse(""), Error::ExpectedFragment { position: 0 }));
+        assert!(matches!(
+            parse("and(pk(A),)"),
+            Error::ExpectedFragment { position: 10 }
+        ));
+        assert!(matches!(parse("and(pk(A)"), Error::UnexpectedEnd));
+        assert!(matches!(
+            parse("pk(A))"),
+            Error::UnexpectedChar {
+                position: 5,
+                ch: ')'
+            }
+        ));
+        assert!(matches!(
+            parse("or(pk(A),nope)"),
+            Error::UnknownFragment { position: 9, .. }
+        ));
+        assert!(matches!(
+            parse("and(pk(A))"),
+            Error::WrongArguments { position: 0, .. }
+        ));
+        assert!(matches!(
+            parse("after(-1)"),
+            Error::BadArgument { position: 6, .. }
+        ));
+        assert!(matches!(parse("older(65536)"), Error::BadArgument { .. }));
+        assert!(matches!(parse("pk(pk(A))"), Error::BadArgument { .. }));
+        assert!(matches!(parse("asm(00)"), Error::BadArgument { .. }));
+        assert!(matches!(
+            parse("thresh(3,pk(A),pk(B))"),
+            Error::BadArgument { position: 7, .. }
+        ));
+        assert!(matches!(
+            parse("multi(3,A,B)"),
+            Error::BadArgument { position: 6, .. }
+        ));
+        assert!(matches!(parse("multi(1)"), Error::WrongArguments { .. }));
+        assert!(matches!(parse("multi(1,pk(A))"), Error::BadArgument { .. }));
+        assert!(matches!(
+            parse("ripemd160(H,H)"),
+            Error::WrongArguments { position: 0, .. }
+        ));
+        assert!(matches!(parse("hash160"), Error::WrongArguments { .. }));
+        let deep = "and(TRIVIAL,".repeat(1000) + "TRIVIAL" + &")".repeat(1000);
+        assert!(matches!(parse(&deep), Error::MaxDepthExceeded));
+    }
+
+    #[test]
+    fn weighted() {
+        let parse = |s: &str| WeightedPolicy::<String>::from_str(s);
+        let policy = parse("and(pk(A),or( 9 @pk(B),1@ or(pk(C),older(6),3@TRIVIAL)))").unwrap();
+        assert_eq!(
+            policy.to_string(),
+            "and(pk(A),or(9@pk(B),or(pk(C),older(6),3@TRIVIAL)))"
+        );
+        assert_eq!(parse(&policy.to_string()).unwrap(), policy);
+
+        let unweighted = "thresh(1,pk(A),or(pk(B),TRIVIAL))";
+        assert_eq!(
+            parse(unweighted).unwrap(),
+            WeightedPolicy::from(Policy::from_str(unweighted).unwrap()),
+        );
+
+        let error = |s: &str| parse(s).unwrap_err();
+        assert!(matches!(
+            error("and(1@pk(A),pk(B))"),
+            Error::UnexpectedChar {
+                position: 5,
+                ch: '@'
+            }
+        ));
+        assert!(matches!(
+            error("or(1@pk(1@A),pk(B))"),
+            Error::UnexpectedChar {
+                position: 9,
+                ch: '@'
+            }
+        ));
+        assert!(matches!(
+            error("or(0@pk(A),pk(B))"),
+            Error::BadArgument { position: 3, .. }
+        ));
+        assert!(matches!(
+            error("or(x@pk(A),pk(B))"),
+            Error::BadArgument { position: 3, .. }
+        ));
+        assert!(matches!(error("or(pk(A))"), Error::WrongArguments { .. }));
+        assert!(matches!(
+            Policy::<String>::from_str("or(9@pk(A),pk(B))").unwrap_err(),
+            Error::UnexpectedChar {
+                position: 4,
+                ch: '@'
+            }
+        ));
+    }
+}
@@ -4,7 +4,7 @@ use crate::analysis::Cost;
 use crate::jet::Elements;
 use crate::node::{ConstructNode, Hiding, RedeemNode};
 use crate::policy::ToXOnlyPubkey;
-use crate::types;
+use crate::types::{self, Final};
 use crate::{Cmr, Policy, Value};
 
 use elements::bitcoin;
@@ -35,6 +35,21 @@ pub trait Satisfier<Pk: ToXOnlyPubkey> {
         None
     }
 
+    /// Given a double SHA256 hash, look up its preimage.
+    fn lookup_hash256(&self, _: &Pk::Hash256) -> Option<Preimage32> {
+        None
+    }
+
+    /// Given a RIPEMD160 hash, look up its preimage.
+    fn lookup_ripemd160(&self, _: &Pk::Ripemd160) -> Option<Preimage32> {
+        None
+    }
+
+    /// Given a SHA256 followed by RIPEMD160 hash, look up its preimage.
+    fn lookup_hash160(&self, _: &Pk::Hash160) -> Option<Preimage32> {
+        None
+    }
+
     /// Assert that a relative lock time is satisfied.
     fn check_older(&self, _: elements::Sequence) -> bool {
         false
@@ -153,6 +168,27 @@ impl<Pk: ToXOnlyPubkey> Policy<Pk> {
                     super::serialize::sha256::<Pk, _, _>(inference_context, hash, preimage),
                 )
             }
+            Policy::Hash256(ref hash) => {
+                let preimage = satisfier.lookup_hash256(hash).map(Value::u256);
+                ok_if(
+                    preimage.is_some(),
+                    super::serialize::hash256::<Pk, _, _>(inference_context, hash, preimage),
+                )
+            }
+            Policy::Ripemd160(ref hash) => {
+                let preimage = satisfier.lookup_ripemd160(hash).map(Value::u256);
+                ok_if(
+                    preimage.is_some(),
+                    super::serialize::ripemd160::<Pk, _, _>(inference_context, hash, preimage),
+                )
+            }
+            Policy::Hash160(ref hash) => {
+                let preimage = satisfier.lookup_hash160(hash).map(Value::u256);
+                ok_if(
+                    preimage.is_some(),
+                    super::serialize::hash160::<Pk, _, _>(inference_context, hash, preimage),
+                )
+            }
             Policy::And {
                 ref left,
                 ref right,
@@ -231,6 +267,33 @@ impl<Pk: ToXOnlyPubkey> Policy<Pk> {
                     super::serialize::threshold(k, &subs_res, &witness_bits),
                 )
             }
+            Policy::Multi(k, ref keys) => {
+                // Every signature costs the same, so the first `k` are used.
+                let mut remaining = k;
+                let witnesses: Vec<Option<Value>> =
+                    keys.iter()
+                        .map(|key| {
+                            let signature = match remaining {
+                                0 => None,
+                                _ => satisfier
+                                    .lookup_tap_leaf_script_sig(key, &TapLeafHash::all_zeros()),
+                            };
+                            let witness = match signature {
+                                Some(sig) => {
+                                    remaining -= 1;
+                                    Value::some(Value::u512(sig.sig.serialize()))
+                                }
+                                None => Value::none(Final::two_two_n(9)),
+                            };
+                            Some(witness)
+                        })
+                        .collect();
+                let k = u32::try_from(k).expect("k should be less than 2^32");
+                ok_if(
+                    remaining == 0,
+                    super::serialize::multi(inference_context, k, keys, &witnesses),
+                )
+            }
             Policy::Assembly(cmr) => match satisfier.lookup_asm_program(cmr) {
                 Some(program) => Hiding::from(program),
                 None => Hiding::hidden(cmr, inference_context.shallow_clone()),
@@ -269,14 +332,18 @@ mod tests {
     use crate::node::{CoreConstructible, JetConstructible, SimpleFinalizer, WitnessConstructible};
     use crate::policy::serialize;
     use crate::{BitMachine, FailEntropy, SimplicityKey};
+    use bitcoin_miniscript::hash256;
     use elements::bitcoin::key::{Keypair, XOnlyPublicKey};
     use elements::secp256k1_zkp;
-    use hashes::{sha256, Hash};
+    use hashes::{hash160, ripemd160, sha256, Hash};
     use std::collections::HashMap;
     use std::sync::Arc;
 
     pub struct PolicySatisfier<'a, Pk: SimplicityKey> {
         pub preimages: HashMap<Pk::Sha256, Preimage32>,
+        pub hash256_preimages: HashMap<Pk::Hash256, Preimage32>,
+        pub ripemd160_preimages: HashMap<Pk::Ripemd160, Preimage32>,
+        pub hash160_preimages: HashMap<Pk::Hash160, Preimage32>,
         pub signatures: HashMap<Pk, elements::SchnorrSig>,
         pub assembly: HashMap<Cmr, Arc<ConstructNode<Elements>>>,
         pub tx: &'a elements::Transaction,
@@ -296,6 +363,18 @@ mod tests {
             self.preimages.get(hash).copied()
         }
 
+        fn lookup_hash256(&self, hash: &Pk::Hash256) -> Option<Preimage32> {
+            self.hash256_preimages.get(hash).copied()
+        }
+
+        fn lookup_ripemd160(&self, hash: &Pk::Ripemd160) -> Option<Preimage32> {
+            self.ripemd160_preimages.get(hash).copied()
+        }
+
+        fn lookup_hash160(&self, hash: &Pk::Hash160) -> Option<Preimage32> {
+            self.hash160_preimages.get(hash).copied()
+        }
+
         fn check_older(&self, sequence: elements::Sequence) -> bool {
             let self_sequence = self.tx.input[self.index].sequence;
             <elements::Sequence as Satisfier<Pk>>::check_older(&self_sequence, sequence)
@@ -314,10 +393,16 @@ mod tests {
         env: &ElementsEnv<Arc<elements::Transaction>>,
     ) -> PolicySatisfier<XOnlyPublicKey> {
         let mut preimages = HashMap::new();
+        let mut hash256_preimages = HashMap::new();
+        let mut ripemd160_preimages = HashMap::new();
+        let mut hash160_preimages = HashMap::new();
 
         for i in 0..3 {
             let preimage = [i; 32];
             preimages.insert(sha256::Hash::hash(&preimage), preimage);
+            hash256_preimages.insert(hash256::Hash::hash(&preimage), preimage);
+            ripemd160_preimages.insert(ripemd160::Hash::hash(&preimage), preimage);
+            hash160_preimages.insert(hash160::Hash::hash(&preimage), preimage);
         }
 
         let secp = secp256k1_zkp::Secp256k1::new();
@@ -340,6 +425,9 @@ mod tests {
 
         PolicySatisfier {
             preimages,
+            hash256_preimages,
+            ripemd160_preimages,
+            hash160_preimages,
             signatures,
             assembly: HashMap::new(),
             tx: env.tx(),
@@ -447,6 +535,86 @@ mod tests {
         execute_successful(program, &env);
     }
 
+    #[test]
+    fn satisfy_hash256() {
+        let env = ElementsEnv::dummy();
+        let satisfier = get_satisfier(&env);
+        let image = *satisfier.hash256_preimages.keys().next().unwrap();
+        let policy = Policy::Hash256(image);
+
+        let program = policy.satisfy(&satisfier, &env).expect("satisfiable");
+        let witness = to_witness(&program);
+        assert_eq!(1, witness.len());
+
+        let witness_bytes = witness[0]
+            .iter_padded()
+            .try_collect_bytes()
+            .expect("to bytes");
+        let witness_preimage = Preimage32::try_from(witness_bytes.as_slice()).expect("to array");
+        assert_eq!(satisfier.hash256_preimages[&image], witness_preimage);
+
+        execute_successful(program, &env);
+
+        let policy = Policy::Hash256(hash256::Hash::from_byte_array([0; 32]));
+        assert!(policy.satisfy(&satisfier, &env).is_err());
+    }
+
+    #[test]
+    fn satisfy_ripemd160_hash160() {
+        let env = ElementsEnv::dummy();
+        let satisfier = get_satisfier(&env);
+        let image = *satisfier.ripemd160_preimages.keys().next().unwrap();
+        let program = Policy::Ripemd160(image)
+            .satisfy(&satisfier, &env)
+            .expect("satisfiable");
+        assert_eq!(1, to_witness(&program).len());
+        execute_successful(program, &env);
+
+        let image = *satisfier.hash160_preimages.keys().next().unwrap();
+        let program = Policy::Hash160(image)
+            .satisfy(&satisfier, &env)
+            .expect("satisfiable");
+        assert_eq!(1, to_witness(&program).len());
+        execute_successful(program, &env);
+
+        let policy = Policy::Hash160(hash160::Hash::from_byte_array([0; 20]));
+        assert!(policy.satisfy(&satisfier, &env).is_err());
+    }
+
+    #[test]
+    fn satisfy_multi() {
+        let env = ElementsEnv::dummy();
+        let satisfier = get_satisfier(&env);
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let unknown = Keypair::new(&secp, &mut secp256k1_zkp::rand::rngs::OsRng)
+            .x_only_public_key()
+            .0;
+        let known: Vec<_> = satisfier.signatures.keys().copied().collect();
+
+        let assert_signed = |policy: &Policy<XOnlyPublicKey>, signed: &[bool]| {
+            let program = policy.satisfy(&satisfier, &env).expect("satisfiable");
+            let witness = to_witness(&program);
+            assert_eq!(signed.len(), witness.len());
+            for (value, &signed) in witness.iter().zip(signed) {
+                assert_eq!(value.as_right().is_some(), signed);
+            }
+            execute_successful(program, &env);
+        };
+
+        let policy = Policy::Multi(2, vec![known[0], unknown, known[1]]);
+        assert_signed(&policy, &[true, false, true]);
+
+        // Only the first `k` signatures are used.
+        let policy = Policy::Multi(1, vec![unknown, known[0], known[1]]);
+        assert_signed(&policy, &[false, true, false]);
+
+        let policy = Policy::Multi(0, vec![known[0]]);
+        assert_signed(&policy, &[false]);
+
+        let policy = Policy::Multi(2, vec![known[0], unknown]);
+        assert!(policy.satisfy(&satisfier, &env).is_err());
+    }
+
     #[test]
     fn satisfy_after() {
         let height = Height::from_consensus(42).unwrap();
@@ -131,6 +131,379 @@ where
     verify_bexp(&pair_hash_computed_hash, &eq256)
 }
 
+pub fn hash256<Pk, N, W>(inference_context: &types::Context, hash: &Pk::Hash256, witness: W) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements> + WitnessConstructible<W>,
+{
+    let hash_value = Word::u256(Pk::to_hash256(hash).to_byte_array());
+    let const_hash = N::const_word(inference_context, hash_value);
+    let witness256 = N::witness(inference_context, witness);
+    let computed_hash = compute_sha256(&compute_sha256(&witness256));
+    let pair_hash_computed_hash = N::pair(&const_hash, &computed_hash).expect("consistent types");
+    let eq256 = N::jet(inference_context, Elements::Eq256);
+
+    verify_bexp(&pair_hash_computed_hash, &eq256)
+}
+
+/// Word of the message block that is read by each step of the left line of RIPEMD160.
+const RIPEMD160_WORD_LEFT: [usize; 80] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, //
+    7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8, //
+    3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12, //
+    1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2, //
+    4, 0, 5, 9, 7, 12, 2, 10, 14, 1, 3, 8, 11, 6, 15, 13,
+];
+
+/// Word of the message block that is read by each step of the right line of RIPEMD160.
+const RIPEMD160_WORD_RIGHT: [usize; 80] = [
+    5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12, //
+    6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2, //
+    15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13, //
+    8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14, //
+    12, 15, 10, 4, 1, 5, 8, 7, 6, 2, 13, 14, 0, 3, 9, 11,
+];
+
+/// Left rotation of each step of the left line of RIPEMD160.
+const RIPEMD160_ROTATE_LEFT: [u8; 80] = [
+    11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8, //
+    7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12, //
+    11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5, //
+    11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12, //
+    9, 15, 5, 11, 6, 8, 13, 12, 5, 12, 13, 14, 11, 8, 5, 6,
+];
+
+/// Left rotation of each step of the right line of RIPEMD160.
+const RIPEMD160_ROTATE_RIGHT: [u8; 80] = [
+    8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6, //
+    9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11, //
+    9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5, //
+    15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8, //
+    8, 5, 12, 9, 12, 5, 14, 6, 8, 13, 6, 5, 15, 13, 11, 11,
+];
+
+/// Round constants of the left line of RIPEMD160.
+const RIPEMD160_CONSTANT_LEFT: [u32; 5] =
+    [0x00000000, 0x5a827999, 0x6ed9eba1, 0x8f1bbcdc, 0xa953fd4e];
+
+/// Round constants of the right line of RIPEMD160.
+const RIPEMD160_CONSTANT_RIGHT: [u32; 5] =
+    [0x50a28be6, 0x5c4dd124, 0x6d703ef3, 0x7a6d76e9, 0x00000000];
+
+/// Initial chaining value of RIPEMD160.
+const RIPEMD160_IV: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+/// Pad a RIPEMD160 digest to a 256-bit word.
+///
+/// The chaining values are little-endian in the digest, but big-endian in
+/// Simplicity words. The last 96 bits of the word are zero.
+pub fn ripemd160_word(digest: [u8; 20]) -> Word {
+    let mut bytes = [0; 32];
+    for (word, chunk) in bytes.chunks_exact_mut(4).zip(digest.chunks_exact(4)) {
+        word.copy_from_slice(&[chunk[3], chunk[2], chunk[1], chunk[0]]);
+    }
+    Word::u256(bytes)
+}
+
+/// Add two words, discarding the overflow bit.
+///
+/// add(left, right): A → 2^32
+fn add32<N>(inference_context: &types::Context, left: &N, right: &N) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let pair_left_right = N::pair(left, right).expect("consistent types");
+    let add32 = N::jet(inference_context, Elements::Add32);
+    let full_sum = N::comp(&pair_left_right, &add32).expect("consistent types");
+    let drop_iden = N::drop_(&N::iden(inference_context));
+    N::comp(&full_sum, &drop_iden).expect("consistent types")
+}
+
+/// Apply a jet to the pair `(x, (y, z))`.
+fn jet_xyz<N>(inference_context: &types::Context, jet: Elements, x: &N, y: &N, z: &N) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let pair_y_z = N::pair(y, z).expect("consistent types");
+    let pair_x_y_z = N::pair(x, &pair_y_z).expect("consistent types");
+    N::comp(&pair_x_y_z, &N::jet(inference_context, jet)).expect("consistent types")
+}
+
+/// Apply a jet to the pair `(x, y)`.
+fn jet_xy<N>(inference_context: &types::Context, jet: Elements, x: &N, y: &N) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let pair_x_y = N::pair(x, y).expect("consistent types");
+    N::comp(&pair_x_y, &N::jet(inference_context, jet)).expect("consistent types")
+}
+
+/// Apply a jet to `x`.
+fn jet_x<N>(inference_context: &types::Context, jet: Elements, x: &N) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    N::comp(x, &N::jet(inference_context, jet)).expect("consistent types")
+}
+
+/// scribe(n): A → 2^32
+fn scribe_u32<N>(inference_context: &types::Context, n: u32) -> N
+where
+    N: CoreConstructible,
+{
+    let const_n = N::const_word(inference_context, Word::u32(n));
+    N::comp(&N::unit(inference_context), &const_n).expect("consistent types")
+}
+
+/// Select a word of the message block.
+///
+/// The message block is a balanced tree of 16 words.
+///
+/// message_word(i): 2^512 → 2^32
+fn message_word<N>(inference_context: &types::Context, i: usize) -> N
+where
+    N: CoreConstructible,
+{
+    let mut node = N::iden(inference_context);
+    for bit in 0..4 {
+        node = match (i >> bit) & 1 {
+            0 => N::take(&node),
+            _ => N::drop_(&node),
+        };
+    }
+    node
+}
+
+/// Select a register of a line.
+///
+/// The registers are nested as `(A, (B, (C, (D, E))))`.
+///
+/// register(j): 2^160 → 2^32
+fn register<N>(inference_context: &types::Context, j: usize) -> N
+where
+    N: CoreConstructible,
+{
+    let mut node = N::iden(inference_context);
+    if j < 4 {
+        node = N::take(&node);
+    }
+    for _ in 0..j {
+        node = N::drop_(&node);
+    }
+    node
+}
+
+/// Select a register of the state of a line.
+///
+/// The state is the message block, followed by the registers.
+///
+/// state_register(j): 2^512 × 2^160 → 2^32
+fn state_register<N>(inference_context: &types::Context, j: usize) -> N
+where
+    N: CoreConstructible,
+{
+    N::drop_(&register(inference_context, j))
+}
+
+/// Compute the round function `f(B, C, D)` of RIPEMD160.
+///
+/// f(round): 2^512 × 2^160 → 2^32
+fn ripemd160_f<N>(inference_context: &types::Context, round: usize) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let (b, c, d) = (
+        state_register(inference_context, 1),
+        state_register(inference_context, 2),
+        state_register(inference_context, 3),
+    );
+    let ctx = inference_context;
+    match round {
+        0 => jet_xyz(ctx, Elements::XorXor32, &b, &c, &d),
+        1 => jet_xyz(ctx, Elements::Ch32, &b, &c, &d),
+        2 => {
+            let not_c = jet_x(ctx, Elements::Complement32, &c);
+            let or = jet_xy(ctx, Elements::Or32, &b, &not_c);
+            jet_xy(ctx, Elements::Xor32, &or, &d)
+        }
+        3 => jet_xyz(ctx, Elements::Ch32, &d, &b, &c),
+        _ => {
+            let not_d = jet_x(ctx, Elements::Complement32, &d);
+            let or = jet_xy(ctx, Elements::Or32, &c, &not_d);
+            jet_xy(ctx, Elements::Xor32, &b, &or)
+        }
+    }
+}
+
+/// Compute one step of a line of RIPEMD160.
+///
+/// The registers `(A, B, C, D, E)` become `(E, T, B, rol10(C), D)`,
+/// where `T = rol(A + f(B, C, D) + X + K) + E`.
+///
+/// step: 2^512 × 2^160 → 2^512 × 2^160
+fn ripemd160_step<N>(
+    inference_context: &types::Context,
+    round: usize,
+    word: usize,
+    rotate: u8,
+    constant: u32,
+) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let ctx = inference_context;
+    let rotate_left = |amount: u8, x: &N| {
+        let const_amount = N::const_word(ctx, Word::u8(amount));
+        let scribe_amount = N::comp(&N::unit(ctx), &const_amount).expect("consistent types");
+        jet_xy(ctx, Elements::LeftRotate32, &scribe_amount, x)
+    };
+
+    let sum = add32(ctx, &state_register(ctx, 0), &ripemd160_f(ctx, round));
+    let sum = add32(ctx, &sum, &N::take(&message_word(ctx, word)));
+    let sum = add32(ctx, &sum, &scribe_u32(ctx, constant));
+    let t = add32(ctx, &rotate_left(rotate, &sum), &state_register(ctx, 4));
+
+    let c_d = N::pair(
+        &rotate_left(10, &state_register(ctx, 2)),
+        &state_register(ctx, 3),
+    )
+    .expect("consistent types");
+    let b_c_d = N::pair(&state_register(ctx, 1), &c_d).expect("consistent types");
+    let t_b_c_d = N::pair(&t, &b_c_d).expect("consistent types");
+    let registers = N::pair(&state_register(ctx, 4), &t_b_c_d).expect("consistent types");
+    let message = N::take(&N::iden(ctx));
+    N::pair(&message, &registers).expect("consistent types")
+}
+
+/// Compute a line of RIPEMD160, starting from the initial chaining value.
+///
+/// line: 2^512 → 2^160
+fn ripemd160_line<N>(inference_context: &types::Context, left: bool) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let ctx = inference_context;
+    let (words, rotations, constants) = match left {
+        true => (
+            RIPEMD160_WORD_LEFT,
+            RIPEMD160_ROTATE_LEFT,
+            RIPEMD160_CONSTANT_LEFT,
+        ),
+        false => (
+            RIPEMD160_WORD_RIGHT,
+            RIPEMD160_ROTATE_RIGHT,
+            RIPEMD160_CONSTANT_RIGHT,
+        ),
+    };
+
+    let mut iv = scribe_u32(ctx, RIPEMD160_IV[4]);
+    for &h in RIPEMD160_IV[..4].iter().rev() {
+        iv = N::pair(&scribe_u32(ctx, h), &iv).expect("consistent types");
+    }
+    let mut state = N::pair(&N::iden(ctx), &iv).expect("consistent types");
+    for i in 0..80 {
+        // The right line uses the round functions in reverse order.
+        let round = match left {
+            true => i / 16,
+            false => 4 - i / 16,
+        };
+        let step = ripemd160_step(ctx, round, words[i], rotations[i], constants[i / 16]);
+        state = N::comp(&state, &step).expect("consistent types");
+    }
+    N::comp(&state, &N::drop_(&N::iden(ctx))).expect("consistent types")
+}
+
+/// Convert the bytes of each word of a 256-bit value between big-endian and
+/// little-endian order.
+///
+/// byte_swap(n): 2^(2^n) → 2^(2^n)
+fn byte_swap<N>(inference_context: &types::Context, n: usize) -> N
+where
+    N: CoreConstructible,
+{
+    let ctx = inference_context;
+    let swap = |child: &N| N::pair(&N::drop_(child), &N::take(child)).expect("consistent types");
+    let map = |child: &N| N::pair(&N::take(child), &N::drop_(child)).expect("consistent types");
+    match n {
+        3 => N::iden(ctx),
+        4 | 5 => swap(&byte_swap(ctx, n - 1)),
+        _ => map(&byte_swap(ctx, n - 1)),
+    }
+}
+
+/// Compute the RIPEMD160 hash of a 256-bit value.
+///
+/// Simplicity has no jet for RIPEMD160, so the compression function is
+/// computed from 32-bit word jets. The message is padded to a single block.
+/// The digest is padded to 256 bits, as in [`ripemd160_word`].
+pub fn compute_ripemd160<N>(input256: &N) -> N
+where
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let ctx = input256.inference_context();
+    // RIPEMD160 reads the message as little-endian words.
+    let mut padding = [0; 32];
+    padding[3] = 0x80;
+    padding[26] = 0x01; // length of 256 bits in the 15th word
+    let scribe_padding =
+        N::comp(&N::unit(ctx), &N::const_word(ctx, Word::u256(padding))).expect("consistent types");
+    let block = N::pair(&byte_swap(ctx, 8), &scribe_padding).expect("consistent types");
+
+    let pair_lines =
+        N::pair(&ripemd160_line(ctx, true), &ripemd160_line(ctx, false)).expect("consistent types");
+    let left = |j: usize| N::take(&register(ctx, j));
+    let right = |j: usize| N::drop_(&register(ctx, j));
+    let chain = |h: usize, j: usize, k: usize| {
+        let sum = add32(ctx, &scribe_u32(ctx, RIPEMD160_IV[h]), &left(j));
+        add32(ctx, &sum, &right(k))
+    };
+    let h0_h1 = N::pair(&chain(1, 2, 3), &chain(2, 3, 4)).expect("consistent types");
+    let h2_h3 = N::pair(&chain(3, 4, 0), &chain(4, 0, 1)).expect("consistent types");
+    let h4_0 = N::pair(&chain(0, 1, 2), &scribe_u32(ctx, 0)).expect("consistent types");
+    let zero = N::comp(&N::unit(ctx), &N::const_word(ctx, Word::u64(0))).expect("consistent types");
+    let h0_h3 = N::pair(&h0_h1, &h2_h3).expect("consistent types");
+    let h4_0_0 = N::pair(&h4_0, &zero).expect("consistent types");
+    let digest = N::pair(&h0_h3, &h4_0_0).expect("consistent types");
+
+    let block = N::comp(input256, &block).expect("consistent types");
+    let lines = N::comp(&block, &pair_lines).expect("consistent types");
+    N::comp(&lines, &digest).expect("consistent types")
+}
+
+pub fn ripemd160<Pk, N, W>(
+    inference_context: &types::Context,
+    hash: &Pk::Ripemd160,
+    witness: W,
+) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements> + WitnessConstructible<W>,
+{
+    let hash_value = ripemd160_word(Pk::to_ripemd160(hash).to_byte_array());
+    let const_hash = N::const_word(inference_context, hash_value);
+    let witness256 = N::witness(inference_context, witness);
+    let computed_hash = compute_ripemd160(&witness256);
+    let pair_hash_computed_hash = N::pair(&const_hash, &computed_hash).expect("consistent types");
+    let eq256 = N::jet(inference_context, Elements::Eq256);
+
+    verify_bexp(&pair_hash_computed_hash, &eq256)
+}
+
+pub fn hash160<Pk, N, W>(inference_context: &types::Context, hash: &Pk::Hash160, witness: W) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements> + WitnessConstructible<W>,
+{
+    let hash_value = ripemd160_word(Pk::to_hash160(hash).to_byte_array());
+    let const_hash = N::const_word(inference_context, hash_value);
+    let witness256 = N::witness(inference_context, witness);
+    let computed_hash = compute_ripemd160(&compute_sha256(&witness256));
+    let pair_hash_computed_hash = N::pair(&const_hash, &computed_hash).expect("consistent types");
+    let eq256 = N::jet(inference_context, Elements::Eq256);
+
+    verify_bexp(&pair_hash_computed_hash, &eq256)
+}
+
 pub fn and<N>(left: &N, right: &N) -> N
 where
     N: CoreConstructible,
@@ -255,12 +628,111 @@ where
     thresh_verify(&sum, k)
 }
 
+/// Verifies a signature with the key and counts it.
+///
+/// verify(key): 2^512 × 2^32 → 2^32
+pub fn multi_verify<Pk, N>(inference_context: &types::Context, key: &Pk) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements>,
+{
+    let key_value = Word::u256(key.to_x_only_pubkey().serialize());
+    // 1 → 2^256
+    let const_key = N::const_word(inference_context, key_value);
+    // 1 → 2^256
+    let sighash_all = N::jet(inference_context, Elements::SigAllHash);
+    // 1 → 2^256 × 2^256
+    let pair_key_msg = N::pair(&const_key, &sighash_all).expect("consistent types");
+    // 2^512 × 2^32 → 1
+    let unit = N::unit(inference_context);
+    // 2^512 × 2^32 → 2^256 × 2^256
+    let scribe_key_msg = N::comp(&unit, &pair_key_msg).expect("consistent types");
+    // 2^512 × 2^32 → 2^512
+    let sig = N::take(&N::iden(inference_context));
+    // 2^512 × 2^32 → (2^256 × 2^256) × 2^512
+    let pair_key_msg_sig = N::pair(&scribe_key_msg, &sig).expect("consistent types");
+    let bip_0340_verify = N::jet(inference_context, Elements::Bip0340Verify);
+    // 2^512 × 2^32 → 1
+    let verify = N::comp(&pair_key_msg_sig, &bip_0340_verify).expect("consistent types");
+
+    // 2^512 × 2^32 → 2^32
+    let count = N::drop_(&N::iden(inference_context));
+    // 2^512 × 2^32 → 1 × 2^32
+    let pair_verify_count = N::pair(&verify, &count).expect("consistent types");
+    // 2^32 → 2 × 2^32
+    let increment32 = N::jet(inference_context, Elements::Increment32);
+    // Discard the overflow bit, which is never set because there are fewer
+    // than 2^32 keys.
+    // 2^32 → 2^32
+    let increment =
+        N::comp(&increment32, &N::drop_(&N::iden(inference_context))).expect("consistent types");
+
+    // 2^512 × 2^32 → 2^32
+    N::comp(&pair_verify_count, &N::drop_(&increment)).expect("consistent types")
+}
+
+/// Counts the signature in the witness, if there is one.
+///
+/// The witness is of type `1 + 2^512`.
+///
+/// step(key): 2^32 → 2^32
+pub fn multi_step<Pk, N, W>(inference_context: &types::Context, key: &Pk, witness: W) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements> + WitnessConstructible<W>,
+{
+    // 2^32 → (1 + 2^512) × 2^32
+    let witness = N::witness(inference_context, witness);
+    let iden = N::iden(inference_context);
+    let pair_witness_count = N::pair(&witness, &iden).expect("consistent types");
+
+    // 1 × 2^32 → 2^32
+    let drop_iden = N::drop_(&iden);
+    // 2^512 × 2^32 → 2^32
+    let verify = multi_verify(inference_context, key);
+    // (1 + 2^512) × 2^32 → 2^32
+    let skip_or_verify = N::case(&drop_iden, &verify).expect("consistent types");
+
+    N::comp(&pair_witness_count, &skip_or_verify).expect("consistent types")
+}
+
+/// Counts the signatures of the given keys, one key after another, and
+/// checks that there are `k` of them.
+///
+/// Each key has a single witness, which is an optional signature.
+///
+/// multi: 1 → 1
+pub fn multi<Pk, N, W>(
+    inference_context: &types::Context,
+    k: u32,
+    keys: &[Pk],
+    witnesses: &[W],
+) -> N
+where
+    Pk: ToXOnlyPubkey,
+    N: CoreConstructible + JetConstructible<Elements> + WitnessConstructible<W>,
+    W: Clone,
+{
+    let n = u32::try_from(keys.len()).expect("can have at most 2^32 keys in a multi");
+    assert!(k <= n, "threshold must be <= number of keys");
+
+    // 1 → 2^32
+    let mut count = N::const_word(inference_context, Word::u32(0));
+    for (key, witness) in keys.iter().zip(witnesses) {
+        let step = multi_step(inference_context, key, witness.clone());
+        count = N::comp(&count, &step).expect("consistent types");
+    }
+
+    thresh_verify(&count, k)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::jet::elements::ElementsEnv;
     use crate::node::SimpleFinalizer;
     use crate::policy::Policy;
+    use crate::types::Final;
     use crate::{BitMachine, CommitNode, FailEntropy, Value};
     use elements::bitcoin::key::XOnlyPublicKey;
     use elements::locktime::Height;
@@ -389,6 +861,81 @@ mod tests {
         assert!(!execute_successful(&commit, invalid_witness, &env));
     }
 
+    #[test]
+    fn execute_hash256() {
+        let preimage = [1; 32];
+        let image = bitcoin_miniscript::hash256::Hash::hash(&preimage);
+        let (commit, env) = compile(Policy::Hash256(image));
+
+        let valid_witness = vec![Value::u256(preimage)];
+        assert!(execute_successful(&commit, valid_witness, &env));
+
+        let invalid_witness = vec![Value::u256(sha256::Hash::hash(&preimage).to_byte_array())];
+        assert!(!execute_successful(&commit, invalid_witness, &env));
+    }
+
+    #[test]
+    fn execute_ripemd160() {
+        for preimage in [[0; 32], [1; 32], [0xab; 32]] {
+            let image = hashes::ripemd160::Hash::hash(&preimage);
+            let (commit, env) = compile(Policy::Ripemd160(image));
+
+            let valid_witness = vec![Value::u256(preimage)];
+            assert!(execute_successful(&commit, valid_witness, &env));
+
+            let invalid_witness = vec![Value::u256([2; 32])];
+            assert!(!execute_successful(&commit, invalid_witness, &env));
+        }
+    }
+
+    #[test]
+    fn execute_hash160() {
+        let preimage = [1; 32];
+        let image = hashes::hash160::Hash::hash(&preimage);
+        let (commit, env) = compile(Policy::Hash160(image));
+
+        let valid_witness = vec![Value::u256(preimage)];
+        assert!(execute_successful(&commit, valid_witness, &env));
+
+        let invalid_witness = vec![Value::u256(sha256::Hash::hash(&preimage).to_byte_array())];
+        assert!(!execute_successful(&commit, invalid_witness, &env));
+    }
+
+    #[test]
+    fn execute_multi() {
+        let env = ElementsEnv::dummy();
+        let sighash = env.c_tx_env().sighash_all();
+        let message = secp256k1_zkp::Message::from_digest(sighash.to_byte_array());
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let mut keys = vec![];
+        let mut signatures = vec![];
+        for _ in 0..3 {
+            let keypair = secp256k1_zkp::Keypair::new(&secp, &mut secp256k1_zkp::rand::rngs::OsRng);
+            keys.push(keypair.x_only_public_key().0);
+            signatures.push(Value::some(Value::u512(
+                keypair.sign_schnorr(message).serialize(),
+            )));
+        }
+        let no_signature = || Value::none(Final::two_two_n(9));
+
+        let (commit, env) = compile(Policy::Multi(2, keys));
+
+        let valid_witness = vec![signatures[0].clone(), no_signature(), signatures[2].clone()];
+        assert!(execute_successful(&commit, valid_witness, &env));
+
+        let valid_witness = vec![no_signature(), signatures[1].clone(), signatures[2].clone()];
+        assert!(execute_successful(&commit, valid_witness, &env));
+
+        let invalid_witness = vec![no_signature(), signatures[1].clone(), no_signature()];
+        assert!(!execute_successful(&commit, invalid_witness, &env));
+
+        let invalid_witness = signatures.clone();
+        assert!(!execute_successful(&commit, invalid_witness, &env));
+
+        let invalid_witness = vec![signatures[1].clone(), no_signature(), signatures[2].clone()];
+        assert!(!execute_successful(&commit, invalid_witness, &env));
+    }
+
     #[test]
     fn execute_and() {
         let preimage0 = [1; 32];
@@ -1044,6 +1044,71 @@ impl fmt::Display for Word {
     }
 }
 
+/// Parses a word from a hex literal `0x...` or a binary literal `0b...`, the
+/// formats in which it is displayed.
+///
+/// As in the human-readable encoding, hex digits must be lowercase.
+impl core::str::FromStr for Word {
+    type Err = ParseWordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, bits_per_digit, radix) = if let Some(digits) = s.strip_prefix("0x") {
+            (digits, 4, 16)
+        } else if let Some(digits) = s.strip_prefix("0b") {
+            (digits, 1, 2)
+        } else {
+            return Err(ParseWordError::BadPrefix);
+        };
+
+        let mut bits = Vec::with_capacity(digits.len() * bits_per_digit);
+        for ch in digits.chars() {
+            let digit = ch
+                .to_digit(radix)
+                .filter(|_| !ch.is_ascii_uppercase())
+                .ok_or(ParseWordError::BadDigit(ch))?;
+            bits.extend((0..bits_per_digit).rev().map(|i| digit & (1 << i) != 0));
+        }
+        if !bits.len().is_power_of_two() || bits.len() > 1 << 31 {
+            return Err(ParseWordError::BadLength(bits.len()));
+        }
+
+        let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+        for (n, bit) in bits.iter().enumerate() {
+            if *bit {
+                bytes[n / 8] |= 0x80 >> (n % 8);
+            }
+        }
+        let n = bits.len().trailing_zeros();
+        // unwrap ok since the iterator has exactly enough bits
+        Ok(Word::from_bits(&mut BitIter::from(bytes), n).unwrap())
+    }
+}
+
+/// An error parsing a [`Word`] from a string
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseWordError {
+    /// The string did not start with `0x` or `0b`.
+    BadPrefix,
+    /// The string contained a character which is not a digit in its base.
+    BadDigit(char),
+    /// The number of bits was not a power of two of at most 2^31.
+    BadLength(usize),
+}
+
+impl fmt::Display for ParseWordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseWordError::BadPrefix => f.write_str("word does not start with 0x or 0b"),
+            ParseWordError::BadDigit(ch) => write!(f, "invalid digit `{}` in word", ch),
+            ParseWordError::BadLength(bit_length) => {
+                write!(f, "word length {} is not a valid power of 2", bit_length)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseWordError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1210,6 +1275,29 @@ mod tests {
         let new_v = Value::from_padded_bits(&mut iter, &v.ty).unwrap();
         assert_eq!(v, new_v);
     }
+
+    #[test]
+    fn word_from_str() {
+        use core::str::FromStr as _;
+
+        for word in [
+            Word::u1(1),
+            Word::u4(6),
+            Word::u8(0xa5),
+            Word::u256([0x12; 32]),
+        ] {
+            assert_eq!(Word::from_str(&word.to_string()), Ok(word));
+        }
+        assert_eq!(Word::from_str("0x6"), Ok(Word::u4(6)));
+        assert_eq!(Word::from_str("0b10100101"), Ok(Word::u8(0xa5)));
+
+        assert_eq!(Word::from_str("a5"), Err(ParseWordError::BadPrefix));
+        assert_eq!(Word::from_str("0xg5"), Err(ParseWordError::BadDigit('g')));
+        assert_eq!(Word::from_str("0xA5"), Err(ParseWordError::BadDigit('A')));
+        assert_eq!(Word::from_str("0b012"), Err(ParseWordError::BadDigit('2')));
+        assert_eq!(Word::from_str("0xabc"), Err(ParseWordError::BadLength(12)));
+        assert_eq!(Word::from_str("0x"), Err(ParseWordError::BadLength(0)));
+    }
 }
 
 #[cfg(bench)]